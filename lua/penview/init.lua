@@ -41,13 +41,90 @@ M.debounce_ms = 100
 M.port = 0
 M.client = nil
 M.timer = nil
+M.content_dirty = false
+-- Line edits made since the buffer was last sent, or nil when the next update must send the whole
+-- buffer (see `_send_buffer`)
+M.pending_edits = nil
+M.attached_buf = nil
+M.selection_active = false
 M.server_addr = nil
 M.server_job = nil
 M.debug = false
 M.sync_scroll = true
+M.sync_viewport = false
+M.typewriter = false
+M.mirror_scroll = false
+M.max_frame_size = nil
+M.diagnostics = true
+M.lint = false
+M.vale = nil
+M.screenshot_browser = nil
+M.webhooks = {}
+M.webhook_events = nil
+M.spellcheck = nil
+M.blame = false
+M.footer = false
+M.frontmatter_card = false
+M.toc = false
+M.cursor_highlight = "none"
+M.title_format = nil
+M.shortcodes = {}
+M.exec = {}
+M.templating = false
+M.hide_drafts = false
+M.workspace = nil
+M.daily_note_pattern = nil
+M.css = nil
+M.base_path = ""
+M.daemon = false
+M.instance = nil
+M.token = nil
+M.server_token = nil
+M.tunnel = nil
+M.tunnel_url = nil
+M.mdns = true
+M.remote = false
+M.remote_url = nil
+M.root = nil
+M.link_navigation = "navigate"
+M.autolink = "none"
+M.emoji = false
+M.math = "katex"
+M.highlight = "client"
+M.code_theme = nil
+M.code_theme_dark = nil
+M.diagrams = "client"
+M.diagrams = "client"
+M.locale = "en"
+-- Fonts and sizes of the preview, e.g. { font_family = "Georgia, serif", font_size = 18 }
+M.typography = {}
+-- Width and margins of the preview, e.g. { max_width = "60rem", full_width = true }
+M.layout = {}
+M.style = "penview"
+M.styles = { "penview", "github", "solarized", "gruvbox", "high-contrast", "sepia" }
+M.slugs = "github"
+M.link_policy = {}
+M.obfuscate_emails = "none"
+M.editable = false
+M.rate_limit = nil
+M.render_workers = nil
+M.hooks = {}
 M.headless = false
 M.theme = "dark"
 
+-- Token for servers that need one but weren't given any, from the OS random source if available
+local function random_token()
+	local urandom = io.open("/dev/urandom", "rb")
+	if urandom then
+		local bytes = urandom:read(16)
+		urandom:close()
+		return (bytes:gsub(".", function(c)
+			return string.format("%02x", c:byte())
+		end))
+	end
+	return vim.fn.sha256(tostring(vim.loop.hrtime()) .. vim.fn.getpid() .. tostring(math.random()))
+end
+
 function M.setup(opts)
 	opts = opts or {}
 	M.headless = opts.headless or false
@@ -55,6 +132,180 @@ function M.setup(opts)
 	M.port = opts.port or 0
 	M.debug = opts.debug or false
 	M.sync_scroll = opts.sync_scroll ~= false -- default true
+	M.sync_viewport = opts.sync_viewport or false
+	M.typewriter = opts.typewriter or false
+	M.mirror_scroll = opts.mirror_scroll or false
+	M.max_frame_size = opts.max_frame_size
+	M.diagnostics = opts.diagnostics ~= false -- default true
+	M.lint = opts.lint or false
+	M.vale = opts.vale
+	M.screenshot_browser = opts.screenshot_browser
+	M.webhooks = opts.webhooks or {}
+	M.webhook_events = opts.webhook_events
+	M.spellcheck = opts.spellcheck
+	M.blame = opts.blame or false
+	M.footer = opts.footer or false
+	M.frontmatter_card = opts.frontmatter_card or false
+	M.toc = opts.toc or false
+	M.emoji = opts.emoji or false
+	M.title_format = opts.title_format
+	M.shortcodes = opts.shortcodes or {}
+	M.exec = opts.exec or {}
+	M.templating = opts.templating or false
+	M.hide_drafts = opts.hide_drafts or false
+	M.workspace = opts.workspace
+	M.daily_note_pattern = opts.daily_note_pattern
+	M.css = opts.css
+	M.base_path = opts.base_path or M.base_path
+	M.daemon = opts.daemon or false
+	M.token = opts.token
+	M.mdns = opts.mdns ~= false -- default true
+	M.remote = opts.remote or false
+	M.root = opts.root
+	M.rate_limit = opts.rate_limit
+	M.render_workers = opts.render_workers
+	M.hooks = opts.hooks or {}
+
+	if opts.cursor_highlight then
+		if not vim.tbl_contains({ "none", "flash", "border" }, opts.cursor_highlight) then
+			error(
+				'[penview] \'cursor_highlight\' must be "none", "flash" or "border", got: '
+					.. tostring(opts.cursor_highlight)
+			)
+		end
+		M.cursor_highlight = opts.cursor_highlight
+	end
+
+	if opts.link_navigation then
+		if not vim.tbl_contains({ "navigate", "editor", "disabled" }, opts.link_navigation) then
+			error(
+				'[penview] \'link_navigation\' must be "navigate", "editor" or "disabled", got: '
+					.. tostring(opts.link_navigation)
+			)
+		end
+		M.link_navigation = opts.link_navigation
+	end
+
+	if opts.autolink then
+		if not vim.tbl_contains({ "none", "urls", "extended" }, opts.autolink) then
+			error(
+				'[penview] \'autolink\' must be "none", "urls" or "extended", got: '
+					.. tostring(opts.autolink)
+			)
+		end
+		M.autolink = opts.autolink
+	end
+
+	if opts.math then
+		if not vim.tbl_contains({ "katex", "mathjax", "mathml" }, opts.math) then
+			error(
+				'[penview] \'math\' must be "katex", "mathjax" or "mathml", got: '
+					.. tostring(opts.math)
+			)
+		end
+		M.math = opts.math
+	end
+
+	if opts.highlight then
+		if opts.highlight ~= "client" and opts.highlight ~= "server" then
+			error(
+				'[penview] \'highlight\' must be "client" or "server", got: '
+					.. tostring(opts.highlight)
+			)
+		end
+		M.highlight = opts.highlight
+	end
+	M.code_theme = opts.code_theme
+	M.code_theme_dark = opts.code_theme_dark
+
+	if opts.diagrams then
+		if opts.diagrams ~= "client" and opts.diagrams ~= "server" then
+			error(
+				'[penview] \'diagrams\' must be "client" or "server", got: '
+					.. tostring(opts.diagrams)
+			)
+		end
+		M.diagrams = opts.diagrams
+	end
+
+	if opts.style then
+		if not vim.tbl_contains(M.styles, opts.style) then
+			error(
+				"[penview] 'style' must be one of "
+					.. table.concat(M.styles, ", ")
+					.. ", got: "
+					.. tostring(opts.style)
+			)
+		end
+		M.style = opts.style
+	end
+
+	if opts.typography then
+		local keys = { "font_family", "mono_font_family", "font_size", "line_height" }
+		for key, value in pairs(opts.typography) do
+			if not vim.tbl_contains(keys, key) then
+				error("[penview] unknown 'typography' setting: " .. tostring(key))
+			end
+			if type(value) ~= "string" and type(value) ~= "number" then
+				error(
+					"[penview] 'typography." .. key .. "' must be a string or number, got: "
+						.. tostring(value)
+				)
+			end
+		end
+		M.typography = opts.typography
+	end
+
+	if opts.layout then
+		local keys = { "max_width", "margin", "full_width" }
+		for key in pairs(opts.layout) do
+			if not vim.tbl_contains(keys, key) then
+				error("[penview] unknown 'layout' setting: " .. tostring(key))
+			end
+		end
+		M.layout = opts.layout
+	end
+
+	if opts.locale then
+		if type(opts.locale) ~= "string" or not opts.locale:match("^%a+[-_%w]*$") then
+			error(
+				"[penview] 'locale' must be a language tag such as \"de\", got: "
+					.. tostring(opts.locale)
+			)
+		end
+		M.locale = opts.locale
+	end
+
+	if opts.slugs then
+		if not vim.tbl_contains({ "github", "gitlab", "pandoc" }, opts.slugs) then
+			error(
+				'[penview] \'slugs\' must be "github", "gitlab" or "pandoc", got: '
+					.. tostring(opts.slugs)
+			)
+		end
+		M.slugs = opts.slugs
+	end
+
+	if opts.link_policy ~= nil then
+		if type(opts.link_policy) ~= "table" then
+			error("[penview] 'link_policy' must be a table, got: " .. type(opts.link_policy))
+		end
+		M.link_policy = opts.link_policy
+	end
+
+	if opts.obfuscate_emails then
+		if not vim.tbl_contains({ "none", "entities", "script" }, opts.obfuscate_emails) then
+			error(
+				'[penview] \'obfuscate_emails\' must be "none", "entities" or "script", got: '
+					.. tostring(opts.obfuscate_emails)
+			)
+		end
+		M.obfuscate_emails = opts.obfuscate_emails
+	end
+
+	if opts.editable ~= nil then
+		M.editable = opts.editable
+	end
 
 	if opts.theme then
 		if opts.theme ~= "light" and opts.theme ~= "dark" then
@@ -63,7 +314,26 @@ function M.setup(opts)
 		M.theme = opts.theme
 	end
 
-	if M.headless then
+	if opts.tunnel then
+		if not vim.tbl_contains({ "cloudflared", "ngrok" }, opts.tunnel) then
+			error('[penview] \'tunnel\' must be "cloudflared" or "ngrok", got: ' .. tostring(opts.tunnel))
+		end
+		M.tunnel = opts.tunnel
+		-- Anyone with the public URL can reach the server, so it always needs a token
+		M.token = M.token or random_token()
+	end
+
+	if M.daemon then
+		-- Namespaces this editor's buffers on the shared server
+		M.instance = tostring(vim.fn.getpid())
+	end
+
+	if M.remote then
+		-- The server requires a token in remote mode, so this editor picks it
+		M.token = M.token or random_token()
+		-- Browser is optional in remote mode
+		M.browser = opts.browser
+	elseif M.headless then
 		-- Headless mode requires a port
 		if not opts.port or opts.port == 0 then
 			error(
@@ -89,14 +359,126 @@ local function log(msg)
 	end
 end
 
+-- URL prefix of the server's routes, normalized to "/prefix" (or "" for the root) like the server
+local function base_path_prefix()
+	local trimmed = M.base_path:gsub("^/+", ""):gsub("/+$", "")
+	return trimmed == "" and "" or "/" .. trimmed
+end
+
+local function url_encode(str)
+	return (str:gsub("[^%w%-_%.~/]", function(c)
+		return string.format("%%%02X", string.byte(c))
+	end))
+end
+
+-- Query string for the server, tagged with this editor's instance in daemon mode and the
+-- server's token, if it requires one
+local function query(params)
+	if M.instance then
+		params.instance = M.instance
+	end
+	if M.server_token then
+		params.token = M.server_token
+	end
+	local parts = {}
+	for key, value in pairs(params) do
+		table.insert(parts, key .. "=" .. url_encode(value))
+	end
+	table.sort(parts)
+	return table.concat(parts, "&")
+end
+
+-- Open the preview of `path` in the configured browser
+local function open_browser(path)
+	if M.headless or not M.browser then
+		return
+	end
+	local url = "http://" .. M.server_addr .. base_path_prefix() .. "/?" .. query({ path = path })
+	vim.fn.jobstart({ M.browser, url }, { detach = true })
+end
+
+-- Running servers, as announced in their runtime files (see `penview discover`)
+function M.discover()
+	local binary = get_binary_path()
+	if not binary then
+		return {}
+	end
+
+	local output = vim.fn.system({ binary, "discover" })
+	if vim.v.shell_error ~= 0 then
+		return {}
+	end
+	local ok, servers = pcall(vim.json.decode, output, { luanil = { object = true } })
+	if not ok or type(servers) ~= "table" then
+		return {}
+	end
+	return servers
+end
+
+-- Daemon mode: attach to a shared server that is already running, if any
+-- Whether the user allows code blocks in the configured `exec` languages to be run from the
+-- preview in `dir`. Asks once per project and language set; consent is remembered in Neovim's
+-- state directory.
+local function exec_allowed(dir)
+	local languages = vim.tbl_keys(M.exec)
+	table.sort(languages)
+	local file = vim.fn.stdpath("state") .. "/penview/exec-trust.json"
+
+	local trusted = {}
+	if vim.fn.filereadable(file) == 1 then
+		local ok, decoded = pcall(vim.json.decode, table.concat(vim.fn.readfile(file), ""))
+		if ok and type(decoded) == "table" then
+			trusted = decoded
+		end
+	end
+	local allowed = trusted[dir] or {}
+	local missing = vim.tbl_filter(function(lang)
+		return not vim.tbl_contains(allowed, lang)
+	end, languages)
+	if #missing == 0 then
+		return true
+	end
+
+	local prompt = ("[penview] Allow the preview to run %s code blocks from documents in %s?"):format(
+		table.concat(languages, ", "),
+		dir
+	)
+	if vim.fn.confirm(prompt, "&Yes\n&No", 2) ~= 1 then
+		return false
+	end
+	trusted[dir] = languages
+	vim.fn.mkdir(vim.fn.fnamemodify(file, ":h"), "p")
+	vim.fn.writefile({ vim.json.encode(trusted) }, file)
+	return true
+end
+
+local function attach_to_daemon(path, bufnr)
+	for _, server in ipairs(M.discover()) do
+		-- Servers on a unix socket (port 0) can't be reached by browsers
+		if server.shared and server.port ~= 0 and server.base_path == base_path_prefix() then
+			-- Servers bound to all interfaces are reached over loopback
+			M.server_addr = server.address:gsub("^0%.0%.0%.0:", "127.0.0.1:")
+			M.server_token = server.token
+			log("Attaching to shared server at " .. M.server_addr)
+			print("[penview] Attached to shared server at " .. M.server_addr)
+			M._connect(path, bufnr)
+			open_browser(path)
+			return true
+		end
+	end
+	return false
+end
+
 function M.start()
 	local path = vim.fn.expand("%:p")
+	-- The connection completes asynchronously, by when another buffer may be current
+	local bufnr = vim.api.nvim_get_current_buf()
 	if not path:match("%.md$") then
 		print("[penview] Not a markdown file")
 		return
 	end
 
-	if not M.headless and not M.browser then
+	if not M.headless and not M.remote and not M.browser then
 		print("[penview] Browser not configured. Call setup() first with browser option.")
 		return
 	end
@@ -107,6 +489,10 @@ function M.start()
 		return
 	end
 
+	if M.daemon and attach_to_daemon(path, bufnr) then
+		return
+	end
+
 	log("Binary: " .. binary)
 	log("Path: " .. path)
 	log("Headless: " .. tostring(M.headless))
@@ -117,9 +503,15 @@ function M.start()
 	-- Build command
 	-- Note: file path is passed via WebSocket URL in _connect(), not as CLI arg
 	local cmd
-	if M.headless then
+	if M.remote then
+		-- Remote mode: bind to 0.0.0.0 and confine the server to the workspace, no browser open
+		cmd = { binary, "serve", "-q", "-p", tostring(M.port), "--remote", "--theme", M.theme }
+	elseif M.headless then
 		-- Headless mode: bind to 0.0.0.0, no browser open
 		cmd = { binary, "serve", "-q", "-p", tostring(M.port), "-a", "0.0.0.0", "--theme", M.theme }
+	elseif M.daemon then
+		-- Daemon mode: the browser is opened from here, with this editor's instance in the URL
+		cmd = { binary, "serve", "-q", "-p", tostring(M.port), "--theme", M.theme, "--shared" }
 	else
 		-- Normal mode: --open tells server to launch browser with file path
 		cmd = {
@@ -137,29 +529,204 @@ function M.start()
 		}
 	end
 
+	if M.lint then
+		table.insert(cmd, "--lint")
+	end
+	if M.vale then
+		vim.list_extend(cmd, { "--vale", M.vale })
+	end
+	if M.screenshot_browser then
+		vim.list_extend(cmd, { "--screenshot-browser", M.screenshot_browser })
+	end
+	for _, url in ipairs(M.webhooks) do
+		vim.list_extend(cmd, { "--webhook", url })
+	end
+	if M.webhook_events then
+		vim.list_extend(cmd, { "--webhook-events", table.concat(M.webhook_events, ",") })
+	end
+	if M.spellcheck then
+		vim.list_extend(cmd, { "--spellcheck", M.spellcheck })
+	end
+	if M.blame then
+		table.insert(cmd, "--blame")
+	end
+	if M.footer then
+		table.insert(cmd, "--footer")
+	end
+	if M.frontmatter_card then
+		table.insert(cmd, "--frontmatter-card")
+	end
+	if M.toc then
+		table.insert(cmd, "--toc")
+	end
+	if M.cursor_highlight ~= "none" then
+		vim.list_extend(cmd, { "--cursor-highlight", M.cursor_highlight })
+	end
+	if M.link_navigation ~= "navigate" then
+		vim.list_extend(cmd, { "--link-navigation", M.link_navigation })
+	end
+	if M.autolink ~= "none" then
+		vim.list_extend(cmd, { "--autolink", M.autolink })
+	end
+	if M.emoji then
+		table.insert(cmd, "--emoji")
+	end
+	if M.math ~= "katex" then
+		vim.list_extend(cmd, { "--math", M.math })
+	end
+	if M.highlight ~= "client" then
+		vim.list_extend(cmd, { "--highlight", M.highlight })
+	end
+	if M.code_theme then
+		vim.list_extend(cmd, { "--code-theme", M.code_theme })
+	end
+	if M.code_theme_dark then
+		vim.list_extend(cmd, { "--code-theme-dark", M.code_theme_dark })
+	end
+	if M.diagrams ~= "client" then
+		vim.list_extend(cmd, { "--diagrams", M.diagrams })
+	end
+	if M.style ~= "penview" then
+		vim.list_extend(cmd, { "--style", M.style })
+	end
+	for key, value in pairs(M.typography) do
+		vim.list_extend(cmd, { "--" .. key:gsub("_", "-"), tostring(value) })
+	end
+	for key, value in pairs(M.layout) do
+		if key == "full_width" then
+			if value then
+				table.insert(cmd, "--full-width")
+			end
+		else
+			vim.list_extend(cmd, { "--" .. key:gsub("_", "-"), tostring(value) })
+		end
+	end
+	if M.locale ~= "en" then
+		vim.list_extend(cmd, { "--locale", M.locale })
+	end
+	if M.slugs ~= "github" then
+		vim.list_extend(cmd, { "--slugs", M.slugs })
+	end
+	if M.obfuscate_emails ~= "none" then
+		vim.list_extend(cmd, { "--obfuscate-emails", M.obfuscate_emails })
+	end
+	if M.link_policy.rel then
+		vim.list_extend(cmd, { "--link-rel", M.link_policy.rel })
+	end
+	if M.link_policy.target then
+		vim.list_extend(cmd, { "--link-target", M.link_policy.target })
+	end
+	if M.link_policy.referrer_policy then
+		vim.list_extend(cmd, { "--link-referrer-policy", M.link_policy.referrer_policy })
+	end
+	if M.link_policy.schemes then
+		vim.list_extend(cmd, { "--link-schemes", table.concat(M.link_policy.schemes, ",") })
+	end
+	if M.editable then
+		table.insert(cmd, "--editable")
+	end
+	if M.typewriter then
+		table.insert(cmd, "--typewriter")
+	end
+	if M.mirror_scroll then
+		table.insert(cmd, "--mirror-scroll")
+	end
+	if M.max_frame_size then
+		vim.list_extend(cmd, { "--max-frame-size", tostring(M.max_frame_size) })
+	end
+	if M.title_format then
+		vim.list_extend(cmd, { "--title-format", M.title_format })
+	end
+	if M.templating then
+		table.insert(cmd, "--templating")
+	end
+	if M.hide_drafts then
+		table.insert(cmd, "--hide-drafts")
+	end
+	if M.workspace then
+		vim.list_extend(cmd, { "--workspace", vim.fn.expand(M.workspace) })
+	end
+	if M.daily_note_pattern then
+		vim.list_extend(cmd, { "--daily-note-pattern", M.daily_note_pattern })
+	end
+	if M.css then
+		vim.list_extend(cmd, { "--css", vim.fn.expand(M.css) })
+	end
+	if M.base_path ~= "" then
+		vim.list_extend(cmd, { "--base-path", M.base_path })
+	end
+	if M.token then
+		vim.list_extend(cmd, { "--token", M.token })
+	end
+	if M.tunnel then
+		vim.list_extend(cmd, { "--tunnel", M.tunnel })
+	end
+	if not M.mdns then
+		table.insert(cmd, "--no-mdns")
+	end
+	if M.root then
+		vim.list_extend(cmd, { "--root", vim.fn.expand(M.root) })
+	end
+	if M.rate_limit then
+		vim.list_extend(cmd, { "--rate-limit", tostring(M.rate_limit) })
+	end
+	if M.render_workers then
+		vim.list_extend(cmd, { "--render-workers", tostring(M.render_workers) })
+	end
+	M.server_token = M.token
+	for name, template in pairs(M.shortcodes) do
+		vim.list_extend(cmd, { "--shortcode", name .. "=" .. template })
+	end
+	local exec_dir = vim.fn.expand(M.workspace or vim.fn.getcwd())
+	if not vim.tbl_isempty(M.exec) and exec_allowed(exec_dir) then
+		for lang, command in pairs(M.exec) do
+			vim.list_extend(cmd, { "--exec", lang .. "=" .. command })
+		end
+	end
+
 	log("Command: " .. table.concat(cmd, " "))
 
 	-- Store path for use in callback
 	local file_path = path
+	local address_received = false
 
 	-- Start server
 	M.server_job = vim.fn.jobstart(cmd, {
+		-- A shared server outlives the editor that started it
+		detach = M.daemon,
 		stdout_buffered = false, -- Don't buffer - we need the address immediately
 		on_stdout = function(_, data)
 			log("stdout received: " .. vim.inspect(data))
 			for _, line in ipairs(data) do
-				if line and line ~= "" then
+				-- The address comes first; in remote mode the URL other machines reach the server at
+				-- follows it, and with a tunnel the public URL once the tunnel is up
+				local remote_url = line and line:match("^remote (%S+)")
+				local tunnel_url = line and line:match("^tunnel (%S+)")
+				if remote_url then
+					M.remote_url = remote_url
+					vim.schedule(function()
+						print("[penview] Remote preview at " .. M.share_url(file_path))
+					end)
+				elseif tunnel_url then
+					M.tunnel_url = tunnel_url
+					vim.schedule(function()
+						print("[penview] Sharing at " .. M.share_url(file_path))
+					end)
+				elseif line and line ~= "" and not address_received then
+					address_received = true
 					M.server_addr = line:gsub("%s+", "")
-					if M.headless then
+					if M.headless or M.remote then
 						print("[penview] [WARN] Server exposed to network (bound to 0.0.0.0)")
 						print("[penview] Server running at http://" .. M.server_addr)
 					else
 						print("[penview] Server started at " .. M.server_addr)
 					end
 					vim.schedule(function()
-						M._connect(file_path)
+						M._connect(file_path, bufnr)
+						if M.daemon then
+							open_browser(file_path)
+						end
 					end)
-					return -- Only need the first line (the address)
 				end
 			end
 		end,
@@ -187,7 +754,7 @@ function M.start()
 	end
 end
 
-function M._connect(path)
+function M._connect(path, bufnr)
 	log("Connecting to WebSocket...")
 
 	-- Initialize vendored websocket module
@@ -218,7 +785,7 @@ function M._connect(path)
 
 	log("websocket loaded successfully")
 
-	local ws_url = "ws://" .. M.server_addr .. "/api/preview?path=" .. vim.fn.fnameescape(path)
+	local ws_url = "ws://" .. M.server_addr .. base_path_prefix() .. "/api/preview?" .. query({ path = path })
 	log("WebSocket URL: " .. ws_url)
 
 	M.client = WebsocketClient.new({
@@ -226,7 +793,9 @@ function M._connect(path)
 		on_connect = function(_)
 			print("[penview] Connected to preview")
 			vim.schedule(function()
+				M._send_hooks()
 				M._send_buffer()
+				M._send_diagnostics(bufnr)
 				M._setup_autocmds()
 			end)
 		end,
@@ -236,6 +805,9 @@ function M._connect(path)
 		end,
 		on_message = function(_, msg)
 			log("Received message: " .. tostring(msg))
+			vim.schedule(function()
+				M._handle_message(msg)
+			end)
 		end,
 		on_error = function(_, err)
 			print("[penview] WebSocket error: " .. vim.inspect(err))
@@ -259,30 +831,355 @@ function M._send_buffer()
 		local total_lines = #lines
 		local cursor_line = vim.fn.line(".")
 
+		local content = table.concat(lines, "\n")
+		if M.hooks.pre_parse then
+			local ok, filtered = pcall(M.hooks.pre_parse, content)
+			if ok and type(filtered) == "string" then
+				content = filtered
+			else
+				log("pre_parse hook failed: " .. tostring(filtered))
+			end
+		end
+
 		local data = vim.fn.json_encode({
-			content = table.concat(lines, "\n"),
+			content = content,
 			cursor_line = cursor_line,
 			total_lines = total_lines,
+			-- In viewport mode the viewport message below does the scrolling
+			sync_scroll = M.sync_scroll and not M.sync_viewport,
+		})
+		M.client:try_send_data(data)
+		-- From now on only changed lines are sent, unless a hook filters the whole text
+		M.pending_edits = not M.hooks.pre_parse and {} or nil
+
+		if M.sync_viewport then
+			M._send_viewport()
+		end
+	end
+end
+
+-- Send the lines changed since the last update, which the server applies to its copy of the buffer
+function M._send_edits()
+	if M.client then
+		local data = vim.fn.json_encode({
+			type = "lines",
+			edits = M.pending_edits,
+			cursor_line = vim.fn.line("."),
+			total_lines = vim.api.nvim_buf_line_count(0),
+			sync_scroll = M.sync_scroll and not M.sync_viewport,
+		})
+		M.client:try_send_data(data)
+		M.pending_edits = {}
+
+		if M.sync_viewport then
+			M._send_viewport()
+		end
+	end
+end
+
+-- Tell the server which render hooks run in Neovim, so it asks for them while rendering
+function M._send_hooks()
+	if M.client and (M.hooks.event or M.hooks.post_html or M.hooks.slug) then
+		local data = vim.fn.json_encode({
+			type = "hooks",
+			events = M.hooks.event ~= nil,
+			html = M.hooks.post_html ~= nil,
+			slug = M.hooks.slug ~= nil,
+		})
+		M.client:try_send_data(data)
+	end
+end
+
+-- Handle a request from the server: a file to open, from a link clicked in the preview, an edit
+-- made in the preview, or a render hook
+function M._handle_message(msg)
+	local ok, request = pcall(vim.json.decode, msg)
+	if not ok or type(request) ~= "table" then
+		return
+	end
+
+	if request.type == "open" and type(request.path) == "string" then
+		vim.cmd.edit(vim.fn.fnameescape(request.path))
+	elseif request.type == "edit" then
+		M._apply_edit(request)
+	elseif request.type == "resync" then
+		-- The server's copy of the buffer is out of date
+		M._send_buffer()
+	else
+		M._handle_hook_request(request)
+	end
+end
+
+-- Apply a text edit made in the preview to the buffer of the edited file. The replaced text must
+-- occur exactly once in the source block starting at the edited line, which runs up to the next
+-- blank line.
+function M._apply_edit(request)
+	local bufnr
+	for _, buf in ipairs(vim.api.nvim_list_bufs()) do
+		if vim.api.nvim_buf_is_loaded(buf) and vim.api.nvim_buf_get_name(buf) == request.path then
+			bufnr = buf
+			break
+		end
+	end
+	if not bufnr or type(request.line) ~= "number" or type(request.old) ~= "string" then
+		return
+	end
+
+	local block = {}
+	for _, line in ipairs(vim.api.nvim_buf_get_lines(bufnr, request.line - 1, -1, false)) do
+		if line:match("^%s*$") then
+			break
+		end
+		table.insert(block, line)
+	end
+
+	local text = table.concat(block, "\n")
+	local first = text:find(request.old, 1, true)
+	if not first or text:find(request.old, first + 1, true) then
+		vim.notify("[penview] Couldn't find the edited text in the source", vim.log.levels.WARN)
+		return
+	end
+
+	-- Buffer position (0-based row and column) of a byte offset into the block
+	local function position(offset)
+		for i, line in ipairs(block) do
+			if offset <= #line then
+				return request.line + i - 2, offset
+			end
+			offset = offset - #line - 1
+		end
+	end
+
+	local start_row, start_col = position(first - 1)
+	local end_row, end_col = position(first - 1 + #request.old)
+	vim.api.nvim_buf_set_text(
+		bufnr,
+		start_row,
+		start_col,
+		end_row,
+		end_col,
+		vim.split(request.new, "\n", { plain = true })
+	)
+end
+
+-- Answer a render hook request from the server. Failed hooks send back an empty response so
+-- the server renders without them.
+function M._handle_hook_request(request)
+	if not M.client then
+		return
+	end
+
+	local response = { type = "hook_response", id = request.id }
+	if request.type == "rewrite_events" and M.hooks.event then
+		local events = {}
+		for i, event in ipairs(request.events) do
+			local hook_ok, rewritten = pcall(M.hooks.event, event)
+			if not hook_ok then
+				log("event hook failed: " .. tostring(rewritten))
+				events = nil
+				break
+			end
+			events[i] = rewritten or event
+		end
+		response.events = events
+	elseif request.type == "filter_html" and M.hooks.post_html then
+		local hook_ok, html = pcall(M.hooks.post_html, request.html)
+		if hook_ok and type(html) == "string" then
+			response.html = html
+		else
+			log("post_html hook failed: " .. tostring(html))
+		end
+	elseif request.type == "slugify" and M.hooks.slug then
+		local slugs = {}
+		for i, text in ipairs(request.texts) do
+			local hook_ok, slug = pcall(M.hooks.slug, text)
+			if not hook_ok or type(slug) ~= "string" then
+				log("slug hook failed: " .. tostring(slug))
+				slugs = nil
+				break
+			end
+			slugs[i] = slug
+		end
+		response.slugs = slugs
+	else
+		return
+	end
+
+	M.client:try_send_data(vim.fn.json_encode(response))
+end
+
+-- Send only the cursor position; the server forwards it without re-rendering
+function M._send_cursor()
+	if M.client then
+		local data = vim.fn.json_encode({
+			type = "cursor",
+			cursor_line = vim.fn.line("."),
+			total_lines = vim.api.nvim_buf_line_count(0),
+			sync_scroll = M.sync_scroll,
+		})
+		M.client:try_send_data(data)
+	end
+end
+
+-- Send the visible window range so the preview mirrors the editor viewport
+function M._send_viewport()
+	if M.client then
+		local data = vim.fn.json_encode({
+			type = "viewport",
+			topline = vim.fn.line("w0"),
+			botline = vim.fn.line("w$"),
+			cursor_line = vim.fn.line("."),
+			total_lines = vim.api.nvim_buf_line_count(0),
 			sync_scroll = M.sync_scroll,
 		})
 		M.client:try_send_data(data)
 	end
 end
 
+local function in_visual_mode()
+	return vim.fn.mode():match("^[vV\22]") ~= nil
+end
+
+-- Send the visual selection range, or clear it once visual mode is left
+function M._send_selection()
+	if not M.client then
+		return
+	end
+
+	local data
+	if in_visual_mode() then
+		M.selection_active = true
+		data = vim.fn.json_encode({
+			type = "selection",
+			start_line = vim.fn.line("v"),
+			end_line = vim.fn.line("."),
+		})
+	elseif M.selection_active then
+		M.selection_active = false
+		data = vim.fn.json_encode({ type = "selection" })
+	else
+		return
+	end
+	M.client:try_send_data(data)
+end
+
+local severity_names = {
+	[vim.diagnostic.severity.ERROR] = "error",
+	[vim.diagnostic.severity.WARN] = "warning",
+	[vim.diagnostic.severity.INFO] = "info",
+	[vim.diagnostic.severity.HINT] = "hint",
+}
+
+-- Send the buffer's diagnostics so they show up in the preview margin
+function M._send_diagnostics(bufnr)
+	if not M.client or not M.diagnostics then
+		return
+	end
+
+	local diagnostics = {}
+	for _, d in ipairs(vim.diagnostic.get(bufnr)) do
+		table.insert(diagnostics, {
+			line = d.lnum + 1,
+			severity = severity_names[d.severity] or "info",
+			message = d.message,
+		})
+	end
+
+	local data = vim.fn.json_encode({
+		type = "diagnostics",
+		diagnostics = diagnostics,
+	})
+	M.client:try_send_data(data)
+end
+
+function M._schedule_update()
+	if M.timer then
+		vim.fn.timer_stop(M.timer)
+	end
+	M.timer = vim.fn.timer_start(M.debounce_ms, function()
+		vim.schedule(function()
+			if M.content_dirty then
+				M.content_dirty = false
+				-- Many small edits are cheaper to send as the whole buffer
+				if M.pending_edits and #M.pending_edits > 0 and #M.pending_edits <= 100 then
+					M._send_edits()
+				else
+					M._send_buffer()
+				end
+			elseif M.sync_viewport then
+				M._send_viewport()
+			else
+				M._send_cursor()
+			end
+			M._send_selection()
+		end)
+	end)
+end
+
 function M._setup_autocmds()
 	local bufnr = vim.api.nvim_get_current_buf()
 
-	vim.api.nvim_create_autocmd({ "TextChanged", "TextChangedI", "CursorMoved", "CursorMovedI" }, {
+	vim.api.nvim_create_autocmd({ "TextChanged", "TextChangedI" }, {
+		buffer = bufnr,
+		callback = function()
+			M.content_dirty = true
+			M._schedule_update()
+		end,
+	})
+
+	-- Record changed lines, so updates don't have to send the whole buffer
+	if M.attached_buf ~= bufnr then
+		M.attached_buf = bufnr
+		M.pending_edits = nil
+		vim.api.nvim_buf_attach(bufnr, false, {
+			on_lines = function(_, buf, _, first, last, new_last)
+				if M.attached_buf ~= buf then
+					return true
+				end
+				if M.pending_edits then
+					table.insert(M.pending_edits, {
+						first = first,
+						last = last,
+						lines = vim.api.nvim_buf_get_lines(buf, first, new_last, false),
+					})
+				end
+			end,
+			on_reload = function()
+				M.pending_edits = nil
+			end,
+			on_detach = function()
+				M.attached_buf = nil
+			end,
+		})
+	end
+
+	vim.api.nvim_create_autocmd({ "CursorMoved", "CursorMovedI" }, {
+		buffer = bufnr,
+		callback = function()
+			M._schedule_update()
+		end,
+	})
+
+	vim.api.nvim_create_autocmd("DiagnosticChanged", {
 		buffer = bufnr,
 		callback = function()
-			if M.timer then
-				vim.fn.timer_stop(M.timer)
+			M._send_diagnostics(bufnr)
+		end,
+	})
+
+	vim.api.nvim_create_autocmd("ModeChanged", {
+		buffer = bufnr,
+		callback = function()
+			M._schedule_update()
+		end,
+	})
+
+	-- WinScrolled doesn't support buffer-local autocmds, so filter by the current buffer
+	vim.api.nvim_create_autocmd("WinScrolled", {
+		callback = function()
+			if M.sync_viewport and vim.api.nvim_get_current_buf() == bufnr then
+				M._schedule_update()
 			end
-			M.timer = vim.fn.timer_start(M.debounce_ms, function()
-				vim.schedule(function()
-					M._send_buffer()
-				end)
-			end)
 		end,
 	})
 
@@ -300,20 +1197,260 @@ function M.stop()
 		M.client:try_disconnect()
 		M.client = nil
 	end
-	if M.server_job then
+	-- A shared server keeps running for the other editors attached to it
+	if M.server_job and not M.daemon then
 		vim.fn.jobstop(M.server_job)
-		M.server_job = nil
 	end
+	M.server_job = nil
 	if M.timer then
 		vim.fn.timer_stop(M.timer)
 		M.timer = nil
 	end
+	M.content_dirty = false
+	M.pending_edits = nil
+	M.attached_buf = nil
 	M.server_addr = nil
+	M.server_token = nil
+	M.tunnel_url = nil
+	M.remote_url = nil
 	print("[penview] Stopped")
 end
 
+-- Fetch the heading tree of the current buffer from the server, as parsed for the preview
+function M.headings(callback)
+	if not M.server_addr then
+		print("[penview] Server is not running")
+		return
+	end
+
+	local path = vim.api.nvim_buf_get_name(0)
+	local url = "http://" .. M.server_addr .. base_path_prefix() .. "/api/headings?" .. query({ path = path })
+	vim.fn.jobstart({ "curl", "-sf", url }, {
+		stdout_buffered = true,
+		on_stdout = function(_, data)
+			local ok, headings = pcall(vim.json.decode, table.concat(data, ""))
+			if ok and type(headings) == "table" then
+				vim.schedule(function()
+					callback(headings)
+				end)
+			end
+		end,
+	})
+end
+
+-- Reopen the documents that were previewed in browsers when a server last ran in the workspace
+function M.restore()
+	if not M.server_addr then
+		print("[penview] Server is not running")
+		return
+	end
+
+	local url = "http://" .. M.server_addr .. base_path_prefix() .. "/api/session?" .. query({})
+	vim.fn.jobstart({ "curl", "-sf", url }, {
+		stdout_buffered = true,
+		on_stdout = function(_, data)
+			local ok, session = pcall(vim.json.decode, table.concat(data, ""))
+			if not ok or type(session) ~= "table" or type(session.paths) ~= "table" then
+				return
+			end
+			vim.schedule(function()
+				if #session.paths == 0 then
+					print("[penview] No previous session")
+				end
+				for _, path in ipairs(session.paths) do
+					open_browser(path)
+				end
+			end)
+		end,
+	})
+end
+
+-- Pick a section with vim.ui.select (Telescope, fzf-lua, etc. if they override it) and jump to it
+function M.pick_heading()
+	local win = vim.api.nvim_get_current_win()
+	M.headings(function(headings)
+		local items = {}
+		local function flatten(list)
+			for _, heading in ipairs(list) do
+				table.insert(items, heading)
+				flatten(heading.children)
+			end
+		end
+		flatten(headings)
+
+		if #items == 0 then
+			print("[penview] No headings")
+			return
+		end
+
+		vim.ui.select(items, {
+			prompt = "Jump to section",
+			format_item = function(heading)
+				return string.rep("  ", heading.level - 1) .. heading.text
+			end,
+		}, function(heading)
+			if heading and vim.api.nvim_win_is_valid(win) then
+				vim.api.nvim_win_set_cursor(win, { heading.line, 0 })
+			end
+		end)
+	end)
+end
+
+-- URL of the preview of `path` for other machines: through the tunnel if one is open, else the
+-- LAN address in remote mode
+function M.share_url(path)
+	local origin = M.tunnel_url or M.remote_url
+	if not origin then
+		return nil
+	end
+	return origin .. base_path_prefix() .. "/?" .. query({ path = path })
+end
+
+-- Copy the public URL of the current buffer's preview to the clipboard, for sharing with reviewers
+function M.share()
+	local url = M.share_url(vim.api.nvim_buf_get_name(0))
+	if not url then
+		print("[penview] Not reachable from other machines (see the 'tunnel' and 'remote' options)")
+		return
+	end
+	vim.fn.setreg("+", url)
+	print("[penview] Copied " .. url)
+end
+
+-- Command that puts HTML read from stdin on the system clipboard as rich text, if one is available
+local function html_clipboard_command()
+	if vim.fn.has("mac") == 1 then
+		-- pbcopy only copies plain text, so hand the HTML to AppleScript as hex-encoded data
+		return { "sh", "-c", [[osascript -e "set the clipboard to «data HTML$(od -An -v -tx1 | tr -d ' \n')»"]] }
+	elseif vim.env.WAYLAND_DISPLAY and vim.fn.executable("wl-copy") == 1 then
+		return { "wl-copy", "--type", "text/html" }
+	elseif vim.fn.executable("xclip") == 1 then
+		return { "xclip", "-selection", "clipboard", "-target", "text/html" }
+	end
+	return nil
+end
+
+-- Copy the current buffer rendered as HTML to the clipboard, for pasting into mail clients and
+-- word processors with formatting intact
+function M.copy_html()
+	if not M.server_addr then
+		print("[penview] Server is not running")
+		return
+	end
+
+	local path = vim.api.nvim_buf_get_name(0)
+	local url = "http://" .. M.server_addr .. base_path_prefix() .. "/api/export/fragment?" .. query({ path = path })
+	vim.fn.jobstart({ "curl", "-sf", url }, {
+		stdout_buffered = true,
+		on_stdout = function(_, data)
+			local html = table.concat(data, "\n")
+			if html == "" then
+				return
+			end
+			vim.schedule(function()
+				local command = html_clipboard_command()
+				if not command then
+					-- Without a rich text clipboard, the markup can still be pasted as source
+					vim.fn.setreg("+", html)
+					print("[penview] Copied as plain text (install wl-copy or xclip to copy as rich text)")
+					return
+				end
+				local job = vim.fn.jobstart(command, {
+					on_exit = function(_, code)
+						if code ~= 0 then
+							vim.schedule(function()
+								print("[penview] Failed to copy to the clipboard")
+							end)
+						end
+					end,
+				})
+				vim.fn.chansend(job, html)
+				vim.fn.chanclose(job, "stdin")
+				print("[penview] Copied " .. vim.fn.fnamemodify(path, ":t") .. " as rich text")
+			end)
+		end,
+	})
+end
+
+-- Switch the open previews to the "light" or "dark" mode, or to another style, without reloading
+function M.set_theme(theme)
+	if theme ~= "light" and theme ~= "dark" and not vim.tbl_contains(M.styles, theme) then
+		print("[penview] Unknown theme: " .. tostring(theme))
+		return
+	end
+	if M.client then
+		M.client:try_send_data(vim.fn.json_encode({ type = "theme", theme = theme }))
+	end
+end
+
 -- Commands
+-- Formats `M.export` can save to
+M.export_formats = { "html", "pdf" }
+
+-- Save the current buffer rendered as a standalone `format` file, next to it or at `out`
+function M.export(format, out)
+	if not M.server_addr then
+		print("[penview] Server is not running")
+		return
+	end
+
+	format = format or "html"
+	if not vim.tbl_contains(M.export_formats, format) then
+		print("[penview] Unsupported export format: " .. format)
+		return
+	end
+	local path = vim.api.nvim_buf_get_name(0)
+	out = out or (vim.fn.fnamemodify(path, ":r") .. "." .. format)
+	local url = "http://"
+		.. M.server_addr
+		.. base_path_prefix()
+		.. "/api/export?"
+		.. query({ path = path, format = format })
+	vim.fn.jobstart({ "curl", "-sSf", "-o", out, url }, {
+		stderr_buffered = true,
+		on_stderr = function(_, data)
+			local message = vim.trim(table.concat(data, "\n"))
+			if message ~= "" then
+				vim.schedule(function()
+					print("[penview] Export failed: " .. message)
+				end)
+			end
+		end,
+		on_exit = function(_, code)
+			if code == 0 then
+				vim.schedule(function()
+					print("[penview] Exported to " .. out)
+				end)
+			end
+		end,
+	})
+end
+
 vim.api.nvim_create_user_command("PenviewStart", M.start, {})
 vim.api.nvim_create_user_command("PenviewStop", M.stop, {})
+vim.api.nvim_create_user_command("PenviewHeadings", M.pick_heading, {})
+vim.api.nvim_create_user_command("PenviewShare", M.share, {})
+vim.api.nvim_create_user_command("PenviewCopyHtml", M.copy_html, {})
+vim.api.nvim_create_user_command("PenviewExport", function(args)
+	M.export(args.fargs[1], args.fargs[2])
+end, {
+	nargs = "*",
+	complete = function(_, line)
+		-- Only the format is completed; the output is a file name
+		if #vim.split(line, "%s+") > 2 then
+			return {}
+		end
+		return M.export_formats
+	end,
+})
+vim.api.nvim_create_user_command("PenviewRestore", M.restore, {})
+vim.api.nvim_create_user_command("PenviewTheme", function(args)
+	M.set_theme(args.args)
+end, {
+	nargs = 1,
+	complete = function()
+		return vim.list_extend({ "light", "dark" }, M.styles)
+	end,
+})
 
 return M
@@ -0,0 +1,94 @@
+//! Stylesheets, scripts and icons bundled into the binary. The live preview loads them from
+//! content-hashed URLs so browsers can cache them for good, while exports inline them.
+
+use base64::{Engine, engine::general_purpose};
+
+/// A bundled static file.
+#[derive(Debug)]
+pub struct Asset {
+    pub name: &'static str,
+    pub content: &'static str,
+    pub content_type: &'static str,
+}
+
+pub const ASSETS: &[Asset] = &[
+    Asset {
+        name: "page.css",
+        content: include_str!("../templates/assets/page.css"),
+        content_type: "text/css",
+    },
+    Asset {
+        name: "preview.js",
+        content: include_str!("../templates/assets/preview.js"),
+        content_type: "text/javascript",
+    },
+    Asset {
+        name: "search.js",
+        content: include_str!("../templates/assets/search.js"),
+        content_type: "text/javascript",
+    },
+    Asset {
+        name: "icon.svg",
+        content: include_str!("../templates/assets/icon.svg"),
+        content_type: "image/svg+xml",
+    },
+];
+
+/// 64-bit FNV-1a, which is stable across builds unlike the std hasher.
+fn fnv1a(data: &[u8]) -> u64 {
+    data.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+impl Asset {
+    /// The file name with a hash of the content, e.g. `page.1a2b3c4d5e6f7a8b.css`.
+    pub fn hashed_name(&self) -> String {
+        let hash = fnv1a(self.content.as_bytes());
+        match self.name.rsplit_once('.') {
+            Some((stem, extension)) => format!("{stem}.{hash:016x}.{extension}"),
+            None => format!("{}.{hash:016x}", self.name),
+        }
+    }
+
+    /// URL of the asset on a server whose routes are under `base_path`.
+    pub fn url(&self, base_path: &str) -> String {
+        format!("{base_path}/assets/{}", self.hashed_name())
+    }
+
+    /// The content as a `data:` URL, for self-contained exports.
+    pub fn data_url(&self) -> String {
+        let encoded = general_purpose::STANDARD.encode(self.content);
+        format!("data:{};base64,{encoded}", self.content_type)
+    }
+}
+
+/// Looks up a bundled asset by its plain name.
+pub fn get(name: &str) -> Option<&'static Asset> {
+    ASSETS.iter().find(|asset| asset.name == name)
+}
+
+/// Looks up a bundled asset by its hashed name, so only URLs of the current content resolve.
+pub fn find_hashed(hashed_name: &str) -> Option<&'static Asset> {
+    ASSETS
+        .iter()
+        .find(|asset| asset.hashed_name() == hashed_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hashed_names() {
+        let asset = get("page.css").unwrap();
+        let hashed = asset.hashed_name();
+
+        assert!(hashed.starts_with("page.") && hashed.ends_with(".css"));
+        assert_eq!(hashed.len(), "page..css".len() + 16);
+        assert_eq!(asset.url(""), format!("/assets/{hashed}"));
+        assert_eq!(asset.url("/penview"), format!("/penview/assets/{hashed}"));
+        assert!(find_hashed(&hashed).is_some());
+        assert!(find_hashed("page.css").is_none());
+    }
+}
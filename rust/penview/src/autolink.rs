@@ -0,0 +1,233 @@
+//! Linking bare URLs in prose, implemented as a [`RenderTransformer`]. CommonMark only links URLs
+//! written in angle brackets (`<https://example.com>`); GitHub also links bare `http(s)://` URLs,
+//! and optionally `www.` addresses and emails too.
+
+use std::ops::Range;
+
+use pulldown_cmark::{CowStr, Event, LinkType, Tag, TagEnd};
+
+use crate::transform::{Piece, RenderTransformer, split_text, text_run};
+
+/// Turns bare URLs in text into links, leaving code, links and raw HTML alone.
+pub struct Autolink {
+    /// Whether `www.` addresses and email addresses are linked as well as `http(s)://` URLs.
+    pub extended: bool,
+}
+
+impl Autolink {
+    /// The transformer for an `--autolink` mode: "urls" links bare `http(s)://` URLs, "extended"
+    /// also links `www.` addresses and emails, and "none" (or anything else) links nothing.
+    pub fn from_mode(mode: &str) -> Option<Self> {
+        match mode {
+            "urls" => Some(Self { extended: false }),
+            "extended" => Some(Self { extended: true }),
+            _ => None,
+        }
+    }
+
+    /// Splits `text` into plain text and links, or returns `None` if it has no bare URLs.
+    fn linkify(&self, text: &str) -> Option<Vec<Piece<Link>>> {
+        split_text(text, |before, rest| {
+            let preceded_ok = before
+                .chars()
+                .next_back()
+                .is_none_or(|c| c.is_whitespace() || "*_~(".contains(c));
+            // Length of the link, the scheme to add to its destination and how it is written
+            let (len, scheme, link_type) = if !preceded_ok {
+                return None;
+            } else if rest.starts_with("http://") || rest.starts_with("https://") {
+                url_len(rest).map(|len| (len, "", LinkType::Autolink))?
+            } else if self.extended && rest.starts_with("www.") {
+                url_len(rest).map(|len| (len, "http://", LinkType::Autolink))?
+            } else if self.extended {
+                // The HTML writer adds `mailto:` to email links itself
+                email_len(rest).map(|len| (len, "", LinkType::Email))?
+            } else {
+                return None;
+            };
+
+            let link = &rest[..len];
+            Some((
+                len,
+                Link {
+                    link_type,
+                    dest: format!("{scheme}{link}"),
+                    text: link.to_string(),
+                },
+            ))
+        })
+    }
+}
+
+struct Link {
+    link_type: LinkType,
+    dest: String,
+    text: String,
+}
+
+/// Length of the URL at the start of `text`, which runs until whitespace or `<`, without
+/// trailing punctuation or unbalanced closing parentheses (as in GitHub's extended autolinks).
+fn url_len(text: &str) -> Option<usize> {
+    let end = text.find(|c: char| c.is_whitespace() || c == '<');
+    let mut url = &text[..end.unwrap_or(text.len())];
+
+    loop {
+        let trimmed = url.trim_end_matches(['?', '!', '.', ',', ':', '*', '_', '~', '\'', '"']);
+        let trimmed = match trimmed.strip_suffix(')') {
+            Some(rest) if trimmed.matches('(').count() < trimmed.matches(')').count() => rest,
+            _ => trimmed,
+        };
+        if trimmed.len() == url.len() {
+            break;
+        }
+        url = trimmed;
+    }
+
+    // A scheme or `www.` alone isn't a link
+    let host = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.");
+    (!host.is_empty()).then_some(url.len())
+}
+
+/// Length of the email address at the start of `text`, if there is one.
+pub fn email_len(text: &str) -> Option<usize> {
+    let local = text
+        .find(|c: char| !(c.is_ascii_alphanumeric() || "._+-".contains(c)))
+        .unwrap_or(text.len());
+    if local == 0 || !text[local..].starts_with('@') {
+        return None;
+    }
+
+    let domain = &text[local + 1..];
+    let domain = &domain[..domain
+        .find(|c: char| !(c.is_ascii_alphanumeric() || "._-".contains(c)))
+        .unwrap_or(domain.len())];
+    let domain = domain.trim_end_matches('.');
+    if !domain.contains('.') || domain.ends_with(['-', '_']) {
+        return None;
+    }
+    Some(local + 1 + domain.len())
+}
+
+impl RenderTransformer for Autolink {
+    fn name(&self) -> &str {
+        "autolink"
+    }
+
+    fn transform<'a>(&self, events: &mut Vec<(Event<'a>, Range<usize>)>) {
+        let mut skip_depth = 0;
+        let mut out = Vec::with_capacity(events.len());
+        let mut drained = events.drain(..).peekable();
+
+        while let Some((event, range)) = drained.next() {
+            match &event {
+                Event::Start(
+                    Tag::CodeBlock(_)
+                    | Tag::MetadataBlock(_)
+                    | Tag::Link { .. }
+                    | Tag::Image { .. },
+                ) => skip_depth += 1,
+                Event::End(
+                    TagEnd::CodeBlock | TagEnd::MetadataBlock(_) | TagEnd::Link | TagEnd::Image,
+                ) => skip_depth -= 1,
+                _ => {}
+            }
+            let Event::Text(text) = event else {
+                out.push((event, range));
+                continue;
+            };
+            if skip_depth > 0 {
+                out.push((Event::Text(text), range));
+                continue;
+            }
+
+            let (run, run_range) = text_run(text, range, &mut drained);
+            let Some(pieces) = self.linkify(&run) else {
+                out.push((Event::Text(run.into()), run_range));
+                continue;
+            };
+            for piece in pieces {
+                match piece {
+                    Piece::Text(text) => out.push((Event::Text(text.into()), run_range.clone())),
+                    Piece::Match(Link {
+                        link_type,
+                        dest,
+                        text,
+                    }) => {
+                        out.push((
+                            Event::Start(Tag::Link {
+                                link_type,
+                                dest_url: dest.into(),
+                                title: CowStr::Borrowed(""),
+                                id: CowStr::Borrowed(""),
+                            }),
+                            run_range.clone(),
+                        ));
+                        out.push((Event::Text(text.into()), run_range.clone()));
+                        out.push((Event::End(TagEnd::Link), run_range.clone()));
+                    }
+                }
+            }
+        }
+
+        drop(drained);
+        *events = out;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::render::{RenderOptions, render_markdown_to_html};
+
+    #[test]
+    fn test_url_len() {
+        assert_eq!(url_len("https://example.com/a_b."), Some(23));
+        assert_eq!(
+            url_len("https://en.wikipedia.org/wiki/Rust_(language)),"),
+            Some(45)
+        );
+        assert_eq!(url_len("www.example.com<br>"), Some(15));
+        assert_eq!(url_len("https://."), None);
+        assert_eq!(email_len("me+penview@example.com."), Some(22));
+        assert_eq!(email_len("me@localhost"), None);
+        assert_eq!(email_len("@example.com"), None);
+    }
+
+    #[tokio::test]
+    async fn test_autolink() {
+        let content = "See https://example.com/a_b_c, www.example.com or me@example.com.\n\n\
+                       `https://code.example.com` and [https://linked.example.com](https://x.y)\n";
+        let render = |autolink: Option<Autolink>| async {
+            let mut options = RenderOptions::default();
+            if let Some(autolink) = autolink {
+                options.transformers.register(autolink);
+            }
+            render_markdown_to_html(content, Path::new("doc.md"), &options).await
+        };
+        let rest = "<p data-source-line=\"3\"><code data-source-line=\"3\">https://code.example.com</code> and \
+                    <a data-source-line=\"3\" href=\"https://x.y\">https://linked.example.com</a></p>\n";
+
+        assert_eq!(
+            render(Autolink::from_mode("extended")).await,
+            format!(
+                "<p data-source-line=\"1\">See <a data-source-line=\"1\" href=\"https://example.com/a_b_c\">https://example.com/a_b_c</a>, \
+                 <a data-source-line=\"1\" href=\"http://www.example.com\">www.example.com</a> or \
+                 <a data-source-line=\"1\" href=\"mailto:me@example.com\">me@example.com</a>.</p>\n{rest}"
+            )
+        );
+        assert_eq!(
+            render(Autolink::from_mode("urls")).await,
+            format!(
+                "<p data-source-line=\"1\">See <a data-source-line=\"1\" href=\"https://example.com/a_b_c\">https://example.com/a_b_c</a>, \
+                 www.example.com or me@example.com.</p>\n{rest}"
+            )
+        );
+        assert!(Autolink::from_mode("none").is_none());
+        assert!(!render(None).await.contains("href=\"https://example.com"));
+    }
+}
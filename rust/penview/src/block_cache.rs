@@ -0,0 +1,198 @@
+//! Incremental rendering for live preview.
+//!
+//! Documents are split into top-level blocks, and the processed events of each block (parsed,
+//! spellchecked, with images resolved) are cached by the hash of its source. Between keystrokes
+//! usually only one block changes, so only that block goes through the slow parts of rendering
+//! again.
+
+use std::{
+    collections::{HashMap, hash_map::DefaultHasher},
+    hash::{Hash, Hasher},
+    ops::Range,
+    path::Path,
+};
+
+use pulldown_cmark::{Event, Options, Tag};
+
+use crate::{
+    headings::Slugger,
+    html::{LineIndex, push_html_from_line},
+    render::{RenderOptions, preprocess, process_events, render_markdown_to_html},
+    workers,
+};
+
+type Events = Vec<(Event<'static>, Range<usize>)>;
+
+/// Cached events of the blocks of a single document. Options and base path must not change
+/// between renders.
+#[derive(Default)]
+pub struct BlockCache {
+    blocks: HashMap<u64, Events>,
+    /// Length of the last rendered body, to allocate the next one at once.
+    body_len: usize,
+}
+
+/// Splits `content` into the source ranges of its top-level blocks.
+///
+/// Returns `None` when blocks can't be rendered independently: link reference definitions and
+/// footnotes are resolved across the whole document.
+fn top_level_blocks(content: &str) -> Option<Vec<Range<usize>>> {
+    let parser = pulldown_cmark::Parser::new_ext(content, Options::all());
+    if parser.reference_definitions().iter().next().is_some() {
+        return None;
+    }
+
+    let mut blocks = Vec::new();
+    let mut depth = 0;
+    for (event, range) in parser.into_offset_iter() {
+        match event {
+            Event::FootnoteReference(_) | Event::Start(Tag::FootnoteDefinition(_)) => return None,
+            Event::Start(_) => {
+                if depth == 0 {
+                    blocks.push(range);
+                }
+                depth += 1;
+            }
+            Event::End(_) => depth -= 1,
+            _ if depth == 0 => blocks.push(range),
+            _ => {}
+        }
+    }
+
+    Some(blocks)
+}
+
+fn hash(block: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    block.hash(&mut hasher);
+    hasher.finish()
+}
+
+impl BlockCache {
+    /// Renders `content` to an HTML body, reusing the events of unchanged blocks.
+    pub async fn render(
+        &mut self,
+        content: &str,
+        base_path: &Path,
+        options: &RenderOptions,
+    ) -> String {
+        let content = &*preprocess(content, base_path, options);
+        let Some(blocks) = top_level_blocks(content) else {
+            self.blocks.clear();
+            return render_markdown_to_html(content, base_path, options).await;
+        };
+        let keys: Vec<u64> = blocks
+            .iter()
+            .map(|range| hash(&content[range.clone()]))
+            .collect();
+
+        // Parse the changed blocks, then process them together so spellchecking runs once
+        let mut missing: Vec<(u64, Range<usize>)> = Vec::new();
+        let mut pending: Events = Vec::new();
+        workers::run(base_path, || {
+            for (range, &key) in blocks.iter().zip(&keys) {
+                if self.blocks.contains_key(&key) || missing.iter().any(|(k, _)| *k == key) {
+                    continue;
+                }
+
+                let parser =
+                    pulldown_cmark::Parser::new_ext(&content[range.clone()], Options::all());
+                let mut events: Vec<_> = parser.into_offset_iter().collect();
+                options.transformers.apply(&mut events);
+
+                let start = pending.len();
+                pending.extend(
+                    events
+                        .into_iter()
+                        .map(|(event, range)| (event.into_static(), range)),
+                );
+                missing.push((key, start..pending.len()));
+            }
+        })
+        .await;
+        if !pending.is_empty() {
+            process_events(&mut pending, base_path, options).await;
+        }
+        for (key, range) in missing {
+            self.blocks.insert(key, pending[range].to_vec());
+        }
+
+        // Drop blocks that are no longer in the document
+        self.blocks.retain(|key, _| keys.contains(key));
+
+        // Heading anchors depend on the headings before them, so they are assigned in order
+        workers::run(base_path, || {
+            let lines = LineIndex::new(content);
+            let mut slugger = Slugger::new(options.slugs);
+            let mut body = String::with_capacity(self.body_len + self.body_len / 8);
+            for (range, key) in blocks.into_iter().zip(keys) {
+                let source = &content[range.clone()];
+                let first_line = lines.line(range.start);
+                let events = &self.blocks[&key];
+                // Only blocks with headings need their events copied to be given anchors
+                if events
+                    .iter()
+                    .any(|(event, _)| matches!(event, Event::Start(Tag::Heading { .. })))
+                {
+                    let mut events = events.clone();
+                    slugger.assign_ids(&mut events);
+                    push_html_from_line(&mut body, source, first_line, &options.links, events);
+                } else {
+                    push_html_from_line(
+                        &mut body,
+                        source,
+                        first_line,
+                        &options.links,
+                        events.iter().cloned(),
+                    );
+                }
+            }
+            self.body_len = body.len();
+            body
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn full_render(content: &str) -> String {
+        render_markdown_to_html(content, Path::new("doc.md"), &RenderOptions::default()).await
+    }
+
+    #[tokio::test]
+    async fn test_matches_full_render() {
+        let mut cache = BlockCache::default();
+        let options = RenderOptions::default();
+        let path = Path::new("doc.md");
+
+        for content in [
+            "# Title\n\nSome *text*\n\n- one\n- two\n\n| a | b |\n|---|---|\n| 1 | 2 |\n",
+            "# Title\n\nSome *edited* text\n\n- one\n- two\n\n| a | b |\n|---|---|\n| 1 | 2 |\n",
+            "Intro\n\n# Title\n\nSome *edited* text\n\n- one\n- two\n\n---\n\n<div>raw</div>\n",
+            "See [the docs][docs] and a note[^1].\n\n[docs]: https://example.com\n[^1]: Note\n",
+            "# Notes\n\nText\n\n## Notes\n\n## Notes\n",
+            "# Notes\n\n## Notes\n\nText\n\n## Notes\n",
+            "> ## Notes\n\n- ## Notes\n\n## Notes\n",
+        ] {
+            assert_eq!(
+                cache.render(content, path, &options).await,
+                full_render(content).await
+            );
+        }
+    }
+
+    #[test]
+    fn test_top_level_blocks() {
+        let content = "# Title\n\n> quote\n\n---\n";
+        let blocks: Vec<&str> = top_level_blocks(content)
+            .unwrap()
+            .into_iter()
+            .map(|range| &content[range])
+            .collect();
+        assert_eq!(blocks, vec!["# Title\n", "> quote\n", "---\n"]);
+        assert_eq!(top_level_blocks("[a]\n\n[a]: b\n"), None);
+    }
+}
@@ -0,0 +1,189 @@
+//! `penview check`: renders documents headlessly and reports broken local links, missing images
+//! and render errors, so documentation repositories can gate merges on it.
+
+use std::{
+    borrow::Cow,
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use pulldown_cmark::{Event, LinkType, Options, Tag};
+use url::Url;
+
+use crate::{
+    drafts,
+    export::markdown_files,
+    html::LineIndex,
+    links,
+    render::{RenderOptions, render_doc_body},
+    template,
+};
+
+/// Something wrong with a document, at a 1-based line (0 for the whole document).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub path: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.path.display(), self.message)
+        } else {
+            write!(f, "{}:{}: {}", self.path.display(), self.line, self.message)
+        }
+    }
+}
+
+/// The file part of a link destination that points at a local file, if it does.
+fn local_target(dest_url: &str) -> Option<&str> {
+    if dest_url.parse::<Url>().is_ok() {
+        return None;
+    }
+    let path = dest_url.split(['#', '?']).next()?;
+    (!path.is_empty()).then_some(path)
+}
+
+/// Finds the links and images in `content` that point at local files that don't exist.
+/// `files` are the workspace's documents, which wiki-links and Zettelkasten IDs resolve against.
+fn broken_references(content: &str, source: &Path, files: &[PathBuf]) -> Vec<(usize, String)> {
+    let dir = source.parent().unwrap_or(Path::new(""));
+    let lines = LineIndex::new(content);
+
+    let mut broken = Vec::new();
+    let parser = pulldown_cmark::Parser::new_ext(content, Options::all());
+    for (event, range) in parser.into_offset_iter() {
+        let message = match event {
+            Event::Start(Tag::Link {
+                link_type: LinkType::WikiLink { .. },
+                dest_url,
+                ..
+            }) => {
+                let target = dest_url.split('#').next().unwrap_or_default();
+                if target.is_empty() || links::resolve_wiki(target, source, files).is_some() {
+                    continue;
+                }
+                format!("broken wiki-link to `{target}`")
+            }
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                let Some(path) = local_target(&dest_url) else {
+                    continue;
+                };
+                let by_id = || links::find_by_id(links::zettel_id(path)?, files);
+                if dir.join(path).exists() || by_id().is_some() {
+                    continue;
+                }
+                format!("broken link to `{dest_url}`")
+            }
+            Event::Start(Tag::Image { dest_url, .. }) => {
+                let Some(path) = local_target(&dest_url) else {
+                    continue;
+                };
+                if dir.join(path).exists() {
+                    continue;
+                }
+                format!("missing image `{dest_url}`")
+            }
+            _ => continue,
+        };
+        broken.push((lines.line(range.start), message));
+    }
+    broken
+}
+
+/// Checks one document: its local links and images, its template (if templating is enabled),
+/// and that it renders.
+async fn check_document(path: &Path, files: &[PathBuf], options: &RenderOptions) -> Vec<Problem> {
+    let problem = |line: usize, message: String| Problem {
+        path: path.to_path_buf(),
+        line,
+        message,
+    };
+
+    let content = match tokio::fs::read_to_string(path).await {
+        Ok(content) => content,
+        Err(e) => return vec![problem(0, format!("can't be read: {e}"))],
+    };
+    let source = path.canonicalize().unwrap_or(path.to_path_buf());
+
+    let mut problems: Vec<Problem> = broken_references(&content, &source, files)
+        .into_iter()
+        .map(|(line, message)| problem(line, message))
+        .collect();
+
+    // Templates are rendered after drafts are stripped, as in the preview
+    let kept = if options.hide_drafts {
+        drafts::strip_drafts(&content)
+    } else {
+        Cow::Borrowed(content.as_str())
+    };
+    if options.templating
+        && let Err(e) = template::render(&kept, &std::env::vars().collect())
+    {
+        problems.push(problem(0, format!("template error: {e:#}")));
+    }
+
+    if let Err(e) = render_doc_body(path, options).await {
+        problems.push(problem(0, format!("render error: {e:#}")));
+    }
+
+    problems
+}
+
+/// Checks the markdown files in `paths`, searching directories recursively.
+pub async fn check(paths: &[PathBuf], options: &RenderOptions) -> anyhow::Result<Vec<Problem>> {
+    let mut documents = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            documents.extend(markdown_files(path).await?);
+        } else {
+            documents.push(path.clone());
+        }
+    }
+
+    let workspace = options.workspace.canonicalize()?;
+    let files = markdown_files(&workspace).await?;
+
+    let mut problems = Vec::new();
+    for document in &documents {
+        problems.extend(check_document(document, &files, options).await);
+    }
+    Ok(problems)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_broken_references() {
+        let dir = std::env::temp_dir().join("penview-check-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("other.md"), "").unwrap();
+        std::fs::write(dir.join("202401121230 Meeting.md"), "").unwrap();
+        std::fs::write(dir.join("logo.png"), "").unwrap();
+        let dir = dir.canonicalize().unwrap();
+        let files = vec![dir.join("202401121230 Meeting.md"), dir.join("other.md")];
+
+        let content = "\
+[ok](other.md#intro) [web](https://example.com) [top](#top) [id](202401121230)
+[gone](missing.md)
+
+![logo](logo.png) ![none](img/none.png)
+
+[[other]] [[nowhere|Alias]]
+
+`[code](missing.md)`
+";
+        assert_eq!(
+            broken_references(content, &dir.join("index.md"), &files),
+            vec![
+                (2, "broken link to `missing.md`".to_string()),
+                (4, "missing image `img/none.png`".to_string()),
+                (6, "broken wiki-link to `nowhere`".to_string()),
+            ]
+        );
+    }
+}
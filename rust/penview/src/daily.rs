@@ -0,0 +1,69 @@
+//! Previous/next day navigation for daily notes, i.e. files named after a date.
+
+use std::path::Path;
+
+use chrono::{Days, NaiveDate};
+
+use crate::page_template::{DailyLink, DailyNav};
+
+/// Converts a daily-note file name pattern such as `YYYY-MM-DD.md` to a chrono format string.
+fn pattern_to_format(pattern: &str) -> String {
+    pattern
+        .replace('%', "%%")
+        .replace("YYYY", "%Y")
+        .replace("MM", "%m")
+        .replace("DD", "%d")
+}
+
+/// Returns the date of a daily note whose file name matches `pattern`.
+pub fn note_date(path: &Path, pattern: &str) -> Option<NaiveDate> {
+    let file_name = path.file_name()?.to_str()?;
+    NaiveDate::parse_from_str(file_name, &pattern_to_format(pattern)).ok()
+}
+
+/// Links to the notes of the days before and after the daily note at `path`, whether or not they
+/// exist yet. Returns `None` if `path` isn't a daily note.
+pub fn daily_nav(path: &Path, pattern: &str) -> Option<DailyNav> {
+    let date = note_date(path, pattern)?;
+    let format = pattern_to_format(pattern);
+    let link = |date: NaiveDate| {
+        let path = path.with_file_name(date.format(&format).to_string());
+        DailyLink {
+            label: date.format("%Y-%m-%d").to_string(),
+            exists: path.exists(),
+            path: path.to_string_lossy().into_owned(),
+        }
+    };
+
+    Some(DailyNav {
+        prev: link(date.checked_sub_days(Days::new(1))?),
+        next: link(date.checked_add_days(Days::new(1))?),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_note_date() {
+        assert_eq!(
+            note_date(Path::new("/notes/2024-02-29.md"), "YYYY-MM-DD.md"),
+            NaiveDate::from_ymd_opt(2024, 2, 29)
+        );
+        assert_eq!(
+            note_date(Path::new("daily/20240301-log.md"), "YYYYMMDD-log.md"),
+            NaiveDate::from_ymd_opt(2024, 3, 1)
+        );
+        assert_eq!(note_date(Path::new("notes.md"), "YYYY-MM-DD.md"), None);
+        assert_eq!(note_date(Path::new("2024-02-30.md"), "YYYY-MM-DD.md"), None);
+    }
+
+    #[test]
+    fn test_daily_nav() {
+        let nav = daily_nav(Path::new("/notes/2024-03-01.md"), "YYYY-MM-DD.md").unwrap();
+        assert_eq!(nav.prev.path, "/notes/2024-02-29.md");
+        assert_eq!(nav.next.label, "2024-03-02");
+        assert!(!nav.next.exists);
+    }
+}
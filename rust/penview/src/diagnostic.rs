@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+/// Severity levels, named after `vim.diagnostic.severity`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+    Hint,
+}
+
+/// A message attached to a source line, shown as a marker in the preview margin.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    /// 1-based source line.
+    pub line: usize,
+    pub severity: Severity,
+    pub message: String,
+}
@@ -0,0 +1,229 @@
+//! Diagrams from ```` ```mermaid ````, ```` ```graphviz ```` (or `dot`) and ```` ```plantuml ````
+//! (or `puml`) code blocks.
+//!
+//! By default they are left to the page: Mermaid and Graphviz blocks are drawn by scripts loaded
+//! from CDNs, and PlantUML blocks are only marked with a class, since PlantUML has no browser
+//! renderer. With `--diagrams server` they are rendered to SVG by the `mmdc`, `dot` and `plantuml`
+//! programs instead, and embedded as images. A block whose program isn't installed is left to the
+//! page.
+
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    io::ErrorKind,
+    ops::Range,
+    path::Path,
+    process::Stdio,
+    sync::{
+        LazyLock, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+};
+
+use anyhow::{Context, bail};
+use pulldown_cmark::{CodeBlockKind, Event, Tag, TagEnd};
+use pulldown_cmark_escape::escape_html;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::render::data_url;
+
+/// Rendered diagrams by the hash of their language and source, so that a block is only rendered
+/// again once it is edited. Cleared when it grows past [`CACHE_SIZE`].
+static CACHE: LazyLock<Mutex<HashMap<u64, String>>> = LazyLock::new(Default::default);
+const CACHE_SIZE: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Mermaid,
+    Graphviz,
+    PlantUml,
+}
+
+impl Kind {
+    /// The kind of diagram a code block's language tag is for, if any.
+    pub fn from_lang(lang: &str) -> Option<Self> {
+        match lang {
+            "mermaid" => Some(Self::Mermaid),
+            "graphviz" | "dot" => Some(Self::Graphviz),
+            "plantuml" | "puml" => Some(Self::PlantUml),
+            _ => None,
+        }
+    }
+
+    /// Class of the `<pre>` the page draws the diagram from.
+    pub fn class(self) -> &'static str {
+        match self {
+            Self::Mermaid => "mermaid",
+            Self::Graphviz => "penview-graphviz",
+            Self::PlantUml => "penview-plantuml",
+        }
+    }
+
+    fn program(self) -> &'static str {
+        match self {
+            Self::Mermaid => "mmdc",
+            Self::Graphviz => "dot",
+            Self::PlantUml => "plantuml",
+        }
+    }
+}
+
+/// Runs `kind`'s program on `source` and returns the SVG, or `None` if it isn't installed.
+async fn render_svg(kind: Kind, source: &str) -> anyhow::Result<Option<Vec<u8>>> {
+    // mmdc only reads and writes files
+    let files = (kind == Kind::Mermaid).then(|| {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let stem = std::env::temp_dir().join(format!(
+            "penview-diagram-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        (stem.with_extension("mmd"), stem.with_extension("svg"))
+    });
+
+    let mut command = Command::new(kind.program());
+    match (kind, &files) {
+        (Kind::Mermaid, Some((input, output))) => {
+            tokio::fs::write(input, source).await?;
+            command
+                .arg("--quiet")
+                .arg("--input")
+                .arg(input)
+                .arg("--output")
+                .arg(output);
+        }
+        (Kind::Graphviz, _) => {
+            command.arg("-Tsvg");
+        }
+        _ => {
+            command.args(["-tsvg", "-pipe"]);
+        }
+    }
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let result = run(
+        command,
+        kind,
+        source,
+        files.as_ref().map(|(_, svg)| svg.as_path()),
+    )
+    .await;
+    if let Some((input, output)) = &files {
+        let _ = tokio::fs::remove_file(input).await;
+        let _ = tokio::fs::remove_file(output).await;
+    }
+    result
+}
+
+async fn run(
+    mut command: Command,
+    kind: Kind,
+    source: &str,
+    output: Option<&Path>,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to run {}", kind.program())),
+    };
+
+    let mut stdin = child.stdin.take().context("stdin unavailable")?;
+    if output.is_none() {
+        stdin.write_all(source.as_bytes()).await?;
+    }
+    drop(stdin);
+
+    let result = child.wait_with_output().await?;
+    if !result.status.success() {
+        let stderr = String::from_utf8_lossy(&result.stderr);
+        bail!("{}", stderr.trim());
+    }
+    match output {
+        Some(path) => Ok(Some(tokio::fs::read(path).await?)),
+        None => Ok(Some(result.stdout)),
+    }
+}
+
+/// The HTML for a diagram: an image of it, or the error its program reported. `None` leaves the
+/// block to the page.
+async fn diagram(kind: Kind, source: &str) -> Option<String> {
+    let mut hasher = DefaultHasher::new();
+    (kind.class(), source).hash(&mut hasher);
+    let key = hasher.finish();
+    if let Some(html) = CACHE.lock().unwrap().get(&key) {
+        return Some(html.clone());
+    }
+
+    let html = match render_svg(kind, source).await {
+        Ok(None) => return None,
+        Ok(Some(svg)) => format!(
+            "<figure class=\"penview-diagram\"><img src=\"{}\" alt=\"\" /></figure>\n",
+            data_url(&svg, "image/svg+xml")
+        ),
+        Err(e) => {
+            let mut message = String::new();
+            escape_html(&mut message, &format!("{e:#}")).unwrap();
+            format!("<pre class=\"penview-diagram-error\">Diagram error: {message}</pre>\n")
+        }
+    };
+
+    let mut cache = CACHE.lock().unwrap();
+    if cache.len() >= CACHE_SIZE {
+        cache.clear();
+    }
+    cache.insert(key, html.clone());
+    Some(html)
+}
+
+/// Replaces the diagram code blocks in `events` with images of them (see `--diagrams server`).
+pub async fn render_diagrams(events: &mut [(Event<'_>, Range<usize>)]) {
+    let mut i = 0;
+    while i < events.len() {
+        let Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) = &events[i].0 else {
+            i += 1;
+            continue;
+        };
+        let kind = Kind::from_lang(info.split(' ').next().unwrap_or_default());
+        let start = i;
+        let end = start
+            + events[start..]
+                .iter()
+                .position(|(event, _)| matches!(event, Event::End(TagEnd::CodeBlock)))
+                .unwrap_or(events.len() - 1 - start);
+        i = end + 1;
+
+        let Some(kind) = kind else {
+            continue;
+        };
+        let source: String = events[start..end]
+            .iter()
+            .filter_map(|(event, _)| match event {
+                Event::Text(text) => Some(text.as_ref()),
+                _ => None,
+            })
+            .collect();
+        if let Some(html) = diagram(kind, &source).await {
+            events[start].0 = Event::Html(html.into());
+            for (event, _) in &mut events[start + 1..=end] {
+                *event = Event::Html("".into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_lang() {
+        assert_eq!(Kind::from_lang("dot"), Some(Kind::Graphviz));
+        assert_eq!(Kind::from_lang("puml"), Some(Kind::PlantUml));
+        assert_eq!(Kind::from_lang("rust"), None);
+        assert_eq!(Kind::Graphviz.class(), "penview-graphviz");
+    }
+}
@@ -0,0 +1,143 @@
+//! Draft content: documents with `draft: true` frontmatter and sections between
+//! `<!-- draft -->` and `<!-- /draft -->` markers.
+//!
+//! The preview shows drafts with a watermark, while exports strip them unless asked not to.
+
+use std::{borrow::Cow, ops::Range};
+
+use pulldown_cmark::{CowStr, Event, MetadataBlockKind, Tag, TagEnd};
+
+use crate::{frontmatter, transform::RenderTransformer};
+
+const START: &str = "<!-- draft -->";
+const END: &str = "<!-- /draft -->";
+
+fn is_draft_frontmatter(frontmatter: &str, kind: MetadataBlockKind) -> bool {
+    frontmatter::field(frontmatter, kind, "draft").as_deref() == Some("true")
+}
+
+/// Whether the document is marked `draft: true` in its frontmatter.
+pub fn is_draft(content: &str) -> bool {
+    frontmatter::block(content).is_some_and(|(block, kind)| is_draft_frontmatter(block, kind))
+}
+
+/// Removes draft sections, including their markers. An unclosed section runs to the end of the
+/// document.
+pub fn strip_drafts(content: &str) -> Cow<'_, str> {
+    if !content.contains(START) {
+        return Cow::Borrowed(content);
+    }
+
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find(START) {
+        out.push_str(&rest[..start]);
+        rest = match rest[start..].find(END) {
+            Some(end) => &rest[start + end + END.len()..],
+            None => "",
+        };
+    }
+    out.push_str(rest);
+    Cow::Owned(out)
+}
+
+/// Turns draft markers into elements the preview styles as drafts, and adds a watermark to draft
+/// documents.
+pub struct Drafts;
+
+impl RenderTransformer for Drafts {
+    fn name(&self) -> &str {
+        "drafts"
+    }
+
+    fn transform<'a>(&self, events: &mut Vec<(Event<'a>, Range<usize>)>) {
+        let mut metadata = None;
+        let mut draft = false;
+        let mut watermark = None;
+
+        for (index, (event, range)) in events.iter_mut().enumerate() {
+            match event {
+                Event::Start(Tag::MetadataBlock(kind)) => metadata = Some(*kind),
+                Event::End(TagEnd::MetadataBlock(_)) => {
+                    metadata = None;
+                    if draft {
+                        watermark = Some((index + 1, range.end..range.end));
+                    }
+                }
+                Event::Text(text) if metadata.is_some() => {
+                    draft |= metadata.is_some_and(|kind| is_draft_frontmatter(text, kind));
+                }
+                Event::Html(html) => {
+                    if let Some(replacement) = marker_element(html, "div") {
+                        *html = replacement;
+                    }
+                }
+                Event::InlineHtml(html) => {
+                    if let Some(replacement) = marker_element(html, "span") {
+                        *html = replacement;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if let Some((index, range)) = watermark {
+            let html = "<div class=\"penview-draft-watermark\">Draft</div>";
+            events.insert(index, (Event::Html(html.into()), range));
+        }
+    }
+}
+
+/// Replaces a draft marker with the opening or closing tag of `element`.
+fn marker_element(html: &str, element: &str) -> Option<CowStr<'static>> {
+    match html.trim() {
+        START => Some(format!("<{element} class=\"penview-draft\">").into()),
+        END => Some(format!("</{element}>").into()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::render::{RenderOptions, render_markdown_to_html};
+
+    #[test]
+    fn test_is_draft() {
+        assert!(is_draft("---\ntitle: Notes\ndraft: true\n---\n# Notes\n"));
+        assert!(is_draft("+++\ndraft = true\n+++\n"));
+        assert!(!is_draft("---\ndraft: false\n---\n"));
+        assert!(!is_draft("# Notes\n\ndraft: true\n"));
+    }
+
+    #[test]
+    fn test_strip_drafts() {
+        assert_eq!(
+            strip_drafts(
+                "Intro\n\n<!-- draft -->\nSecret\n<!-- /draft -->\n\nOutro <!-- draft -->x"
+            ),
+            "Intro\n\n\n\nOutro "
+        );
+    }
+
+    #[tokio::test]
+    async fn test_preview_marks_drafts() {
+        let content = "---\ndraft: true\n---\n\n<!-- draft -->\nSecret\n<!-- /draft -->\n";
+        let html =
+            render_markdown_to_html(content, Path::new("doc.md"), &RenderOptions::default()).await;
+        assert_eq!(
+            html,
+            "<div class=\"penview-draft-watermark\">Draft</div><div class=\"penview-draft\">\n\
+             <p data-source-line=\"6\">Secret</p>\n</div>"
+        );
+
+        let options = RenderOptions {
+            hide_drafts: true,
+            ..Default::default()
+        };
+        let html = render_markdown_to_html(content, Path::new("doc.md"), &options).await;
+        assert_eq!(html, "<div class=\"penview-draft-watermark\">Draft</div>");
+    }
+}
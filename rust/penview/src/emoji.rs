@@ -0,0 +1,115 @@
+//! GitHub-style emoji shortcodes (`:tada:`), implemented as a [`RenderTransformer`].
+
+use std::ops::Range;
+
+use pulldown_cmark::{Event, Tag, TagEnd};
+
+use crate::transform::RenderTransformer;
+
+/// The most commonly used GitHub shortcodes.
+const SHORTCODES: &[(&str, &str)] = &[
+    ("+1", "👍"),
+    ("-1", "👎"),
+    ("bug", "🐛"),
+    ("bulb", "💡"),
+    ("check", "✔️"),
+    ("clap", "👏"),
+    ("construction", "🚧"),
+    ("eyes", "👀"),
+    ("fire", "🔥"),
+    ("heart", "❤️"),
+    ("heavy_check_mark", "✔️"),
+    ("hourglass", "⌛"),
+    ("information_source", "ℹ️"),
+    ("joy", "😂"),
+    ("laughing", "😆"),
+    ("lock", "🔒"),
+    ("memo", "📝"),
+    ("pencil", "📝"),
+    ("question", "❓"),
+    ("rocket", "🚀"),
+    ("smile", "😄"),
+    ("smiley", "😃"),
+    ("sparkles", "✨"),
+    ("star", "⭐"),
+    ("tada", "🎉"),
+    ("thinking", "🤔"),
+    ("thumbsdown", "👎"),
+    ("thumbsup", "👍"),
+    ("warning", "⚠️"),
+    ("wave", "👋"),
+    ("white_check_mark", "✅"),
+    ("wink", "😉"),
+    ("wrench", "🔧"),
+    ("x", "❌"),
+    ("zap", "⚡"),
+];
+
+/// Replaces known `:shortcode:`s in prose with their emoji. Unknown shortcodes and code are left
+/// alone.
+pub struct Emoji;
+
+fn replace_shortcodes(text: &str) -> Option<String> {
+    let mut out = String::new();
+    let mut rest = text;
+    let mut replaced = false;
+
+    while let Some(start) = rest.find(':') {
+        let after = &rest[start + 1..];
+        let Some(end) = after.find(':') else {
+            break;
+        };
+
+        match SHORTCODES.iter().find(|(code, _)| *code == &after[..end]) {
+            Some((_, emoji)) => {
+                out.push_str(&rest[..start]);
+                out.push_str(emoji);
+                rest = &after[end + 1..];
+                replaced = true;
+            }
+            None => {
+                // The closing colon may open the next shortcode
+                out.push_str(&rest[..=start]);
+                rest = after;
+            }
+        }
+    }
+
+    replaced.then(|| out + rest)
+}
+
+impl RenderTransformer for Emoji {
+    fn name(&self) -> &str {
+        "emoji"
+    }
+
+    fn transform<'a>(&self, events: &mut Vec<(Event<'a>, Range<usize>)>) {
+        let mut in_code = 0;
+        for (event, _) in events.iter_mut() {
+            match event {
+                Event::Start(Tag::CodeBlock(_) | Tag::MetadataBlock(_)) => in_code += 1,
+                Event::End(TagEnd::CodeBlock | TagEnd::MetadataBlock(_)) => in_code -= 1,
+                Event::Text(text) if in_code == 0 => {
+                    if let Some(replaced) = replace_shortcodes(text) {
+                        *text = replaced.into();
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_replace_shortcodes() {
+        assert_eq!(
+            replace_shortcodes("Shipped :tada: at 10:30:rocket:").unwrap(),
+            "Shipped 🎉 at 10:30🚀"
+        );
+        assert_eq!(replace_shortcodes(":unknown: and 12:00"), None);
+    }
+}
@@ -0,0 +1,119 @@
+//! Running fenced code blocks on demand and capturing their output, for literate-programming
+//! style documents. Only languages given a command with `--exec LANG=COMMAND` can run, and only
+//! when a reader clicks a block's run button; the Neovim plugin asks for consent per project
+//! before passing any.
+
+use std::{path::Path, process::Stdio, time::Duration};
+
+use anyhow::Context;
+use pulldown_cmark::{CodeBlockKind, Event, Tag, TagEnd};
+use serde::Serialize;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::html::LineIndex;
+
+/// How long a code block may run before it is killed.
+const EXEC_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What running a code block printed.
+#[derive(Debug, Serialize)]
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+    /// Exit code, absent if the process was killed by a signal or timed out.
+    pub status: Option<i32>,
+    pub timed_out: bool,
+}
+
+/// Language and code of the fenced code block starting at 1-based `line` of `content`.
+pub fn code_block_at(content: &str, line: usize) -> Option<(String, String)> {
+    let lines = LineIndex::new(content);
+    let parser = pulldown_cmark::Parser::new_ext(content, pulldown_cmark::Options::all());
+
+    let mut block: Option<(String, String)> = None;
+    for (event, range) in parser.into_offset_iter() {
+        match (&mut block, event) {
+            (None, Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))))
+                if lines.line(range.start) == line =>
+            {
+                let lang = info.split(' ').next().unwrap_or_default().to_string();
+                block = Some((lang, String::new()));
+            }
+            (Some((_, code)), Event::Text(text)) => code.push_str(&text),
+            (Some(_), Event::End(TagEnd::CodeBlock)) => return block,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Runs `code` by piping it into `command` (e.g. `python3 -`), in `dir`.
+pub async fn run(command: &str, code: &str, dir: &Path) -> anyhow::Result<Output> {
+    let mut args = command.split_whitespace();
+    let program = args.next().context("Empty command")?;
+    let mut child = Command::new(program)
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("Failed to run {program}"))?;
+
+    let mut stdin = child.stdin.take().context("Stdin unavailable")?;
+    stdin.write_all(code.as_bytes()).await?;
+    drop(stdin);
+
+    // Dropping the child on timeout kills it
+    let Ok(output) = tokio::time::timeout(EXEC_TIMEOUT, child.wait_with_output()).await else {
+        return Ok(Output {
+            stdout: String::new(),
+            stderr: format!("Timed out after {} seconds", EXEC_TIMEOUT.as_secs()),
+            status: None,
+            timed_out: true,
+        });
+    };
+    let output = output?;
+    Ok(Output {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        status: output.status.code(),
+        timed_out: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_block_at() {
+        let content = "# Run\n\n```bash title=\"demo\"\necho one\necho two\n```\n\n- item\n\n  ```python\n  print(1)\n  ```\n";
+        assert_eq!(
+            code_block_at(content, 3),
+            Some(("bash".to_string(), "echo one\necho two\n".to_string()))
+        );
+        assert_eq!(
+            code_block_at(content, 10),
+            Some(("python".to_string(), "print(1)\n".to_string()))
+        );
+        assert_eq!(code_block_at(content, 1), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run() {
+        let output = run(
+            "sh",
+            "echo out; echo err >&2; exit 3",
+            &std::env::temp_dir(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(output.stdout, "out\n");
+        assert_eq!(output.stderr, "err\n");
+        assert_eq!(output.status, Some(3));
+        assert!(!output.timed_out);
+    }
+}
@@ -0,0 +1,233 @@
+//! Batch export of a directory tree of markdown files to standalone HTML, optionally as a static
+//! site with working links between documents.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use pulldown_cmark::{Event, Tag};
+use tokio::fs;
+use url::Url;
+
+use crate::{
+    drafts,
+    render::{RenderOptions, is_markdown, render_doc, render_page},
+};
+
+/// Recursively collects the markdown files under `dir`, sorted by path.
+///
+/// Hidden files and directories (e.g. `.git`) are skipped.
+pub async fn markdown_files(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let mut entries = fs::read_dir(&dir)
+            .await
+            .with_context(|| format!("Failed to read {}", dir.display()))?;
+
+        while let Some(entry) = entries.next_entry().await? {
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+
+            let path = entry.path();
+            if entry.file_type().await?.is_dir() {
+                pending.push(path);
+            } else if is_markdown(&path) {
+                files.push(path);
+            }
+        }
+    }
+
+    files.sort();
+    Ok(files)
+}
+
+/// Maps a markdown file under `dir` to its `.html` counterpart under `out`, keeping its
+/// position in the tree.
+fn out_path(dir: &Path, out: &Path, file: &Path) -> PathBuf {
+    let relative = file.strip_prefix(dir).unwrap_or(file);
+    out.join(relative).with_extension("html")
+}
+
+/// Returns the relative image and link targets in `content` that aren't markdown documents,
+/// i.e. the local files an exported page needs next to it.
+fn local_assets(content: &str) -> Vec<String> {
+    let parser = pulldown_cmark::Parser::new_ext(content, pulldown_cmark::Options::all());
+
+    parser
+        .filter_map(|event| match event {
+            Event::Start(Tag::Image { dest_url, .. } | Tag::Link { dest_url, .. }) => {
+                Some(dest_url)
+            }
+            _ => None,
+        })
+        .filter_map(|dest_url| {
+            let path = dest_url.split(['#', '?']).next().unwrap_or_default();
+            let is_local = !path.is_empty()
+                && dest_url.parse::<Url>().is_err()
+                && Path::new(path).is_relative()
+                && !is_markdown(Path::new(path));
+            is_local.then(|| path.to_string())
+        })
+        .collect()
+}
+
+/// Markdown for a directory index: a list of `documents` (relative to the directory), each
+/// linked to the path returned by `link`.
+pub fn index_markdown(
+    title: &str,
+    documents: &[PathBuf],
+    link: impl Fn(&Path) -> PathBuf,
+) -> String {
+    let mut markdown = format!("# {title}\n\n");
+    for document in documents {
+        markdown.push_str(&format!(
+            "- [{}](<{}>)\n",
+            document.display(),
+            link(document).display()
+        ));
+    }
+    markdown
+}
+
+/// Copies the local assets referenced by `file` to the same place relative to its exported page.
+/// Assets outside `dir` or missing on disk are skipped.
+async fn copy_assets(dir: &Path, out: &Path, file: &Path, content: &str) -> anyhow::Result<()> {
+    let Some(parent) = file.parent() else {
+        return Ok(());
+    };
+
+    for asset in local_assets(content) {
+        let Ok(source) = parent.join(&asset).canonicalize() else {
+            continue;
+        };
+        let Ok(relative) = source.strip_prefix(dir) else {
+            continue;
+        };
+        if !source.is_file() {
+            continue;
+        }
+
+        let target = out.join(relative);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::copy(&source, &target)
+            .await
+            .with_context(|| format!("Failed to copy {}", source.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Renders every markdown file under `dir` to a standalone HTML file under `out`, overwriting
+/// existing files. Returns the paths written.
+///
+/// With [`RenderOptions::site`] set this produces a static site: links between documents point
+/// at the exported pages, referenced local assets are copied over, and an `index.html` listing
+/// every document is added unless one of the documents already exports to it.
+pub async fn export(
+    dir: &Path,
+    out: &Path,
+    options: &RenderOptions,
+) -> anyhow::Result<Vec<PathBuf>> {
+    let dir = dir
+        .canonicalize()
+        .with_context(|| format!("Failed to open {}", dir.display()))?;
+
+    let mut files = Vec::new();
+    for file in markdown_files(&dir).await? {
+        if options.hide_drafts && drafts::is_draft(&fs::read_to_string(&file).await?) {
+            continue;
+        }
+        files.push(file);
+    }
+
+    let mut written = Vec::new();
+    for file in &files {
+        let html = render_doc(file, false, options)
+            .await
+            .with_context(|| format!("Failed to render {}", file.display()))?;
+
+        let out_file = out_path(&dir, out, file);
+        if let Some(parent) = out_file.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        fs::write(&out_file, html)
+            .await
+            .with_context(|| format!("Failed to write {}", out_file.display()))?;
+
+        if options.site {
+            let content = fs::read_to_string(file).await?;
+            copy_assets(&dir, out, file, &content).await?;
+        }
+
+        written.push(out_file);
+    }
+
+    let index = out.join("index.html");
+    if options.site && !written.contains(&index) {
+        let title = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Index".to_string());
+        let documents: Vec<PathBuf> = files
+            .iter()
+            .filter_map(|file| file.strip_prefix(&dir).ok())
+            .map(Path::to_path_buf)
+            .collect();
+
+        let html = render_page(
+            &dir.join("index.md"),
+            &index_markdown(&title, &documents, |document| {
+                document.with_extension("html")
+            }),
+            false,
+            options,
+        )
+        .await?;
+        fs::create_dir_all(out).await?;
+        fs::write(&index, html)
+            .await
+            .with_context(|| format!("Failed to write {}", index.display()))?;
+
+        written.push(index);
+    }
+
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_out_path() {
+        assert_eq!(
+            out_path(
+                Path::new("/docs"),
+                Path::new("site"),
+                Path::new("/docs/guide/intro.md")
+            ),
+            PathBuf::from("site/guide/intro.html")
+        );
+    }
+
+    #[test]
+    fn test_local_assets() {
+        let content = "![Diagram](img/flow.png) [Guide](guide.md) [Spec](spec.pdf#page=2) \
+                       [Site](https://example.com) [Top](#top)";
+        assert_eq!(local_assets(content), vec!["img/flow.png", "spec.pdf"]);
+    }
+
+    #[test]
+    fn test_index_markdown() {
+        let documents = [PathBuf::from("README.md"), PathBuf::from("guide/intro.md")];
+        assert_eq!(
+            index_markdown("docs", &documents, |document| document
+                .with_extension("html")),
+            "# docs\n\n- [README.md](<README.html>)\n- [guide/intro.md](<guide/intro.html>)\n"
+        );
+    }
+}
@@ -0,0 +1,221 @@
+//! Footnote placement, chosen with the `footnotes` frontmatter field: `inline` shows each footnote
+//! in a popover next to its reference, and `end` collects every definition in a "Footnotes"
+//! section at the end of the document, in the order they are referenced. Otherwise definitions
+//! stay where they are written.
+
+use std::{collections::HashMap, ops::Range};
+
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+
+use crate::{frontmatter, transform::RenderTransformer};
+
+/// Events of a footnote definition, including its start and end tags.
+type Definition<'a> = Vec<(Event<'a>, Range<usize>)>;
+
+/// The `footnotes` field of the document's frontmatter.
+fn placement(events: &[(Event<'_>, Range<usize>)]) -> Option<String> {
+    let mut metadata = None;
+    events.iter().find_map(|(event, _)| match event {
+        Event::Start(Tag::MetadataBlock(kind)) => {
+            metadata = Some(*kind);
+            None
+        }
+        Event::End(TagEnd::MetadataBlock(_)) => {
+            metadata = None;
+            None
+        }
+        Event::Text(text) => frontmatter::field(text, metadata?, "footnotes"),
+        _ => None,
+    })
+}
+
+/// Moves footnote definitions next to their references in documents that ask for it. Footnotes
+/// are always rendered as a whole document (see [`crate::block_cache`]), so the frontmatter and
+/// every definition are in the events.
+pub struct InlineFootnotes;
+
+impl RenderTransformer for InlineFootnotes {
+    fn name(&self) -> &str {
+        "inline-footnotes"
+    }
+
+    fn transform<'a>(&self, events: &mut Vec<(Event<'a>, Range<usize>)>) {
+        if placement(events).as_deref() != Some("inline") {
+            return;
+        }
+
+        // Take the definitions out of the document, without their paragraph tags so that they
+        // fit in a line of text
+        let mut definitions: HashMap<CowStr<'a>, Vec<(Event<'a>, Range<usize>)>> = HashMap::new();
+        let mut current: Option<(CowStr<'a>, usize)> = None;
+        let mut rest = Vec::with_capacity(events.len());
+        for (event, range) in events.drain(..) {
+            match (&mut current, event) {
+                (None, Event::Start(Tag::FootnoteDefinition(name))) => {
+                    current = Some((name, 0));
+                }
+                (None, event) => rest.push((event, range)),
+                (Some((name, depth)), event) => {
+                    let content = definitions.entry(name.clone()).or_default();
+                    match event {
+                        Event::End(TagEnd::FootnoteDefinition) if *depth == 0 => current = None,
+                        Event::Start(Tag::Paragraph) if *depth == 0 => {
+                            if !content.is_empty() {
+                                content.push((Event::InlineHtml("<br />".into()), range));
+                            }
+                            *depth += 1;
+                        }
+                        Event::End(TagEnd::Paragraph) if *depth == 1 => *depth -= 1,
+                        event => {
+                            match event {
+                                Event::Start(_) => *depth += 1,
+                                Event::End(_) => *depth -= 1,
+                                _ => {}
+                            }
+                            content.push((event, range));
+                        }
+                    }
+                }
+            }
+        }
+
+        // Follow each reference with its footnote, numbered like the reference
+        let mut numbers: HashMap<CowStr<'a>, usize> = HashMap::new();
+        for (event, range) in rest {
+            let Event::FootnoteReference(name) = &event else {
+                events.push((event, range));
+                continue;
+            };
+            let len = numbers.len() + 1;
+            let number = *numbers.entry(name.clone()).or_insert(len);
+            let content = definitions.get(name).cloned();
+            events.push((event, range.clone()));
+
+            if let Some(content) = content {
+                let open = format!(
+                    "<span class=\"penview-footnote\" role=\"note\"><span class=\"penview-footnote-label\">{number}</span> "
+                );
+                events.push((Event::InlineHtml(open.into()), range.clone()));
+                events.extend(content);
+                events.push((Event::InlineHtml("</span>".into()), range));
+            }
+        }
+    }
+}
+
+/// Moves footnote definitions to a section at the end of documents that ask for it, numbered and
+/// ordered by their first reference. Definitions that are never referenced come last.
+pub struct EndFootnotes;
+
+impl RenderTransformer for EndFootnotes {
+    fn name(&self) -> &str {
+        "end-footnotes"
+    }
+
+    fn transform<'a>(&self, events: &mut Vec<(Event<'a>, Range<usize>)>) {
+        if placement(events).as_deref() != Some("end") {
+            return;
+        }
+
+        let mut definitions: Vec<(CowStr<'a>, Definition<'a>)> = Vec::new();
+        let mut references: Vec<CowStr<'a>> = Vec::new();
+        let mut in_definition = false;
+        let mut rest = Vec::with_capacity(events.len());
+        for (event, range) in events.drain(..) {
+            match &event {
+                Event::Start(Tag::FootnoteDefinition(name)) => {
+                    in_definition = true;
+                    definitions.push((name.clone(), Vec::new()));
+                }
+                Event::FootnoteReference(name) if !references.contains(name) => {
+                    references.push(name.clone());
+                }
+                _ => {}
+            }
+
+            if in_definition {
+                in_definition = !matches!(event, Event::End(TagEnd::FootnoteDefinition));
+                definitions.last_mut().unwrap().1.push((event, range));
+            } else {
+                rest.push((event, range));
+            }
+        }
+        *events = rest;
+        if definitions.is_empty() {
+            return;
+        }
+
+        definitions.sort_by_key(|(name, _)| {
+            references
+                .iter()
+                .position(|reference| reference == name)
+                .unwrap_or(usize::MAX)
+        });
+        let end = events
+            .last()
+            .map_or(0..0, |(_, range)| range.end..range.end);
+        events.push((
+            Event::Html("<section class=\"footnotes\">\n<hr />\n<h2>Footnotes</h2>\n".into()),
+            end.clone(),
+        ));
+        events.extend(
+            definitions
+                .into_iter()
+                .flat_map(|(_, definition)| definition),
+        );
+        events.push((Event::Html("</section>\n".into()), end));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use crate::render::{RenderOptions, render_markdown_to_html};
+
+    #[tokio::test]
+    async fn test_end_footnotes() {
+        let content = "---\nfootnotes: end\n---\n\n[^b]: Second.\n\nClaim[^a] and[^b].\n\n\
+                       [^a]: First.\n\n# Later\n\n[^c]: Unused.\n";
+        let html =
+            render_markdown_to_html(content, Path::new("doc.md"), &RenderOptions::default()).await;
+        let (body, footnotes) = html.split_once("<section class=\"footnotes\">").unwrap();
+
+        assert!(!body.contains("footnote-definition"));
+        assert!(body.ends_with("<h1 id=\"later\" data-source-line=\"11\">Later</h1>\n"));
+        let a = footnotes.find("id=\"a\"").unwrap();
+        let b = footnotes.find("id=\"b\"").unwrap();
+        let c = footnotes.find("id=\"c\"").unwrap();
+        assert!(footnotes.starts_with("\n<hr />\n<h2>Footnotes</h2>\n"));
+        assert!(a < b && b < c);
+        assert!(footnotes.contains("<sup class=\"footnote-definition-label\">1</sup>"));
+        assert!(footnotes.ends_with("</section>\n"));
+    }
+
+    #[tokio::test]
+    async fn test_inline_footnotes() {
+        let content = "---\nfootnotes: inline\n---\n\nClaim[^a] and again[^a].\n\n\
+                       [^a]: Source *one*.\n";
+        let html =
+            render_markdown_to_html(content, Path::new("doc.md"), &RenderOptions::default()).await;
+        let note = "<span class=\"penview-footnote\" role=\"note\">\
+                    <span class=\"penview-footnote-label\">1</span> \
+                    Source <em>one</em>.</span>";
+        assert_eq!(
+            html,
+            format!(
+                "<p data-source-line=\"5\">Claim<sup class=\"footnote-reference\"><a href=\"#a\">1</a></sup>{note} \
+                 and again<sup class=\"footnote-reference\"><a href=\"#a\">1</a></sup>{note}.</p>\n"
+            )
+        );
+
+        // Without the frontmatter field, footnotes stay at the bottom
+        let html = render_markdown_to_html(
+            "Claim[^a].\n\n[^a]: Source.\n",
+            Path::new("doc.md"),
+            &RenderOptions::default(),
+        )
+        .await;
+        assert!(html.contains("<div class=\"footnote-definition\" id=\"a\""));
+    }
+}
@@ -0,0 +1,204 @@
+//! Reading YAML (`---`) and TOML (`+++`) frontmatter, and the card showing it above the document
+//! (see `--frontmatter-card`).
+
+use pulldown_cmark::MetadataBlockKind;
+use pulldown_cmark_escape::escape_html;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::tags;
+
+/// Returns the frontmatter of `content`, without its delimiters, and which kind it is.
+pub fn block(content: &str) -> Option<(&str, MetadataBlockKind)> {
+    let (delimiter, kind) = [
+        ("---", MetadataBlockKind::YamlStyle),
+        ("+++", MetadataBlockKind::PlusesStyle),
+    ]
+    .into_iter()
+    .find(|(delimiter, _)| content.lines().next().map(str::trim_end) == Some(*delimiter))?;
+    let rest = &content[content.find('\n')? + 1..];
+
+    let mut offset = 0;
+    for line in rest.split_inclusive('\n') {
+        if line.trim_end() == delimiter {
+            return Some((&rest[..offset], kind));
+        }
+        offset += line.len();
+    }
+    None
+}
+
+/// Reads the fields of the frontmatter of `content`.
+pub fn fields(content: &str) -> Map<String, Value> {
+    block(content)
+        .map(|(block, kind)| parse(block, kind))
+        .unwrap_or_default()
+}
+
+/// Reads the fields of a frontmatter `block`, without its delimiters. Frontmatter that fails to
+/// parse, or isn't a table of fields, has none.
+pub fn parse(block: &str, kind: MetadataBlockKind) -> Map<String, Value> {
+    let value = match kind {
+        MetadataBlockKind::YamlStyle => serde_norway::from_str(block).ok(),
+        MetadataBlockKind::PlusesStyle => block
+            .parse::<toml::Table>()
+            .ok()
+            .map(|table| from_toml(toml::Value::Table(table))),
+    };
+    match value {
+        Some(Value::Object(fields)) => fields,
+        _ => Map::new(),
+    }
+}
+
+/// Converts TOML to JSON, with dates and times as strings.
+fn from_toml(value: toml::Value) -> Value {
+    match value {
+        toml::Value::String(s) => Value::String(s),
+        toml::Value::Integer(i) => i.into(),
+        toml::Value::Float(f) => f.into(),
+        toml::Value::Boolean(b) => b.into(),
+        toml::Value::Datetime(datetime) => Value::String(datetime.to_string()),
+        toml::Value::Array(items) => items.into_iter().map(from_toml).collect(),
+        toml::Value::Table(table) => Value::Object(
+            table
+                .into_iter()
+                .map(|(key, value)| (key, from_toml(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// A top-level scalar field of a frontmatter `block`, as text.
+pub fn field(block: &str, kind: MetadataBlockKind, key: &str) -> Option<String> {
+    match parse(block, kind).remove(key)? {
+        Value::String(s) => (!s.is_empty()).then_some(s),
+        value @ (Value::Bool(_) | Value::Number(_)) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// The frontmatter fields penview knows the meaning of, along with all of them as read.
+#[derive(Debug, Default, Serialize)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub date: Option<String>,
+    /// Tags without their leading `#`, as in the tag index.
+    pub tags: Vec<String>,
+    pub fields: Map<String, Value>,
+}
+
+impl Metadata {
+    pub fn from_fields(fields: Map<String, Value>) -> Self {
+        let string = |key| match fields.get(key) {
+            Some(Value::String(s)) if !s.is_empty() => Some(s.clone()),
+            _ => None,
+        };
+        Self {
+            title: string("title"),
+            date: string("date"),
+            tags: tags::tags_field(fields.get("tags").cloned()),
+            fields,
+        }
+    }
+
+    /// The metadata of the document `content`.
+    pub fn of(content: &str) -> Self {
+        Self::from_fields(fields(content))
+    }
+
+    /// A card with the title, date and tags, linked to the tag index of the server whose routes
+    /// are under `base_path`, or `None` if there are none of them.
+    pub fn card(&self, base_path: &str) -> Option<String> {
+        if self.title.is_none() && self.date.is_none() && self.tags.is_empty() {
+            return None;
+        }
+
+        let mut html = "<header class=\"penview-frontmatter\" data-source-line=\"1\">".to_string();
+        if let Some(title) = &self.title {
+            html.push_str("<div class=\"penview-frontmatter-title\">");
+            escape_html(&mut html, title).unwrap();
+            html.push_str("</div>");
+        }
+        if let Some(date) = &self.date {
+            html.push_str("<time datetime=\"");
+            escape_html(&mut html, date).unwrap();
+            html.push_str("\">");
+            escape_html(&mut html, date).unwrap();
+            html.push_str("</time>");
+        }
+        if !self.tags.is_empty() {
+            html.push_str("<ul class=\"penview-frontmatter-tags\">");
+            for tag in &self.tags {
+                html.push_str("<li><a href=\"");
+                escape_html(&mut html, &tags::tag_link(tag, base_path)).unwrap();
+                html.push_str("\">#");
+                escape_html(&mut html, tag).unwrap();
+                html.push_str("</a></li>");
+            }
+            html.push_str("</ul>");
+        }
+        html.push_str("</header>\n");
+        Some(html)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fields() {
+        let parsed =
+            fields("---\ntitle: \"Notes\"\ntags:\n  - a\n  - b\ndraft: true\n---\n# Notes\n");
+        assert_eq!(parsed["title"], "Notes");
+        assert_eq!(parsed["tags"], serde_json::json!(["a", "b"]));
+        assert_eq!(parsed["draft"], true);
+
+        let parsed = fields("+++\ntags = [\"x\", 'y']\n+++\n");
+        assert_eq!(parsed["tags"], serde_json::json!(["x", "y"]));
+        assert!(fields("# Notes\ntitle: no\n").is_empty());
+        assert!(fields("---\n: [\n---\n").is_empty());
+    }
+
+    #[test]
+    fn test_toml_tables() {
+        let parsed = fields(
+            "+++\ntitle = \"Notes\"\nweight = 3\ndate = 2024-05-01\n\n[extra]\ntitle = \"Other\"\n+++\n",
+        );
+        assert_eq!(parsed["title"], "Notes");
+        assert_eq!(parsed["weight"], 3);
+        assert_eq!(parsed["date"], "2024-05-01");
+        assert_eq!(parsed["extra"], serde_json::json!({"title": "Other"}));
+    }
+
+    #[test]
+    fn test_yaml_nesting() {
+        let parsed = fields(
+            "---\nauthor:\n  name: Ada\n  site: \"https://example.com\"\nsummary: |\n  a: b\n  c = d\nweight: 1.5\n---\n",
+        );
+        assert_eq!(
+            parsed["author"],
+            serde_json::json!({"name": "Ada", "site": "https://example.com"})
+        );
+        assert_eq!(parsed["summary"], "a: b\nc = d\n");
+        assert_eq!(parsed["weight"], 1.5);
+        assert_eq!(
+            field("draft: true\n", MetadataBlockKind::YamlStyle, "draft").as_deref(),
+            Some("true")
+        );
+    }
+
+    #[test]
+    fn test_card() {
+        let metadata = Metadata::of("---\ntitle: A & B\ndate: 2024-05-01\ntags: [rust]\n---\n");
+        assert_eq!(metadata.title.as_deref(), Some("A & B"));
+        assert_eq!(metadata.tags, vec!["rust"]);
+
+        let card = metadata.card("/penview").unwrap();
+        assert!(card.contains("<div class=\"penview-frontmatter-title\">A &amp; B</div>"));
+        assert!(card.contains("<time datetime=\"2024-05-01\">2024-05-01</time>"));
+        assert!(card.contains("<a href=\"/penview/tags?tag=rust\">#rust</a>"));
+        assert!(Metadata::of("---\ndraft: true\n---\n").card("").is_none());
+    }
+}
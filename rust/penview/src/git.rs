@@ -0,0 +1,301 @@
+//! Git metadata for previewed documents, gathered by running the `git` CLI.
+
+use std::{collections::HashMap, path::Path, process::Stdio};
+
+use anyhow::Context;
+use serde::Serialize;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+/// A run of consecutive lines last changed by the same commit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BlameHunk {
+    /// First line of the hunk, 1-based.
+    pub start_line: usize,
+    /// Last line of the hunk, inclusive.
+    pub end_line: usize,
+    /// Abbreviated commit hash; all zeros for uncommitted lines.
+    pub commit: String,
+    pub author: String,
+    /// Author time as a Unix timestamp.
+    pub time: i64,
+    pub summary: String,
+}
+
+/// A commit that touched a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Revision {
+    /// Full commit hash.
+    pub commit: String,
+    pub author: String,
+    /// Author time as a Unix timestamp.
+    pub time: i64,
+    pub summary: String,
+}
+
+/// Builds a `git` command running in the directory containing `path`.
+fn git(path: &Path) -> Command {
+    let mut command = Command::new("git");
+    if let Some(dir) = path.parent() {
+        command.current_dir(dir);
+    }
+    command.stderr(Stdio::null()).kill_on_drop(true);
+    command
+}
+
+/// The checked out branch and commit of the repository containing a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Head {
+    /// Branch name, or "HEAD" when detached.
+    pub branch: String,
+    /// Abbreviated commit hash.
+    pub commit: String,
+}
+
+/// Returns the current branch and commit of the repository containing `path`.
+pub async fn head(path: &Path) -> anyhow::Result<Head> {
+    Ok(Head {
+        branch: rev_parse(path, &["--abbrev-ref", "HEAD"]).await?,
+        commit: rev_parse(path, &["--short", "HEAD"]).await?,
+    })
+}
+
+async fn rev_parse(path: &Path, args: &[&str]) -> anyhow::Result<String> {
+    let output = git(path)
+        .arg("rev-parse")
+        .args(args)
+        .output()
+        .await
+        .context("Failed to run git")?;
+    anyhow::ensure!(
+        output.status.success(),
+        "git rev-parse exited with {}",
+        output.status
+    );
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Lists the commits that touched `path`, newest first, following renames.
+pub async fn log(path: &Path) -> anyhow::Result<Vec<Revision>> {
+    let file_name = path.file_name().context("Not a file")?;
+
+    let output = git(path)
+        .args(["log", "--follow", "--format=%H%x1f%an%x1f%at%x1f%s", "--"])
+        .arg(file_name)
+        .output()
+        .await
+        .context("Failed to run git")?;
+    anyhow::ensure!(
+        output.status.success(),
+        "git log exited with {}",
+        output.status
+    );
+
+    Ok(parse_log(&String::from_utf8_lossy(&output.stdout)))
+}
+
+fn parse_log(output: &str) -> Vec<Revision> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\x1f');
+            Some(Revision {
+                commit: fields.next()?.to_string(),
+                author: fields.next()?.to_string(),
+                time: fields.next()?.parse().ok()?,
+                summary: fields.next()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Returns the contents of `path` as of `commit`.
+///
+/// `commit` must be a (possibly abbreviated) hash so it can't be mistaken for an option.
+pub async fn show(path: &Path, commit: &str) -> anyhow::Result<String> {
+    anyhow::ensure!(
+        !commit.is_empty() && commit.bytes().all(|b| b.is_ascii_hexdigit()),
+        "Invalid commit {commit:?}"
+    );
+    let file_name = path.file_name().context("Not a file")?.to_string_lossy();
+
+    let output = git(path)
+        .arg("show")
+        .arg(format!("{commit}:./{file_name}"))
+        .output()
+        .await
+        .context("Failed to run git")?;
+    anyhow::ensure!(
+        output.status.success(),
+        "{file_name} doesn't exist at {commit}"
+    );
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Blames `path`. When `contents` is given it is blamed in place of the file on disk, so unsaved
+/// buffer changes show up as uncommitted.
+pub async fn blame(path: &Path, contents: Option<&str>) -> anyhow::Result<Vec<BlameHunk>> {
+    let file_name = path.file_name().context("Not a file")?;
+
+    let mut command = git(path);
+    command.args(["blame", "--porcelain"]);
+    if contents.is_some() {
+        command.args(["--contents", "-"]);
+    }
+    command
+        .arg("--")
+        .arg(file_name)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped());
+
+    let mut child = command.spawn().context("Failed to run git")?;
+    let mut stdin = child.stdin.take().context("git stdin unavailable")?;
+    if let Some(contents) = contents {
+        stdin.write_all(contents.as_bytes()).await?;
+    }
+    drop(stdin);
+
+    let output = child.wait_with_output().await?;
+    anyhow::ensure!(
+        output.status.success(),
+        "git blame exited with {}",
+        output.status
+    );
+
+    Ok(parse_blame(&String::from_utf8_lossy(&output.stdout)))
+}
+
+#[derive(Default)]
+struct CommitInfo {
+    author: String,
+    time: i64,
+    summary: String,
+}
+
+/// Parses `git blame --porcelain` output into hunks.
+///
+/// Commit details are only printed the first time a commit appears, so they are collected by
+/// hash and looked up for every line.
+fn parse_blame(output: &str) -> Vec<BlameHunk> {
+    let mut commits: HashMap<&str, CommitInfo> = HashMap::new();
+    let mut lines: Vec<(usize, &str)> = Vec::new();
+    let mut current: Option<&str> = None;
+
+    for line in output.lines() {
+        if line.starts_with('\t') {
+            continue;
+        }
+
+        let mut fields = line.split(' ');
+        let first = fields.next().unwrap_or_default();
+        if first.len() == 40 && first.bytes().all(|b| b.is_ascii_hexdigit()) {
+            let final_line = fields.nth(1).and_then(|l| l.parse().ok());
+            if let Some(final_line) = final_line {
+                lines.push((final_line, first));
+            }
+            commits.entry(first).or_default();
+            current = Some(first);
+            continue;
+        }
+
+        let Some(info) = current.and_then(|sha| commits.get_mut(sha)) else {
+            continue;
+        };
+        let value = line.split_once(' ').map(|(_, v)| v).unwrap_or_default();
+        match first {
+            "author" => info.author = value.to_string(),
+            "author-time" => info.time = value.parse().unwrap_or_default(),
+            "summary" => info.summary = value.to_string(),
+            _ => {}
+        }
+    }
+
+    lines.sort_by_key(|(line, _)| *line);
+
+    let mut hunks: Vec<BlameHunk> = Vec::new();
+    for (line, sha) in lines {
+        let commit = &sha[..8];
+        if let Some(last) = hunks.last_mut()
+            && last.commit == commit
+            && last.end_line + 1 == line
+        {
+            last.end_line = line;
+            continue;
+        }
+
+        let info = &commits[sha];
+        hunks.push(BlameHunk {
+            start_line: line,
+            end_line: line,
+            commit: commit.to_string(),
+            author: info.author.clone(),
+            time: info.time,
+            summary: info.summary.clone(),
+        });
+    }
+
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log() {
+        let output = "abc123\x1fAda\x1f1700000000\x1fFix typo\nbroken line\n";
+        assert_eq!(
+            parse_log(output),
+            vec![Revision {
+                commit: "abc123".to_string(),
+                author: "Ada".to_string(),
+                time: 1700000000,
+                summary: "Fix typo".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_parse_blame() {
+        let output = "\
+1111111111111111111111111111111111111111 1 1 2
+author Ada
+author-mail <ada@example.com>
+author-time 1700000000
+author-tz +0000
+summary First draft
+filename notes.md
+\t# Notes
+1111111111111111111111111111111111111111 2 2
+\t
+0000000000000000000000000000000000000000 3 3 1
+author Not Committed Yet
+author-time 1800000000
+summary Version of notes.md from notes.md
+filename notes.md
+\tNew line
+";
+        assert_eq!(
+            parse_blame(output),
+            vec![
+                BlameHunk {
+                    start_line: 1,
+                    end_line: 2,
+                    commit: "11111111".to_string(),
+                    author: "Ada".to_string(),
+                    time: 1700000000,
+                    summary: "First draft".to_string(),
+                },
+                BlameHunk {
+                    start_line: 3,
+                    end_line: 3,
+                    commit: "00000000".to_string(),
+                    author: "Not Committed Yet".to_string(),
+                    time: 1800000000,
+                    summary: "Version of notes.md from notes.md".to_string(),
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,16 @@
+use askama::Template;
+
+use crate::messages::Messages;
+
+#[derive(Debug, Template)]
+#[template(path = "graph.html")]
+pub struct GraphTemplate {
+    pub theme: String,
+    /// Document to highlight, if the graph was opened from its preview.
+    pub path: Option<String>,
+    /// URL prefix of the server's routes.
+    pub base_path: String,
+    /// Language tag of the page.
+    pub lang: String,
+    pub messages: &'static Messages,
+}
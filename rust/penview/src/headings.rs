@@ -0,0 +1,463 @@
+//! The heading outline of a document, for editor pickers, and the anchors headings get in the
+//! rendered page.
+
+use std::{collections::HashMap, ops::Range};
+
+use pulldown_cmark::{CowStr, Event, Tag, TagEnd};
+use serde::Serialize;
+
+use crate::{html::LineIndex, transform::Transformers};
+
+/// A heading and the headings nested under it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Heading {
+    /// 1 for `#`, up to 6.
+    pub level: u8,
+    /// Plain text of the heading, without formatting.
+    pub text: String,
+    /// Line of the heading, 1-based.
+    pub line: usize,
+    /// Explicit `{#id}` of the heading, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The `id` of the heading in the rendered page: the explicit one, or a slug of its text (see
+    /// [`SlugStyle`]), so `#anchor` links to it.
+    pub anchor: String,
+    pub children: Vec<Heading>,
+}
+
+/// Returns the headings of `content` as a tree: each heading holds the following headings of a
+/// deeper level until the next one of the same or a shallower level. The `transformers` the page
+/// is rendered with are applied first, and anchors are generated in the page's `style`, so that
+/// texts and anchors match the page.
+pub fn headings(content: &str, transformers: &Transformers, style: SlugStyle) -> Vec<Heading> {
+    let lines = LineIndex::new(content);
+    let parser = pulldown_cmark::Parser::new_ext(content, pulldown_cmark::Options::all());
+    let mut events: Vec<_> = parser.into_offset_iter().collect();
+    transformers.apply(&mut events);
+
+    let mut slugger = Slugger::new(style);
+    let mut flat = Vec::new();
+    let mut current: Option<Heading> = None;
+    for (event, range) in events {
+        match event {
+            Event::Start(Tag::Heading { level, id, .. }) => {
+                current = Some(Heading {
+                    level: level as u8,
+                    text: String::new(),
+                    line: lines.line(range.start),
+                    id: id.map(|id| id.to_string()),
+                    anchor: String::new(),
+                    children: Vec::new(),
+                });
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some(mut heading) = current.take() {
+                    heading.anchor = match &heading.id {
+                        Some(id) => slugger.reserve(id),
+                        None => slugger.slug(&heading.text),
+                    };
+                    flat.push(heading);
+                }
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(heading) = &mut current {
+                    heading.text.push_str(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    nest(flat)
+}
+
+/// Returns the section of `content` under the heading with `anchor`: the heading and everything
+/// up to the next heading of the same or a shallower level.
+pub fn section<'a>(
+    content: &'a str,
+    anchor: &str,
+    transformers: &Transformers,
+    style: SlugStyle,
+) -> Option<&'a str> {
+    fn find(
+        headings: &[Heading],
+        anchor: &str,
+        end: Option<usize>,
+    ) -> Option<(usize, Option<usize>)> {
+        for (i, heading) in headings.iter().enumerate() {
+            let next = headings.get(i + 1).map(|next| next.line).or(end);
+            if heading.anchor == anchor {
+                return Some((heading.line, next));
+            }
+            if let Some(section) = find(&heading.children, anchor, next) {
+                return Some(section);
+            }
+        }
+        None
+    }
+
+    let (start, end) = find(&headings(content, transformers, style), anchor, None)?;
+    // Byte offset of the start of a 1-based line
+    let offset = |line: usize| match line.checked_sub(2) {
+        Some(newlines) => content
+            .match_indices('\n')
+            .nth(newlines)
+            .map_or(0, |(i, _)| i + 1),
+        None => 0,
+    };
+    let end = end.map_or(content.len(), offset);
+
+    Some(&content[offset(start)..end])
+}
+
+/// How heading texts are turned into anchors, to match the platform a document is published on.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SlugStyle {
+    /// Lowercased, without punctuation, with spaces as dashes.
+    #[default]
+    Github,
+    /// Like GitHub, with runs of dashes collapsed into one.
+    Gitlab,
+    /// Like GitHub, but keeping periods and dropping everything before the first letter, or
+    /// `section` if nothing is left.
+    Pandoc,
+}
+
+impl SlugStyle {
+    /// The style named by `--slugs`; unknown names fall back to GitHub's.
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "gitlab" => Self::Gitlab,
+            "pandoc" => Self::Pandoc,
+            _ => Self::Github,
+        }
+    }
+
+    /// The anchor for `text` before repeats are numbered.
+    pub fn base(self, text: &str) -> String {
+        let keep = |c: char| {
+            c.is_alphanumeric() || c == '-' || c == '_' || (c == '.' && self == Self::Pandoc)
+        };
+        let mut base: String = text
+            .trim()
+            .to_lowercase()
+            .chars()
+            .filter_map(|c| match c {
+                c if c.is_whitespace() => Some('-'),
+                c if keep(c) => Some(c),
+                _ => None,
+            })
+            .collect();
+
+        match self {
+            Self::Github => {}
+            Self::Gitlab => {
+                while base.contains("--") {
+                    base = base.replace("--", "-");
+                }
+            }
+            Self::Pandoc => {
+                let start = base.find(char::is_alphabetic).unwrap_or(base.len());
+                base.drain(..start);
+                if base.is_empty() {
+                    base = "section".to_string();
+                }
+            }
+        }
+        base
+    }
+}
+
+/// Generates heading anchors in a [`SlugStyle`], appending `-1`, `-2`, ... to repeats. One
+/// slugger is used for a whole document, in order, so that repeats are numbered the same however
+/// the document is rendered.
+#[derive(Debug, Default)]
+pub struct Slugger {
+    style: SlugStyle,
+    /// Anchors handed out so far, with how many repeats of each have been numbered.
+    seen: HashMap<String, usize>,
+    /// Anchors for heading texts computed by the editor's Lua slug hook, used instead of the
+    /// style's.
+    custom: HashMap<String, String>,
+}
+
+impl Slugger {
+    pub fn new(style: SlugStyle) -> Self {
+        Self {
+            style,
+            ..Self::default()
+        }
+    }
+
+    /// Uses the given anchor for each heading text in `custom`, as computed by a Lua hook.
+    pub fn with_custom(mut self, custom: HashMap<String, String>) -> Self {
+        self.custom = custom;
+        self
+    }
+
+    /// The anchor for a heading with `text`.
+    pub fn slug(&mut self, text: &str) -> String {
+        let base = match self.custom.get(text) {
+            Some(custom) => custom.clone(),
+            None => self.style.base(text),
+        };
+
+        // A numbered slug may itself be taken, e.g. by a heading titled "Notes 1"
+        let mut slug = base.clone();
+        while self.seen.contains_key(&slug) {
+            let repeats = self.seen.entry(base.clone()).or_default();
+            *repeats += 1;
+            slug = format!("{base}-{repeats}");
+        }
+        self.seen.insert(slug.clone(), 0);
+        slug
+    }
+
+    /// Records an explicit `{#id}`, so that generated anchors don't collide with it.
+    pub fn reserve(&mut self, id: &str) -> String {
+        self.seen.entry(id.to_string()).or_default();
+        id.to_string()
+    }
+
+    /// Gives every heading in `events` without an explicit id its anchor.
+    pub fn assign_ids(&mut self, events: &mut [(Event<'_>, Range<usize>)]) {
+        for_each_heading(events, |id, text| {
+            *id = Some(
+                match id {
+                    Some(id) => self.reserve(id),
+                    None => self.slug(text),
+                }
+                .into(),
+            );
+        });
+    }
+}
+
+/// The texts of the headings in `events` that get a generated anchor, i.e. have no explicit id,
+/// for the Lua slug hook.
+pub fn slug_texts(events: &[(Event<'_>, Range<usize>)]) -> Vec<String> {
+    let mut texts = Vec::new();
+    let mut current = None;
+    for (event, _) in events {
+        match event {
+            Event::Start(Tag::Heading { id: None, .. }) => current = Some(String::new()),
+            Event::Text(t) | Event::Code(t) => {
+                if let Some(text) = &mut current {
+                    text.push_str(t);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => texts.extend(current.take()),
+            _ => {}
+        }
+    }
+    texts
+}
+
+/// Calls `f` with the id and plain text of every heading in `events`, in order.
+fn for_each_heading<'a>(
+    events: &mut [(Event<'a>, Range<usize>)],
+    mut f: impl FnMut(&mut Option<CowStr<'a>>, &str),
+) {
+    let mut start = None;
+    let mut text = String::new();
+    for index in 0..events.len() {
+        match &events[index].0 {
+            Event::Start(Tag::Heading { .. }) => {
+                start = Some(index);
+                text.clear();
+            }
+            Event::Text(t) | Event::Code(t) if start.is_some() => text.push_str(t),
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some(Event::Start(Tag::Heading { id, .. })) =
+                    start.take().map(|start| &mut events[start].0)
+                {
+                    f(id, &text);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Drops the headings deeper than `depth` from `tree`, for a table of contents of the top levels.
+pub fn prune(tree: Vec<Heading>, depth: u8) -> Vec<Heading> {
+    tree.into_iter()
+        .filter(|heading| heading.level <= depth)
+        .map(|heading| Heading {
+            children: prune(heading.children, depth),
+            ..heading
+        })
+        .collect()
+}
+
+/// Nests a flat list of headings by level.
+fn nest(flat: Vec<Heading>) -> Vec<Heading> {
+    // Headings still open for children, from the outermost
+    let mut stack: Vec<Heading> = Vec::new();
+    let mut roots = Vec::new();
+
+    let close = |stack: &mut Vec<Heading>, roots: &mut Vec<Heading>| {
+        let heading = stack.pop().expect("stack is not empty");
+        match stack.last_mut() {
+            Some(parent) => parent.children.push(heading),
+            None => roots.push(heading),
+        }
+    };
+
+    for heading in flat {
+        while stack.last().is_some_and(|open| open.level >= heading.level) {
+            close(&mut stack, &mut roots);
+        }
+        stack.push(heading);
+    }
+    while !stack.is_empty() {
+        close(&mut stack, &mut roots);
+    }
+
+    roots
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outline(headings: &[Heading]) -> Vec<(String, usize, usize)> {
+        headings
+            .iter()
+            .map(|h| (h.text.clone(), h.line, h.children.len()))
+            .collect()
+    }
+
+    #[test]
+    fn test_headings() {
+        let content =
+            "# Title\n\n## `setup()` options {#setup}\n\n### Nested\n\n## Usage\n\n# Appendix\n";
+        let tree = headings(content, &Transformers::default(), SlugStyle::Github);
+
+        assert_eq!(
+            outline(&tree),
+            vec![("Title".to_string(), 1, 2), ("Appendix".to_string(), 9, 0)]
+        );
+        assert_eq!(
+            outline(&tree[0].children),
+            vec![
+                ("setup() options".to_string(), 3, 1),
+                ("Usage".to_string(), 7, 0)
+            ]
+        );
+        assert_eq!(tree[0].children[0].id.as_deref(), Some("setup"));
+        assert_eq!(tree[0].children[0].children[0].level, 3);
+    }
+
+    #[test]
+    fn test_prune() {
+        let content = "# Title\n\n## Setup\n\n### Nested\n\n#### Deeper\n";
+        let tree = prune(
+            headings(content, &Transformers::default(), SlugStyle::Github),
+            2,
+        );
+        assert_eq!(outline(&tree), vec![("Title".to_string(), 1, 1)]);
+        assert_eq!(
+            outline(&tree[0].children),
+            vec![("Setup".to_string(), 3, 0)]
+        );
+    }
+
+    #[test]
+    fn test_section() {
+        let content = "# Title\n\n## Setup\n\n### Nested\n\ntext\n\n## Usage\n\nmore\n";
+        let section =
+            |anchor| section(content, anchor, &Transformers::default(), SlugStyle::Github);
+
+        assert_eq!(section("setup"), Some("## Setup\n\n### Nested\n\ntext\n\n"));
+        assert_eq!(section("nested"), Some("### Nested\n\ntext\n\n"));
+        assert_eq!(section("usage"), Some("## Usage\n\nmore\n"));
+        assert_eq!(section("title"), Some(content));
+        assert_eq!(section("missing"), None);
+    }
+
+    #[test]
+    fn test_slugger() {
+        let mut slugger = Slugger::default();
+        let slugs: Vec<String> = [
+            "Notes",
+            "`setup()` Options!",
+            "Notes",
+            "Notes 1",
+            "Notes",
+            "Café",
+        ]
+        .iter()
+        .map(|text| slugger.slug(text))
+        .collect();
+        assert_eq!(
+            slugs,
+            [
+                "notes",
+                "setup-options",
+                "notes-1",
+                "notes-1-1",
+                "notes-2",
+                "café"
+            ]
+        );
+
+        // Explicit ids are kept, and generated anchors avoid them
+        let mut slugger = Slugger::default();
+        assert_eq!(slugger.reserve("usage"), "usage");
+        assert_eq!(slugger.slug("Usage"), "usage-1");
+
+        for (style, expected) in [
+            (
+                SlugStyle::Github,
+                ["12-release--notes", "section-ab", "", "c"],
+            ),
+            (
+                SlugStyle::Gitlab,
+                ["12-release-notes", "section-ab", "", "c"],
+            ),
+            (
+                SlugStyle::Pandoc,
+                ["release--notes", "section-a.b", "section", "c"],
+            ),
+        ] {
+            let mut slugger = Slugger::new(style);
+            let slugs: Vec<String> = ["1.2 Release — Notes", "Section A.B", "?!", "C"]
+                .iter()
+                .map(|text| slugger.slug(text))
+                .collect();
+            assert_eq!(slugs, expected, "{style:?}");
+        }
+
+        // Slugs from the Lua hook are still numbered when repeated
+        let mut slugger =
+            Slugger::default().with_custom(HashMap::from([("Notes".into(), "n".into())]));
+        assert_eq!(slugger.slug("Notes"), "n");
+        assert_eq!(slugger.slug("Notes"), "n-1");
+        assert_eq!(slugger.slug("Other"), "other");
+
+        let tree = headings(
+            "# Intro :tada:\n\n## Intro 🎉\n\n## Setup {#intro--1}\n",
+            &Transformers::with_extensions("none", "none", true),
+            SlugStyle::Github,
+        );
+        assert_eq!(tree[0].text, "Intro 🎉");
+        assert_eq!(tree[0].anchor, "intro-");
+        assert_eq!(tree[0].children[0].anchor, "intro--1");
+        assert_eq!(tree[0].children[1].anchor, "intro--1");
+    }
+
+    #[test]
+    fn test_headings_skipping_levels() {
+        let tree = headings(
+            "### Deep\n\n# Top\n\n### Child\n",
+            &Transformers::default(),
+            SlugStyle::Github,
+        );
+        assert_eq!(
+            outline(&tree),
+            vec![("Deep".to_string(), 1, 0), ("Top".to_string(), 3, 1)]
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! Syntax highlighting of fenced code blocks on the server (see `--highlight server`), with
+//! [syntect](https://github.com/trishume/syntect), so pages don't load a highlighter script.
+//!
+//! Tokens are marked with `hl-` classes rather than inline colors, so that the page can switch
+//! between the light and dark themes' stylesheets like it does for highlight.js.
+
+use std::sync::LazyLock;
+
+use pulldown_cmark::{CodeBlockKind, CowStr, Event, Tag, TagEnd};
+use syntect::{
+    highlighting::ThemeSet,
+    html::{ClassStyle, ClassedHTMLGenerator, css_for_theme_with_class_style},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+use crate::diagram::Kind;
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+static SYNTAXES: LazyLock<SyntaxSet> = LazyLock::new(SyntaxSet::load_defaults_newlines);
+static THEMES: LazyLock<ThemeSet> = LazyLock::new(ThemeSet::load_defaults);
+
+/// Validates a `--code-theme`, listing the themes there are if it isn't one of them.
+pub fn theme_name(name: &str) -> Result<String, String> {
+    if THEMES.themes.contains_key(name) {
+        Ok(name.to_string())
+    } else {
+        let names: Vec<_> = THEMES.themes.keys().map(String::as_str).collect();
+        Err(format!(
+            "unknown theme, expected one of: {}",
+            names.join(", ")
+        ))
+    }
+}
+
+/// `code` as HTML with its tokens in `hl-` classes, or `None` if `lang` isn't known.
+pub fn highlight(code: &str, lang: &str) -> Option<String> {
+    let syntax = SYNTAXES.find_syntax_by_token(lang)?;
+    let mut generator = ClassedHTMLGenerator::new_with_class_style(syntax, &SYNTAXES, CLASS_STYLE);
+    for line in LinesWithEndings::from(code) {
+        generator
+            .parse_html_for_line_which_includes_newline(line)
+            .ok()?;
+    }
+    Some(generator.finalize())
+}
+
+/// Highlights the fenced code blocks in `events`. The text of a block is replaced by the
+/// highlighted HTML; blocks in unknown languages and diagrams are left alone.
+pub fn highlight_code_blocks(events: &mut [(Event<'_>, std::ops::Range<usize>)]) {
+    let mut i = 0;
+    while i < events.len() {
+        let Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))) = &events[i].0 else {
+            i += 1;
+            continue;
+        };
+        let lang = info.split(' ').next().unwrap_or_default().to_string();
+        let start = i + 1;
+        let end = start
+            + events[start..]
+                .iter()
+                .position(|(event, _)| matches!(event, Event::End(TagEnd::CodeBlock)))
+                .unwrap_or(events.len() - start);
+        i = end;
+
+        if lang.is_empty() || lang == "chart" || Kind::from_lang(&lang).is_some() {
+            continue;
+        }
+        let code: String = events[start..end]
+            .iter()
+            .filter_map(|(event, _)| match event {
+                Event::Text(text) => Some(text.as_ref()),
+                _ => None,
+            })
+            .collect();
+        if let Some(html) = highlight(&code, &lang) {
+            for (n, (event, _)) in events[start..end].iter_mut().enumerate() {
+                *event = if n == 0 {
+                    Event::Html(CowStr::from(html.clone()))
+                } else {
+                    Event::Text(CowStr::Borrowed(""))
+                };
+            }
+        }
+    }
+}
+
+/// The stylesheet of the `light` and `dark` themes, each applying in the matching mode of the
+/// page.
+pub fn stylesheet(light: &str, dark: &str) -> String {
+    let mut css = String::new();
+    for (theme, scope) in [
+        (light, "html:not([data-theme=\"dark\"])"),
+        (dark, "html[data-theme=\"dark\"]"),
+    ] {
+        let Some(theme) = THEMES.themes.get(theme) else {
+            continue;
+        };
+        let Ok(rules) = css_for_theme_with_class_style(theme, CLASS_STYLE) else {
+            continue;
+        };
+        css.push_str(&scoped(&rules, scope));
+    }
+    css
+}
+
+/// Prefixes every selector in `css` with `scope`.
+fn scoped(css: &str, scope: &str) -> String {
+    let mut out = String::with_capacity(css.len() * 2);
+    for line in css.lines() {
+        match line.strip_suffix(" {") {
+            Some(selectors) if selectors.starts_with('.') => {
+                let selectors: Vec<_> = selectors
+                    .split(", ")
+                    .map(|selector| format!("{scope} {selector}"))
+                    .collect();
+                out.push_str(&selectors.join(", "));
+                out.push_str(" {");
+            }
+            _ => out.push_str(line),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        let html = highlight("fn main() {}\n", "rust").unwrap();
+        assert!(html.contains("<span class=\"hl-"));
+        assert!(html.contains("main"));
+        assert!(highlight("x", "no-such-language").is_none());
+    }
+
+    #[test]
+    fn test_highlight_code_blocks() {
+        let content = "```rust\nlet x = 1;\n```\n\n```mermaid\ngraph TD\n```\n";
+        let parser = pulldown_cmark::Parser::new_ext(content, pulldown_cmark::Options::all());
+        let mut events: Vec<_> = parser.into_offset_iter().collect();
+        highlight_code_blocks(&mut events);
+
+        assert!(matches!(&events[1].0, Event::Html(html) if html.contains("hl-")));
+        assert!(
+            events.iter().any(
+                |(event, _)| matches!(event, Event::Text(text) if text.as_ref() == "graph TD\n")
+            )
+        );
+    }
+
+    #[test]
+    fn test_stylesheet() {
+        let css = stylesheet("InspiredGitHub", "base16-ocean.dark");
+        assert!(css.contains("html:not([data-theme=\"dark\"]) .hl-"));
+        assert!(css.contains("html[data-theme=\"dark\"] .hl-"));
+        assert!(theme_name("InspiredGitHub").is_ok());
+        assert!(theme_name("Nope").is_err());
+    }
+}
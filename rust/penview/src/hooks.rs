@@ -0,0 +1,314 @@
+//! Render hooks implemented in Lua by the Neovim plugin.
+//!
+//! The plugin announces which hooks it has over the preview WebSocket (see
+//! `lua/penview/init.lua`). While rendering, the server sends a request for each hook stage and
+//! waits briefly for the response, which the plugin computes in its websocket-ffi message
+//! callback. If the editor doesn't answer in time the stage is skipped, so a broken hook can
+//! slow the preview down but never stall it.
+
+use std::{
+    collections::HashMap,
+    ops::Range,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use pulldown_cmark::{Event, Tag};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{mpsc, oneshot};
+
+/// How long to wait for the editor to answer a hook request.
+const HOOK_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The hooks the editor has registered.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Hooks {
+    /// Rewrite inline events (text, code, HTML, links and images) before HTML is written.
+    pub events: bool,
+    /// Filter the rendered HTML body.
+    pub html: bool,
+    /// Generate heading anchors from heading texts.
+    pub slug: bool,
+}
+
+impl Hooks {
+    pub fn any(&self) -> bool {
+        self.events || self.html || self.slug
+    }
+}
+
+/// The parts of an event a Lua hook can see and change.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum HookEvent {
+    Text { text: String },
+    Code { text: String },
+    Html { html: String },
+    Link { url: String, title: String },
+    Image { url: String, title: String },
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum HookRequest<'a> {
+    RewriteEvents { id: u64, events: &'a [HookEvent] },
+    FilterHtml { id: u64, html: &'a str },
+    Slugify { id: u64, texts: &'a [String] },
+}
+
+/// The editor's answer to a hook request. Fields are absent when the hook failed.
+#[derive(Debug, Deserialize)]
+pub struct HookResponse {
+    pub id: u64,
+    #[serde(default)]
+    pub events: Option<Vec<HookEvent>>,
+    #[serde(default)]
+    pub html: Option<String>,
+    #[serde(default)]
+    pub slugs: Option<Vec<String>>,
+}
+
+fn hook_event(event: &Event<'_>) -> Option<HookEvent> {
+    Some(match event {
+        Event::Text(text) => HookEvent::Text {
+            text: text.to_string(),
+        },
+        Event::Code(text) => HookEvent::Code {
+            text: text.to_string(),
+        },
+        Event::InlineHtml(html) => HookEvent::Html {
+            html: html.to_string(),
+        },
+        Event::Start(Tag::Link {
+            dest_url, title, ..
+        }) => HookEvent::Link {
+            url: dest_url.to_string(),
+            title: title.to_string(),
+        },
+        Event::Start(Tag::Image {
+            dest_url, title, ..
+        }) => HookEvent::Image {
+            url: dest_url.to_string(),
+            title: title.to_string(),
+        },
+        _ => return None,
+    })
+}
+
+/// Collects the events hooks can rewrite, in document order.
+pub fn extract(events: &[(Event<'_>, Range<usize>)]) -> Vec<HookEvent> {
+    events
+        .iter()
+        .filter_map(|(event, _)| hook_event(event))
+        .collect()
+}
+
+/// Writes rewritten events back in place of the ones returned by [`extract`].
+///
+/// Text, code and HTML may be turned into each other; links and images keep their kind. The
+/// rewrite is ignored if the hook returned a different number of events.
+pub fn apply(events: &mut [(Event<'_>, Range<usize>)], rewritten: Vec<HookEvent>) {
+    let targets: Vec<usize> = (0..events.len())
+        .filter(|&i| hook_event(&events[i].0).is_some())
+        .collect();
+    if targets.len() != rewritten.len() {
+        return;
+    }
+
+    for (index, new) in targets.into_iter().zip(rewritten) {
+        let event = &mut events[index].0;
+        match (event, new) {
+            (
+                Event::Start(Tag::Link {
+                    dest_url, title, ..
+                }),
+                HookEvent::Link { url, title: t },
+            )
+            | (
+                Event::Start(Tag::Image {
+                    dest_url, title, ..
+                }),
+                HookEvent::Image { url, title: t },
+            ) => {
+                *dest_url = url.into();
+                *title = t.into();
+            }
+            (Event::Start(_), _) => {}
+            (event, HookEvent::Text { text }) => *event = Event::Text(text.into()),
+            (event, HookEvent::Code { text }) => *event = Event::Code(text.into()),
+            (event, HookEvent::Html { html }) => *event = Event::InlineHtml(html.into()),
+            (_, HookEvent::Link { .. } | HookEvent::Image { .. }) => {}
+        }
+    }
+}
+
+/// Sends hook requests to the editor and matches up the responses.
+#[derive(Clone)]
+pub struct HookClient {
+    outbound: mpsc::UnboundedSender<String>,
+    pending: Arc<Mutex<HashMap<u64, oneshot::Sender<HookResponse>>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl HookClient {
+    /// `outbound` carries requests to the editor's WebSocket.
+    pub fn new(outbound: mpsc::UnboundedSender<String>) -> Self {
+        Self {
+            outbound,
+            pending: Arc::default(),
+            next_id: Arc::default(),
+        }
+    }
+
+    async fn call<'a>(&self, request: impl FnOnce(u64) -> HookRequest<'a>) -> Option<HookResponse> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(id, tx);
+
+        let json = serde_json::to_string(&request(id)).ok()?;
+        let response = match self.outbound.send(json) {
+            Ok(()) => tokio::time::timeout(HOOK_TIMEOUT, rx).await.ok()?.ok(),
+            Err(_) => None,
+        };
+
+        self.pending.lock().unwrap().remove(&id);
+        response
+    }
+
+    /// Runs the editor's event hook over `events`.
+    pub async fn rewrite_events(&self, events: &[HookEvent]) -> Option<Vec<HookEvent>> {
+        self.call(|id| HookRequest::RewriteEvents { id, events })
+            .await?
+            .events
+    }
+
+    /// Runs the editor's HTML hook over a rendered body.
+    pub async fn filter_html(&self, html: &str) -> Option<String> {
+        self.call(|id| HookRequest::FilterHtml { id, html })
+            .await?
+            .html
+    }
+
+    /// Runs the editor's slug hook over heading texts, returning the anchor for each text. The
+    /// result is ignored if the hook returned a different number of anchors.
+    pub async fn slugify(&self, texts: &[String]) -> Option<HashMap<String, String>> {
+        let slugs = self
+            .call(|id| HookRequest::Slugify { id, texts })
+            .await?
+            .slugs?;
+        (slugs.len() == texts.len()).then(|| texts.iter().cloned().zip(slugs).collect())
+    }
+
+    /// Hands a response from the editor to the request waiting for it.
+    pub fn resolve(&self, response: HookResponse) {
+        if let Some(tx) = self.pending.lock().unwrap().remove(&response.id) {
+            let _ = tx.send(response);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::{Options, Parser};
+
+    #[test]
+    fn test_extract_and_apply() {
+        let source = "Hi `x` [link](a.md)";
+        let mut events: Vec<_> = Parser::new_ext(source, Options::all())
+            .into_offset_iter()
+            .collect();
+
+        let extracted = extract(&events);
+        assert_eq!(
+            extracted,
+            vec![
+                HookEvent::Text {
+                    text: "Hi ".to_string()
+                },
+                HookEvent::Code {
+                    text: "x".to_string()
+                },
+                HookEvent::Text {
+                    text: " ".to_string()
+                },
+                HookEvent::Link {
+                    url: "a.md".to_string(),
+                    title: String::new()
+                },
+                HookEvent::Text {
+                    text: "link".to_string()
+                },
+            ]
+        );
+
+        let mut rewritten = extracted.clone();
+        rewritten[0] = HookEvent::Html {
+            html: "<b>Hey</b> ".to_string(),
+        };
+        rewritten[3] = HookEvent::Link {
+            url: "b.md".to_string(),
+            title: "B".to_string(),
+        };
+        apply(&mut events, rewritten);
+
+        let mut html = String::new();
+        crate::html::push_html(
+            &mut html,
+            source,
+            &crate::link_policy::LinkPolicy::default(),
+            events.clone(),
+        );
+        assert!(html.contains("<b>Hey</b> <code"));
+        assert!(html.contains("href=\"b.md\" title=\"B\""));
+
+        // Mismatched lengths are ignored
+        apply(&mut events, Vec::new());
+        assert_eq!(extract(&events).len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_client_round_trip() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let client = HookClient::new(tx);
+
+        let editor = client.clone();
+        tokio::spawn(async move {
+            let request: serde_json::Value =
+                serde_json::from_str(&rx.recv().await.unwrap()).unwrap();
+            assert_eq!(request["type"], "filter_html");
+            editor.resolve(HookResponse {
+                id: request["id"].as_u64().unwrap(),
+                events: None,
+                html: Some("<p>filtered</p>".to_string()),
+                slugs: None,
+            });
+
+            let request: serde_json::Value =
+                serde_json::from_str(&rx.recv().await.unwrap()).unwrap();
+            assert_eq!(request["type"], "slugify");
+            assert_eq!(request["texts"], serde_json::json!(["Intro"]));
+            editor.resolve(HookResponse {
+                id: request["id"].as_u64().unwrap(),
+                events: None,
+                html: None,
+                slugs: Some(vec!["intro-x".to_string()]),
+            });
+        });
+
+        assert_eq!(
+            client.filter_html("<p>raw</p>").await.as_deref(),
+            Some("<p>filtered</p>")
+        );
+        assert_eq!(
+            client.slugify(&["Intro".to_string()]).await,
+            Some(HashMap::from([(
+                "Intro".to_string(),
+                "intro-x".to_string()
+            )]))
+        );
+    }
+}
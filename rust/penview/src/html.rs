@@ -0,0 +1,712 @@
+//! HTML writer for rendered markdown.
+//!
+//! Follows `pulldown_cmark::html::push_html`, but consumes events together with their source
+//! ranges and tags block elements, table rows and cells, links, images and inline code with a
+//! `data-source-line` attribute (1-based), which the page uses to map rendered elements back to
+//! buffer lines.
+
+use std::{collections::HashMap, fmt::Write, ops::Range};
+
+use pulldown_cmark::{
+    Alignment, BlockQuoteKind, CodeBlockKind, CowStr, Event, LinkType, Tag, TagEnd,
+};
+use pulldown_cmark_escape::{escape_href, escape_html, escape_html_body_text};
+
+use crate::{diagram::Kind, link_policy::LinkPolicy};
+
+/// Maps byte offsets in the source to 1-based line numbers.
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self { line_starts }
+    }
+
+    /// Returns the 1-based line containing `offset`.
+    pub fn line(&self, offset: usize) -> usize {
+        match self.line_starts.binary_search(&offset) {
+            Ok(line) => line + 1,
+            Err(line) => line,
+        }
+    }
+}
+
+enum TableState {
+    Head,
+    Body,
+}
+
+struct HtmlWriter<'a, 'b> {
+    out: &'b mut String,
+    lines: LineIndex,
+    /// Line number of the first line of the source, for rendering part of a document.
+    first_line: usize,
+
+    /// Whether or not the last write wrote a newline.
+    end_newline: bool,
+
+    /// Whether inside a metadata block (text should not be written).
+    in_non_writing_block: bool,
+
+    /// Whether inside a mermaid code block (text is written raw for the client-side renderer).
+    in_mermaid_block: bool,
+
+    /// Whether inside a chart or diagram code block, drawn by the page from the block's text.
+    in_drawn_block: bool,
+
+    table_state: TableState,
+    table_alignments: Vec<Alignment>,
+    table_cell_index: usize,
+    numbers: HashMap<CowStr<'a>, usize>,
+
+    /// Attributes and allowed schemes of links.
+    links: &'b LinkPolicy,
+}
+
+/// Renders `events` to HTML, appending to `out`.
+///
+/// `source` must be the text the event ranges refer to. Links are written following `links`.
+pub fn push_html<'a>(
+    out: &mut String,
+    source: &str,
+    links: &LinkPolicy,
+    events: impl IntoIterator<Item = (Event<'a>, Range<usize>)>,
+) {
+    push_html_from_line(out, source, 1, links, events);
+}
+
+/// Like [`push_html`], for a `source` excerpt that starts at line `first_line` of the document.
+pub fn push_html_from_line<'a>(
+    out: &mut String,
+    source: &str,
+    first_line: usize,
+    links: &LinkPolicy,
+    events: impl IntoIterator<Item = (Event<'a>, Range<usize>)>,
+) {
+    let mut writer = HtmlWriter {
+        out,
+        lines: LineIndex::new(source),
+        first_line,
+        end_newline: true,
+        in_non_writing_block: false,
+        in_mermaid_block: false,
+        in_drawn_block: false,
+        table_state: TableState::Head,
+        table_alignments: vec![],
+        table_cell_index: 0,
+        numbers: HashMap::new(),
+        links,
+    };
+
+    writer.run(events.into_iter());
+}
+
+impl<'a> HtmlWriter<'a, '_> {
+    /// Writes a buffer, and tracks whether or not a newline was written.
+    fn write(&mut self, s: &str) {
+        self.out.push_str(s);
+
+        if !s.is_empty() {
+            self.end_newline = s.ends_with('\n');
+        }
+    }
+
+    /// Writes formatted text straight into the output, so that `write!(self, ...)` doesn't
+    /// allocate a string.
+    fn write_fmt(&mut self, args: std::fmt::Arguments<'_>) {
+        let len = self.out.len();
+        let _ = self.out.write_fmt(args);
+        if self.out.len() > len {
+            self.end_newline = self.out.ends_with('\n');
+        }
+    }
+
+    /// Writes a newline unless the output already ends with one.
+    fn ensure_newline(&mut self) {
+        if !self.end_newline {
+            self.write("\n");
+        }
+    }
+
+    // Writing into a `String` cannot fail, so the escape results are discarded.
+    fn escape(&mut self, s: &str) {
+        let _ = escape_html(&mut *self.out, s);
+    }
+
+    fn escape_body(&mut self, s: &str) {
+        let _ = escape_html_body_text(&mut *self.out, s);
+    }
+
+    fn escape_href(&mut self, s: &str) {
+        let _ = escape_href(&mut *self.out, s);
+    }
+
+    fn write_source_line(&mut self, range: &Range<usize>) {
+        let line = self.lines.line(range.start) + self.first_line - 1;
+        let _ = write!(self.out, " data-source-line=\"{line}\"");
+    }
+
+    fn run(&mut self, mut iter: impl Iterator<Item = (Event<'a>, Range<usize>)>) {
+        while let Some((event, range)) = iter.next() {
+            match event {
+                Event::Start(Tag::Image {
+                    dest_url, title, ..
+                }) => {
+                    self.write("<img");
+                    self.write_source_line(&range);
+                    self.write(" src=\"");
+                    self.escape_href(&dest_url);
+                    self.write("\" alt=\"");
+                    self.raw_text(&mut iter);
+                    if !title.is_empty() {
+                        self.write("\" title=\"");
+                        self.escape(&title);
+                    }
+                    self.write("\" />");
+                }
+                Event::Start(tag) => self.write_start_tag(tag, &range),
+                Event::End(tag) => self.write_end_tag(tag),
+                Event::Text(text) => {
+                    if self.in_mermaid_block {
+                        // Mermaid needs the raw diagram source
+                        self.write(&text);
+                    } else if !self.in_non_writing_block {
+                        self.escape_body(&text);
+                        self.end_newline = text.ends_with('\n');
+                    }
+                }
+                Event::Code(text) => {
+                    self.write("<code");
+                    self.write_source_line(&range);
+                    self.write(">");
+                    self.escape_body(&text);
+                    self.write("</code>");
+                }
+                Event::InlineMath(text) => {
+                    self.write(r#"<span class="math math-inline">"#);
+                    self.escape(&text);
+                    self.write("</span>");
+                }
+                Event::DisplayMath(text) => {
+                    self.write(r#"<span class="math math-display">"#);
+                    self.escape(&text);
+                    self.write("</span>");
+                }
+                Event::Html(html) | Event::InlineHtml(html) => self.write(&html),
+                Event::SoftBreak => self.write("\n"),
+                Event::HardBreak => self.write("<br />\n"),
+                Event::Rule => {
+                    self.ensure_newline();
+                    self.write("<hr");
+                    self.write_source_line(&range);
+                    self.write(" />\n");
+                }
+                Event::FootnoteReference(name) => {
+                    let len = self.numbers.len() + 1;
+                    self.write("<sup class=\"footnote-reference\"><a href=\"#");
+                    self.escape(&name);
+                    self.write("\">");
+                    let number = *self.numbers.entry(name).or_insert(len);
+                    write!(self, "{number}");
+                    self.write("</a></sup>");
+                }
+                Event::TaskListMarker(true) => {
+                    self.write("<input disabled=\"\" type=\"checkbox\" checked=\"\"/>\n");
+                }
+                Event::TaskListMarker(false) => {
+                    self.write("<input disabled=\"\" type=\"checkbox\"/>\n");
+                }
+            }
+        }
+    }
+
+    /// Writes the start of an HTML tag, annotating block elements with their source line.
+    fn write_start_tag(&mut self, tag: Tag<'a>, range: &Range<usize>) {
+        match tag {
+            Tag::HtmlBlock => {}
+            Tag::Paragraph => {
+                self.ensure_newline();
+                self.write("<p");
+                self.write_source_line(range);
+                self.write(">");
+            }
+            Tag::Heading {
+                level,
+                id,
+                classes,
+                attrs,
+            } => {
+                self.ensure_newline();
+                write!(self, "<{level}");
+                if let Some(id) = id {
+                    self.write(" id=\"");
+                    self.escape(&id);
+                    self.write("\"");
+                }
+                if !classes.is_empty() {
+                    self.write(" class=\"");
+                    self.escape(&classes.join(" "));
+                    self.write("\"");
+                }
+                for (attr, value) in attrs {
+                    self.write(" ");
+                    self.escape(&attr);
+                    self.write("=\"");
+                    if let Some(value) = value {
+                        self.escape(&value);
+                    }
+                    self.write("\"");
+                }
+                self.write_source_line(range);
+                self.write(">");
+            }
+            Tag::Table(alignments) => {
+                self.table_alignments = alignments;
+                self.write("<table");
+                self.write_source_line(range);
+                self.write(">");
+            }
+            Tag::TableHead => {
+                self.table_state = TableState::Head;
+                self.table_cell_index = 0;
+                self.write("<thead><tr");
+                self.write_source_line(range);
+                self.write(">");
+            }
+            Tag::TableRow => {
+                self.table_cell_index = 0;
+                self.write("<tr");
+                self.write_source_line(range);
+                self.write(">");
+            }
+            Tag::TableCell => {
+                match self.table_state {
+                    TableState::Head => self.write("<th"),
+                    TableState::Body => self.write("<td"),
+                }
+                self.write_source_line(range);
+                match self.table_alignments.get(self.table_cell_index) {
+                    Some(Alignment::Left) => self.write(" style=\"text-align: left\">"),
+                    Some(Alignment::Center) => self.write(" style=\"text-align: center\">"),
+                    Some(Alignment::Right) => self.write(" style=\"text-align: right\">"),
+                    _ => self.write(">"),
+                }
+            }
+            Tag::BlockQuote(None) => {
+                self.ensure_newline();
+                self.write("<blockquote");
+                self.write_source_line(range);
+                self.write(">\n");
+            }
+            // Alerts are written as GitHub writes them, titled and with an octicon
+            Tag::BlockQuote(Some(kind)) => {
+                let (name, title, icon) = alert(kind);
+                self.ensure_newline();
+                let _ = write!(
+                    self.out,
+                    "<div class=\"markdown-alert markdown-alert-{name}\""
+                );
+                self.write_source_line(range);
+                let _ = write!(
+                    self.out,
+                    ">\n<p class=\"markdown-alert-title\"><svg class=\"octicon\" viewBox=\"0 0 16 16\" \
+                     width=\"16\" height=\"16\" aria-hidden=\"true\"><path d=\"{icon}\"></path></svg>\
+                     {title}</p>\n"
+                );
+            }
+            Tag::CodeBlock(kind) => {
+                self.ensure_newline();
+                let lang = match &kind {
+                    CodeBlockKind::Fenced(info) => info.split(' ').next().unwrap_or_default(),
+                    CodeBlockKind::Indented => "",
+                };
+                let drawn_class = match Kind::from_lang(lang) {
+                    Some(kind) => Some(kind.class()),
+                    None if lang == "chart" => Some("penview-chart"),
+                    None => None,
+                };
+                if let Some(class) = drawn_class {
+                    if lang == "mermaid" {
+                        self.in_mermaid_block = true;
+                    } else {
+                        self.in_drawn_block = true;
+                    }
+                    self.write("<pre class=\"");
+                    self.write(class);
+                    self.write("\"");
+                    self.write_source_line(range);
+                    self.write(">");
+                } else if lang.is_empty() {
+                    self.write("<pre");
+                    self.write_source_line(range);
+                    self.write("><code>");
+                } else {
+                    self.write("<pre");
+                    self.write_source_line(range);
+                    self.write("><code class=\"language-");
+                    self.escape(lang);
+                    self.write("\">");
+                }
+            }
+            Tag::List(start) => {
+                self.ensure_newline();
+                match start {
+                    Some(1) => self.write("<ol"),
+                    Some(start) => write!(self, "<ol start=\"{start}\""),
+                    None => self.write("<ul"),
+                }
+                self.write_source_line(range);
+                self.write(">\n");
+            }
+            Tag::Item => {
+                self.ensure_newline();
+                self.write("<li");
+                self.write_source_line(range);
+                self.write(">");
+            }
+            Tag::DefinitionList => {
+                self.ensure_newline();
+                self.write("<dl");
+                self.write_source_line(range);
+                self.write(">\n");
+            }
+            Tag::DefinitionListTitle => {
+                self.ensure_newline();
+                self.write("<dt");
+                self.write_source_line(range);
+                self.write(">");
+            }
+            Tag::DefinitionListDefinition => {
+                self.ensure_newline();
+                self.write("<dd");
+                self.write_source_line(range);
+                self.write(">");
+            }
+            Tag::Subscript => self.write("<sub>"),
+            Tag::Superscript => self.write("<sup>"),
+            Tag::Emphasis => self.write("<em>"),
+            Tag::Strong => self.write("<strong>"),
+            Tag::Strikethrough => self.write("<del>"),
+            Tag::Link {
+                link_type,
+                dest_url,
+                title,
+                ..
+            } => {
+                self.write("<a");
+                self.write_source_line(range);
+                let email = link_type == LinkType::Email;
+                let address = if email {
+                    Some(&*dest_url)
+                } else {
+                    dest_url
+                        .get(..7)
+                        .filter(|scheme| scheme.eq_ignore_ascii_case("mailto:"))
+                        .map(|_| &dest_url[7..])
+                };
+                if self.links.allows(if email { "mailto:" } else { &dest_url }) {
+                    if let (Some(obfuscate), Some(address)) = (self.links.emails, address) {
+                        self.write(&obfuscate.link_attributes(address));
+                    } else {
+                        self.write(" href=\"");
+                        if email {
+                            self.write("mailto:");
+                        }
+                        self.escape_href(&dest_url);
+                        self.write("\"");
+                    }
+                }
+                if !title.is_empty() {
+                    self.write(" title=\"");
+                    self.escape(&title);
+                    self.write("\"");
+                }
+                let links = self.links;
+                for (name, value) in links.attributes() {
+                    write!(self, " {name}=\"");
+                    self.escape(value);
+                    self.write("\"");
+                }
+                self.write(">");
+            }
+            // Images are written by `run` since they consume their alt text
+            Tag::Image { .. } => {}
+            Tag::FootnoteDefinition(name) => {
+                self.ensure_newline();
+                self.write("<div class=\"footnote-definition\" id=\"");
+                self.escape(&name);
+                self.write("\"");
+                self.write_source_line(range);
+                self.write("><sup class=\"footnote-definition-label\">");
+                let len = self.numbers.len() + 1;
+                let number = *self.numbers.entry(name).or_insert(len);
+                write!(self, "{number}");
+                self.write("</sup>");
+            }
+            Tag::MetadataBlock(_) => self.in_non_writing_block = true,
+        }
+    }
+
+    fn write_end_tag(&mut self, tag: TagEnd) {
+        match tag {
+            TagEnd::HtmlBlock => {}
+            TagEnd::Paragraph => self.write("</p>\n"),
+            TagEnd::Heading(level) => writeln!(self, "</{level}>"),
+            TagEnd::Table => self.write("</tbody></table>\n"),
+            TagEnd::TableHead => {
+                self.write("</tr></thead><tbody>\n");
+                self.table_state = TableState::Body;
+            }
+            TagEnd::TableRow => self.write("</tr>\n"),
+            TagEnd::TableCell => {
+                match self.table_state {
+                    TableState::Head => self.write("</th>"),
+                    TableState::Body => self.write("</td>"),
+                }
+                self.table_cell_index += 1;
+            }
+            TagEnd::BlockQuote(None) => self.write("</blockquote>\n"),
+            TagEnd::BlockQuote(Some(_)) => self.write("</div>\n"),
+            TagEnd::CodeBlock => {
+                if self.in_mermaid_block || self.in_drawn_block {
+                    self.in_mermaid_block = false;
+                    self.in_drawn_block = false;
+                    self.write("</pre>\n");
+                } else {
+                    self.write("</code></pre>\n");
+                }
+            }
+            TagEnd::List(true) => self.write("</ol>\n"),
+            TagEnd::List(false) => self.write("</ul>\n"),
+            TagEnd::Item => self.write("</li>\n"),
+            TagEnd::DefinitionList => self.write("</dl>\n"),
+            TagEnd::DefinitionListTitle => self.write("</dt>\n"),
+            TagEnd::DefinitionListDefinition => self.write("</dd>\n"),
+            TagEnd::Emphasis => self.write("</em>"),
+            TagEnd::Superscript => self.write("</sup>"),
+            TagEnd::Subscript => self.write("</sub>"),
+            TagEnd::Strong => self.write("</strong>"),
+            TagEnd::Strikethrough => self.write("</del>"),
+            TagEnd::Link => self.write("</a>"),
+            TagEnd::Image => {}
+            TagEnd::FootnoteDefinition => self.write("</div>\n"),
+            TagEnd::MetadataBlock(_) => self.in_non_writing_block = false,
+        }
+    }
+
+    /// Writes the plain text of the current element (used for image alt text), consuming its
+    /// end tag.
+    fn raw_text(&mut self, iter: &mut impl Iterator<Item = (Event<'a>, Range<usize>)>) {
+        let mut nest = 0;
+        for (event, _) in iter.by_ref() {
+            match event {
+                Event::Start(_) => nest += 1,
+                Event::End(_) => {
+                    if nest == 0 {
+                        break;
+                    }
+                    nest -= 1;
+                }
+                Event::Html(_) => {}
+                Event::InlineHtml(text) | Event::Code(text) | Event::Text(text) => {
+                    self.escape(&text);
+                    self.end_newline = text.ends_with('\n');
+                }
+                Event::InlineMath(text) => {
+                    self.write("$");
+                    self.escape(&text);
+                    self.write("$");
+                }
+                Event::DisplayMath(text) => {
+                    self.write("$$");
+                    self.escape(&text);
+                    self.write("$$");
+                }
+                Event::SoftBreak | Event::HardBreak | Event::Rule => self.write(" "),
+                Event::FootnoteReference(name) => {
+                    let len = self.numbers.len() + 1;
+                    let number = *self.numbers.entry(name).or_insert(len);
+                    write!(self, "[{number}]");
+                }
+                Event::TaskListMarker(true) => self.write("[x]"),
+                Event::TaskListMarker(false) => self.write("[ ]"),
+            }
+        }
+    }
+}
+
+/// The class suffix, title and octicon path of a GFM alert (`> [!NOTE]`).
+fn alert(kind: BlockQuoteKind) -> (&'static str, &'static str, &'static str) {
+    match kind {
+        BlockQuoteKind::Note => (
+            "note",
+            "Note",
+            "M0 8a8 8 0 1 1 16 0A8 8 0 0 1 0 8Zm8-6.5a6.5 6.5 0 1 0 0 13 6.5 6.5 0 0 0 0-13ZM6.5 \
+             7.75A.75.75 0 0 1 7.25 7h1a.75.75 0 0 1 .75.75v2.75h.25a.75.75 0 0 1 0 1.5h-2a.75.75 \
+             0 0 1 0-1.5h.25v-2h-.25a.75.75 0 0 1-.75-.75ZM8 6a1 1 0 1 1 0-2 1 1 0 0 1 0 2Z",
+        ),
+        BlockQuoteKind::Tip => (
+            "tip",
+            "Tip",
+            "M8 1.5c-2.363 0-4 1.69-4 3.75 0 .984.424 1.625.984 \
+             2.304l.214.253c.223.264.47.556.673.848.284.411.537.896.621 1.49a.75.75 0 0 \
+             1-1.484.211c-.04-.282-.163-.547-.37-.847a8.456 8.456 0 0 \
+             0-.542-.68c-.084-.1-.173-.205-.268-.32C3.201 7.75 2.5 6.766 2.5 5.25 2.5 2.31 4.863 \
+             0 8 0s5.5 2.31 5.5 5.25c0 1.516-.701 2.5-1.328 \
+             3.259-.095.115-.184.22-.268.319-.207.245-.383.453-.541.681-.208.3-.33.565-.37.847a.751.751 \
+             0 0 \
+             1-1.485-.212c.084-.593.337-1.078.621-1.489.203-.292.45-.584.673-.848.075-.088.147-.173.213-.253.561-.679.985-1.32.985-2.304 \
+             0-2.06-1.637-3.75-4-3.75ZM5.75 12h4.5a.75.75 0 0 1 0 1.5h-4.5a.75.75 0 0 1 0-1.5ZM6 \
+             15.25a.75.75 0 0 1 .75-.75h2.5a.75.75 0 0 1 0 1.5h-2.5a.75.75 0 0 1-.75-.75Z",
+        ),
+        BlockQuoteKind::Important => (
+            "important",
+            "Important",
+            "M0 1.75C0 .784.784 0 1.75 0h12.5C15.216 0 16 .784 16 1.75v9.5A1.75 1.75 0 0 1 14.25 \
+             13H8.06l-2.573 2.573A1.458 1.458 0 0 1 3 14.543V13H1.75A1.75 1.75 0 0 1 0 \
+             11.25Zm1.75-.25a.25.25 0 0 0-.25.25v9.5c0 .138.112.25.25.25h2a.75.75 0 0 1 \
+             .75.75v2.19l2.72-2.72a.749.749 0 0 1 .53-.22h6.5a.25.25 0 0 0 .25-.25v-9.5a.25.25 0 \
+             0 0-.25-.25Zm7 2.25v2.5a.75.75 0 0 1-1.5 0v-2.5a.75.75 0 0 1 1.5 0ZM9 9a1 1 0 1 1-2 \
+             0 1 1 0 0 1 2 0Z",
+        ),
+        BlockQuoteKind::Warning => (
+            "warning",
+            "Warning",
+            "M6.457 1.047c.659-1.234 2.427-1.234 3.086 0l6.082 11.378A1.75 1.75 0 0 1 14.082 \
+             15H1.918a1.75 1.75 0 0 1-1.543-2.575Zm1.763.707a.25.25 0 0 0-.44 0L1.698 \
+             13.132a.25.25 0 0 0 .22.368h12.164a.25.25 0 0 0 .22-.368Zm.53 3.996v2.5a.75.75 0 0 \
+             1-1.5 0v-2.5a.75.75 0 0 1 1.5 0ZM9 11a1 1 0 1 1-2 0 1 1 0 0 1 2 0Z",
+        ),
+        BlockQuoteKind::Caution => (
+            "caution",
+            "Caution",
+            "M4.47.22A.749.749 0 0 1 5 0h6c.199 0 .389.079.53.22l4.25 \
+             4.25c.141.14.22.331.22.53v6a.749.749 0 0 1-.22.53l-4.25 4.25A.749.749 0 0 1 11 \
+             16H5a.749.749 0 0 1-.53-.22L.22 11.53A.749.749 0 0 1 0 \
+             11V5c0-.199.079-.389.22-.53Zm.84 1.28L1.5 5.31v5.38l3.81 \
+             3.81h5.38l3.81-3.81V5.31L10.69 1.5ZM8 4a.75.75 0 0 1 .75.75v3.5a.75.75 0 0 1-1.5 \
+             0v-3.5A.75.75 0 0 1 8 4Zm0 8a1 1 0 1 1 0-2 1 1 0 0 1 0 2Z",
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pulldown_cmark::{Options, Parser};
+
+    fn render(source: &str) -> String {
+        let mut out = String::new();
+        push_html(
+            &mut out,
+            source,
+            &LinkPolicy::default(),
+            Parser::new_ext(source, Options::all()).into_offset_iter(),
+        );
+        out
+    }
+
+    #[test]
+    fn test_line_index() {
+        let lines = LineIndex::new("a\nbc\n\nd");
+        assert_eq!(lines.line(0), 1);
+        assert_eq!(lines.line(2), 2);
+        assert_eq!(lines.line(3), 2);
+        assert_eq!(lines.line(5), 3);
+        assert_eq!(lines.line(6), 4);
+    }
+
+    #[test]
+    fn test_block_source_lines() {
+        let html = render("# Title\n\nSome text\n\n- one\n- two\n");
+        assert!(html.contains("<h1 data-source-line=\"1\">Title</h1>"));
+        assert!(html.contains("<p data-source-line=\"3\">Some text</p>"));
+        assert!(html.contains("<ul data-source-line=\"5\">"));
+        assert!(html.contains("<li data-source-line=\"6\">two</li>"));
+    }
+
+    #[test]
+    fn test_inline_source_lines() {
+        let html = render("Intro\n[link](a.md) `code`\n![alt](b.png)\n");
+        assert!(html.contains("<a data-source-line=\"2\" href=\"a.md\">link</a>"));
+        assert!(html.contains("<code data-source-line=\"2\">code</code>"));
+        assert!(html.contains("<img data-source-line=\"3\" src=\"b.png\" alt=\"alt\" />"));
+    }
+
+    #[test]
+    fn test_table_source_lines() {
+        let html = render("| a | b |\n|:--|---|\n| 1 | 2 |\n");
+        assert!(html.contains("<thead><tr data-source-line=\"1\"><th data-source-line=\"1\" style=\"text-align: left\">a</th>"));
+        assert!(html.contains("<tr data-source-line=\"3\"><td data-source-line=\"3\" style=\"text-align: left\">1</td><td data-source-line=\"3\">2</td>"));
+    }
+
+    #[test]
+    fn test_alert() {
+        let html = render("> [!WARNING]\n> Careful\n\n> Quote\n");
+        assert!(html.starts_with(
+            "<div class=\"markdown-alert markdown-alert-warning\" data-source-line=\"1\">\n\
+             <p class=\"markdown-alert-title\"><svg class=\"octicon\""
+        ));
+        assert!(
+            html.contains("</svg>Warning</p>\n<p data-source-line=\"2\">Careful</p>\n</div>\n")
+        );
+        assert!(html.contains("<blockquote data-source-line=\"4\">"));
+    }
+
+    #[test]
+    fn test_mermaid_block_is_raw() {
+        let html = render("```mermaid\ngraph TD\nA-->B\n```\n");
+        assert_eq!(
+            html,
+            "<pre class=\"mermaid\" data-source-line=\"1\">graph TD\nA-->B\n</pre>\n"
+        );
+    }
+
+    #[test]
+    fn test_chart_block() {
+        let html = render(
+            "```chart
+type: bar
+---
+day,runs
+Mon,<3
+```
+",
+        );
+        assert_eq!(
+            html,
+            "<pre class=\"penview-chart\" data-source-line=\"1\">type: bar\n---\nday,runs\nMon,&lt;3\n</pre>\n"
+        );
+    }
+
+    #[test]
+    fn test_link_policy() {
+        let source = "[a](https://a.org \"A\") [b](javascript:alert(1)) <me@a.org>";
+        let links = LinkPolicy {
+            rel: Some("noopener noreferrer".to_string()),
+            target: Some("_blank".to_string()),
+            referrer_policy: None,
+            schemes: vec!["https".to_string()],
+            emails: None,
+        };
+        let mut html = String::new();
+        push_html(
+            &mut html,
+            source,
+            &links,
+            Parser::new_ext(source, Options::all()).into_offset_iter(),
+        );
+        assert_eq!(
+            html,
+            "<p data-source-line=\"1\"><a data-source-line=\"1\" href=\"https://a.org\" title=\"A\" rel=\"noopener noreferrer\" target=\"_blank\">a</a> \
+             <a data-source-line=\"1\" rel=\"noopener noreferrer\" target=\"_blank\">b</a> \
+             <a data-source-line=\"1\" rel=\"noopener noreferrer\" target=\"_blank\">me@a.org</a></p>\n"
+        );
+    }
+}
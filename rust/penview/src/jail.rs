@@ -0,0 +1,46 @@
+//! Confinement of file access to a root directory (`serve --root`), so an exposed server can't be
+//! used to read arbitrary files the process has access to.
+
+use std::path::{Component, Path};
+
+use resolve_path::PathResolveExt;
+
+/// Whether `path` lies inside `root` once symlinks and `..` are resolved. Files that don't exist
+/// yet are checked through their closest existing ancestor. `root` must be canonical.
+pub fn contains(root: &Path, path: &Path) -> bool {
+    let path = path.resolve();
+    let Some((ancestor, canonical)) = path
+        .ancestors()
+        .find_map(|ancestor| Some((ancestor, ancestor.canonicalize().ok()?)))
+    else {
+        return false;
+    };
+    let rest = path.strip_prefix(ancestor).unwrap_or(Path::new(""));
+    canonical.starts_with(root)
+        && rest
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contains() {
+        let root =
+            std::env::temp_dir().join(format!("penview-jail-test-{}-contains", std::process::id()));
+        std::fs::create_dir_all(root.join("notes")).unwrap();
+        std::fs::write(root.join("notes/a.md"), "").unwrap();
+        let root = root.canonicalize().unwrap();
+
+        assert!(contains(&root, &root.join("notes/a.md")));
+        assert!(contains(&root, &root.join("notes")));
+        assert!(contains(&root, &root.join("notes/new/b.md")));
+        assert!(!contains(&root, &root.join("notes/../../etc/passwd")));
+        assert!(!contains(&root, &root.join("missing/../../x.md")));
+        assert!(!contains(&root, Path::new("/etc/passwd")));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
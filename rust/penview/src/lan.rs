@@ -0,0 +1,146 @@
+//! Reaching the preview from other machines on the local network: finding this machine's LAN
+//! address, and announcing the server over mDNS (as an `_http._tcp` service), so tablets and
+//! laptops can discover it without typing IPs.
+
+use std::net::{IpAddr, Ipv4Addr};
+
+use mdns_sd::{ServiceDaemon, ServiceInfo};
+use tracing::info;
+
+/// Announced as a plain web server, so generic Bonjour browsers and tools list the preview.
+const SERVICE_TYPE: &str = "_http._tcp.local.";
+
+/// The address other machines reach this one at: that of the interface routing outside traffic.
+/// Connecting a UDP socket sends nothing; it only picks the route.
+pub fn lan_ip() -> Option<Ipv4Addr> {
+    let socket = std::net::UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).ok()?;
+    // TEST-NET-2, only used to select the default route
+    socket.connect((Ipv4Addr::new(198, 51, 100, 1), 80)).ok()?;
+    match socket.local_addr().ok()?.ip() {
+        std::net::IpAddr::V4(ip) if !ip.is_loopback() && !ip.is_unspecified() => Some(ip),
+        _ => None,
+    }
+}
+
+/// A preview server as announced over mDNS.
+#[derive(Debug, Clone)]
+pub struct Service {
+    /// Human-readable instance name shown by browsers, e.g. the document title.
+    pub name: String,
+    /// Host label, announced as `<host>.local`.
+    pub host: String,
+    pub ip: Ipv4Addr,
+    pub port: u16,
+    /// URL path to open, announced as the `path` TXT key.
+    pub path: String,
+}
+
+impl Service {
+    /// Names fit in a single DNS label, so longer titles are cut at a character boundary.
+    fn instance(&self) -> &str {
+        let mut end = self.name.len().min(63);
+        while !self.name.is_char_boundary(end) {
+            end -= 1;
+        }
+        &self.name[..end]
+    }
+
+    fn info(&self) -> mdns_sd::Result<ServiceInfo> {
+        ServiceInfo::new(
+            SERVICE_TYPE,
+            self.instance(),
+            &format!("{}.local.", self.host),
+            IpAddr::V4(self.ip),
+            self.port,
+            &[("path", self.path.as_str())][..],
+        )
+    }
+}
+
+/// A service being announced. Call [`Announcement::withdraw`] on shutdown, so other machines
+/// drop it from their caches right away.
+pub struct Announcement {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+/// Announces `service` on the network, and answers queries for it until withdrawn.
+pub fn announce(service: Service) -> mdns_sd::Result<Announcement> {
+    let info = service.info()?;
+    let fullname = info.get_fullname().to_string();
+    let daemon = ServiceDaemon::new()?;
+    daemon.register(info)?;
+
+    info!(
+        "Announcing \"{}\" over mDNS at {}.local",
+        service.instance(),
+        service.host
+    );
+    Ok(Announcement { daemon, fullname })
+}
+
+impl Announcement {
+    pub async fn withdraw(self) {
+        // Waits for the goodbye packets to go out before stopping the daemon
+        if let Ok(status) = self.daemon.unregister(&self.fullname) {
+            let _ = status.recv_async().await;
+        }
+        let _ = self.daemon.shutdown();
+    }
+}
+
+/// This machine's host name as a DNS label, for naming the announced host.
+pub fn host_label() -> String {
+    let hostname = std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .unwrap_or_default();
+    let label: String = hostname
+        .trim()
+        .split('.')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect();
+    // Kept apart from the machine's own `<hostname>.local`, which the system may announce
+    if label.is_empty() {
+        "penview".to_string()
+    } else {
+        format!("{label}-penview")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service() -> Service {
+        Service {
+            name: "Meeting notes".to_string(),
+            host: "laptop-penview".to_string(),
+            ip: Ipv4Addr::new(192, 168, 1, 20),
+            port: 8080,
+            path: "/?path=notes.md".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_info() {
+        let info = service().info().unwrap();
+        assert_eq!(info.get_type(), "_http._tcp.local.");
+        assert_eq!(info.get_fullname(), "Meeting notes._http._tcp.local.");
+        assert_eq!(info.get_hostname(), "laptop-penview.local.");
+        assert_eq!(info.get_port(), 8080);
+        assert_eq!(info.get_property_val_str("path"), Some("/?path=notes.md"));
+    }
+
+    #[test]
+    fn test_instance() {
+        let service = Service {
+            name: "é".repeat(40),
+            ..service()
+        };
+        assert_eq!(service.instance(), "é".repeat(31));
+    }
+}
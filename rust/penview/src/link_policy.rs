@@ -0,0 +1,85 @@
+//! Attributes added to the links of rendered pages and the URL schemes they may use, so the
+//! page of an untrusted document can be kept from opening `javascript:` URLs or leaking the
+//! referrer.
+
+use crate::obfuscate::ObfuscateEmails;
+
+/// What the `<a>` tags written for markdown links look like. The default adds nothing and
+/// allows any URL.
+#[derive(Debug, Clone, Default)]
+pub struct LinkPolicy {
+    /// `rel` attribute, e.g. `noopener noreferrer`.
+    pub rel: Option<String>,
+    /// `target` attribute, e.g. `_blank`.
+    pub target: Option<String>,
+    /// `referrerpolicy` attribute, e.g. `no-referrer`.
+    pub referrer_policy: Option<String>,
+    /// URL schemes links may use, e.g. `https` and `mailto`. Relative URLs are always
+    /// allowed; if empty, so is every scheme.
+    pub schemes: Vec<String>,
+    /// How `mailto:` links hide their address, if they do.
+    pub emails: Option<ObfuscateEmails>,
+}
+
+impl LinkPolicy {
+    /// Whether a link to `url` keeps its `href`.
+    pub fn allows(&self, url: &str) -> bool {
+        self.schemes.is_empty()
+            || scheme(url).is_none_or(|scheme| {
+                self.schemes
+                    .iter()
+                    .any(|allowed| allowed.eq_ignore_ascii_case(&scheme))
+            })
+    }
+
+    /// The attributes to add to every link, by name.
+    pub fn attributes(&self) -> impl Iterator<Item = (&'static str, &str)> {
+        [
+            ("rel", &self.rel),
+            ("target", &self.target),
+            ("referrerpolicy", &self.referrer_policy),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name, value.as_deref()?)))
+    }
+}
+
+/// The scheme of `url`, or `None` for a relative URL. Like browsers, ignores leading
+/// whitespace and control characters and tabs or newlines anywhere, so that `java\tscript:`
+/// counts as `javascript`.
+fn scheme(url: &str) -> Option<String> {
+    let url: String = url
+        .trim_start_matches(|c: char| c <= ' ')
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\n' | '\r'))
+        .collect();
+    let (scheme, _) = url.split_once(':')?;
+    let mut chars = scheme.chars();
+    (chars.next()?.is_ascii_alphabetic()
+        && chars.all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')))
+    .then(|| scheme.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allows() {
+        let policy = LinkPolicy {
+            schemes: vec!["https".to_string(), "MAILTO".to_string()],
+            ..LinkPolicy::default()
+        };
+        assert!(policy.allows("https://example.com"));
+        assert!(policy.allows("HTTPS://example.com"));
+        assert!(policy.allows("mailto:me@example.com"));
+        assert!(policy.allows("notes/a.md"));
+        assert!(policy.allows("#setup"));
+        assert!(policy.allows("a.md?x=1:2"));
+        assert!(!policy.allows("http://example.com"));
+        assert!(!policy.allows("javascript:alert(1)"));
+        assert!(!policy.allows(" java\tscript:alert(1)"));
+
+        assert!(LinkPolicy::default().allows("javascript:alert(1)"));
+    }
+}
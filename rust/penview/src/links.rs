@@ -0,0 +1,360 @@
+//! An index of the links between the workspace's documents, from markdown links and
+//! `[[wiki-links]]`.
+
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+use pulldown_cmark::{Event, Options, Tag};
+use serde::Serialize;
+use url::Url;
+
+use crate::{
+    export::markdown_files,
+    html::LineIndex,
+    render::{document_title, is_markdown},
+};
+
+/// Longest context shown for a link, in characters.
+const MAX_CONTEXT: usize = 200;
+
+/// A link from one document to another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Link {
+    pub source: PathBuf,
+    pub target: PathBuf,
+    /// 1-based line of the link in the source document.
+    pub line: usize,
+    /// The source line containing the link.
+    pub context: String,
+}
+
+/// Returns the targets of the `[[wiki-links]]` in `text`, with their offsets, dropping any
+/// `|alias` and `#heading`.
+fn wiki_links(text: &str) -> Vec<(usize, &str)> {
+    let mut links = Vec::new();
+    let mut offset = 0;
+
+    while let Some(start) = text[offset..].find("[[") {
+        let start = offset + start;
+        let Some(len) = text[start + 2..].find("]]") else {
+            break;
+        };
+        let inner = &text[start + 2..start + 2 + len];
+        offset = start + 2 + len + 2;
+
+        if inner.contains('\n') {
+            continue;
+        }
+        let target = inner.split(['|', '#']).next().unwrap_or_default().trim();
+        if !target.is_empty() {
+            links.push((start, target));
+        }
+    }
+
+    links
+}
+
+/// Whether a link target (without extension) looks like a Zettelkasten note ID: at least eight
+/// digits, such as a `202401121230` timestamp.
+fn is_zettel_id(target: &str) -> bool {
+    target.len() >= 8 && target.bytes().all(|b| b.is_ascii_digit())
+}
+
+/// Returns the Zettelkasten ID a link target refers to, if it is one (`202401121230` or
+/// `202401121230.md`).
+pub fn zettel_id(target: &str) -> Option<&str> {
+    let id = target
+        .strip_suffix(".md")
+        .or_else(|| target.strip_suffix(".markdown"))
+        .unwrap_or(target);
+    is_zettel_id(id).then_some(id)
+}
+
+/// Finds the note whose file name starts with the Zettelkasten `id`, followed by the extension or
+/// a separator, e.g. `202401121230 Meeting notes.md` or `202401121230-meeting.md`.
+pub fn find_by_id<'a>(id: &str, files: &'a [PathBuf]) -> Option<&'a PathBuf> {
+    files.iter().find(|file| {
+        file.file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.strip_prefix(id))
+            .is_some_and(|rest| !rest.starts_with(|c: char| c.is_ascii_alphanumeric()))
+    })
+}
+
+/// Returns the path of `path` relative to the directory `dir`, e.g. `../notes/a.md`.
+pub fn relative_to(path: &Path, dir: &Path) -> PathBuf {
+    let common = path
+        .components()
+        .zip(dir.components())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    let mut relative = PathBuf::new();
+    for _ in dir.components().skip(common) {
+        relative.push("..");
+    }
+    for component in path.components().skip(common) {
+        relative.push(component);
+    }
+    relative
+}
+
+/// Resolves a wiki-link target against the workspace's `files`: first as a path relative to the
+/// linking document, then by file name anywhere in the workspace (case-insensitively, with or
+/// without the extension), then as a Zettelkasten ID.
+pub fn resolve_wiki(target: &str, source: &Path, files: &[PathBuf]) -> Option<PathBuf> {
+    let target_path = Path::new(target);
+    let with_extension = if is_markdown(target_path) {
+        target_path.to_path_buf()
+    } else {
+        target_path.with_extension("md")
+    };
+
+    if let Some(relative) = source.parent().map(|dir| dir.join(&with_extension))
+        && files.contains(&relative)
+    {
+        return Some(relative);
+    }
+
+    files
+        .iter()
+        .find(|file| {
+            file.ends_with(&with_extension)
+                || file.to_string_lossy().to_lowercase().ends_with(&format!(
+                    "/{}",
+                    with_extension.to_string_lossy().to_lowercase()
+                ))
+        })
+        .or_else(|| find_by_id(zettel_id(target)?, files))
+        .cloned()
+}
+
+/// Resolves a markdown link to another document, ignoring URLs and fragments. Links to missing
+/// files are looked up as Zettelkasten IDs.
+fn resolve_markdown(dest_url: &str, source: &Path, files: &[PathBuf]) -> Option<PathBuf> {
+    if dest_url.parse::<Url>().is_ok() {
+        return None;
+    }
+    let path = dest_url.split(['#', '?']).next()?;
+    if path.is_empty() {
+        return None;
+    }
+
+    let Ok(target) = source.parent()?.join(path).canonicalize() else {
+        return find_by_id(zettel_id(path)?, files).cloned();
+    };
+    is_markdown(&target).then_some(target)
+}
+
+fn line_at(content: &str, offset: usize) -> String {
+    let start = content[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let end = content[offset..]
+        .find('\n')
+        .map_or(content.len(), |i| offset + i);
+    content[start..end]
+        .trim()
+        .chars()
+        .take(MAX_CONTEXT)
+        .collect()
+}
+
+/// Finds the links from `source` to other documents among `files`.
+pub fn document_links(content: &str, source: &Path, files: &[PathBuf]) -> Vec<Link> {
+    let lines = LineIndex::new(content);
+    let link = |target: PathBuf, offset: usize| Link {
+        source: source.to_path_buf(),
+        target,
+        line: lines.line(offset),
+        context: line_at(content, offset),
+    };
+
+    let mut links = Vec::new();
+    let mut code: Vec<Range<usize>> = Vec::new();
+    let parser = pulldown_cmark::Parser::new_ext(content, Options::all());
+    for (event, range) in parser.into_offset_iter() {
+        match event {
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                if let Some(target) = resolve_markdown(&dest_url, source, files) {
+                    links.push(link(target, range.start));
+                }
+            }
+            Event::Start(Tag::CodeBlock(_)) | Event::Code(_) => code.push(range),
+            _ => {}
+        }
+    }
+
+    for (offset, target) in wiki_links(content) {
+        if code.iter().any(|range| range.contains(&offset)) {
+            continue;
+        }
+        if let Some(target) = resolve_wiki(target, source, files) {
+            links.push(link(target, offset));
+        }
+    }
+
+    links.sort_by_key(|link| link.line);
+    links
+}
+
+/// Scans every markdown file under `workspace` for links to other documents.
+pub async fn link_index(workspace: &Path) -> anyhow::Result<Vec<Link>> {
+    let files = markdown_files(workspace).await?;
+
+    let mut links = Vec::new();
+    for file in &files {
+        let Ok(content) = tokio::fs::read_to_string(file).await else {
+            continue;
+        };
+        links.extend(document_links(&content, file, &files));
+    }
+
+    Ok(links)
+}
+
+/// A document in the [`Graph`].
+#[derive(Debug, Serialize)]
+pub struct GraphNode {
+    pub path: PathBuf,
+    pub title: String,
+}
+
+/// An edge between two documents in the [`Graph`], by index into its nodes.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct GraphLink {
+    pub source: usize,
+    pub target: usize,
+}
+
+/// Every document in the workspace and the links between them. Each pair of documents is
+/// linked at most once per direction.
+#[derive(Debug, Serialize)]
+pub struct Graph {
+    pub nodes: Vec<GraphNode>,
+    pub links: Vec<GraphLink>,
+}
+
+/// Builds the link graph of the markdown files under `workspace`.
+pub async fn graph(workspace: &Path) -> anyhow::Result<Graph> {
+    let files = markdown_files(workspace).await?;
+
+    let mut nodes = Vec::new();
+    let mut links = Vec::new();
+    for file in &files {
+        let content = tokio::fs::read_to_string(file).await.unwrap_or_default();
+        nodes.push(GraphNode {
+            path: file.clone(),
+            title: document_title(&content, file),
+        });
+        links.extend(document_links(&content, file, &files));
+    }
+
+    let index = |path: &Path| files.iter().position(|file| file == path);
+    let mut edges = Vec::new();
+    for link in &links {
+        let (Some(source), Some(target)) = (index(&link.source), index(&link.target)) else {
+            continue;
+        };
+        let edge = GraphLink { source, target };
+        if source != target && !edges.contains(&edge) {
+            edges.push(edge);
+        }
+    }
+
+    Ok(Graph {
+        nodes,
+        links: edges,
+    })
+}
+
+/// The links pointing at `target` from other documents.
+pub fn backlinks<'a>(links: &'a [Link], target: &'a Path) -> impl Iterator<Item = &'a Link> {
+    links
+        .iter()
+        .filter(move |link| link.target == target && link.source != target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wiki_links() {
+        assert_eq!(
+            wiki_links("See [[Other Note|the note]] and [[dir/page#Intro]], not [[]]."),
+            vec![(4, "Other Note"), (32, "dir/page")]
+        );
+    }
+
+    #[test]
+    fn test_resolve_wiki() {
+        let files = vec![
+            PathBuf::from("/notes/Other Note.md"),
+            PathBuf::from("/notes/dir/page.md"),
+            PathBuf::from("/notes/a/index.md"),
+            PathBuf::from("/notes/b/index.md"),
+        ];
+        let source = Path::new("/notes/b/today.md");
+
+        assert_eq!(
+            resolve_wiki("other note", source, &files),
+            Some(PathBuf::from("/notes/Other Note.md"))
+        );
+        assert_eq!(
+            resolve_wiki("dir/page", source, &files),
+            Some(PathBuf::from("/notes/dir/page.md"))
+        );
+        assert_eq!(
+            resolve_wiki("index", source, &files),
+            Some(PathBuf::from("/notes/b/index.md"))
+        );
+        assert_eq!(resolve_wiki("missing", source, &files), None);
+    }
+
+    #[test]
+    fn test_zettel_ids() {
+        let files = vec![
+            PathBuf::from("/zk/2024011212301 Other.md"),
+            PathBuf::from("/zk/202401121230 Meeting notes.md"),
+        ];
+        let source = Path::new("/zk/index.md");
+
+        assert_eq!(zettel_id("202401121230.md"), Some("202401121230"));
+        assert_eq!(zettel_id("2024-01-12"), None);
+        assert_eq!(
+            resolve_wiki("202401121230", source, &files),
+            Some(files[1].clone())
+        );
+        assert_eq!(
+            resolve_markdown("202401121230#agenda", source, &files),
+            Some(files[1].clone())
+        );
+        assert_eq!(find_by_id("20240112", &files), None);
+    }
+
+    #[test]
+    fn test_relative_to() {
+        assert_eq!(
+            relative_to(Path::new("/zk/a/note.md"), Path::new("/zk/b/c")),
+            PathBuf::from("../../a/note.md")
+        );
+    }
+
+    #[test]
+    fn test_document_links_skip_code() {
+        let files = vec![PathBuf::from("/notes/target.md")];
+        let content = "Intro\n\nLinks to [[target]].\n\n`[[target]]`\n\n```\n[[target]]\n```\n";
+        let links = document_links(content, Path::new("/notes/source.md"), &files);
+
+        assert_eq!(
+            links,
+            vec![Link {
+                source: PathBuf::from("/notes/source.md"),
+                target: PathBuf::from("/notes/target.md"),
+                line: 3,
+                context: "Links to [[target]].".to_string(),
+            }]
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! markdownlint-style checks over the parsed event stream.
+//!
+//! Rule names and numbers follow markdownlint so warnings are familiar.
+
+use std::{collections::HashMap, ops::Range};
+
+use pulldown_cmark::{Event, HeadingLevel, Options, Parser, Tag, TagEnd};
+
+use crate::{
+    diagnostic::{Diagnostic, Severity},
+    html::LineIndex,
+};
+
+/// Runs every check against `content`, returning warnings sorted by line.
+pub fn lint(content: &str) -> Vec<Diagnostic> {
+    let lines = LineIndex::new(content);
+    let parser = Parser::new_ext(content, Options::all());
+
+    let mut diagnostics = Vec::new();
+    let mut code_blocks: Vec<Range<usize>> = Vec::new();
+    let mut headings: HashMap<String, usize> = HashMap::new();
+    let mut previous_level: Option<HeadingLevel> = None;
+    // Text of the heading currently being parsed, and its line
+    let mut heading: Option<(String, usize)> = None;
+    let mut link_depth = 0;
+
+    for (event, range) in parser.into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => code_blocks.push(range),
+            Event::Start(Tag::Link { .. }) => link_depth += 1,
+            Event::End(TagEnd::Link) => link_depth -= 1,
+            Event::Start(Tag::Heading { level, .. }) => {
+                let line = lines.line(range.start);
+                if let Some(previous) = previous_level
+                    && level as usize > previous as usize + 1
+                {
+                    diagnostics.push(warning(
+                        line,
+                        format!(
+                            "MD001/heading-increment: Heading level jumps from h{} to h{}",
+                            previous as usize, level as usize
+                        ),
+                    ));
+                }
+                previous_level = Some(level);
+                heading = Some((String::new(), line));
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some((text, line)) = heading.take() {
+                    let text = text.trim().to_string();
+                    match headings.get(&text) {
+                        Some(first) => diagnostics.push(warning(
+                            line,
+                            format!(
+                                "MD024/no-duplicate-heading: Same heading text as line {first}"
+                            ),
+                        )),
+                        None => {
+                            headings.insert(text, line);
+                        }
+                    }
+                }
+            }
+            Event::Code(text) => {
+                if let Some((heading_text, _)) = heading.as_mut() {
+                    heading_text.push_str(&text);
+                }
+            }
+            Event::Text(text) => {
+                if let Some((heading_text, _)) = heading.as_mut() {
+                    heading_text.push_str(&text);
+                }
+                if link_depth == 0 && (text.contains("http://") || text.contains("https://")) {
+                    diagnostics.push(warning(
+                        lines.line(range.start),
+                        "MD034/no-bare-urls: Bare URL used, wrap it in <> or a link".to_string(),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut offset = 0;
+    for (index, line) in content.split_inclusive('\n').enumerate() {
+        let start = offset;
+        offset += line.len();
+
+        if code_blocks.iter().any(|block| block.contains(&start)) {
+            continue;
+        }
+
+        let line = line.trim_end_matches(['\n', '\r']);
+        let trimmed = line.trim_end();
+        let trailing = &line[trimmed.len()..];
+        // Exactly two spaces is a hard line break
+        if !trailing.is_empty() && !trimmed.is_empty() && trailing != "  " {
+            diagnostics.push(warning(
+                index + 1,
+                format!(
+                    "MD009/no-trailing-spaces: {} trailing whitespace character(s)",
+                    trailing.chars().count()
+                ),
+            ));
+        }
+    }
+
+    diagnostics.sort_by_key(|d| d.line);
+    diagnostics
+}
+
+fn warning(line: usize, message: String) -> Diagnostic {
+    Diagnostic {
+        line,
+        severity: Severity::Warning,
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(content: &str) -> Vec<(usize, String)> {
+        lint(content)
+            .into_iter()
+            .map(|d| (d.line, d.message.split('/').next().unwrap().to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_heading_increment() {
+        assert_eq!(
+            rules("# One\n\n### Three\n"),
+            vec![(3, "MD001".to_string())]
+        );
+        assert!(rules("# One\n\n## Two\n\n# One again\n").is_empty());
+    }
+
+    #[test]
+    fn test_duplicate_heading() {
+        assert_eq!(
+            rules("# Notes\n\n## Setup\n\n## Setup\n"),
+            vec![(5, "MD024".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_bare_url() {
+        assert_eq!(
+            rules("See https://example.com\n\n[ok](https://example.com) <https://example.com>\n"),
+            vec![(1, "MD034".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_trailing_spaces() {
+        assert_eq!(
+            rules("one \ntwo  \nthree\n\n```\ncode   \n```\n"),
+            vec![(1, "MD009".to_string())]
+        );
+    }
+}
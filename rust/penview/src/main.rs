@@ -1,119 +1,1028 @@
+mod assets;
+mod autolink;
+mod block_cache;
+mod check;
+mod daily;
+mod diagnostic;
+mod diagram;
+mod drafts;
+mod emoji;
+mod exec;
+mod export;
+mod footnotes;
+mod frontmatter;
+mod git;
+mod graph_template;
+mod headings;
+mod highlight;
+mod hooks;
+mod html;
+mod jail;
+mod lan;
+mod link_policy;
+mod links;
+mod lint;
+mod mathml;
+mod messages;
+mod obfuscate;
 mod page_template;
+mod pdf;
+mod plot;
+mod project;
 mod render;
 mod routes;
+mod runtime;
+mod sanitize;
+mod screenshot;
+mod search;
+mod session;
+mod shortcode;
+mod sourcemap;
+mod spellcheck;
 mod state;
 mod svg_template;
+mod tags;
+mod tasks;
+mod template;
+mod transform;
+mod tunnel;
+mod vale;
+mod webhook;
+mod workers;
 
-use std::path::PathBuf;
+use std::{
+    io::Write,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    pin::Pin,
+    process::ExitCode,
+    time::Instant,
+};
 
 use clap::Parser;
+use headings::SlugStyle;
 use inquire::Confirm;
-use render::render_doc;
-use routes::construct_router;
+use link_policy::LinkPolicy;
+use obfuscate::ObfuscateEmails;
+use page_template::{Layout, Typography};
+use render::{RenderOptions, render_doc, render_doc_body};
+use routes::{Client, construct_router};
 use tokio::{
     fs::File,
     io::{AsyncWriteExt, BufWriter},
 };
-use tracing::info;
+use tracing::{info, warn};
+use transform::Transformers;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 enum Args {
-    Serve {
-        /// Whether to print logs.
-        /// If true, Penview will exclusively print out the `address:port` of the listening server once it starts.
-        #[arg(short, long, default_value_t = false)]
-        quiet: bool,
-
-        /// Which port to start listening. Defaults to a random, unoccupied port assigned by the
-        /// operating system.
-        #[arg(short, long, default_value_t = 0)]
-        port: u16,
-
-        /// Which address to listen on.
-        #[arg(short, long, default_value_t = ("127.0.0.1").to_string())]
-        address: String,
-
-        /// Specify a file path to open in a browser.
-        #[arg(short, long)]
-        open: Option<PathBuf>,
-
-        /// Specify a browser binary to use when opening (e.g., "firefox", "chromium").
-        /// Only used when --open is also provided.
-        #[arg(short, long)]
-        browser: Option<String>,
-
-        /// Initial theme for the preview page ("light" or "dark").
-        #[arg(short = 't', long, default_value_t = ("light").to_string())]
-        theme: String,
-    },
+    Serve(Box<ServeArgs>),
     Render {
         /// The location of the Markdown file to render.
         in_file: PathBuf,
 
         /// The path the final `HTML` file should be saved.
-        /// Defaults to the same path as the `in_file`, but with the `.md` replaced with `.pdf`.
+        /// Defaults to printing the `HTML` to stdout.
         #[arg(short, long)]
         out_file: Option<PathBuf>,
+
+        #[command(flatten)]
+        render: RenderArgs,
+
+        /// Only output the rendered Markdown, without the surrounding page, styles and scripts.
+        #[arg(long, default_value_t = false)]
+        body_only: bool,
+
+        /// Keep draft documents and `<!-- draft -->` sections, which are stripped by default.
+        #[arg(long, default_value_t = false)]
+        drafts: bool,
+    },
+    /// Render every Markdown file in a directory tree to standalone HTML.
+    Export {
+        /// The directory to search for Markdown files.
+        dir: PathBuf,
+
+        /// The directory to write the `HTML` files to. Existing files are overwritten.
+        #[arg(short, long)]
+        out: PathBuf,
+
+        /// Build a static site: link documents to each other's `.html` files, copy referenced
+        /// local assets, and add an `index.html` listing every document.
+        #[arg(long, default_value_t = false)]
+        site: bool,
+
+        /// Format of the page titles. Supports the placeholders {title} (frontmatter title, first
+        /// heading or file name), {h1} (first heading, frontmatter title or file name),
+        /// {filename}, {relpath} and {path}.
+        #[arg(long, default_value_t = ("{title}").to_string())]
+        title_format: String,
+
+        #[command(flatten)]
+        render: RenderArgs,
+
+        /// Keep draft documents and `<!-- draft -->` sections, which are stripped by default.
+        #[arg(long, default_value_t = false)]
+        drafts: bool,
+
+        /// URL to POST a JSON description of the export to once it completes. May be given
+        /// multiple times.
+        #[arg(long = "webhook", value_name = "URL")]
+        webhooks: Vec<String>,
     },
+    /// Render Markdown files without serving them and report broken local links, missing images
+    /// and render errors. Exits with a non-zero status if there are any, for use in CI.
+    Check {
+        /// The Markdown files, or directories to search for them, to check.
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+
+        /// Root directory that wiki-links and Zettelkasten IDs are resolved against.
+        /// Defaults to the current directory.
+        #[arg(long)]
+        workspace: Option<PathBuf>,
+
+        /// Also check that the documents render as Jinja-style templates.
+        #[arg(long, default_value_t = false)]
+        templating: bool,
+
+        /// Keep draft documents and `<!-- draft -->` sections, which are stripped by default.
+        #[arg(long, default_value_t = false)]
+        drafts: bool,
+    },
+    /// List the running preview servers as JSON, from the runtime files they write at startup.
+    Discover,
+}
+
+#[derive(clap::Args, Debug)]
+struct ServeArgs {
+    /// A Markdown file or directory to open in a browser. The preview reloads whenever the
+    /// file is saved, so no editor integration is needed.
+    #[arg(conflicts_with = "open")]
+    path: Option<PathBuf>,
+
+    /// Whether to print logs.
+    /// If true, Penview will exclusively print out the `address:port` of the listening server once it starts.
+    #[arg(short, long, default_value_t = false)]
+    quiet: bool,
+
+    /// Which port to start listening. Defaults to a random, unoccupied port assigned by the
+    /// operating system.
+    #[arg(short, long, default_value_t = 0)]
+    port: u16,
+
+    /// Which address to listen on.
+    #[arg(short, long, default_value_t = ("127.0.0.1").to_string())]
+    address: String,
+
+    /// Specify a file path to open in a browser.
+    #[arg(short, long)]
+    open: Option<PathBuf>,
+
+    /// Specify a browser binary to use when opening (e.g., "firefox", "chromium").
+    /// Only used when --open is also provided.
+    #[arg(short, long)]
+    browser: Option<String>,
+
+    /// Initial theme for the preview page ("light" or "dark").
+    #[arg(short = 't', long, default_value_t = ("light").to_string())]
+    theme: String,
+
+    /// Show markdownlint-style warnings (bare URLs, skipped heading levels, trailing spaces,
+    /// duplicate headings) as markers in the preview margin.
+    #[arg(long, default_value_t = false)]
+    lint: bool,
+
+    /// Run Vale (the given binary, e.g. "vale") against the previewed content once editing
+    /// pauses, and show its alerts in the preview margin.
+    #[arg(long)]
+    vale: Option<String>,
+
+    /// Headless browser (e.g. "chromium" or "firefox") that takes the screenshots served at
+    /// `/api/export/screenshot` and, if Chromium-based, prints PDFs for `/api/export`.
+    /// Defaults to the first of Chromium, Chrome and Firefox found on the PATH.
+    #[arg(long)]
+    screenshot_browser: Option<String>,
+
+    /// URL to POST a JSON description of preview events to, e.g. to rebuild a site or send a
+    /// notification. May be given multiple times.
+    #[arg(long = "webhook", value_name = "URL")]
+    webhooks: Vec<String>,
+
+    /// Comma-separated events sent to the webhooks. Defaults to all of them.
+    #[arg(long, value_delimiter = ',')]
+    webhook_events: Vec<webhook::Event>,
+
+    /// Underline misspelled words using the given hunspell dictionary (e.g. "en_US").
+    /// Requires `hunspell` on the PATH.
+    #[arg(long)]
+    spellcheck: Option<String>,
+
+    /// Show the last commit author and date of each block in a gutter (via `git blame`).
+    #[arg(long, default_value_t = false)]
+    blame: bool,
+
+    #[command(flatten)]
+    render: RenderArgs,
+
+    /// How to highlight the block under the Neovim cursor ("none", "flash" or "border").
+    #[arg(long, default_value_t = ("none").to_string())]
+    cursor_highlight: String,
+
+    /// What clicking a link to a local file in the preview does: "navigate" the preview to
+    /// it, open it in the connected Neovim ("editor"), or nothing ("disabled").
+    #[arg(long, default_value_t = ("navigate").to_string(), value_parser = ["navigate", "editor", "disabled"])]
+    link_navigation: String,
+
+    /// Let paragraphs, headings, list items and table cells be edited in the preview. Small
+    /// text edits are sent to the connected Neovim and applied to its buffer.
+    #[arg(long, default_value_t = false)]
+    editable: bool,
+
+    /// Scroll the preview so that the block under the Neovim cursor stays vertically
+    /// centered, like typewriter scrolling in the editor.
+    #[arg(long, default_value_t = false)]
+    typewriter: bool,
+
+    /// Mirror scrolling between the browsers previewing the same document, so that e.g. a
+    /// second monitor and a tablet show the same place.
+    #[arg(long, default_value_t = false)]
+    mirror_scroll: bool,
+
+    /// Largest WebSocket message sent to browsers, in bytes. Bigger updates (e.g. of
+    /// documents with many inlined images) are split into chunks the page puts back
+    /// together, so that proxies limiting frame sizes let them through. 0 disables chunking.
+    #[arg(long, default_value_t = 1 << 20)]
+    max_frame_size: usize,
+
+    /// Format of the page title. Supports the placeholders {title} (frontmatter title, first
+    /// heading or file name), {h1} (first heading, frontmatter title or file name),
+    /// {filename}, {relpath} and {path}.
+    #[arg(long, default_value_t = ("{title}").to_string())]
+    title_format: String,
+
+    /// Register a custom shortcode as NAME=TEMPLATE. In the template, {key} is replaced by
+    /// the argument key="value" and {0}, {1}, ... by positional arguments. Can be repeated.
+    #[arg(long = "shortcode", value_parser = parse_shortcode)]
+    shortcodes: Vec<(String, String)>,
+
+    /// Strip `<!-- draft -->` sections from the preview instead of showing them with a
+    /// watermark.
+    #[arg(long, default_value_t = false)]
+    hide_drafts: bool,
+
+    /// Root directory of the documents indexed for the `/tags` page and backlinks, and
+    /// searched for `[[wiki-links]]` and Zettelkasten IDs. Defaults to the current directory.
+    #[arg(long)]
+    workspace: Option<PathBuf>,
+
+    /// File name pattern of daily notes, which get links to the previous and next day.
+    /// YYYY, MM and DD stand for the year, month and day. Pass an empty pattern to disable.
+    #[arg(long, default_value_t = ("YYYY-MM-DD.md").to_string())]
+    daily_note_pattern: String,
+
+    /// Watch the opened directory (or the opened file's directory) and switch the preview to
+    /// whichever markdown file in it was saved last.
+    #[arg(long, default_value_t = false)]
+    follow: bool,
+
+    /// URL prefix to serve every route under (e.g. "/penview"), for running behind a reverse
+    /// proxy that forwards a sub-path without stripping it.
+    #[arg(long, default_value_t = String::new(), value_parser = parse_base_path)]
+    base_path: String,
+
+    /// Listen on a unix socket at this path instead of a TCP port, e.g. as the upstream of a
+    /// reverse proxy. A stale socket left by a previous run is replaced.
+    #[arg(long, conflicts_with_all = ["path", "open", "port", "address", "restore"])]
+    unix_socket: Option<PathBuf>,
+
+    /// Require this token on every request, as a bearer token, a `token` query parameter or
+    /// the cookie set after one. Opened pages get it in their URL.
+    #[arg(long, value_parser = parse_token)]
+    token: Option<String>,
+
+    /// Keep running for other editors to attach to; recorded in the runtime file that
+    /// `penview discover` reads.
+    #[arg(long, default_value_t = false)]
+    shared: bool,
+
+    /// Expose the preview at a public URL through a tunnel ("cloudflared" or "ngrok", which
+    /// must be on the PATH), for sharing with reviewers. The URL is printed once the tunnel
+    /// is up. Requires --token, since anyone with the URL can reach the server.
+    #[arg(long, requires = "token", conflicts_with = "unix_socket")]
+    tunnel: Option<tunnel::Backend>,
+
+    /// Don't announce the preview over mDNS. Servers listening on a LAN address are
+    /// otherwise announced as an `_http._tcp` service named after the opened document.
+    #[arg(long, default_value_t = false)]
+    no_mdns: bool,
+
+    /// Serve the preview to other machines, e.g. from Neovim on a server to a browser on a
+    /// laptop: listen on all interfaces, require a token (generated unless --token is
+    /// given), only serve files inside the workspace, and print the URL to open elsewhere.
+    #[arg(long, default_value_t = false, conflicts_with_all = ["address", "unix_socket"])]
+    remote: bool,
+
+    /// Refuse to read, link to or inline any file outside this directory. In remote mode,
+    /// defaults to the workspace.
+    #[arg(long)]
+    root: Option<PathBuf>,
+
+    /// Requests (including WebSocket connections) each client address may make per second,
+    /// with bursts of five seconds' worth. 0 disables the limit. In remote mode, defaults to
+    /// 20; otherwise off.
+    #[arg(long)]
+    rate_limit: Option<u32>,
+
+    /// How many documents may be parsed or written as HTML at once, so heavy documents
+    /// can't starve other previews. 0 uses the number of CPUs.
+    #[arg(long, default_value_t = 0)]
+    render_workers: usize,
+
+    /// Reopen the documents that were previewed in browsers when a server last ran in the
+    /// workspace. They are also listed at `/api/session`.
+    #[arg(long, default_value_t = false)]
+    restore: bool,
+
+    /// Don't save the previewed documents, e.g. for a server that shouldn't affect the
+    /// workspace's next session.
+    #[arg(long, default_value_t = false, conflicts_with = "restore")]
+    no_session: bool,
+
+    /// Let task checkboxes be clicked in previews of files that aren't open in Neovim, which
+    /// checks or unchecks the task in the file on disk.
+    #[arg(long, default_value_t = false)]
+    toggle_tasks: bool,
+
+    /// Let fenced code blocks in LANG be run from the preview as LANG=COMMAND, e.g.
+    /// `python=python3`. The code is piped into the command, which runs in the document's
+    /// directory, and its output is shown beneath the block. Can be repeated.
+    #[arg(long = "exec", value_parser = parse_exec)]
+    exec: Vec<(String, String)>,
 }
 
+// Flags that change how documents are rendered, shared by `serve`, `render` and `export`. Not
+// a doc comment, which clap would show as the description of each of those commands.
+#[derive(clap::Args, Debug)]
+struct RenderArgs {
+    /// Add a footer with the file's modification time, git branch/commit, and word count.
+    #[arg(long, default_value_t = false)]
+    footer: bool,
+
+    /// Show the frontmatter's title, date and tags in a card above the document.
+    #[arg(long, default_value_t = false)]
+    frontmatter_card: bool,
+
+    /// Show a collapsible table of contents of the document's headings beside it.
+    #[arg(long, default_value_t = false)]
+    toc: bool,
+
+    /// Run the source through a Jinja-style template pass before rendering, with the
+    /// frontmatter fields and environment variables (as `env`) in scope.
+    #[arg(long, default_value_t = false)]
+    templating: bool,
+
+    /// Link bare URLs in prose: "none" only links URLs in angle brackets, "urls" also links
+    /// bare http(s):// URLs like GitHub does, and "extended" links www. addresses and emails
+    /// too.
+    #[arg(long, default_value_t = ("none").to_string(), value_parser = ["none", "urls", "extended"])]
+    autolink: String,
+
+    /// Replace GitHub emoji shortcodes such as `:tada:` with the emoji.
+    #[arg(long, default_value_t = false)]
+    emoji: bool,
+
+    /// How to render math: "katex" or "mathjax" in the browser, or "mathml" converted on the
+    /// server (supports common TeX only, but needs no script).
+    #[arg(long, default_value_t = ("katex").to_string(), value_parser = ["katex", "mathjax", "mathml"])]
+    math: String,
+
+    /// Where to highlight fenced code blocks: "client" in the browser with highlight.js, or
+    /// "server" so the page loads no highlighter script.
+    #[arg(long, default_value_t = ("client").to_string(), value_parser = ["client", "server"])]
+    highlight: String,
+
+    /// Theme of code highlighted on the server in light mode, e.g. "InspiredGitHub",
+    /// "Solarized (light)" or "base16-ocean.light".
+    #[arg(long, default_value_t = ("InspiredGitHub").to_string(), value_parser = highlight::theme_name)]
+    code_theme: String,
+
+    /// Theme of code highlighted on the server in dark mode, e.g. "base16-ocean.dark",
+    /// "base16-eighties.dark" or "Solarized (dark)".
+    #[arg(long, default_value_t = ("base16-ocean.dark").to_string(), value_parser = highlight::theme_name)]
+    code_theme_dark: String,
+
+    /// Where to draw Mermaid, Graphviz and PlantUML diagrams: "client" in the browser, or
+    /// "server" with `mmdc`, `dot` and `plantuml`, embedding them as images. PlantUML
+    /// diagrams are only drawn on the server.
+    #[arg(long, default_value_t = ("client").to_string(), value_parser = ["client", "server"])]
+    diagrams: String,
+
+    /// Language of the page around the document, e.g. "de" or "fr-CA": translates its
+    /// controls (English, German, Spanish and French are available) and sets its `lang`.
+    #[arg(long, default_value_t = ("en").to_string())]
+    locale: String,
+
+    /// Look of the page: "penview", "github" to match how github.com shows markdown, or one
+    /// of the color styles "solarized", "gruvbox", "high-contrast" and "sepia". The page can
+    /// switch styles without reloading.
+    #[arg(long, default_value_t = ("penview").to_string(), value_parser = ["penview", "github", "solarized", "gruvbox", "high-contrast", "sepia"])]
+    style: String,
+
+    /// Font stack of the document's prose, e.g. "Georgia, serif".
+    #[arg(long, value_parser = page_template::css_value)]
+    font_family: Option<String>,
+
+    /// Font stack of code in the document.
+    #[arg(long, value_parser = page_template::css_value)]
+    mono_font_family: Option<String>,
+
+    /// Base font size of the document, a CSS length such as "18px" (plain numbers are
+    /// pixels).
+    #[arg(long, value_parser = page_template::css_value)]
+    font_size: Option<String>,
+
+    /// Line height of the document's prose, e.g. "1.7".
+    #[arg(long, value_parser = page_template::css_value)]
+    line_height: Option<String>,
+
+    /// Widest the document gets, a CSS length such as "60rem" (plain numbers are pixels).
+    #[arg(long, value_parser = page_template::css_value)]
+    max_width: Option<String>,
+
+    /// Space around the document, a CSS length (plain numbers are pixels).
+    #[arg(long, value_parser = page_template::css_value)]
+    margin: Option<String>,
+
+    /// Let the document span the whole window, e.g. for wide tables. The preview has a
+    /// button to toggle it.
+    #[arg(long)]
+    full_width: bool,
+
+    /// How heading anchors are generated, to match where the document is published:
+    /// "github", "gitlab" or "pandoc".
+    #[arg(long, default_value_t = ("github").to_string(), value_parser = ["github", "gitlab", "pandoc"])]
+    slugs: String,
+
+    /// Hide email addresses in links and text from scrapers: "entities" writes them as HTML
+    /// character references, and "script" leaves them out of the HTML for the page's script
+    /// to assemble.
+    #[arg(long, default_value_t = ("none").to_string(), value_parser = ["none", "entities", "script"])]
+    obfuscate_emails: String,
+
+    /// `rel` attribute added to every link, e.g. "noopener noreferrer".
+    #[arg(long)]
+    link_rel: Option<String>,
+
+    /// `target` attribute added to every link, e.g. "_blank".
+    #[arg(long)]
+    link_target: Option<String>,
+
+    /// `referrerpolicy` attribute added to every link, e.g. "no-referrer".
+    #[arg(long)]
+    link_referrer_policy: Option<String>,
+
+    /// Comma-separated URL schemes links may use, e.g. "https,mailto". Links with other
+    /// schemes are written without their URL. Relative links are always allowed.
+    #[arg(long, value_delimiter = ',')]
+    link_schemes: Vec<String>,
+
+    /// A stylesheet to apply on top of the built-in theme. Previews pick up changes to it
+    /// without reloading the page.
+    #[arg(long)]
+    css: Option<PathBuf>,
+}
+
+impl RenderArgs {
+    /// The render options these flags set, with the rest left at their defaults.
+    fn options(&self) -> RenderOptions {
+        RenderOptions {
+            footer: self.footer,
+            frontmatter_card: self.frontmatter_card,
+            toc: self.toc,
+            transformers: Transformers::with_extensions(
+                &self.autolink,
+                &self.obfuscate_emails,
+                self.emoji,
+            ),
+            autolink: self.autolink.clone(),
+            emoji: self.emoji,
+            math: self.math.clone(),
+            highlight: self.highlight.clone(),
+            code_theme: self.code_theme.clone(),
+            code_theme_dark: self.code_theme_dark.clone(),
+            diagrams: self.diagrams.clone(),
+            locale: self.locale.clone(),
+            style: self.style.clone(),
+            typography: Typography {
+                font_family: self.font_family.clone(),
+                mono_font_family: self.mono_font_family.clone(),
+                font_size: self.font_size.clone(),
+                line_height: self.line_height.clone(),
+            },
+            layout: Layout {
+                max_width: self.max_width.clone(),
+                margin: self.margin.clone(),
+                full_width: self.full_width,
+            },
+            slugs: SlugStyle::from_name(&self.slugs),
+            obfuscate_emails: self.obfuscate_emails.clone(),
+            links: LinkPolicy {
+                rel: self.link_rel.clone(),
+                target: self.link_target.clone(),
+                referrer_policy: self.link_referrer_policy.clone(),
+                schemes: self.link_schemes.clone(),
+                emails: ObfuscateEmails::from_mode(&self.obfuscate_emails),
+            },
+            templating: self.templating,
+            css: self
+                .css
+                .as_ref()
+                .map(|css| css.canonicalize().unwrap_or(css.clone())),
+            ..Default::default()
+        }
+    }
+}
+
+/// Accepts tokens that can go in URLs and cookies as they are.
+fn parse_token(s: &str) -> Result<String, String> {
+    if s.is_empty()
+        || !s
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.' | b'~'))
+    {
+        return Err("expected letters, digits, '-', '_', '.' or '~'".to_string());
+    }
+    Ok(s.to_string())
+}
+
+/// A random token, for servers that must have one but weren't given any.
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    getrandom::fill(&mut bytes).expect("Failed to read the OS random source.");
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Normalizes a URL prefix to `/prefix` form, or empty for the root.
+fn parse_base_path(s: &str) -> Result<String, String> {
+    let trimmed = s.trim().trim_matches('/');
+    if trimmed.contains(['?', '#']) {
+        return Err(format!("expected a URL path, got `{s}`"));
+    }
+    Ok(if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("/{trimmed}")
+    })
+}
+
+fn parse_shortcode(s: &str) -> Result<(String, String), String> {
+    let (name, template) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=TEMPLATE, got `{s}`"))?;
+    Ok((name.trim().to_string(), template.to_string()))
+}
+
+fn parse_exec(s: &str) -> Result<(String, String), String> {
+    let (lang, command) = s
+        .split_once('=')
+        .ok_or_else(|| format!("expected LANG=COMMAND, got `{s}`"))?;
+    if command.trim().is_empty() {
+        return Err(format!("no command given for `{lang}`"));
+    }
+    Ok((lang.trim().to_string(), command.to_string()))
+}
+
+/// Name the server is announced under on the network: the title of the opened document, or the
+/// name of the opened directory.
+fn announced_name(target: &Path) -> String {
+    let title = match std::fs::read_to_string(target) {
+        Ok(content) => render::document_title(&content, target),
+        Err(_) => target
+            .file_name()
+            .unwrap_or(target.as_os_str())
+            .to_string_lossy()
+            .into_owned(),
+    };
+    format!("{title} (penview)")
+}
+
+/// Resolves on Ctrl-C or, on unix, SIGTERM (as sent when Neovim stops the server).
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(_) => std::future::pending().await,
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        () = terminate => {}
+    }
+}
+
+/// A server accepting connections, which only completes if it fails.
+type Server = Pin<Box<dyn Future<Output = std::io::Result<()>> + Send>>;
+
+/// Serves `app` on a unix socket at `path`, replacing a stale socket left by a previous run.
+#[cfg(unix)]
+fn serve_unix(path: &Path, app: axum::Router) -> std::io::Result<Server> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.file_type().is_socket()) {
+        let _ = std::fs::remove_file(path);
+    }
+
+    let listener = tokio::net::UnixListener::bind(path)?;
+    Ok(Box::pin(
+        axum::serve(
+            listener,
+            app.into_make_service_with_connect_info::<Client>(),
+        )
+        .into_future(),
+    ))
+}
+
+#[cfg(not(unix))]
+fn serve_unix(_path: &Path, _app: axum::Router) -> std::io::Result<Server> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "unix sockets are not supported on this platform",
+    ))
+}
+
+/// Exit code of `render` when the document can't be read or rendered.
+const EXIT_RENDER_FAILED: u8 = 1;
+/// Exit code of `render` when the output can't be written.
+const EXIT_WRITE_FAILED: u8 = 2;
+/// Exit code of `check` when a document has problems.
+const EXIT_CHECK_FAILED: u8 = 1;
+
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     let args = Args::parse();
 
     match args {
-        Args::Serve {
-            quiet,
-            port,
-            address,
-            open,
-            browser,
-            theme,
-        } => {
+        Args::Serve(args) => {
+            let ServeArgs {
+                path,
+                quiet,
+                port,
+                address,
+                open,
+                browser,
+                theme,
+                lint,
+                vale,
+                screenshot_browser,
+                webhooks,
+                webhook_events,
+                spellcheck,
+                blame,
+                render,
+                cursor_highlight,
+                link_navigation,
+                editable,
+                typewriter,
+                mirror_scroll,
+                max_frame_size,
+                title_format,
+                shortcodes,
+                hide_drafts,
+                workspace,
+                daily_note_pattern,
+                follow,
+                base_path,
+                unix_socket,
+                token,
+                shared,
+                tunnel,
+                no_mdns,
+                remote,
+                root,
+                rate_limit,
+                render_workers,
+                restore,
+                no_session,
+                toggle_tasks,
+                exec,
+            } = *args;
             if !quiet {
                 tracing_subscriber::fmt::init();
             }
+            workers::configure(render_workers);
+
+            let workspace_dir = match workspace {
+                Some(workspace) => match workspace.canonicalize() {
+                    Ok(workspace) => workspace,
+                    Err(e) => {
+                        eprintln!("Invalid workspace {}: {e}", workspace.display());
+                        return ExitCode::FAILURE;
+                    }
+                },
+                None => std::env::current_dir().unwrap_or_default(),
+            };
+            // A root that can't be resolved mustn't leave files unconfined
+            let root = match root.map(|root| root.canonicalize().map_err(|e| (root, e))) {
+                Some(Ok(root)) => Some(root),
+                Some(Err((root, e))) => {
+                    eprintln!("Invalid root directory {}: {e}", root.display());
+                    return ExitCode::FAILURE;
+                }
+                None => None,
+            };
+
+            let address = if remote {
+                "0.0.0.0".to_string()
+            } else {
+                address
+            };
+            let token = token.or_else(|| remote.then(generate_token));
+
+            let session_file = session::session_file(&workspace_dir);
+            let previous_session = session::load(&session_file).await;
 
-            let state = state::AppState::new(theme);
+            let state = state::AppState::new(
+                RenderOptions {
+                    theme,
+                    lint,
+                    spellcheck,
+                    blame,
+                    cursor_highlight,
+                    link_navigation,
+                    editable,
+                    typewriter,
+                    mirror_scroll,
+                    max_frame_size,
+                    toggle_tasks,
+                    exec: exec.into_iter().collect(),
+                    title_format,
+                    shortcodes: shortcodes.into_iter().collect(),
+                    hide_drafts,
+                    workspace: workspace_dir.clone(),
+                    daily_note_pattern: Some(daily_note_pattern).filter(|p| !p.is_empty()),
+                    base_path: base_path.clone(),
+                    root: root.or(remote.then(|| workspace_dir.clone())),
+                    ..render.options()
+                },
+                state::ServeOptions {
+                    vale,
+                    screenshot_browser,
+                    webhooks: webhook::Webhooks::new(webhooks, webhook_events),
+                    token: token.clone(),
+                    rate_limit: rate_limit.unwrap_or(if remote { 20 } else { 0 }),
+                    session: (!no_session).then_some(session_file),
+                    previous_session: previous_session.clone(),
+                },
+            );
             let app = construct_router(state);
 
-            let listener = tokio::net::TcpListener::bind((address, port))
-                .await
-                .unwrap();
+            // Servers on a unix socket have no address to open, announce or tunnel to; the
+            // options that need one conflict with --unix-socket
+            let (server, local_addr, listening) = match &unix_socket {
+                Some(socket) => match serve_unix(socket, app) {
+                    Ok(server) => (server, None, socket.display().to_string()),
+                    Err(e) => {
+                        eprintln!("Failed to listen on {}: {e}", socket.display());
+                        return ExitCode::FAILURE;
+                    }
+                },
+                None => {
+                    let listener = tokio::net::TcpListener::bind((address, port))
+                        .await
+                        .unwrap();
+                    let local_addr = listener.local_addr().unwrap();
+                    // Client addresses key the rate limit
+                    let server: Server = Box::pin(
+                        axum::serve(
+                            listener,
+                            app.into_make_service_with_connect_info::<Client>(),
+                        )
+                        .into_future(),
+                    );
+                    (server, Some(local_addr), local_addr.to_string())
+                }
+            };
 
             if quiet {
-                println!("{}", listener.local_addr().unwrap());
+                println!("{listening}");
             } else {
-                info!("Listening on {}", listener.local_addr().unwrap());
-            }
-
-            if let Some(path) = open {
-                let url = format!(
-                    "http://{}?path={}",
-                    listener.local_addr().unwrap(),
-                    path.as_os_str().to_str().unwrap()
-                );
-                match browser {
-                    Some(browser) => open::with(&url, &browser).unwrap(),
-                    None => open::that(&url).unwrap(),
+                info!("Listening on {listening}");
+            }
+
+            let runtime_file = runtime::write(&runtime::RuntimeInfo {
+                pid: std::process::id(),
+                address: listening,
+                port: local_addr.map_or(0, |addr| addr.port()),
+                token: token.clone(),
+                base_path: base_path.clone(),
+                workspace: workspace_dir.clone(),
+                shared,
+            })
+            .inspect_err(|e| info!("Failed to write runtime file: {e}"))
+            .ok();
+
+            // Other machines can only reach servers bound beyond loopback
+            let lan_ip = match local_addr.map(|addr| addr.ip()) {
+                Some(IpAddr::V4(ip)) if ip.is_unspecified() => lan::lan_ip(),
+                Some(IpAddr::V4(ip)) if !ip.is_loopback() => Some(ip),
+                _ => None,
+            };
+            let document = path.or(open);
+            let target = document
+                .as_deref()
+                .map(|document| document.canonicalize().unwrap_or(document.to_path_buf()))
+                .unwrap_or(workspace_dir.clone());
+            let query = url::form_urlencoded::Serializer::new(String::new())
+                .append_pair("path", &target.to_string_lossy())
+                .finish();
+
+            if let Some(local_addr) = local_addr.filter(|_| remote) {
+                let origin = match lan_ip {
+                    Some(ip) => format!("http://{ip}:{}", local_addr.port()),
+                    None => format!("http://{local_addr}"),
+                };
+                if quiet {
+                    println!("remote {origin}");
+                } else {
+                    let token = token.as_deref().unwrap_or_default();
+                    info!("Open {origin}{base_path}/?{query}&token={token} from other machines");
+                }
+            }
+
+            let announcement = match local_addr.zip(lan_ip).filter(|_| !no_mdns) {
+                Some((local_addr, ip)) => lan::announce(lan::Service {
+                    name: announced_name(&target),
+                    host: lan::host_label(),
+                    ip,
+                    port: local_addr.port(),
+                    path: format!("{base_path}/?{query}"),
+                })
+                .inspect_err(|e| info!("Failed to announce over mDNS: {e}"))
+                .ok(),
+                None => None,
+            };
+
+            if let Some(local_addr) = local_addr.filter(|_| restore) {
+                for path in previous_session
+                    .paths
+                    .iter()
+                    .filter(|path| **path != target)
+                {
+                    let mut query = url::form_urlencoded::Serializer::new(String::new());
+                    query.append_pair("path", &path.to_string_lossy());
+                    if let Some(token) = &token {
+                        query.append_pair("token", token);
+                    }
+                    let url = format!("http://{local_addr}{base_path}/?{}", query.finish());
+                    let opened = match &browser {
+                        Some(browser) => open::with(&url, browser),
+                        None => open::that(&url),
+                    };
+                    if let Err(e) = opened {
+                        eprintln!("Failed to reopen {}: {e}", path.display());
+                    }
+                }
+            }
+
+            if let Some((path, local_addr)) = document.zip(local_addr) {
+                let mut query = url::form_urlencoded::Serializer::new(String::new());
+                query.append_pair("path", &path.to_string_lossy());
+                if let Some(token) = &token {
+                    query.append_pair("token", token);
+                }
+                if follow {
+                    let path = path.canonicalize().unwrap_or(path.clone());
+                    let dir = if path.is_dir() {
+                        path.as_path()
+                    } else {
+                        path.parent().unwrap_or(&path)
+                    };
+                    query.append_pair("follow", &dir.to_string_lossy());
+                }
+                let url = format!("http://{local_addr}{base_path}/?{}", query.finish());
+                let opened = match &browser {
+                    Some(browser) => open::with(&url, browser),
+                    None => open::that(&url),
+                };
+                if let Err(e) = opened {
+                    warn!("Failed to open {}: {e}", path.display());
                 }
             }
 
-            axum::serve(listener, app).await.unwrap();
+            // Serves already while the tunnel starts; the client is killed along with the task
+            let tunnel = tunnel.zip(local_addr).map(|(backend, local_addr)| {
+                tokio::spawn(async move {
+                    match tunnel::open(backend, local_addr).await {
+                        Ok(tunnel) => {
+                            if quiet {
+                                println!("tunnel {}", tunnel.url);
+                            } else {
+                                info!("Tunnel open at {}", tunnel.url);
+                            }
+                            std::future::pending::<()>().await;
+                        }
+                        Err(e) => eprintln!("Failed to open tunnel: {e:#}"),
+                    }
+                })
+            });
+
+            tokio::select! {
+                result = server => result.unwrap(),
+                () = shutdown_signal() => {}
+            }
+            if let Some(tunnel) = tunnel {
+                tunnel.abort();
+            }
+            if let Some(announcement) = announcement {
+                announcement.withdraw().await;
+            }
+            if let Some(runtime_file) = runtime_file {
+                runtime::remove(&runtime_file);
+            }
+            if let Some(socket) = unix_socket {
+                let _ = std::fs::remove_file(socket);
+            }
+            ExitCode::SUCCESS
+        }
+        Args::Check {
+            paths,
+            workspace,
+            templating,
+            drafts,
+        } => {
+            let mut options = RenderOptions {
+                templating,
+                hide_drafts: !drafts,
+                ..Default::default()
+            };
+            if let Some(workspace) = workspace {
+                options.workspace = workspace;
+            }
+            let problems = match check::check(&paths, &options).await {
+                Ok(problems) => problems,
+                Err(e) => {
+                    eprintln!("Failed to check documents: {e:#}");
+                    return ExitCode::FAILURE;
+                }
+            };
+
+            for problem in &problems {
+                println!("{problem}");
+            }
+            if problems.is_empty() {
+                ExitCode::SUCCESS
+            } else {
+                eprintln!("{} problems found", problems.len());
+                ExitCode::from(EXIT_CHECK_FAILED)
+            }
+        }
+        Args::Discover => {
+            let servers = runtime::discover().await;
+            println!("{}", serde_json::to_string_pretty(&servers).unwrap());
+            ExitCode::SUCCESS
         }
         Args::Render {
-            mut in_file,
+            in_file,
             out_file,
+            render,
+            body_only,
+            drafts,
         } => {
-            let html = render_doc(&in_file, false, "light")
-                .await
-                .expect("Failed to render document.");
+            let options = RenderOptions {
+                hide_drafts: !drafts,
+                ..render.options()
+            };
+            let html = if body_only {
+                render_doc_body(&in_file, &options).await
+            } else {
+                render_doc(&in_file, false, &options).await
+            };
+            let html = match html {
+                Ok(html) => html,
+                Err(e) => {
+                    eprintln!("Failed to render {}: {e:#}", in_file.display());
+                    return ExitCode::from(EXIT_RENDER_FAILED);
+                }
+            };
 
-            let out_file = out_file.unwrap_or_else(move || {
-                in_file.set_extension("html");
-                in_file
-            });
+            let Some(out_file) = out_file else {
+                let mut stdout = std::io::stdout().lock();
+                if let Err(e) = stdout
+                    .write_all(html.as_bytes())
+                    .and_then(|()| stdout.flush())
+                {
+                    eprintln!("Failed to write output: {e}");
+                    return ExitCode::from(EXIT_WRITE_FAILED);
+                }
+                return ExitCode::SUCCESS;
+            };
 
             if out_file.exists() {
                 let ans = Confirm::new("The output file exists. Do you wish to overwrite?")
@@ -126,23 +1035,58 @@ async fn main() {
                     }
                     Ok(false) => {
                         println!("Exiting...");
-                        return;
+                        return ExitCode::SUCCESS;
                     }
                     Err(_) => println!("Failed to recognize confirmation."),
                 }
             }
 
-            let out_file = File::create(out_file)
-                .await
-                .expect("Unable to open out_file.");
-            let mut out_file = BufWriter::new(out_file);
-
-            out_file
-                .write_all(html.as_bytes())
-                .await
-                .expect("Unable to write to file.");
+            let written = async {
+                let mut writer = BufWriter::new(File::create(&out_file).await?);
+                writer.write_all(html.as_bytes()).await?;
+                writer.flush().await
+            };
+            if let Err(e) = written.await {
+                eprintln!("Failed to write {}: {e}", out_file.display());
+                return ExitCode::from(EXIT_WRITE_FAILED);
+            }
+            ExitCode::SUCCESS
+        }
+        Args::Export {
+            dir,
+            out,
+            site,
+            title_format,
+            render,
+            drafts,
+            webhooks,
+        } => {
+            let options = RenderOptions {
+                site,
+                title_format,
+                workspace: dir.canonicalize().unwrap_or(dir.clone()),
+                hide_drafts: !drafts,
+                ..render.options()
+            };
+            let started = Instant::now();
+            let written = match export::export(&dir, &out, &options).await {
+                Ok(written) => written,
+                Err(e) => {
+                    eprintln!("Failed to export documents: {e:#}");
+                    return ExitCode::FAILURE;
+                }
+            };
 
-            out_file.flush().await.expect("Unable to write to file.");
+            println!("Exported {} documents to {}", written.len(), out.display());
+            let mut payload =
+                webhook::Payload::new(webhook::Event::Exported, &dir).duration(started.elapsed());
+            payload.format = Some("html");
+            payload.out = Some(out);
+            payload.documents = Some(written.len());
+            webhook::Webhooks::new(webhooks, Vec::new())
+                .send(&payload)
+                .await;
+            ExitCode::SUCCESS
         }
     }
 }
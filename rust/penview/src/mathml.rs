@@ -0,0 +1,384 @@
+//! Server-side math rendering: converts the common subset of TeX used in `$...$` and `$$...$$`
+//! to MathML, which browsers display without a script (see `--math mathml`). Commands outside the
+//! subset are shown as errors; the TeX source is kept as an annotation.
+
+use pulldown_cmark_escape::escape_html;
+
+/// Greek letters and other symbols written as identifiers.
+const IDENTIFIERS: &[(&str, &str)] = &[
+    ("alpha", "α"),
+    ("beta", "β"),
+    ("gamma", "γ"),
+    ("delta", "δ"),
+    ("epsilon", "ϵ"),
+    ("varepsilon", "ε"),
+    ("zeta", "ζ"),
+    ("eta", "η"),
+    ("theta", "θ"),
+    ("vartheta", "ϑ"),
+    ("iota", "ι"),
+    ("kappa", "κ"),
+    ("lambda", "λ"),
+    ("mu", "μ"),
+    ("nu", "ν"),
+    ("xi", "ξ"),
+    ("pi", "π"),
+    ("rho", "ρ"),
+    ("sigma", "σ"),
+    ("tau", "τ"),
+    ("upsilon", "υ"),
+    ("phi", "ϕ"),
+    ("varphi", "φ"),
+    ("chi", "χ"),
+    ("psi", "ψ"),
+    ("omega", "ω"),
+    ("Gamma", "Γ"),
+    ("Delta", "Δ"),
+    ("Theta", "Θ"),
+    ("Lambda", "Λ"),
+    ("Xi", "Ξ"),
+    ("Pi", "Π"),
+    ("Sigma", "Σ"),
+    ("Upsilon", "Υ"),
+    ("Phi", "Φ"),
+    ("Psi", "Ψ"),
+    ("Omega", "Ω"),
+    ("infty", "∞"),
+    ("partial", "∂"),
+    ("nabla", "∇"),
+    ("ell", "ℓ"),
+    ("hbar", "ℏ"),
+    ("emptyset", "∅"),
+];
+
+/// Operators, relations and arrows.
+const OPERATORS: &[(&str, &str)] = &[
+    ("sum", "∑"),
+    ("prod", "∏"),
+    ("int", "∫"),
+    ("iint", "∬"),
+    ("oint", "∮"),
+    ("cdot", "⋅"),
+    ("times", "×"),
+    ("div", "÷"),
+    ("pm", "±"),
+    ("mp", "∓"),
+    ("ast", "∗"),
+    ("circ", "∘"),
+    ("leq", "≤"),
+    ("le", "≤"),
+    ("geq", "≥"),
+    ("ge", "≥"),
+    ("neq", "≠"),
+    ("ne", "≠"),
+    ("approx", "≈"),
+    ("equiv", "≡"),
+    ("sim", "∼"),
+    ("propto", "∝"),
+    ("in", "∈"),
+    ("notin", "∉"),
+    ("subset", "⊂"),
+    ("subseteq", "⊆"),
+    ("supset", "⊃"),
+    ("cup", "∪"),
+    ("cap", "∩"),
+    ("forall", "∀"),
+    ("exists", "∃"),
+    ("neg", "¬"),
+    ("land", "∧"),
+    ("lor", "∨"),
+    ("to", "→"),
+    ("rightarrow", "→"),
+    ("leftarrow", "←"),
+    ("Rightarrow", "⇒"),
+    ("Leftarrow", "⇐"),
+    ("leftrightarrow", "↔"),
+    ("Leftrightarrow", "⇔"),
+    ("implies", "⟹"),
+    ("iff", "⟺"),
+    ("mapsto", "↦"),
+    ("ldots", "…"),
+    ("cdots", "⋯"),
+    ("dots", "…"),
+    ("langle", "⟨"),
+    ("rangle", "⟩"),
+    ("lfloor", "⌊"),
+    ("rfloor", "⌋"),
+    ("lceil", "⌈"),
+    ("rceil", "⌉"),
+    ("mid", "∣"),
+    ("{", "{"),
+    ("}", "}"),
+    ("|", "‖"),
+];
+
+/// Functions written upright, like `\sin`.
+const FUNCTIONS: &[&str] = &[
+    "sin", "cos", "tan", "cot", "sec", "csc", "arcsin", "arccos", "arctan", "sinh", "cosh", "tanh",
+    "log", "ln", "exp", "lim", "max", "min", "sup", "inf", "det", "dim", "gcd", "deg", "arg",
+];
+
+/// Converts TeX math to a `<math>` element, as a block if `display`.
+pub fn to_mathml(tex: &str, display: bool) -> String {
+    let mut parser = Parser { tex, pos: 0 };
+    let body = parser.row(None);
+    format!(
+        "<math{}><semantics>{body}<annotation encoding=\"application/x-tex\">{}</annotation></semantics></math>",
+        if display { " display=\"block\"" } else { "" },
+        escaped(tex)
+    )
+}
+
+fn escaped(text: &str) -> String {
+    let mut out = String::new();
+    escape_html(&mut out, text).unwrap();
+    out
+}
+
+struct Parser<'a> {
+    tex: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.tex[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    /// Parses items until the end of the source or `end`, as an `<mrow>`.
+    fn row(&mut self, end: Option<&str>) -> String {
+        let mut items: Vec<String> = Vec::new();
+        loop {
+            self.skip_whitespace();
+            let rest = self.rest();
+            if rest.is_empty() || end.is_some_and(|end| rest.starts_with(end)) {
+                break;
+            }
+            if rest.starts_with('}') {
+                // Unbalanced, skip it
+                self.pos += 1;
+                continue;
+            }
+
+            if let Some(script) = rest.chars().next().filter(|c| *c == '^' || *c == '_') {
+                self.pos += 1;
+                let base = items.pop().unwrap_or_else(|| "<mrow></mrow>".to_string());
+                let argument = self.atom();
+                items.push(self.scripts(base, script, argument));
+                continue;
+            }
+
+            let item = self.atom();
+            items.push(item);
+        }
+        format!("<mrow>{}</mrow>", items.concat())
+    }
+
+    /// Adds a sub- or superscript to `base`, and the other one if it follows.
+    fn scripts(&mut self, base: String, script: char, argument: String) -> String {
+        self.skip_whitespace();
+        let other = if script == '^' { '_' } else { '^' };
+        if self.rest().starts_with(other) {
+            self.pos += 1;
+            let second = self.atom();
+            let (sub, sup) = if script == '_' {
+                (argument, second)
+            } else {
+                (second, argument)
+            };
+            return format!("<msubsup>{base}{sub}{sup}</msubsup>");
+        }
+        let tag = if script == '^' { "msup" } else { "msub" };
+        format!("<{tag}>{base}{argument}</{tag}>")
+    }
+
+    /// Parses a single item: a group, number, letter, symbol or command with its arguments.
+    fn atom(&mut self) -> String {
+        self.skip_whitespace();
+        let rest = self.rest();
+        let Some(c) = rest.chars().next() else {
+            return "<mrow></mrow>".to_string();
+        };
+
+        if c == '{' {
+            self.pos += 1;
+            let row = self.row(Some("}"));
+            if self.rest().starts_with('}') {
+                self.pos += 1;
+            }
+            return row;
+        }
+        if c == '\\' {
+            self.pos += 1;
+            return self.command();
+        }
+        if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .unwrap_or(rest.len());
+            self.pos += len;
+            return format!("<mn>{}</mn>", &rest[..len]);
+        }
+
+        self.pos += c.len_utf8();
+        if c.is_alphabetic() {
+            format!("<mi>{c}</mi>")
+        } else if c == '~' {
+            "<mspace width=\"0.333em\"></mspace>".to_string()
+        } else if c == '\'' {
+            "<mo>′</mo>".to_string()
+        } else {
+            format!("<mo>{}</mo>", escaped(&c.to_string()))
+        }
+    }
+
+    /// Parses a command after its backslash.
+    fn command(&mut self) -> String {
+        let rest = self.rest();
+        let len = match rest.find(|c: char| !c.is_ascii_alphabetic()) {
+            Some(0) => rest.chars().next().map_or(0, char::len_utf8),
+            Some(len) => len,
+            None => rest.len(),
+        };
+        let name = &rest[..len];
+        self.pos += len;
+
+        match name {
+            "frac" | "dfrac" | "tfrac" => {
+                let numerator = self.atom();
+                let denominator = self.atom();
+                format!("<mfrac>{numerator}{denominator}</mfrac>")
+            }
+            "sqrt" => {
+                self.skip_whitespace();
+                if self.rest().starts_with('[') {
+                    self.pos += 1;
+                    let index = self.row(Some("]"));
+                    if self.rest().starts_with(']') {
+                        self.pos += 1;
+                    }
+                    let radicand = self.atom();
+                    format!("<mroot>{radicand}{index}</mroot>")
+                } else {
+                    format!("<msqrt>{}</msqrt>", self.atom())
+                }
+            }
+            "text" | "textrm" | "mbox" => format!("<mtext>{}</mtext>", escaped(&self.raw())),
+            "mathrm" | "operatorname" => {
+                format!(
+                    "<mi mathvariant=\"normal\">{}</mi>",
+                    escaped(self.raw().trim())
+                )
+            }
+            "mathbf" | "boldsymbol" => self.variant("bold"),
+            "mathit" => self.variant("italic"),
+            "mathbb" => self.variant("double-struck"),
+            "mathcal" => self.variant("script"),
+            "mathfrak" => self.variant("fraktur"),
+            "left" | "right" | "big" | "Big" | "bigg" | "Bigg" => {
+                self.skip_whitespace();
+                let delimiter = self.atom();
+                // `\left.` stands for no delimiter
+                if delimiter == "<mo>.</mo>" {
+                    String::new()
+                } else {
+                    delimiter
+                }
+            }
+            "," | ":" | ">" => "<mspace width=\"0.167em\"></mspace>".to_string(),
+            ";" => "<mspace width=\"0.278em\"></mspace>".to_string(),
+            " " => "<mspace width=\"0.333em\"></mspace>".to_string(),
+            "quad" => "<mspace width=\"1em\"></mspace>".to_string(),
+            "qquad" => "<mspace width=\"2em\"></mspace>".to_string(),
+            "!" => String::new(),
+            "\\" => "<mspace linebreak=\"newline\"></mspace>".to_string(),
+            _ => {
+                if let Some((_, symbol)) = IDENTIFIERS.iter().find(|(n, _)| *n == name) {
+                    format!("<mi>{symbol}</mi>")
+                } else if let Some((_, symbol)) = OPERATORS.iter().find(|(n, _)| *n == name) {
+                    format!("<mo>{symbol}</mo>")
+                } else if FUNCTIONS.contains(&name) {
+                    format!("<mi mathvariant=\"normal\">{name}</mi>")
+                } else {
+                    format!("<merror><mtext>\\{}</mtext></merror>", escaped(name))
+                }
+            }
+        }
+    }
+
+    /// The text of a braced argument, or of the next character, without parsing it.
+    fn raw(&mut self) -> String {
+        self.skip_whitespace();
+        let rest = self.rest();
+        if let Some(inner) = rest.strip_prefix('{') {
+            let mut depth = 0;
+            for (i, c) in inner.char_indices() {
+                match c {
+                    '{' => depth += 1,
+                    '}' if depth == 0 => {
+                        self.pos += i + 2;
+                        return inner[..i].to_string();
+                    }
+                    '}' => depth -= 1,
+                    _ => {}
+                }
+            }
+            self.pos = self.tex.len();
+            return inner.to_string();
+        }
+        let c = rest.chars().next().map(String::from).unwrap_or_default();
+        self.pos += c.len();
+        c
+    }
+
+    /// The next argument with its letters in a font variant.
+    fn variant(&mut self, variant: &str) -> String {
+        let argument = self.atom();
+        argument.replace("<mi>", &format!("<mi mathvariant=\"{variant}\">"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_mathml() {
+        let body = |tex: &str| {
+            let math = to_mathml(tex, false);
+            math["<math><semantics>".len()..math.find("<annotation").unwrap()].to_string()
+        };
+
+        assert_eq!(
+            body("x^2 + y_{i}^{n}"),
+            "<mrow><msup><mi>x</mi><mn>2</mn></msup><mo>+</mo>\
+             <msubsup><mi>y</mi><mrow><mi>i</mi></mrow><mrow><mi>n</mi></mrow></msubsup></mrow>"
+        );
+        assert_eq!(
+            body(r"\frac{\alpha}{2} \leq \sqrt[3]{x}"),
+            "<mrow><mfrac><mrow><mi>α</mi></mrow><mrow><mn>2</mn></mrow></mfrac><mo>≤</mo>\
+             <mroot><mrow><mi>x</mi></mrow><mrow><mn>3</mn></mrow></mroot></mrow>"
+        );
+        assert_eq!(
+            body(r"\sin x \text{ if } \mathbb{R} \unknown"),
+            "<mrow><mi mathvariant=\"normal\">sin</mi><mi>x</mi><mtext> if </mtext>\
+             <mrow><mi mathvariant=\"double-struck\">R</mi></mrow>\
+             <merror><mtext>\\unknown</mtext></merror></mrow>"
+        );
+        assert_eq!(
+            body(r"\left( a < b \right."),
+            "<mrow><mo>(</mo><mi>a</mi><mo>&lt;</mo><mi>b</mi></mrow>"
+        );
+
+        assert_eq!(
+            to_mathml("a<b", true),
+            "<math display=\"block\"><semantics><mrow><mi>a</mi><mo>&lt;</mo><mi>b</mi></mrow>\
+             <annotation encoding=\"application/x-tex\">a&lt;b</annotation></semantics></math>"
+        );
+    }
+}
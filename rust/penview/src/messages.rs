@@ -0,0 +1,214 @@
+//! Text of the preview's controls, overlays and status lines, by locale (see `--locale`). The
+//! document itself is left as written; only the page around it is translated.
+
+use serde::Serialize;
+
+/// The strings shown by a page in one language.
+#[derive(Debug)]
+pub struct Messages {
+    pub toggle_theme: &'static str,
+    pub toggle_source: &'static str,
+    /// Title of the style picker.
+    pub style: &'static str,
+    pub graph: &'static str,
+    pub toggle_focus: &'static str,
+    pub toggle_width: &'static str,
+    pub revisions: &'static str,
+    pub search_placeholder: &'static str,
+    pub search_label: &'static str,
+    pub previous_match: &'static str,
+    pub next_match: &'static str,
+    pub previous_day: &'static str,
+    pub next_day: &'static str,
+    /// Added to the title of the link to a daily note that doesn't exist.
+    pub not_created_yet: &'static str,
+    pub linked_mentions: &'static str,
+    /// Heading of the table of contents.
+    pub contents: &'static str,
+    /// Precedes the modification time in the footer.
+    pub last_modified: &'static str,
+    /// Follows the word count in the footer.
+    pub words: &'static str,
+    /// Title of the graph page.
+    pub graph_title: &'static str,
+    pub loading: &'static str,
+    /// Strings the page's script shows.
+    pub script: ScriptMessages,
+}
+
+/// The strings of [`Messages`] used by the page's script, which gets them as JSON. `{lang}` and
+/// `{status}` are replaced by the script.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScriptMessages {
+    pub working_copy: &'static str,
+    pub not_committed: &'static str,
+    pub not_committed_yet: &'static str,
+    pub run: &'static str,
+    pub running: &'static str,
+    pub run_block: &'static str,
+    pub no_output: &'static str,
+    pub killed: &'static str,
+    pub exited: &'static str,
+}
+
+static EN: Messages = Messages {
+    toggle_theme: "Toggle dark mode",
+    style: "Page style",
+    toggle_source: "Toggle source view",
+    graph: "Graph of linked documents",
+    toggle_focus: "Toggle focus mode (z)",
+    toggle_width: "Toggle full width",
+    revisions: "Browse revisions",
+    search_placeholder: "Search (/)",
+    search_label: "Search this document",
+    previous_match: "Previous match (Shift+Enter)",
+    next_match: "Next match (Enter)",
+    previous_day: "Previous day",
+    next_day: "Next day",
+    not_created_yet: "(not created yet)",
+    linked_mentions: "Linked mentions",
+    contents: "Contents",
+    last_modified: "Last modified",
+    words: "words",
+    graph_title: "Graph",
+    loading: "Loading…",
+    script: ScriptMessages {
+        working_copy: "Working copy",
+        not_committed: "Not committed",
+        not_committed_yet: "Not committed yet",
+        run: "Run",
+        running: "Running…",
+        run_block: "Run this {lang} block",
+        no_output: "No output",
+        killed: "Killed by a signal",
+        exited: "Exited with status {status}",
+    },
+};
+
+static DE: Messages = Messages {
+    toggle_theme: "Dunkelmodus umschalten",
+    style: "Seitenstil",
+    toggle_source: "Quelltextansicht umschalten",
+    graph: "Graph der verlinkten Dokumente",
+    toggle_focus: "Fokusmodus umschalten (z)",
+    toggle_width: "Volle Breite umschalten",
+    revisions: "Versionen durchsuchen",
+    search_placeholder: "Suchen (/)",
+    search_label: "Dieses Dokument durchsuchen",
+    previous_match: "Vorheriger Treffer (Umschalt+Eingabe)",
+    next_match: "Nächster Treffer (Eingabe)",
+    previous_day: "Vorheriger Tag",
+    next_day: "Nächster Tag",
+    not_created_yet: "(noch nicht angelegt)",
+    linked_mentions: "Verlinkte Erwähnungen",
+    contents: "Inhalt",
+    last_modified: "Zuletzt geändert",
+    words: "Wörter",
+    graph_title: "Graph",
+    loading: "Wird geladen…",
+    script: ScriptMessages {
+        working_copy: "Arbeitskopie",
+        not_committed: "Nicht committet",
+        not_committed_yet: "Noch nicht committet",
+        run: "Ausführen",
+        running: "Läuft…",
+        run_block: "Diesen {lang}-Block ausführen",
+        no_output: "Keine Ausgabe",
+        killed: "Durch ein Signal beendet",
+        exited: "Mit Status {status} beendet",
+    },
+};
+
+static ES: Messages = Messages {
+    toggle_theme: "Cambiar modo oscuro",
+    style: "Estilo de la página",
+    toggle_source: "Mostrar u ocultar el código fuente",
+    graph: "Grafo de documentos enlazados",
+    toggle_focus: "Cambiar modo de enfoque (z)",
+    toggle_width: "Cambiar ancho completo",
+    revisions: "Explorar revisiones",
+    search_placeholder: "Buscar (/)",
+    search_label: "Buscar en este documento",
+    previous_match: "Coincidencia anterior (Mayús+Intro)",
+    next_match: "Coincidencia siguiente (Intro)",
+    previous_day: "Día anterior",
+    next_day: "Día siguiente",
+    not_created_yet: "(aún no creado)",
+    linked_mentions: "Menciones enlazadas",
+    contents: "Contenido",
+    last_modified: "Última modificación",
+    words: "palabras",
+    graph_title: "Grafo",
+    loading: "Cargando…",
+    script: ScriptMessages {
+        working_copy: "Copia de trabajo",
+        not_committed: "Sin confirmar",
+        not_committed_yet: "Aún sin confirmar",
+        run: "Ejecutar",
+        running: "Ejecutando…",
+        run_block: "Ejecutar este bloque de {lang}",
+        no_output: "Sin salida",
+        killed: "Terminado por una señal",
+        exited: "Terminó con estado {status}",
+    },
+};
+
+static FR: Messages = Messages {
+    toggle_theme: "Basculer le mode sombre",
+    style: "Style de la page",
+    toggle_source: "Afficher ou masquer la source",
+    graph: "Graphe des documents liés",
+    toggle_focus: "Basculer le mode concentration (z)",
+    toggle_width: "Basculer la pleine largeur",
+    revisions: "Parcourir les révisions",
+    search_placeholder: "Rechercher (/)",
+    search_label: "Rechercher dans ce document",
+    previous_match: "Résultat précédent (Maj+Entrée)",
+    next_match: "Résultat suivant (Entrée)",
+    previous_day: "Jour précédent",
+    next_day: "Jour suivant",
+    not_created_yet: "(pas encore créé)",
+    linked_mentions: "Mentions liées",
+    contents: "Sommaire",
+    last_modified: "Dernière modification",
+    words: "mots",
+    graph_title: "Graphe",
+    loading: "Chargement…",
+    script: ScriptMessages {
+        working_copy: "Copie de travail",
+        not_committed: "Non commité",
+        not_committed_yet: "Pas encore commité",
+        run: "Exécuter",
+        running: "Exécution…",
+        run_block: "Exécuter ce bloc {lang}",
+        no_output: "Aucune sortie",
+        killed: "Interrompu par un signal",
+        exited: "Terminé avec le code {status}",
+    },
+};
+
+/// The catalog for `locale`, a language tag such as `de` or `de-AT`. Regional variants use the
+/// catalog of their language, and unknown languages get English.
+pub fn for_locale(locale: &str) -> &'static Messages {
+    let language = locale.split(['-', '_']).next().unwrap_or_default();
+    match language.to_ascii_lowercase().as_str() {
+        "de" => &DE,
+        "es" => &ES,
+        "fr" => &FR,
+        _ => &EN,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_for_locale() {
+        assert_eq!(for_locale("de").words, "Wörter");
+        assert_eq!(for_locale("fr-CA").words, "mots");
+        assert_eq!(for_locale("es_MX").words, "palabras");
+        assert_eq!(for_locale("pt-BR").words, "words");
+    }
+}
@@ -0,0 +1,222 @@
+//! Hiding email addresses from scrapers, for documents that get published. Addresses in
+//! `mailto:` links and in prose are either written as HTML character references, which browsers
+//! show as usual but naive scrapers don't decode, or left out of the HTML entirely and assembled
+//! by a script in the page.
+//!
+//! Addresses in prose are rewritten by a [`RenderTransformer`]; links are written by the HTML
+//! writer, after checking them against the [`LinkPolicy`](crate::link_policy::LinkPolicy).
+
+use std::ops::Range;
+
+use pulldown_cmark::{Event, Tag, TagEnd};
+use pulldown_cmark_escape::escape_html;
+
+use crate::{
+    autolink::email_len,
+    transform::{Piece, RenderTransformer, split_text, text_run},
+};
+
+/// Obfuscates email addresses in text, leaving code and image descriptions alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObfuscateEmails {
+    /// Whether addresses are assembled by the page's script rather than written as character
+    /// references.
+    pub script: bool,
+}
+
+impl ObfuscateEmails {
+    /// The transformer for an `--obfuscate-emails` mode: "entities" or "script", or `None` for
+    /// "none" (or anything else).
+    pub fn from_mode(mode: &str) -> Option<Self> {
+        match mode {
+            "entities" => Some(Self { script: false }),
+            "script" => Some(Self { script: true }),
+            _ => None,
+        }
+    }
+
+    /// Attributes of a link to `address` taking the place of its `href`.
+    pub fn link_attributes(&self, address: &str) -> String {
+        if self.script {
+            format!(
+                " class=\"penview-email\" data-email=\"{}\"",
+                escaped(&reversed(address))
+            )
+        } else {
+            format!(" href=\"{}\"", references(&format!("mailto:{address}")))
+        }
+    }
+
+    /// An address written in text.
+    fn address(&self, address: &str) -> String {
+        if !self.script {
+            return references(address);
+        }
+        // Shown as `me [at] example.com` until the script runs
+        let (user, domain) = address.split_once('@').unwrap_or((address, ""));
+        format!(
+            "<span class=\"penview-email\" data-email=\"{}\">{} [at] {}</span>",
+            escaped(&reversed(address)),
+            escaped(user),
+            escaped(domain)
+        )
+    }
+
+    /// Splits `text` into plain text and HTML for the addresses in it, or returns `None` if it
+    /// has none.
+    fn split<'a>(&self, text: &str) -> Option<Vec<Event<'a>>> {
+        let pieces = split_text(text, |before, rest| {
+            let preceded_ok = before
+                .chars()
+                .next_back()
+                .is_none_or(|c| !(c.is_ascii_alphanumeric() || "._+-".contains(c)));
+            let len = email_len(rest).filter(|_| preceded_ok)?;
+            Some((len, self.address(&rest[..len])))
+        })?;
+
+        Some(
+            pieces
+                .into_iter()
+                .map(|piece| match piece {
+                    Piece::Text(text) => Event::Text(text.into()),
+                    Piece::Match(html) => Event::InlineHtml(html.into()),
+                })
+                .collect(),
+        )
+    }
+}
+
+/// `s` written as decimal character references.
+fn references(s: &str) -> String {
+    s.chars().map(|c| format!("&#{};", c as u32)).collect()
+}
+
+fn reversed(s: &str) -> String {
+    s.chars().rev().collect()
+}
+
+fn escaped(s: &str) -> String {
+    let mut out = String::new();
+    let _ = escape_html(&mut out, s);
+    out
+}
+
+impl RenderTransformer for ObfuscateEmails {
+    fn name(&self) -> &str {
+        "obfuscate-emails"
+    }
+
+    fn transform<'a>(&self, events: &mut Vec<(Event<'a>, Range<usize>)>) {
+        // Image descriptions become `alt` attributes, which can't hold markup
+        let mut skip_depth = 0;
+        let mut out = Vec::with_capacity(events.len());
+        let mut drained = events.drain(..).peekable();
+
+        while let Some((event, range)) = drained.next() {
+            match &event {
+                Event::Start(Tag::CodeBlock(_) | Tag::MetadataBlock(_) | Tag::Image { .. }) => {
+                    skip_depth += 1
+                }
+                Event::End(TagEnd::CodeBlock | TagEnd::MetadataBlock(_) | TagEnd::Image) => {
+                    skip_depth -= 1
+                }
+                _ => {}
+            }
+            let Event::Text(text) = event else {
+                out.push((event, range));
+                continue;
+            };
+            if skip_depth > 0 {
+                out.push((Event::Text(text), range));
+                continue;
+            }
+
+            let (run, run_range) = text_run(text, range, &mut drained);
+            match self.split(&run) {
+                Some(split) => out.extend(split.into_iter().map(|e| (e, run_range.clone()))),
+                None => out.push((Event::Text(run.into()), run_range)),
+            }
+        }
+
+        drop(drained);
+        *events = out;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{
+        link_policy::LinkPolicy,
+        render::{RenderOptions, render_markdown_to_html},
+    };
+
+    async fn render(content: &str, script: bool, links: LinkPolicy) -> String {
+        let obfuscate = ObfuscateEmails { script };
+        let mut options = RenderOptions {
+            links: LinkPolicy {
+                emails: Some(obfuscate),
+                ..links
+            },
+            ..RenderOptions::default()
+        };
+        options.transformers.register(obfuscate);
+        render_markdown_to_html(content, Path::new("doc.md"), &options).await
+    }
+
+    #[tokio::test]
+    async fn test_obfuscate_emails() {
+        let content = "Mail [me](mailto:me@a.io \"Me\"), <us@a.io> or\nx_y@a.io. `no@a.io`\n\n![me@a.io here](https://a.io/me.png)\n";
+
+        let html = render(content, false, LinkPolicy::default()).await;
+        assert!(!html.contains("a.io\""));
+        assert!(html.contains(&format!(
+            "<a data-source-line=\"1\" href=\"{}\" title=\"Me\">me</a>",
+            references("mailto:me@a.io")
+        )));
+        assert!(html.contains(&format!(
+            "<a data-source-line=\"1\" href=\"{}\">{}</a>",
+            references("mailto:us@a.io"),
+            references("us@a.io")
+        )));
+        assert!(html.contains(&format!("{}.", references("x_y@a.io"))));
+        assert!(html.contains("<code data-source-line=\"2\">no@a.io</code>"));
+        assert!(html.contains("alt=\"me@a.io here\""));
+
+        let html = render(content, true, LinkPolicy::default()).await;
+        assert!(html.contains(
+            "<a data-source-line=\"1\" class=\"penview-email\" data-email=\"oi.a@em\" title=\"Me\">me</a>"
+        ));
+        assert!(html.contains(
+            "<span class=\"penview-email\" data-email=\"oi.a@y_x\">x_y [at] a.io</span>."
+        ));
+        assert!(!html.contains("mailto:"));
+    }
+
+    #[tokio::test]
+    async fn test_obfuscate_emails_link_policy() {
+        let links = LinkPolicy {
+            rel: Some("noopener".to_string()),
+            target: Some("_blank".to_string()),
+            schemes: vec!["https".to_string()],
+            ..LinkPolicy::default()
+        };
+        for script in [false, true] {
+            let html = render(
+                "[me](mailto:me@a.io) and <us@a.io>\n",
+                script,
+                links.clone(),
+            )
+            .await;
+            assert!(!html.contains("href="));
+            assert!(!html.contains("data-email=\"oi.a@em\""));
+            assert!(
+                html.contains(
+                    "<a data-source-line=\"1\" rel=\"noopener\" target=\"_blank\">me</a>"
+                )
+            );
+        }
+    }
+}
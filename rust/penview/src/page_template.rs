@@ -1,5 +1,7 @@
 use askama::Template;
 
+use crate::{assets, git::Head, messages::Messages};
+
 #[derive(Debug, Template)]
 #[template(path = "page.html")]
 pub struct PageTemplate {
@@ -7,4 +9,296 @@ pub struct PageTemplate {
     pub body: String,
     pub use_websocket: bool,
     pub theme: String,
+    /// Look of the page, one of [`STYLES`].
+    pub style: String,
+    pub lint: bool,
+    pub blame: bool,
+    pub cursor_highlight: String,
+    pub link_navigation: String,
+    pub editable: bool,
+    pub typewriter: bool,
+    pub mirror_scroll: bool,
+    pub toggle_tasks: bool,
+    /// Languages whose code blocks get a button to run them.
+    pub exec: Vec<String>,
+    /// Math engine: "katex", "mathjax" or "mathml" (rendered on the server, no script needed).
+    pub math: String,
+    /// Stylesheet of the code highlighted on the server, or `None` to highlight it in the
+    /// browser with highlight.js.
+    pub code_stylesheet: Option<String>,
+    /// How email addresses are obfuscated; "script" adds the script assembling them.
+    pub obfuscate_emails: String,
+    pub footer: Option<Footer>,
+    /// Whether to show the table of contents sidebar.
+    pub toc: bool,
+    pub daily: Option<DailyNav>,
+    /// The user stylesheet, inlined after the built-in styles.
+    pub css: Option<String>,
+    /// URL prefix of the server's routes, for links and requests from the page.
+    pub base_path: String,
+    /// Public origin of the server behind a proxy, or empty to use the page's own location.
+    pub origin: String,
+    /// Language tag of the page, e.g. `de` or `fr-CA`.
+    pub lang: String,
+    /// Text of the page's controls, in the page's language.
+    pub messages: &'static Messages,
+    pub typography: Typography,
+    pub layout: Layout,
+}
+
+/// Fonts and sizes of the document, each left to the style when unset.
+#[derive(Debug, Clone, Default)]
+pub struct Typography {
+    /// Font stack of prose, e.g. `Georgia, serif`.
+    pub font_family: Option<String>,
+    /// Font stack of code.
+    pub mono_font_family: Option<String>,
+    /// Base font size as a CSS length, e.g. `18px` or `1.1rem`; plain numbers are pixels.
+    pub font_size: Option<String>,
+    /// Line height of prose, e.g. `1.7`.
+    pub line_height: Option<String>,
+}
+
+impl Typography {
+    /// Declarations of the CSS variables read by the page's stylesheet, for the settings that
+    /// are set.
+    fn css_variables(&self) -> String {
+        let font_size = self
+            .font_size
+            .as_ref()
+            .map(|size| match size.parse::<f64>() {
+                Ok(_) => format!("{size}px"),
+                Err(_) => size.clone(),
+            });
+        [
+            ("font-family", &self.font_family),
+            ("mono-font-family", &self.mono_font_family),
+            ("font-size", &font_size),
+            ("line-height", &self.line_height),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some(format!("--penview-{name}: {};", value.as_deref()?)))
+        .collect::<Vec<_>>()
+        .join(" ")
+    }
+}
+
+/// Width and margins of the document.
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    /// Widest the document gets, as a CSS length; plain numbers are pixels.
+    pub max_width: Option<String>,
+    /// Space around the document, as a CSS length; plain numbers are pixels.
+    pub margin: Option<String>,
+    /// Whether the document spans the whole window initially. The page can toggle it.
+    pub full_width: bool,
+}
+
+impl Layout {
+    fn css_variables(&self) -> String {
+        [("max-width", &self.max_width), ("margin", &self.margin)]
+            .into_iter()
+            .filter_map(|(name, value)| {
+                Some(format!("--penview-{name}: {};", pixels(value.as_deref()?)))
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// `length` with plain numbers taken as pixels.
+fn pixels(length: &str) -> String {
+    match length.parse::<f64>() {
+        Ok(_) => format!("{length}px"),
+        Err(_) => length.to_string(),
+    }
+}
+
+/// Checks that `value` can be written into the page's stylesheet as the value of a property,
+/// without ending it.
+pub fn css_value(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    if value.is_empty() || value.contains(['<', '>', '{', '}', ';', '\\']) {
+        return Err(format!("not a CSS value: `{value}`"));
+    }
+    Ok(value.to_string())
+}
+
+/// A built-in look of the page, which the page can switch to without reloading.
+#[derive(Debug)]
+pub struct Style {
+    pub name: &'static str,
+    /// Name shown in the style picker.
+    pub label: &'static str,
+    /// highlight.js themes for code blocks in light and dark mode.
+    pub code: [&'static str; 2],
+}
+
+pub const STYLES: &[Style] = &[
+    Style {
+        name: "penview",
+        label: "Penview",
+        code: ["default", "default"],
+    },
+    Style {
+        name: "github",
+        label: "GitHub",
+        code: ["github", "github-dark"],
+    },
+    Style {
+        name: "solarized",
+        label: "Solarized",
+        code: ["base16/solarized-light", "base16/solarized-dark"],
+    },
+    Style {
+        name: "gruvbox",
+        label: "Gruvbox",
+        code: ["base16/gruvbox-light-medium", "base16/gruvbox-dark-medium"],
+    },
+    Style {
+        name: "high-contrast",
+        label: "High contrast",
+        code: ["a11y-light", "a11y-dark"],
+    },
+    Style {
+        name: "sepia",
+        label: "Sepia",
+        code: ["default", "github-dark"],
+    },
+];
+
+/// URL of a highlight.js theme.
+fn code_theme_url(theme: &str) -> String {
+    format!("https://cdnjs.cloudflare.com/ajax/libs/highlight.js/11.9.0/styles/{theme}.min.css")
+}
+
+impl PageTemplate {
+    /// URL of the code block theme of the page's style, in light or dark mode.
+    fn code_theme(&self, dark: bool) -> String {
+        let style = STYLES
+            .iter()
+            .find(|style| style.name == self.style)
+            .unwrap_or(&STYLES[0]);
+        code_theme_url(style.code[usize::from(dark)])
+    }
+
+    fn styles(&self) -> &'static [Style] {
+        STYLES
+    }
+
+    /// Declarations of the CSS variables for the page's settings, or empty if the stylesheet's
+    /// defaults apply.
+    fn css_variables(&self) -> String {
+        let variables = [self.typography.css_variables(), self.layout.css_variables()];
+        variables
+            .into_iter()
+            .filter(|variables| !variables.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// The styles, with the URLs of their code themes, as a JSON array for the style picker.
+    fn styles_json(&self) -> String {
+        let styles: Vec<_> = STYLES
+            .iter()
+            .map(|style| {
+                serde_json::json!({
+                    "name": style.name,
+                    "code": style.code.map(code_theme_url),
+                })
+            })
+            .collect();
+        serde_json::to_string(&styles).unwrap()
+    }
+
+    /// Content-hashed URL of a bundled asset, for the live preview.
+    fn asset_url(&self, name: &str) -> String {
+        assets::get(name)
+            .map(|asset| format!("{}{}", self.origin, asset.url(&self.base_path)))
+            .unwrap_or_default()
+    }
+
+    /// Origin for the page's WebSockets: `wss://` when the proxy in front terminates HTTPS.
+    fn socket_origin(&self) -> String {
+        if let Some(host) = self.origin.strip_prefix("https://") {
+            format!("wss://{host}")
+        } else if let Some(host) = self.origin.strip_prefix("http://") {
+            format!("ws://{host}")
+        } else {
+            String::new()
+        }
+    }
+
+    /// The strings used by the page's script, as a JSON object.
+    fn script_messages(&self) -> String {
+        serde_json::to_string(&self.messages.script).unwrap()
+    }
+
+    /// A bundled asset as a `data:` URL, for inlining into exports.
+    fn asset_data_url(&self, name: &str) -> String {
+        assets::get(name)
+            .map(assets::Asset::data_url)
+            .unwrap_or_default()
+    }
+
+    /// Content of a bundled asset, for inlining into exports.
+    fn asset_content(&self, name: &str) -> &'static str {
+        assets::get(name).map_or("", |asset| asset.content)
+    }
+}
+
+/// Links to the neighbouring days of a daily note, shown above it.
+#[derive(Debug)]
+pub struct DailyNav {
+    pub prev: DailyLink,
+    pub next: DailyLink,
+}
+
+#[derive(Debug)]
+pub struct DailyLink {
+    pub path: String,
+    /// The date, formatted for display.
+    pub label: String,
+    /// Whether the note exists yet.
+    pub exists: bool,
+}
+
+/// Provenance shown at the bottom of the page.
+#[derive(Debug)]
+pub struct Footer {
+    /// Last modification time of the file, formatted for display.
+    pub modified: Option<String>,
+    pub head: Option<Head>,
+    pub words: usize,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_typography() {
+        let typography = Typography {
+            font_family: Some("Georgia, serif".to_string()),
+            font_size: Some("18".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            typography.css_variables(),
+            "--penview-font-family: Georgia, serif; --penview-font-size: 18px;"
+        );
+        assert_eq!(Typography::default().css_variables(), "");
+
+        let layout = Layout {
+            max_width: Some("60rem".to_string()),
+            margin: Some("20".to_string()),
+            full_width: false,
+        };
+        assert_eq!(
+            layout.css_variables(),
+            "--penview-max-width: 60rem; --penview-margin: 20px;"
+        );
+        assert!(css_value("1.7").is_ok());
+        assert!(css_value("x</style>").is_err());
+    }
 }
@@ -0,0 +1,96 @@
+//! PDF exports of rendered documents, printed by a headless Chromium-based browser run as a
+//! subprocess. The page's print styles hide its controls, so only the document is printed.
+//!
+//! Firefox can't print to PDF from the command line, so it is only used for screenshots.
+
+use std::{path::Path, process::Stdio, time::Duration};
+
+use anyhow::{Context, bail};
+use tokio::process::Command;
+
+use crate::screenshot::{self, is_firefox, work_dir};
+
+/// How long the browser gets to load and print the page, including its stylesheets and scripts
+/// from CDNs.
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The browser to print with: the configured one, else the first Chromium-based one on the
+/// `PATH`.
+pub fn find_browser(configured: Option<&str>) -> Option<String> {
+    match configured {
+        Some(browser) => Some(browser.to_string()),
+        None => screenshot::find_browser().filter(|browser| !is_firefox(browser)),
+    }
+}
+
+/// Prints the standalone page `html` with `browser`, and returns the PDF.
+pub async fn print(browser: &str, html: &str) -> anyhow::Result<Vec<u8>> {
+    if is_firefox(browser) {
+        bail!("{browser} can't print to PDF, a Chromium-based browser is needed");
+    }
+
+    let dir = work_dir();
+    let profile = dir.join("profile");
+    tokio::fs::create_dir_all(&profile).await?;
+    let result = print_in(&dir, &profile, browser, html).await;
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    result
+}
+
+async fn print_in(
+    dir: &Path,
+    profile: &Path,
+    browser: &str,
+    html: &str,
+) -> anyhow::Result<Vec<u8>> {
+    let page = dir.join("page.html");
+    let out = dir.join("document.pdf");
+    tokio::fs::write(&page, html).await?;
+    let url = url::Url::from_file_path(&page)
+        .map_err(|_| anyhow::anyhow!("Invalid path {}", page.display()))?;
+
+    let status = Command::new(browser)
+        .args([
+            "--headless",
+            "--disable-gpu",
+            "--no-first-run",
+            "--no-pdf-header-footer",
+            "--virtual-time-budget=5000",
+        ])
+        .arg(format!("--user-data-dir={}", profile.display()))
+        .arg(format!("--print-to-pdf={}", out.display()))
+        .arg(url.as_str())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .status();
+    let status = tokio::time::timeout(TIMEOUT, status)
+        .await
+        .with_context(|| format!("{browser} took longer than {}s", TIMEOUT.as_secs()))?
+        .with_context(|| format!("Failed to run {browser}"))?;
+    if !status.success() {
+        bail!("{browser} exited with {status}");
+    }
+
+    let pdf = tokio::fs::read(&out)
+        .await
+        .with_context(|| format!("{browser} didn't save a PDF"))?;
+    if !pdf.starts_with(b"%PDF") {
+        bail!("{browser} saved something other than a PDF");
+    }
+    Ok(pdf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_browser() {
+        assert_eq!(
+            find_browser(Some("/opt/chrome/chrome")).as_deref(),
+            Some("/opt/chrome/chrome")
+        );
+    }
+}
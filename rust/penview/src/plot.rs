@@ -0,0 +1,431 @@
+//! Charts from ```` ```plot ```` code blocks, rendered to SVG on the server so that data notes can
+//! include quick plots. A block is a list of `key: value` lines:
+//!
+//! ```text
+//! title: Weekly distance
+//! type: bar
+//! x: Mon, Tue, Wed, Thu
+//! ylabel: km
+//! Running: 3, 5, 2.5, 8
+//! Cycling: 12, 0, 20, 15
+//! ```
+//!
+//! `title`, `type` (`line`, the default, `bar` or `scatter`), `x` (labels of the points),
+//! `xlabel` and `ylabel` are settings; any other key is a data series. Lines starting with `#`
+//! are comments.
+
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::Range,
+    sync::{LazyLock, Mutex},
+};
+
+use pulldown_cmark::{CodeBlockKind, Event, Tag, TagEnd};
+use pulldown_cmark_escape::escape_html;
+
+use crate::transform::RenderTransformer;
+
+const WIDTH: f64 = 640.0;
+const HEIGHT: f64 = 320.0;
+const MARGIN_LEFT: f64 = 56.0;
+const MARGIN_RIGHT: f64 = 16.0;
+const MARGIN_TOP: f64 = 36.0;
+const MARGIN_BOTTOM: f64 = 56.0;
+/// Upper bound on y axis ticks, reached only when the data spans most of the f64 range.
+const MAX_TICKS: usize = 10;
+
+/// Series colors, from GitHub's palette.
+const COLORS: &[&str] = &[
+    "#0969da", "#cf222e", "#1a7f37", "#8250df", "#bf8700", "#1b7c83",
+];
+
+/// Rendered charts by a hash of their source, since live previews render the same blocks over and
+/// over. Cleared when it grows past [`CACHE_SIZE`].
+static CACHE: LazyLock<Mutex<HashMap<u64, String>>> = LazyLock::new(Default::default);
+const CACHE_SIZE: usize = 256;
+
+#[derive(Debug, Default, PartialEq)]
+enum Kind {
+    #[default]
+    Line,
+    Bar,
+    Scatter,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct Plot {
+    title: Option<String>,
+    kind: Kind,
+    labels: Vec<String>,
+    xlabel: Option<String>,
+    ylabel: Option<String>,
+    series: Vec<(String, Vec<f64>)>,
+}
+
+fn parse(source: &str) -> Result<Plot, String> {
+    let mut plot = Plot::default();
+    for (index, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (key, value) = line
+            .split_once(':')
+            .ok_or_else(|| format!("Line {}: expected `key: value`", index + 1))?;
+        let (key, value) = (key.trim(), value.trim());
+
+        match key {
+            "title" => plot.title = Some(value.to_string()),
+            "xlabel" => plot.xlabel = Some(value.to_string()),
+            "ylabel" => plot.ylabel = Some(value.to_string()),
+            "x" => plot.labels = value.split(',').map(|l| l.trim().to_string()).collect(),
+            "type" => {
+                plot.kind = match value {
+                    "line" => Kind::Line,
+                    "bar" => Kind::Bar,
+                    "scatter" => Kind::Scatter,
+                    _ => return Err(format!("Line {}: unknown type `{value}`", index + 1)),
+                }
+            }
+            name => {
+                let values = value
+                    .split(',')
+                    .map(|v| {
+                        v.trim()
+                            .parse::<f64>()
+                            .ok()
+                            .filter(|v| v.is_finite())
+                            .ok_or_else(|| {
+                                format!("Line {}: `{}` isn't a number", index + 1, v.trim())
+                            })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                plot.series.push((name.to_string(), values));
+            }
+        }
+    }
+
+    if plot.series.is_empty() {
+        return Err("No data series".to_string());
+    }
+    Ok(plot)
+}
+
+/// Evenly spaced y axis ticks at round numbers, covering `min..=max` and zero.
+fn ticks(min: f64, max: f64) -> Vec<f64> {
+    let (min, max) = (min.min(0.0), max.max(0.0));
+    let range = if max > min { max - min } else { 1.0 };
+    let rough = range / 5.0;
+    let magnitude = 10f64.powf(rough.log10().floor());
+    let step = [1.0, 2.0, 5.0, 10.0]
+        .into_iter()
+        .map(|m| m * magnitude)
+        .find(|step| *step >= rough)
+        .unwrap_or(10.0 * magnitude);
+    // Values near the ends of the f64 range under- or overflow the step
+    let step = if step > 0.0 && step.is_finite() {
+        step
+    } else {
+        1.0
+    };
+
+    let first = (min / step).floor();
+    let last = (max / step).ceil().max(first + 1.0);
+    if last - first > MAX_TICKS as f64 {
+        // Interpolated between the ends, as the span itself may overflow
+        return (0..=MAX_TICKS)
+            .map(|i| {
+                let t = i as f64 / MAX_TICKS as f64;
+                min * (1.0 - t) + max * t
+            })
+            .collect();
+    }
+
+    (first as i64..=last as i64)
+        .map(|i| (i as f64 * step * 1e9).round() / 1e9)
+        .collect()
+}
+
+fn escaped(text: &str) -> String {
+    let mut out = String::new();
+    escape_html(&mut out, text).unwrap();
+    out
+}
+
+fn render(plot: &Plot) -> String {
+    let points = plot.series.iter().map(|(_, v)| v.len()).max().unwrap_or(0);
+    let values = plot.series.iter().flat_map(|(_, v)| v.iter().copied());
+    let (min, max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| {
+        (lo.min(v), hi.max(v))
+    });
+    let ticks = ticks(min, max);
+    let (low, high) = (ticks[0], ticks[ticks.len() - 1]);
+
+    let plot_width = WIDTH - MARGIN_LEFT - MARGIN_RIGHT;
+    let plot_height = HEIGHT - MARGIN_TOP - MARGIN_BOTTOM;
+    // Halved so that spans across most of the f64 range don't overflow
+    let y = |v: f64| MARGIN_TOP + plot_height * ((high / 2.0 - v / 2.0) / (high / 2.0 - low / 2.0));
+    // Bars sit in slots; lines and points span the axis
+    let x = |i: usize| match plot.kind {
+        Kind::Bar => MARGIN_LEFT + plot_width * (i as f64 + 0.5) / points as f64,
+        _ if points == 1 => MARGIN_LEFT + plot_width / 2.0,
+        _ => MARGIN_LEFT + plot_width * i as f64 / (points - 1) as f64,
+    };
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"0 0 {WIDTH} {HEIGHT}\" \
+         role=\"img\" font-family=\"sans-serif\" font-size=\"12\" fill=\"currentColor\">"
+    );
+    if let Some(title) = &plot.title {
+        let _ = write!(
+            svg,
+            "<title>{0}</title><text x=\"{1}\" y=\"20\" text-anchor=\"middle\" font-size=\"14\" font-weight=\"bold\">{0}</text>",
+            escaped(title),
+            WIDTH / 2.0
+        );
+    }
+
+    // Grid lines and y axis labels
+    for tick in &ticks {
+        let (line_y, opacity) = (y(*tick), if *tick == 0.0 { "0.8" } else { "0.3" });
+        let _ = write!(
+            svg,
+            "<line x1=\"{MARGIN_LEFT}\" x2=\"{}\" y1=\"{line_y:.1}\" y2=\"{line_y:.1}\" stroke=\"#8c959f\" stroke-opacity=\"{opacity}\"/>\
+             <text x=\"{}\" y=\"{:.1}\" text-anchor=\"end\">{tick}</text>",
+            WIDTH - MARGIN_RIGHT,
+            MARGIN_LEFT - 6.0,
+            line_y + 4.0,
+        );
+    }
+
+    // X axis labels
+    for i in 0..points {
+        let label = plot
+            .labels
+            .get(i)
+            .cloned()
+            .unwrap_or_else(|| (i + 1).to_string());
+        let _ = write!(
+            svg,
+            "<text x=\"{:.1}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+            x(i),
+            HEIGHT - MARGIN_BOTTOM + 16.0,
+            escaped(&label)
+        );
+    }
+    if let Some(xlabel) = &plot.xlabel {
+        let _ = write!(
+            svg,
+            "<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{}</text>",
+            MARGIN_LEFT + plot_width / 2.0,
+            HEIGHT - MARGIN_BOTTOM + 34.0,
+            escaped(xlabel)
+        );
+    }
+    if let Some(ylabel) = &plot.ylabel {
+        let _ = write!(
+            svg,
+            "<text transform=\"translate(14 {}) rotate(-90)\" text-anchor=\"middle\">{}</text>",
+            MARGIN_TOP + plot_height / 2.0,
+            escaped(ylabel)
+        );
+    }
+
+    // Data
+    let slot = plot_width / points.max(1) as f64;
+    let bar_width = slot * 0.8 / plot.series.len() as f64;
+    for (index, (_, values)) in plot.series.iter().enumerate() {
+        let color = COLORS[index % COLORS.len()];
+        match plot.kind {
+            Kind::Line => {
+                let points: Vec<String> = values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| format!("{:.1},{:.1}", x(i), y(*v)))
+                    .collect();
+                let _ = write!(
+                    svg,
+                    "<polyline points=\"{}\" fill=\"none\" stroke=\"{color}\" stroke-width=\"2\"/>",
+                    points.join(" ")
+                );
+            }
+            Kind::Scatter => {
+                for (i, v) in values.iter().enumerate() {
+                    let _ = write!(
+                        svg,
+                        "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"3.5\" fill=\"{color}\"/>",
+                        x(i),
+                        y(*v)
+                    );
+                }
+            }
+            Kind::Bar => {
+                for (i, v) in values.iter().enumerate() {
+                    let left = x(i) - slot * 0.4 + bar_width * index as f64;
+                    let (top, bottom) = (y(v.max(0.0)), y(v.min(0.0)));
+                    let _ = write!(
+                        svg,
+                        "<rect x=\"{left:.1}\" y=\"{top:.1}\" width=\"{bar_width:.1}\" height=\"{:.1}\" fill=\"{color}\"/>",
+                        bottom - top
+                    );
+                }
+            }
+        }
+    }
+
+    // Legend, when there is more than one series
+    if plot.series.len() > 1 {
+        let mut left = MARGIN_LEFT;
+        for (index, (name, _)) in plot.series.iter().enumerate() {
+            let _ = write!(
+                svg,
+                "<rect x=\"{left:.1}\" y=\"{}\" width=\"10\" height=\"10\" fill=\"{}\"/>\
+                 <text x=\"{:.1}\" y=\"{}\">{}</text>",
+                HEIGHT - 18.0,
+                COLORS[index % COLORS.len()],
+                left + 14.0,
+                HEIGHT - 9.0,
+                escaped(name)
+            );
+            left += 24.0 + 7.0 * name.chars().count() as f64;
+        }
+    }
+
+    svg.push_str("</svg>");
+    svg
+}
+
+/// The chart for a plot block's source, or an error message in its place.
+fn chart(source: &str) -> String {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    let key = hasher.finish();
+    if let Some(svg) = CACHE.lock().unwrap().get(&key) {
+        return svg.clone();
+    }
+
+    let html = match parse(source) {
+        Ok(plot) => format!(
+            "<figure class=\"penview-plot\">{}</figure>\n",
+            render(&plot)
+        ),
+        Err(e) => format!(
+            "<pre class=\"penview-plot-error\">Plot error: {}</pre>\n",
+            escaped(&e)
+        ),
+    };
+
+    let mut cache = CACHE.lock().unwrap();
+    if cache.len() >= CACHE_SIZE {
+        cache.clear();
+    }
+    cache.insert(key, html.clone());
+    html
+}
+
+/// Replaces ```` ```plot ```` code blocks with their chart.
+pub struct Plots;
+
+impl RenderTransformer for Plots {
+    fn name(&self) -> &str {
+        "plot"
+    }
+
+    fn transform<'a>(&self, events: &mut Vec<(Event<'a>, Range<usize>)>) {
+        let mut out = Vec::with_capacity(events.len());
+        let mut block: Option<(String, Range<usize>)> = None;
+
+        for (event, range) in events.drain(..) {
+            match (&mut block, event) {
+                (None, Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(info))))
+                    if info.split(' ').next() == Some("plot") =>
+                {
+                    block = Some((String::new(), range));
+                }
+                (None, event) => out.push((event, range)),
+                (Some((source, _)), Event::Text(text)) => source.push_str(&text),
+                (Some(_), Event::End(TagEnd::CodeBlock)) => {
+                    let (source, range) = block.take().unwrap();
+                    out.push((Event::Html(chart(&source).into()), range));
+                }
+                (Some(_), _) => {}
+            }
+        }
+        *events = out;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::render::{RenderOptions, render_markdown_to_html};
+
+    #[test]
+    fn test_parse() {
+        let plot = parse("# Runs\ntitle: Week\ntype: bar\nx: Mon, Tue\nkm: 3, 5.5\n").unwrap();
+        assert_eq!(
+            plot,
+            Plot {
+                title: Some("Week".to_string()),
+                kind: Kind::Bar,
+                labels: vec!["Mon".to_string(), "Tue".to_string()],
+                series: vec![("km".to_string(), vec![3.0, 5.5])],
+                ..Default::default()
+            }
+        );
+
+        assert_eq!(
+            parse("km 3"),
+            Err("Line 1: expected `key: value`".to_string())
+        );
+        assert_eq!(
+            parse("km: 3, x"),
+            Err("Line 1: `x` isn't a number".to_string())
+        );
+        assert_eq!(
+            parse("type: pie\n"),
+            Err("Line 1: unknown type `pie`".to_string())
+        );
+        assert_eq!(parse("title: Empty\n"), Err("No data series".to_string()));
+    }
+
+    #[test]
+    fn test_ticks() {
+        assert_eq!(ticks(3.0, 8.0), vec![0.0, 2.0, 4.0, 6.0, 8.0]);
+        assert_eq!(ticks(-0.3, 0.2), vec![-0.3, -0.2, -0.1, 0.0, 0.1, 0.2]);
+        assert_eq!(ticks(0.0, 0.0), vec![0.0, 0.2]);
+    }
+
+    #[test]
+    fn test_ticks_extreme_values() {
+        assert_eq!(ticks(5e-324, 5e-324), vec![0.0, 1.0]);
+
+        let ticks = ticks(-f64::MAX, f64::MAX);
+        assert_eq!(ticks.len(), MAX_TICKS + 1);
+        assert!(ticks.iter().all(|tick| tick.is_finite()));
+        assert_eq!(ticks[0], -f64::MAX);
+
+        let plot = parse("y: 5e-324\nz: 1e308, -1e308\n").unwrap();
+        let svg = render(&plot);
+        assert!(svg.starts_with("<svg"));
+        assert!(!svg.contains("NaN") && !svg.contains("inf"));
+    }
+
+    #[tokio::test]
+    async fn test_plot_blocks() {
+        let content = "```plot\ntype: line\nA: 1, 2, 3\nB: 3, 2, 1\n```\n\n```plot\nA: one\n```\n";
+        let html =
+            render_markdown_to_html(content, Path::new("doc.md"), &RenderOptions::default()).await;
+
+        assert!(html.starts_with("<figure class=\"penview-plot\"><svg"));
+        assert_eq!(html.matches("<polyline").count(), 2);
+        assert!(html.contains(
+            "<pre class=\"penview-plot-error\">Plot error: Line 1: `one` isn&#39;t a number</pre>"
+        ));
+        assert!(!html.contains("<code"));
+    }
+}
@@ -0,0 +1,365 @@
+//! Per-project settings. The nearest `.penview.toml` above a document overrides the options the
+//! server or command was started with, so that documents of different projects can be previewed
+//! with the theme, stylesheet and rendering rules of where they are published:
+//!
+//! ```toml
+//! theme = "dark"
+//! style = "github"
+//! locale = "de"
+//! font-family = "Georgia, serif"
+//! font-size = "18px"
+//! max-width = "60rem"
+//! full-width = false
+//! frontmatter-card = true
+//! toc = true
+//! css = "docs/preview.css" # relative to this file
+//! math = "mathml"
+//! highlight = "client"
+//! code-theme = "Solarized (light)"
+//! diagrams = "client"
+//! slugs = "gitlab"
+//! autolink = "urls"
+//! obfuscate-emails = "entities"
+//! title-format = "{h1} - Handbook"
+//!
+//! [links]
+//! rel = "noopener"
+//! target = "_blank"
+//! referrer-policy = "no-referrer"
+//! schemes = ["https", "mailto"]
+//! ```
+//!
+//! As the file comes with the documents, it can't switch `highlight` or `diagrams` to "server",
+//! which would run tools on them, unless the server was started that way.
+
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, anyhow, bail};
+use serde::Deserialize;
+use tracing::warn;
+
+use crate::{
+    headings::SlugStyle,
+    highlight,
+    obfuscate::ObfuscateEmails,
+    page_template::{STYLES, css_value},
+    render::RenderOptions,
+    transform::Transformers,
+};
+
+/// Name of the settings file looked for in the directories above a document.
+pub const FILE_NAME: &str = ".penview.toml";
+
+/// The settings in a `.penview.toml`. Settings left out keep the value of the global options.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct ProjectConfig {
+    theme: Option<String>,
+    style: Option<String>,
+    locale: Option<String>,
+    font_family: Option<String>,
+    mono_font_family: Option<String>,
+    font_size: Option<String>,
+    line_height: Option<String>,
+    max_width: Option<String>,
+    margin: Option<String>,
+    full_width: Option<bool>,
+    frontmatter_card: Option<bool>,
+    toc: Option<bool>,
+    css: Option<PathBuf>,
+    math: Option<String>,
+    highlight: Option<String>,
+    code_theme: Option<String>,
+    code_theme_dark: Option<String>,
+    diagrams: Option<String>,
+    slugs: Option<String>,
+    autolink: Option<String>,
+    obfuscate_emails: Option<String>,
+    title_format: Option<String>,
+    links: LinksConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct LinksConfig {
+    rel: Option<String>,
+    target: Option<String>,
+    referrer_policy: Option<String>,
+    schemes: Option<Vec<String>>,
+}
+
+/// The `value` of the setting `key` over `current`, unless it asks for "server" where the global
+/// options didn't: processing documents on the server is up to whoever started it.
+fn lowered(key: &str, value: Option<String>, current: &str) -> String {
+    match value {
+        Some(value) if value == "server" && current != "server" => {
+            warn!("Ignoring `{key} = \"server\"`, which the server wasn't started with");
+            current.to_string()
+        }
+        Some(value) => value,
+        None => current.to_string(),
+    }
+}
+
+/// Fails unless the setting `key` is one of `allowed`, like the command line options do.
+fn check(key: &str, value: &Option<String>, allowed: &[&str]) -> anyhow::Result<()> {
+    match value {
+        Some(value) if !allowed.contains(&value.as_str()) => {
+            bail!(
+                "`{key}` must be one of {}, got `{value}`",
+                allowed.join(", ")
+            )
+        }
+        _ => Ok(()),
+    }
+}
+
+impl ProjectConfig {
+    fn parse(content: &str) -> anyhow::Result<Self> {
+        let config: Self = toml::from_str(content)?;
+        check("theme", &config.theme, &["light", "dark"])?;
+        let styles: Vec<_> = STYLES.iter().map(|style| style.name).collect();
+        check("style", &config.style, &styles)?;
+        check("math", &config.math, &["katex", "mathjax", "mathml"])?;
+        check("highlight", &config.highlight, &["client", "server"])?;
+        check("diagrams", &config.diagrams, &["client", "server"])?;
+        for (key, value) in [
+            ("code-theme", &config.code_theme),
+            ("code-theme-dark", &config.code_theme_dark),
+        ] {
+            if let Some(value) = value {
+                highlight::theme_name(value).map_err(|e| anyhow!("`{key}` is an {e}"))?;
+            }
+        }
+        check("slugs", &config.slugs, &["github", "gitlab", "pandoc"])?;
+        check("autolink", &config.autolink, &["none", "urls", "extended"])?;
+        check(
+            "obfuscate-emails",
+            &config.obfuscate_emails,
+            &["none", "entities", "script"],
+        )?;
+        for (key, value) in [
+            ("font-family", &config.font_family),
+            ("mono-font-family", &config.mono_font_family),
+            ("font-size", &config.font_size),
+            ("line-height", &config.line_height),
+            ("max-width", &config.max_width),
+            ("margin", &config.margin),
+        ] {
+            if let Some(value) = value {
+                css_value(value).map_err(|e| anyhow!("`{key}` is {e}"))?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Overrides `options` with the settings of the file in `dir`.
+    fn apply(self, options: &mut RenderOptions, dir: &Path) {
+        let links = self.links;
+        options.theme = self.theme.unwrap_or(options.theme.clone());
+        options.style = self.style.unwrap_or(options.style.clone());
+        options.locale = self.locale.unwrap_or(options.locale.clone());
+        let typography = &mut options.typography;
+        typography.font_family = self.font_family.or(typography.font_family.take());
+        typography.mono_font_family = self.mono_font_family.or(typography.mono_font_family.take());
+        typography.font_size = self.font_size.or(typography.font_size.take());
+        typography.line_height = self.line_height.or(typography.line_height.take());
+        let layout = &mut options.layout;
+        layout.max_width = self.max_width.or(layout.max_width.take());
+        layout.margin = self.margin.or(layout.margin.take());
+        layout.full_width = self.full_width.unwrap_or(layout.full_width);
+        // The stylesheet is inlined into the page, so it is confined like any other file
+        if let Some(css) = self.css.map(|css| dir.join(css)) {
+            let css = css.canonicalize().unwrap_or(css);
+            if options.allows(&css) {
+                options.css = Some(css);
+            } else {
+                warn!("Ignoring stylesheet {} outside of the root", css.display());
+            }
+        }
+        options.math = self.math.unwrap_or(options.math.clone());
+        options.highlight = lowered("highlight", self.highlight, &options.highlight);
+        options.code_theme = self.code_theme.unwrap_or(options.code_theme.clone());
+        options.code_theme_dark = self
+            .code_theme_dark
+            .unwrap_or(options.code_theme_dark.clone());
+        options.diagrams = lowered("diagrams", self.diagrams, &options.diagrams);
+        options.frontmatter_card = self.frontmatter_card.unwrap_or(options.frontmatter_card);
+        options.toc = self.toc.unwrap_or(options.toc);
+        options.title_format = self.title_format.unwrap_or(options.title_format.clone());
+        if let Some(slugs) = self.slugs {
+            options.slugs = SlugStyle::from_name(&slugs);
+        }
+        options.links.rel = links.rel.or(options.links.rel.take());
+        options.links.target = links.target.or(options.links.target.take());
+        options.links.referrer_policy = links
+            .referrer_policy
+            .or(options.links.referrer_policy.take());
+        if let Some(schemes) = links.schemes {
+            options.links.schemes = schemes;
+        }
+
+        // The transformers are built from both modes, so rebuild them if either changed
+        if self.autolink.is_some() || self.obfuscate_emails.is_some() {
+            options.autolink = self.autolink.unwrap_or(options.autolink.clone());
+            options.obfuscate_emails = self
+                .obfuscate_emails
+                .unwrap_or(options.obfuscate_emails.clone());
+            options.links.emails = ObfuscateEmails::from_mode(&options.obfuscate_emails);
+            options.transformers = Transformers::with_extensions(
+                &options.autolink,
+                &options.obfuscate_emails,
+                options.emoji,
+            );
+        }
+    }
+}
+
+/// The nearest settings file in the directories containing `path`, not looking outside of the
+/// root directory if file access is restricted.
+pub fn find(path: &Path, options: &RenderOptions) -> Option<PathBuf> {
+    path.ancestors()
+        .skip(if path.is_dir() { 0 } else { 1 })
+        .take_while(|dir| options.allows(dir))
+        .map(|dir| dir.join(FILE_NAME))
+        .find(|file| file.is_file())
+}
+
+/// The options to render the document at `path` with: `options` overridden by the nearest
+/// settings file, if any. A file that can't be read is ignored with a warning.
+pub async fn options_for<'a>(options: &'a RenderOptions, path: &Path) -> Cow<'a, RenderOptions> {
+    let Some(file) = find(path, options) else {
+        return Cow::Borrowed(options);
+    };
+
+    let config = match tokio::fs::read_to_string(&file).await {
+        Ok(content) => ProjectConfig::parse(&content).with_context(|| file.display().to_string()),
+        Err(e) => Err(e).with_context(|| file.display().to_string()),
+    };
+    match config {
+        Ok(config) => {
+            let mut options = options.clone();
+            config.apply(&mut options, file.parent().unwrap_or(Path::new("")));
+            Cow::Owned(options)
+        }
+        Err(e) => {
+            warn!("Ignoring project settings: {e:#}");
+            Cow::Borrowed(options)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_options_for() {
+        let dir = std::env::temp_dir().join(format!(
+            "penview-project-test-{}-options-for",
+            std::process::id()
+        ));
+        let docs = dir.join("docs");
+        std::fs::create_dir_all(&docs).unwrap();
+        std::fs::write(
+            dir.join(FILE_NAME),
+            "theme = \"dark\"\ncss = \"style.css\"\nautolink = \"urls\"\n\n[links]\ntarget = \"_blank\"\n",
+        )
+        .unwrap();
+
+        let mut global = RenderOptions::default();
+        global.links.rel = Some("noopener".to_string());
+        let options = options_for(&global, &docs.join("doc.md")).await;
+        assert_eq!(options.theme, "dark");
+        assert_eq!(options.css, Some(dir.join("style.css")));
+        assert_eq!(options.autolink, "urls");
+        assert_eq!(options.links.target.as_deref(), Some("_blank"));
+        assert_eq!(options.links.rel.as_deref(), Some("noopener"));
+        assert_eq!(options.math, "katex");
+
+        // Nothing is read from outside of the root
+        global.root = Some(docs.clone());
+        assert!(matches!(
+            options_for(&global, &docs.join("doc.md")).await,
+            Cow::Borrowed(_)
+        ));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_css_outside_root() {
+        let dir = std::env::temp_dir().join(format!(
+            "penview-project-test-{}-css-outside-root",
+            std::process::id()
+        ));
+        let root = dir.join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(dir.join("outside.css"), "").unwrap();
+        std::fs::write(root.join("inside.css"), "").unwrap();
+
+        let global = RenderOptions {
+            root: Some(root.canonicalize().unwrap()),
+            ..Default::default()
+        };
+        let css_for = async |css: &str| {
+            std::fs::write(root.join(FILE_NAME), format!("css = \"{css}\"\n")).unwrap();
+            options_for(&global, &root.join("doc.md")).await.css.clone()
+        };
+        assert_eq!(
+            css_for("inside.css").await,
+            Some(root.join("inside.css").canonicalize().unwrap())
+        );
+        assert_eq!(css_for("../outside.css").await, None);
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("outside.css"), root.join("link.css")).unwrap();
+            assert_eq!(css_for("link.css").await, None);
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_server_settings_only_lowered() {
+        let dir = std::env::temp_dir().join(format!(
+            "penview-project-test-{}-server-settings",
+            std::process::id()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let options_with = async |settings: &str, global: &RenderOptions| {
+            std::fs::write(dir.join(FILE_NAME), settings).unwrap();
+            let options = options_for(global, &dir.join("doc.md")).await;
+            (options.highlight.clone(), options.diagrams.clone())
+        };
+
+        let client = RenderOptions::default();
+        let server = RenderOptions {
+            highlight: "server".to_string(),
+            diagrams: "server".to_string(),
+            ..Default::default()
+        };
+        let raise = "highlight = \"server\"\ndiagrams = \"server\"\n";
+        let lower = "highlight = \"client\"\ndiagrams = \"client\"\n";
+        let client_modes = ("client".to_string(), "client".to_string());
+        let server_modes = ("server".to_string(), "server".to_string());
+        assert_eq!(options_with(raise, &client).await, client_modes);
+        assert_eq!(options_with(raise, &server).await, server_modes);
+        assert_eq!(options_with(lower, &server).await, client_modes);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_parse() {
+        assert!(ProjectConfig::parse("math = \"mathml\"\n").is_ok());
+        assert!(ProjectConfig::parse("math = \"latex\"\n").is_err());
+        assert!(ProjectConfig::parse("colour = \"red\"\n").is_err());
+        assert!(ProjectConfig::parse("code-theme = \"Solarized (dark)\"\n").is_ok());
+        assert!(ProjectConfig::parse("code-theme = \"monokai\"\n").is_err());
+        assert!(ProjectConfig::parse("font-family = \"a; } body { color: red\"\n").is_err());
+    }
+}
@@ -1,14 +1,37 @@
-use crate::{page_template::PageTemplate, svg_template::SvgTemplate};
+use crate::{
+    daily, diagram, drafts,
+    export::markdown_files,
+    frontmatter::{self, Metadata},
+    git,
+    headings::{SlugStyle, Slugger},
+    highlight, html, jail,
+    link_policy::LinkPolicy,
+    links, mathml, messages,
+    page_template::{Footer, Layout, PageTemplate, Typography},
+    project, shortcode, spellcheck,
+    svg_template::SvgTemplate,
+    template,
+    transform::Transformers,
+    workers,
+};
 use askama::Template;
 use base64::{Engine, engine::general_purpose};
-use pulldown_cmark::{CodeBlockKind, CowStr, Event, LinkType, Tag, TagEnd};
+use chrono::{DateTime, Local};
+use pulldown_cmark::{Event, HeadingLevel, LinkType, Tag, TagEnd};
 use resolve_path::PathResolveExt;
-use std::path::{Path, PathBuf};
+use std::{
+    borrow::Cow,
+    collections::{HashMap, HashSet},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+use tracing::warn;
 use url::Url;
 
 use tokio::fs::{read, read_to_string};
 
-fn data_url(data: &[u8], mime_type: &str) -> String {
+/// Encodes `data` as a `data:` URL, for embedding it in the page.
+pub fn data_url(data: &[u8], mime_type: &str) -> String {
     let encoded = general_purpose::STANDARD.encode(data);
 
     format!("data:{};base64,{encoded}", mime_type)
@@ -39,6 +62,170 @@ fn generate_message_data_url(message: impl AsRef<str>, color: impl AsRef<str>) -
     )
 }
 
+/// Options controlling how documents are rendered.
+#[derive(Debug, Clone)]
+pub struct RenderOptions {
+    /// Initial theme for the page ("light" or "dark").
+    pub theme: String,
+    /// Whether to show markdownlint-style warnings as margin markers.
+    pub lint: bool,
+    /// Hunspell dictionary (e.g. "en_US") used to underline misspelled words, if enabled.
+    pub spellcheck: Option<String>,
+    /// Whether to show `git blame` information for each block in a gutter.
+    pub blame: bool,
+    /// Whether to add a footer with modification time, git branch/commit, and word count.
+    pub footer: bool,
+    /// Whether to show the frontmatter's title, date and tags in a card above the document,
+    /// instead of hiding the frontmatter.
+    pub frontmatter_card: bool,
+    /// Whether to show a collapsible table of contents of the document's headings beside it.
+    pub toc: bool,
+    /// How to highlight the block under the editor cursor ("none", "flash" or "border").
+    pub cursor_highlight: String,
+    /// What clicking a link to a local file does ("navigate" the preview, open it in the
+    /// "editor", or nothing when "disabled").
+    pub link_navigation: String,
+    /// Whether text in the preview can be edited, with the edits applied to the editor's buffer.
+    pub editable: bool,
+    /// Whether scroll sync keeps the block under the editor cursor centered in the window.
+    pub typewriter: bool,
+    /// Whether browsers previewing the same document follow each other's scrolling.
+    pub mirror_scroll: bool,
+    /// Largest WebSocket message sent to browsers, in bytes; longer ones are sent in chunks.
+    /// 0 for no limit.
+    pub max_frame_size: usize,
+    /// Whether task checkboxes in previews of files not open in Neovim can be clicked, checking
+    /// or unchecking the task in the file.
+    pub toggle_tasks: bool,
+    /// Commands that fenced code blocks can be run with from the preview, by language (see
+    /// [`crate::exec`]). Empty unless explicitly configured.
+    pub exec: HashMap<String, String>,
+    /// Which bare URLs in prose are linked ("none", "urls" or "extended"); see
+    /// [`crate::autolink`]. Only recorded here, the linking is done by a transformer.
+    pub autolink: String,
+    /// Whether `:shortcode:` emoji are replaced (see [`crate::emoji`]). Only recorded here, like
+    /// `autolink`.
+    pub emoji: bool,
+    /// Look of the page, one of [`crate::page_template::STYLES`]: penview's own ("penview"), as
+    /// close to github.com as possible ("github"), or a color style.
+    pub style: String,
+    /// Fonts and sizes of the document.
+    pub typography: Typography,
+    /// Width and margins of the document.
+    pub layout: Layout,
+    /// Language of the page around the document (e.g. "de" or "fr-CA"): its controls are
+    /// translated if there is a catalog for it (see [`messages`]), and it is the page's `lang`.
+    pub locale: String,
+    /// How math is rendered: in the browser by "katex" or "mathjax", or converted to MathML on
+    /// the server ("mathml", see [`mathml`]). Only the selected library is loaded by the page.
+    pub math: String,
+    /// Where fenced code blocks are highlighted: in the browser by highlight.js ("client"), or
+    /// on the server ("server", see [`highlight`]) so the page loads no highlighter.
+    pub highlight: String,
+    /// Theme of code highlighted on the server in light mode, one of syntect's defaults.
+    pub code_theme: String,
+    /// Theme of code highlighted on the server in dark mode.
+    pub code_theme_dark: String,
+    /// Where Mermaid, Graphviz and PlantUML diagrams are drawn: by the page ("client"), or on the
+    /// server ("server", see [`diagram`]) by their command line tools.
+    pub diagrams: String,
+    /// How email addresses are hidden from scrapers: "none", written as character references
+    /// ("entities"), or assembled by the page ("script"); see [`crate::obfuscate`].
+    pub obfuscate_emails: String,
+    /// How heading anchors are generated from their text.
+    pub slugs: SlugStyle,
+    /// Attributes added to links and the URL schemes they may use.
+    pub links: LinkPolicy,
+    /// Whether the document is part of an exported static site, in which case links to other
+    /// documents point at their `.html` files and local images are linked rather than inlined.
+    pub site: bool,
+    /// Format of the page `<title>`; see [`format_title`] for the placeholders.
+    pub title_format: String,
+    /// Event stream transformers run on every render. Includes the built-in draft markers and
+    /// footnotes.
+    pub transformers: Transformers,
+    /// Custom shortcodes, mapping names to templates; see [`shortcode::expand`].
+    pub shortcodes: HashMap<String, String>,
+    /// Whether to run the source through the [`template`] engine before parsing.
+    pub templating: bool,
+    /// Whether to strip draft sections (see [`drafts`]) instead of showing them with a
+    /// watermark. Exports also skip draft documents.
+    pub hide_drafts: bool,
+    /// Root directory of the documents links can point to, used for workspace-wide features
+    /// such as tags, backlinks and links by Zettelkasten ID.
+    pub workspace: PathBuf,
+    /// File name pattern of daily notes (e.g. `YYYY-MM-DD.md`), which get links to the previous
+    /// and next day.
+    pub daily_note_pattern: Option<String>,
+    /// User stylesheet applied on top of the built-in theme.
+    pub css: Option<PathBuf>,
+    /// URL prefix the server is mounted under behind a reverse proxy (e.g. `/penview`), or empty.
+    pub base_path: String,
+    /// Origin the browser reaches the server at when it is proxied (see `X-Forwarded-Proto` and
+    /// `X-Forwarded-Host`), used for absolute URLs in the page.
+    pub origin: Option<String>,
+    /// Canonical directory outside of which no file is read, linked to or inlined, if file
+    /// access is restricted (see [`jail`]).
+    pub root: Option<PathBuf>,
+}
+
+impl RenderOptions {
+    /// Whether `path` may be read, i.e. lies inside the root directory, if there is one.
+    pub fn allows(&self, path: &Path) -> bool {
+        self.root
+            .as_ref()
+            .is_none_or(|root| jail::contains(root, path))
+    }
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        Self {
+            theme: "light".to_string(),
+            lint: false,
+            spellcheck: None,
+            blame: false,
+            footer: false,
+            frontmatter_card: false,
+            toc: false,
+            cursor_highlight: "none".to_string(),
+            link_navigation: "navigate".to_string(),
+            style: "penview".to_string(),
+            typography: Typography::default(),
+            layout: Layout::default(),
+            locale: "en".to_string(),
+            autolink: "none".to_string(),
+            emoji: false,
+            math: "katex".to_string(),
+            highlight: "client".to_string(),
+            diagrams: "client".to_string(),
+            code_theme: "InspiredGitHub".to_string(),
+            code_theme_dark: "base16-ocean.dark".to_string(),
+            obfuscate_emails: "none".to_string(),
+            slugs: SlugStyle::Github,
+            links: LinkPolicy::default(),
+            editable: false,
+            typewriter: false,
+            mirror_scroll: false,
+            max_frame_size: 0,
+            toggle_tasks: false,
+            exec: HashMap::new(),
+            site: false,
+            title_format: "{title}".to_string(),
+            transformers: Transformers::builtin(),
+            shortcodes: HashMap::new(),
+            templating: false,
+            hide_drafts: false,
+            workspace: std::env::current_dir().unwrap_or_default(),
+            daily_note_pattern: Some("YYYY-MM-DD.md".to_string()),
+            css: None,
+            base_path: String::new(),
+            origin: None,
+            root: None,
+        }
+    }
+}
+
 /// Renders a file to an HTML string.
 ///
 /// `use_websocket` determines whether to include code for automatically updating the document with a
@@ -46,88 +233,432 @@ fn generate_message_data_url(message: impl AsRef<str>, color: impl AsRef<str>) -
 pub async fn render_doc(
     path: impl AsRef<Path>,
     use_websocket: bool,
-    theme: &str,
+    options: &RenderOptions,
 ) -> anyhow::Result<String> {
     let path = path.as_ref().canonicalize()?;
     let file = read_to_string(&path).await?;
-    let title = path.as_os_str().to_string_lossy().to_string();
 
-    let body = render_markdown_to_html(&file, &path).await;
+    render_page(&path, &file, use_websocket, options).await
+}
+
+/// Renders a file to the HTML body only, without the surrounding page.
+pub async fn render_doc_body(
+    path: impl AsRef<Path>,
+    options: &RenderOptions,
+) -> anyhow::Result<String> {
+    let path = path.as_ref().canonicalize()?;
+    let file = read_to_string(&path).await?;
+    let options = project::options_for(options, &path).await;
+
+    render_content(&file, &path, &options).await
+}
+
+/// Renders `content` as a full page for the document at `path`, which doesn't have to match the
+/// file on disk (e.g. a past revision).
+pub async fn render_page(
+    path: &Path,
+    content: &str,
+    use_websocket: bool,
+    options: &RenderOptions,
+) -> anyhow::Result<String> {
+    let options = &*project::options_for(options, path).await;
+    let title = format_title(&options.title_format, content, path);
+
+    let body = render_markdown_to_html(content, path, options).await;
+
+    let footer = if options.footer {
+        Some(render_footer(content, path).await)
+    } else {
+        None
+    };
+
+    let css = match &options.css {
+        Some(css) => read_to_string(css)
+            .await
+            .inspect_err(|e| warn!("Failed to read stylesheet {}: {e}", css.display()))
+            .ok(),
+        None => None,
+    };
 
     let template = PageTemplate {
         body,
         title,
         use_websocket,
-        theme: theme.to_string(),
+        theme: options.theme.clone(),
+        style: options.style.clone(),
+        lint: options.lint,
+        blame: options.blame,
+        cursor_highlight: options.cursor_highlight.clone(),
+        link_navigation: options.link_navigation.clone(),
+        editable: options.editable,
+        typewriter: options.typewriter,
+        mirror_scroll: options.mirror_scroll,
+        toggle_tasks: options.toggle_tasks,
+        exec: {
+            let mut languages: Vec<_> = options.exec.keys().cloned().collect();
+            languages.sort();
+            languages
+        },
+        math: options.math.clone(),
+        code_stylesheet: (options.highlight == "server")
+            .then(|| highlight::stylesheet(&options.code_theme, &options.code_theme_dark)),
+        obfuscate_emails: options.obfuscate_emails.clone(),
+        footer,
+        toc: options.toc,
+        // Links to other days only work in the live preview
+        daily: options
+            .daily_note_pattern
+            .as_deref()
+            .filter(|_| use_websocket)
+            .and_then(|pattern| daily::daily_nav(path, pattern)),
+        css,
+        base_path: options.base_path.clone(),
+        origin: options.origin.clone().unwrap_or_default(),
+        lang: options.locale.replace('_', "-"),
+        messages: messages::for_locale(&options.locale),
+        typography: options.typography.clone(),
+        layout: options.layout.clone(),
     };
 
     Ok(template.render().unwrap())
 }
 
+/// Picks a readable title for a document: its first level-1 heading, else the `title` from its
+/// frontmatter, else its file name.
+pub fn document_title(content: &str, path: &Path) -> String {
+    let parser = pulldown_cmark::Parser::new_ext(content, pulldown_cmark::Options::all());
+
+    let mut frontmatter_title = None;
+    let mut metadata = None;
+    let mut heading: Option<String> = None;
+    for event in parser {
+        match event {
+            Event::Start(Tag::MetadataBlock(kind)) => metadata = Some(kind),
+            Event::End(TagEnd::MetadataBlock(_)) => metadata = None,
+            Event::Text(text) if metadata.is_some() && frontmatter_title.is_none() => {
+                frontmatter_title =
+                    metadata.and_then(|kind| frontmatter::field(&text, kind, "title"));
+            }
+            Event::Start(Tag::Heading {
+                level: HeadingLevel::H1,
+                ..
+            }) => heading = Some(String::new()),
+            Event::End(TagEnd::Heading(HeadingLevel::H1)) => {
+                if let Some(heading) = heading.take().filter(|h| !h.trim().is_empty()) {
+                    return heading.trim().to_string();
+                }
+            }
+            Event::Text(text) | Event::Code(text) => {
+                if let Some(heading) = &mut heading {
+                    heading.push_str(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    frontmatter_title.unwrap_or_else(|| {
+        path.file_name()
+            .unwrap_or(path.as_os_str())
+            .to_string_lossy()
+            .into_owned()
+    })
+}
+
+/// Replaces the frontmatter in `events` with a card showing it, if it has anything to show.
+fn show_frontmatter(events: &mut [(Event<'_>, Range<usize>)], base_path: &str) {
+    let Some(start) = events
+        .iter()
+        .position(|(event, _)| matches!(event, Event::Start(Tag::MetadataBlock(_))))
+    else {
+        return;
+    };
+    let Event::Start(Tag::MetadataBlock(kind)) = events[start].0 else {
+        return;
+    };
+    let Some(end) = events[start..]
+        .iter()
+        .position(|(event, _)| matches!(event, Event::End(TagEnd::MetadataBlock(_))))
+        .map(|end| start + end)
+    else {
+        return;
+    };
+
+    let block: String = events[start..end]
+        .iter()
+        .filter_map(|(event, _)| match event {
+            Event::Text(text) => Some(text.as_ref()),
+            _ => None,
+        })
+        .collect();
+    if let Some(card) = Metadata::from_fields(frontmatter::parse(&block, kind)).card(base_path) {
+        events[start].0 = Event::Html(card.into());
+        for (event, _) in &mut events[start + 1..=end] {
+            *event = Event::Html("".into());
+        }
+    }
+}
+
+/// Expands a title format such as `"{filename} — penview"`. Supported placeholders:
+///
+/// - `{title}`: the frontmatter title, falling back to the first level-1 heading and the file name
+/// - `{h1}`: the first level-1 heading, falling back to the frontmatter title and the file name
+/// - `{filename}`: the file name
+/// - `{relpath}`: the path relative to the current directory, or absolute when outside it
+/// - `{path}`: the absolute path
+fn format_title(format: &str, content: &str, path: &Path) -> String {
+    let mut title = format.to_string();
+
+    if title.contains("{title}") {
+        let frontmatter_title = Metadata::of(content).title;
+        title = title.replace(
+            "{title}",
+            &frontmatter_title.unwrap_or_else(|| document_title(content, path)),
+        );
+    }
+    if title.contains("{h1}") {
+        title = title.replace("{h1}", &document_title(content, path));
+    }
+    if let Some(filename) = path.file_name() {
+        title = title.replace("{filename}", &filename.to_string_lossy());
+    }
+    if title.contains("{relpath}") {
+        let relpath = get_relative_path_under_cwd(path.to_path_buf()).unwrap_or(path.into());
+        title = title.replace("{relpath}", &relpath.to_string_lossy());
+    }
+
+    title.replace("{path}", &path.to_string_lossy())
+}
+
+/// Gathers the provenance shown in the page footer. Missing metadata (e.g. outside a git
+/// repository) is left out rather than failing the render.
+async fn render_footer(content: &str, path: &Path) -> Footer {
+    let modified = tokio::fs::metadata(path)
+        .await
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .map(|time| {
+            DateTime::<Local>::from(time)
+                .format("%Y-%m-%d %H:%M")
+                .to_string()
+        });
+
+    Footer {
+        modified,
+        head: git::head(path).await.ok(),
+        words: word_count(content),
+    }
+}
+
+/// Counts whitespace-separated words in the document's prose and inline code, ignoring code
+/// blocks and metadata.
+pub fn word_count(content: &str) -> usize {
+    let parser = pulldown_cmark::Parser::new_ext(content, pulldown_cmark::Options::all());
+
+    let mut words = 0;
+    let mut skip_depth = 0;
+    for event in parser {
+        match event {
+            Event::Start(Tag::CodeBlock(_) | Tag::MetadataBlock(_)) => skip_depth += 1,
+            Event::End(TagEnd::CodeBlock | TagEnd::MetadataBlock(_)) => skip_depth -= 1,
+            Event::Text(text) | Event::Code(text) if skip_depth == 0 => {
+                words += text.split_whitespace().count()
+            }
+            _ => {}
+        }
+    }
+
+    words
+}
+
 /// Renders markdown content from a string to HTML body.
 /// Used for live preview where content comes from Neovim buffer.
 ///
 /// `base_path` is used to resolve relative image paths.
-pub async fn render_content(content: &str, base_path: &Path) -> anyhow::Result<String> {
-    Ok(render_markdown_to_html(content, base_path).await)
+pub async fn render_content(
+    content: &str,
+    base_path: &Path,
+    options: &RenderOptions,
+) -> anyhow::Result<String> {
+    Ok(render_markdown_to_html(content, base_path, options).await)
 }
 
 /// Core markdown rendering logic shared by render_doc and render_content.
-async fn render_markdown_to_html(content: &str, base_path: &Path) -> String {
-    let options = pulldown_cmark::Options::all();
-    let parser = pulldown_cmark::Parser::new_ext(content, options);
-    let mut events: Vec<_> = parser.collect();
+pub async fn render_markdown_to_html(
+    content: &str,
+    base_path: &Path,
+    options: &RenderOptions,
+) -> String {
+    let content = preprocess(content, base_path, options);
+    let mut events = markdown_events(&content, base_path, options).await;
 
-    // Track mermaid code block state
-    let mut in_mermaid_block = false;
+    workers::run(base_path, || {
+        Slugger::new(options.slugs).assign_ids(&mut events);
+        // The HTML is usually somewhat longer than its source
+        let mut body = String::with_capacity(content.len() * 3 / 2);
+        html::push_html(&mut body, &content, &options.links, events);
+        body
+    })
+    .await
+}
 
-    // Handle URLs and mermaid blocks
-    for event in events.iter_mut() {
-        // Handle mermaid code blocks - replace <pre><code> with <pre class="mermaid">
-        match event {
-            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang)))
-                if lang.as_ref() == "mermaid" =>
-            {
-                in_mermaid_block = true;
-                *event = Event::Html(CowStr::from("<pre class=\"mermaid\">"));
-            }
-            Event::Text(text) if in_mermaid_block => {
-                // Emit as raw HTML to prevent escaping (mermaid needs raw text)
-                *event = Event::Html(text.clone());
+/// Rewrites the markdown source before it is parsed: strips drafts and renders it as a template
+/// if enabled, then expands shortcodes. A template that fails to render is left as written.
+pub fn preprocess<'a>(content: &'a str, base_path: &Path, options: &RenderOptions) -> Cow<'a, str> {
+    let mut content = Cow::Borrowed(content);
+    if options.hide_drafts {
+        content = rewrite(content, drafts::strip_drafts);
+    }
+    if options.templating {
+        content = rewrite(content, |content| {
+            match template::render(content, &std::env::vars().collect()) {
+                Ok(rendered) => Cow::Owned(rendered),
+                Err(e) => {
+                    warn!("Failed to render template {}: {e:#}", base_path.display());
+                    Cow::Borrowed(content)
+                }
             }
-            Event::End(TagEnd::CodeBlock) if in_mermaid_block => {
-                in_mermaid_block = false;
-                *event = Event::Html(CowStr::from("</pre>"));
+        });
+    }
+    rewrite(content, |content| {
+        shortcode::expand(
+            content,
+            base_path,
+            &options.shortcodes,
+            options.root.as_deref(),
+        )
+    })
+}
+
+/// Applies a rewrite to `content`, keeping it borrowed if nothing changed.
+fn rewrite<'a>(content: Cow<'a, str>, f: impl FnOnce(&str) -> Cow<'_, str>) -> Cow<'a, str> {
+    match f(&content) {
+        Cow::Borrowed(_) => content,
+        Cow::Owned(rewritten) => Cow::Owned(rewritten),
+    }
+}
+
+/// Parses markdown into events with their source ranges, with spellchecking applied and links
+/// and images rewritten. Heading anchors are left to a [`Slugger`].
+pub async fn markdown_events<'a>(
+    content: &'a str,
+    base_path: &Path,
+    options: &RenderOptions,
+) -> Vec<(Event<'a>, Range<usize>)> {
+    let mut events = workers::run(base_path, || {
+        let parser = pulldown_cmark::Parser::new_ext(content, pulldown_cmark::Options::all());
+        let mut events: Vec<_> = parser.into_offset_iter().collect();
+        options.transformers.apply(&mut events);
+        events
+    })
+    .await;
+
+    process_events(&mut events, base_path, options).await;
+    events
+}
+
+/// Applies spellchecking, renders math server-side if asked to, and rewrites links and images in
+/// parsed events. Runs after the [`Transformers`], so links they add get resolved too.
+pub async fn process_events(
+    events: &mut [(Event<'_>, Range<usize>)],
+    base_path: &Path,
+    options: &RenderOptions,
+) {
+    if let Some(lang) = &options.spellcheck {
+        mark_misspelled_words(events, lang).await;
+    }
+
+    if options.frontmatter_card {
+        show_frontmatter(events, &options.base_path);
+    }
+
+    if options.diagrams == "server" {
+        diagram::render_diagrams(events).await;
+    }
+
+    if options.highlight == "server" {
+        workers::run(base_path, || highlight::highlight_code_blocks(events)).await;
+    }
+
+    if options.math == "mathml" {
+        for (event, _) in events.iter_mut() {
+            match event {
+                Event::InlineMath(tex) => {
+                    *event = Event::InlineHtml(mathml::to_mathml(tex, false).into());
+                }
+                Event::DisplayMath(tex) => {
+                    *event = Event::InlineHtml(mathml::to_mathml(tex, true).into());
+                }
+                _ => {}
             }
-            _ => {}
         }
+    }
+
+    // Handle URLs. The workspace's documents are only listed once a wiki-link needs them.
+    let mut workspace_files = None;
+    for (event, _) in events.iter_mut() {
         // Resolve image links asynchronously
         if let Event::Start(Tag::Image {
-            link_type: LinkType::Inline,
+            link_type,
             dest_url,
             ..
         }) = event
+            && has_destination(*link_type)
         {
-            if dest_url.parse::<Url>().is_ok() {
+            // Exported sites copy images next to the pages instead
+            if dest_url.parse::<Url>().is_ok() || options.site {
                 continue;
             } else {
                 let image_path: PathBuf = dest_url.parse().unwrap();
-                *dest_url = path_to_data_url(image_path.resolve_in(base_path))
-                    .await
-                    .unwrap_or(generate_message_data_url("Disk error.", "red"))
-                    .into()
+                let image_path = image_path.resolve_in(base_path);
+                *dest_url = if options.allows(&image_path) {
+                    path_to_data_url(image_path)
+                        .await
+                        .unwrap_or(generate_message_data_url("Disk error.", "red"))
+                } else {
+                    generate_message_data_url("Outside of the root directory.", "red")
+                }
+                .into()
+            }
+        }
+
+        // Point wiki-links at the note they name
+        if let Event::Start(Tag::Link {
+            link_type: LinkType::WikiLink { .. },
+            dest_url,
+            ..
+        }) = event
+        {
+            if workspace_files.is_none() {
+                workspace_files =
+                    Some(markdown_files(&options.workspace).await.unwrap_or_default());
+            }
+            let files = workspace_files.as_deref().unwrap_or_default();
+            if let Some(href) = wiki_href(dest_url, base_path, files, options) {
+                *dest_url = href.into();
             }
         }
 
         // Rewrite URLs to open links
         if let Event::Start(Tag::Link {
-            link_type: LinkType::Inline,
+            link_type,
             dest_url,
             ..
         }) = event
+            && has_destination(*link_type)
         {
             // If the link is a valid URL, leave it
             if dest_url.parse::<Url>().is_err() {
+                if let Some(href) = zettel_href(dest_url, base_path, options).await {
+                    *dest_url = href.into();
+                    continue;
+                }
+
+                if options.site {
+                    *dest_url = site_link(dest_url).into();
+                    continue;
+                }
+
                 // Otherwise, try to parse it as a file path
                 let file_path: PathBuf = dest_url.parse().unwrap();
                 // If it's a filepath check if it's relative
@@ -141,20 +672,187 @@ async fn render_markdown_to_html(content: &str, base_path: &Path) -> String {
                         file_path.into()
                     };
 
+                    // Files outside the root directory can't be previewed, so aren't linked
+                    if !options.allows(&file_path) {
+                        continue;
+                    }
+
                     // If possible, return a relative path from the cwd
                     let file_path = match get_relative_path_under_cwd(file_path.clone()) {
                         Some(path) => path,
                         None => file_path,
                     };
-                    *dest_url = format!("/?path={}", file_path.to_str().unwrap()).into()
+                    *dest_url = format!(
+                        "{}/?path={}",
+                        options.base_path,
+                        file_path.to_str().unwrap()
+                    )
+                    .into()
                 }
             }
         }
     }
+}
+
+/// Whether a link or image has a written destination, inline (`[text](url)`) or in a reference
+/// definition (`[text][ref]`, `[ref][]`, `[ref]`), rather than being an autolink or a wiki-link.
+fn has_destination(link_type: LinkType) -> bool {
+    matches!(
+        link_type,
+        LinkType::Inline
+            | LinkType::Reference
+            | LinkType::ReferenceUnknown
+            | LinkType::Collapsed
+            | LinkType::CollapsedUnknown
+            | LinkType::Shortcut
+            | LinkType::ShortcutUnknown
+    )
+}
+
+/// Resolves a link to a missing file that looks like a Zettelkasten ID (see [`links::zettel_id`])
+/// to the note in `workspace` with that ID, returning it with the link's `#fragment`, if any.
+async fn resolve_zettel_link(
+    dest_url: &str,
+    base_path: &Path,
+    workspace: &Path,
+) -> Option<(PathBuf, String)> {
+    let (path, fragment) = match dest_url.split_once('#') {
+        Some((path, fragment)) => (path, format!("#{fragment}")),
+        None => (dest_url, String::new()),
+    };
+    let id = links::zettel_id(path)?;
+    if base_path.parent()?.join(path).exists() {
+        return None;
+    }
+
+    let files = markdown_files(workspace).await.ok()?;
+    let target = links::find_by_id(id, &files)?.clone();
+    Some((target, fragment))
+}
+
+/// Returns the link to the note a Zettelkasten ID link points at, if it does.
+async fn zettel_href(dest_url: &str, base_path: &Path, options: &RenderOptions) -> Option<String> {
+    let (target, fragment) = resolve_zettel_link(dest_url, base_path, &options.workspace).await?;
+    note_href(&target, &fragment, base_path, options)
+}
+
+/// Returns the link to the note a wiki-link (`[[note]]`, `[[note#Heading]]` or `[[note|alias]]`)
+/// names, resolved among the workspace's `files` like [`links::resolve_wiki`] does. A heading is
+/// turned into its anchor.
+fn wiki_href(
+    dest_url: &str,
+    base_path: &Path,
+    files: &[PathBuf],
+    options: &RenderOptions,
+) -> Option<String> {
+    let (target, heading) = match dest_url.split_once('#') {
+        Some((target, heading)) => (target, Some(heading)),
+        None => (dest_url, None),
+    };
+    let fragment = heading.map_or(String::new(), |heading| {
+        format!("#{}", options.slugs.base(heading))
+    });
+    // `[[#Heading]]` links within the document
+    if target.is_empty() {
+        return heading.is_some().then_some(fragment);
+    }
+
+    let target = links::resolve_wiki(target, base_path, files)?;
+    note_href(&target, &fragment, base_path, options)
+}
+
+/// The link to the note at `target` from the document at `base_path`, followed by `fragment`.
+fn note_href(
+    target: &Path,
+    fragment: &str,
+    base_path: &Path,
+    options: &RenderOptions,
+) -> Option<String> {
+    if !options.allows(target) {
+        return None;
+    }
+
+    Some(if options.site {
+        let dir = base_path.parent().unwrap_or(Path::new(""));
+        let relative = links::relative_to(target, dir).with_extension("html");
+        format!("{}{fragment}", relative.display())
+    } else {
+        format!("{}/?path={}{fragment}", options.base_path, target.display())
+    })
+}
+
+/// Returns whether `path` looks like a markdown document.
+pub fn is_markdown(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("md") || ext.eq_ignore_ascii_case("markdown"))
+}
+
+/// Points a relative link to another markdown document at the exported `.html` file, keeping
+/// any fragment. Other links are returned unchanged.
+fn site_link(dest_url: &str) -> String {
+    let (path, fragment) = match dest_url.split_once('#') {
+        Some((path, fragment)) => (path, Some(fragment)),
+        None => (dest_url, None),
+    };
+
+    let path = Path::new(path);
+    if !is_markdown(path) {
+        return dest_url.to_string();
+    }
+
+    let mut link = path.with_extension("html").to_string_lossy().into_owned();
+    if let Some(fragment) = fragment {
+        link.push('#');
+        link.push_str(fragment);
+    }
+    link
+}
+
+/// Replaces prose text events containing misspelled words with HTML that underlines them.
+///
+/// Code, math, metadata and image alt text are left alone. Spellchecking is best-effort: if
+/// `hunspell` can't be run the events are left untouched.
+async fn mark_misspelled_words(events: &mut [(Event<'_>, Range<usize>)], lang: &str) {
+    let mut prose = Vec::new();
+    let mut skip_depth = 0;
+    for (index, (event, _)) in events.iter().enumerate() {
+        match event {
+            Event::Start(Tag::CodeBlock(_) | Tag::Image { .. } | Tag::MetadataBlock(_)) => {
+                skip_depth += 1
+            }
+            Event::End(TagEnd::CodeBlock | TagEnd::Image | TagEnd::MetadataBlock(_)) => {
+                skip_depth -= 1
+            }
+            Event::Text(_) if skip_depth == 0 => prose.push(index),
+            _ => {}
+        }
+    }
+
+    let words: HashSet<&str> = prose
+        .iter()
+        .filter_map(|&index| match &events[index].0 {
+            Event::Text(text) => Some(text.as_ref()),
+            _ => None,
+        })
+        .flat_map(|text| spellcheck::words(text).map(|(_, word)| word))
+        .collect();
 
-    let mut body = String::new();
-    pulldown_cmark::html::push_html(&mut body, events.into_iter());
-    body
+    let misspelled = match spellcheck::misspelled_words(lang, words).await {
+        Ok(misspelled) => misspelled,
+        Err(e) => {
+            warn!("Spellcheck failed: {:#}", e);
+            return;
+        }
+    };
+
+    for index in prose {
+        let (event, _) = &mut events[index];
+        if let Event::Text(text) = event
+            && let Some(html) = spellcheck::mark_misspelled(text, &misspelled)
+        {
+            *event = Event::Html(html.into());
+        }
+    }
 }
 
 /// Returns a relative path to a file if it is under the working directory
@@ -364,6 +1062,119 @@ mod tests {
         assert!(!is_child_path(current_file, file));
     }
 
+    #[test]
+    fn test_is_markdown() {
+        assert!(is_markdown(Path::new("notes/todo.md")));
+        assert!(is_markdown(Path::new("README.Markdown")));
+        assert!(!is_markdown(Path::new("image.png")));
+        assert!(!is_markdown(Path::new("md")));
+    }
+
+    #[test]
+    fn test_site_link() {
+        assert_eq!(site_link("guide/intro.md"), "guide/intro.html");
+        assert_eq!(site_link("../notes.md#setup"), "../notes.html#setup");
+        assert_eq!(site_link("#usage"), "#usage");
+        assert_eq!(site_link("diagram.png"), "diagram.png");
+    }
+
+    #[tokio::test]
+    async fn test_reference_links() {
+        let content = "[a](other.md) [b][ref] [ref][] [ref]\n\n\
+                       ![c](missing.png) ![d][img]\n\n\
+                       [ref]: other.md\n[img]: missing.png\n";
+
+        let options = RenderOptions {
+            site: true,
+            ..Default::default()
+        };
+        let html = render_markdown_to_html(content, Path::new("doc.md"), &options).await;
+        assert_eq!(html.matches("href=\"other.html\"").count(), 4);
+
+        let base_path = std::env::temp_dir().join("doc.md");
+        let html = render_markdown_to_html(content, &base_path, &RenderOptions::default()).await;
+        assert_eq!(html.matches("src=\"data:").count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_wiki_links() {
+        let workspace = std::env::temp_dir().join(format!("penview-wiki-{}", std::process::id()));
+        std::fs::create_dir_all(workspace.join("projects")).unwrap();
+        std::fs::write(workspace.join("projects/Roadmap.md"), "# Roadmap").unwrap();
+
+        let options = RenderOptions {
+            workspace: workspace.clone(),
+            ..Default::default()
+        };
+        let content = "[[roadmap|the plan]] [[Roadmap#Next Steps]] [[#Intro]] [[Missing]]\n";
+        let html = render_markdown_to_html(content, &workspace.join("index.md"), &options).await;
+        std::fs::remove_dir_all(&workspace).unwrap();
+
+        let roadmap = workspace.join("projects/Roadmap.md");
+        assert!(html.contains(&format!(
+            "href=\"/?path={}\">the plan</a>",
+            roadmap.display()
+        )));
+        assert!(html.contains(&format!("href=\"/?path={}#next-steps\"", roadmap.display())));
+        assert!(html.contains("href=\"#intro\""));
+        assert!(html.contains("href=\"Missing\""));
+    }
+
+    #[test]
+    fn test_document_title() {
+        let path = Path::new("/notes/todo.md");
+        assert_eq!(
+            document_title("## Intro\n\n# The `penview` *guide*\n\n# Later", path),
+            "The penview guide"
+        );
+        assert_eq!(
+            document_title("---\ntitle: \"From frontmatter\"\n---\n\nText", path),
+            "From frontmatter"
+        );
+        assert_eq!(
+            document_title("+++\ntitle = 'Toml'\n+++\n\n# Heading", path),
+            "Heading"
+        );
+        assert_eq!(document_title("No headings", path), "todo.md");
+    }
+
+    #[test]
+    fn test_format_title() {
+        let path = Path::new("/notes/todo.md");
+        assert_eq!(
+            format_title("{filename} — penview", "# Todo", path),
+            "todo.md — penview"
+        );
+        assert_eq!(
+            format_title("{h1} ({path})", "# Todo", path),
+            "Todo (/notes/todo.md)"
+        );
+        let content = "---\ntitle: Plans\n---\n\n# Todo";
+        assert_eq!(format_title("{title}", content, path), "Plans");
+        assert_eq!(format_title("{h1}", content, path), "Todo");
+        assert_eq!(format_title("{title}", "# Todo", path), "Todo");
+    }
+
+    #[test]
+    fn test_frontmatter_card() {
+        let content = "---\ntitle: Plans\ntags: [a]\n---\n\nText\n";
+        let parser = pulldown_cmark::Parser::new_ext(content, pulldown_cmark::Options::all());
+        let mut events: Vec<_> = parser.into_offset_iter().collect();
+        show_frontmatter(&mut events, "");
+
+        let mut html = String::new();
+        html::push_html(&mut html, content, &LinkPolicy::default(), events);
+        assert!(html.starts_with("<header class=\"penview-frontmatter\""));
+        assert!(!html.contains("tags: [a]"));
+        assert!(html.contains("Text"));
+    }
+
+    #[test]
+    fn test_word_count() {
+        let content = "# Two words\n\nThree `more` words.\n\n```\nnot counted\n```\n";
+        assert_eq!(word_count(content), 5);
+    }
+
     #[test]
     fn test_get_relative_path_under_cwd() {
         let current_dir = std::env::current_dir().unwrap();
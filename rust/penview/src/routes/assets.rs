@@ -0,0 +1,21 @@
+use axum::{
+    extract::Path,
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+
+use crate::assets::find_hashed;
+
+/// Serves a bundled stylesheet or script. Their URLs change with their content, so browsers may
+/// cache them indefinitely.
+pub async fn asset(Path(file): Path<String>) -> Result<impl IntoResponse, StatusCode> {
+    let asset = find_hashed(&file).ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, asset.content_type),
+            (header::CACHE_CONTROL, "public, max-age=31536000, immutable"),
+        ],
+        asset.content,
+    ))
+}
@@ -0,0 +1,94 @@
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::state::AppState;
+
+/// Cookie remembering the token once a page was opened with it, so the page's own requests and
+/// WebSockets don't need it in their URLs.
+const COOKIE: &str = "penview_token";
+
+/// Compares in constant time, so the token can't be guessed from response times.
+fn matches(candidate: &str, token: &str) -> bool {
+    candidate.len() == token.len()
+        && candidate
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn bearer(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+}
+
+fn cookie(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get_all(header::COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(';'))
+        .find_map(|pair| pair.trim().strip_prefix(COOKIE)?.strip_prefix('='))
+}
+
+fn query_token(request: &Request) -> Option<String> {
+    url::form_urlencoded::parse(request.uri().query()?.as_bytes())
+        .find(|(key, _)| key == "token")
+        .map(|(_, value)| value.into_owned())
+}
+
+/// Rejects requests without the server's token, when it has one. The token is accepted as a
+/// bearer token, a `token` query parameter or the cookie set after the latter.
+pub async fn require_token(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let Some(token) = &state.serve.token else {
+        return next.run(request).await;
+    };
+
+    let headers = request.headers();
+    if bearer(headers)
+        .or_else(|| cookie(headers))
+        .is_some_and(|t| matches(t, token))
+    {
+        return next.run(request).await;
+    }
+
+    if !query_token(&request).is_some_and(|t| matches(&t, token)) {
+        return (StatusCode::UNAUTHORIZED, "Missing or invalid token").into_response();
+    }
+
+    let mut response = next.run(request).await;
+    let cookie = format!("{COOKIE}={token}; Path=/; HttpOnly; SameSite=Strict");
+    if let Ok(cookie) = HeaderValue::from_str(&cookie) {
+        response.headers_mut().append(header::SET_COOKIE, cookie);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cookie() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::COOKIE,
+            HeaderValue::from_static("theme=dark; penview_token=abc123"),
+        );
+        assert_eq!(cookie(&headers), Some("abc123"));
+        assert!(matches("abc123", "abc123"));
+        assert!(!matches("abc124", "abc123"));
+        assert!(!matches("abc", "abc123"));
+    }
+}
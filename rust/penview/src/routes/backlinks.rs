@@ -0,0 +1,61 @@
+use std::path::PathBuf;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use resolve_path::PathResolveExt;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    links::{backlinks as find_backlinks, link_index},
+    render::document_title,
+    state::AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct BacklinksParams {
+    path: PathBuf,
+    /// Neovim instance whose buffer to read, when several share the server.
+    #[serde(default)]
+    instance: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Backlink {
+    path: PathBuf,
+    title: String,
+    line: usize,
+    context: String,
+}
+
+/// Returns the workspace documents linking to the file as JSON.
+pub async fn backlinks(
+    Query(BacklinksParams { path, instance }): Query<BacklinksParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Backlink>>, (StatusCode, String)> {
+    let path = path
+        .resolve()
+        .canonicalize()
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("{e:#}")))?;
+    let links = link_index(&state.options.workspace)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("{e:#}")))?;
+
+    let mut backlinks = Vec::new();
+    for link in find_backlinks(&links, &path) {
+        let content = state
+            .source(&instance, &link.source)
+            .await
+            .unwrap_or_default();
+        backlinks.push(Backlink {
+            title: document_title(&content, &link.source),
+            path: link.source.clone(),
+            line: link.line,
+            context: link.context.clone(),
+        });
+    }
+
+    Ok(Json(backlinks))
+}
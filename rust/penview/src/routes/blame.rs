@@ -0,0 +1,22 @@
+use std::path::PathBuf;
+
+use axum::{Json, extract::Query, http::StatusCode};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+
+use crate::git::{self, BlameHunk};
+
+#[derive(Debug, Deserialize)]
+pub struct BlameParams {
+    path: PathBuf,
+}
+
+/// Returns `git blame` hunks for the file on disk as JSON.
+pub async fn blame(
+    Query(BlameParams { path }): Query<BlameParams>,
+) -> Result<Json<Vec<BlameHunk>>, (StatusCode, String)> {
+    git::blame(&path.resolve(), None)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("{e:#}")))
+}
@@ -0,0 +1,47 @@
+use std::path::PathBuf;
+
+use axum::{Json, extract::State};
+use chrono::SecondsFormat;
+use serde::Serialize;
+
+use crate::state::AppState;
+
+/// What `/api/channels` reports about the channel of a previewed document.
+#[derive(Debug, Serialize)]
+pub struct ChannelInfo {
+    /// Neovim instance the document belongs to, empty for a server started by a single editor.
+    instance: String,
+    path: PathBuf,
+    /// Number of browsers watching the document.
+    subscribers: usize,
+    /// When the last update was sent to the browsers (RFC 3339), if one was.
+    last_message: Option<String>,
+    /// Whether the Neovim instance has a preview socket open.
+    editor_connected: bool,
+}
+
+/// Describes the live update channel of every document, to debug previews that don't update:
+/// whether browsers are watching, Neovim is connected, and when updates last went out.
+pub async fn channels(State(state): State<AppState>) -> Json<Vec<ChannelInfo>> {
+    let editors = state.editors.lock().await;
+    let mut channels: Vec<ChannelInfo> = state
+        .channels
+        .lock()
+        .await
+        .iter()
+        .map(|(key, channel)| ChannelInfo {
+            instance: key.instance.clone(),
+            path: key.path.clone(),
+            subscribers: channel.subscribers(),
+            last_message: channel
+                .last_message()
+                .map(|time| time.to_rfc3339_opts(SecondsFormat::Millis, true)),
+            editor_connected: editors
+                .get(&key.instance)
+                .is_some_and(|editor| editor.receiver_count() > 0),
+        })
+        .collect();
+    channels.sort_by(|a, b| (&a.instance, &a.path).cmp(&(&b.instance, &b.path)));
+
+    Json(channels)
+}
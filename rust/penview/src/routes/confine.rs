@@ -0,0 +1,30 @@
+use std::path::Path;
+
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::state::AppState;
+
+/// Query parameters routes read files or directories from.
+const PATH_PARAMS: [&str; 3] = ["path", "follow", "dir"];
+
+/// Rejects requests for files outside the server's root directory, when it has one.
+pub async fn confine_to_root(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let query = request.uri().query().unwrap_or_default();
+    let outside = url::form_urlencoded::parse(query.as_bytes())
+        .filter(|(key, _)| PATH_PARAMS.contains(&key.as_ref()))
+        .any(|(_, value)| !state.options.allows(Path::new(value.as_ref())));
+    if outside {
+        return (StatusCode::FORBIDDEN, "Outside of the root directory").into_response();
+    }
+
+    next.run(request).await
+}
@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+use axum::{
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+
+use crate::{project, state::AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct CssParams {
+    /// The previewed document, whose project may have its own stylesheet.
+    path: Option<PathBuf>,
+}
+
+/// Returns the user stylesheet, so the preview can swap it in when it changes.
+pub async fn css(
+    Query(CssParams { path }): Query<CssParams>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let options = match path {
+        Some(path) => project::options_for(&state.options, &path.resolve()).await,
+        None => std::borrow::Cow::Borrowed(&state.options),
+    };
+    let Some(css) = &options.css else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "No stylesheet configured".to_string(),
+        ));
+    };
+
+    let content = tokio::fs::read_to_string(css)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+    Ok(([(header::CONTENT_TYPE, "text/css")], content))
+}
@@ -0,0 +1,64 @@
+use std::path::PathBuf;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+
+use super::open::REQUEST_HEADER;
+use crate::state::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct EditParams {
+    path: PathBuf,
+    /// Neovim instance editing the file, when several share the server.
+    #[serde(default)]
+    instance: String,
+}
+
+/// A text edit made in the preview.
+#[derive(Debug, Deserialize)]
+pub struct Edit {
+    /// Source line of the edited block, from its `data-source-line` attribute.
+    line: usize,
+    /// Text of the block that was replaced, widened to whole words.
+    old: String,
+    /// Text it was replaced with.
+    new: String,
+}
+
+/// Forwards a text edit made in the preview (see `--editable`) to Neovim, which finds `old` in
+/// the block at `line` and replaces it in the buffer.
+pub async fn edit(
+    Query(EditParams { path, instance }): Query<EditParams>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(Edit { line, old, new }): Json<Edit>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if !state.options.editable || !headers.contains_key(REQUEST_HEADER) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "The preview isn't editable".to_string(),
+        ));
+    }
+    if line == 0 || old.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Nothing to replace".to_string()));
+    }
+
+    let request = serde_json::json!({
+        "type": "edit",
+        "path": path.resolve(),
+        "line": line,
+        "old": old,
+        "new": new,
+    });
+    state
+        .editor_channel(&instance)
+        .await
+        .send(request.to_string())
+        .map(|_| StatusCode::NO_CONTENT)
+        .map_err(|_| (StatusCode::NOT_FOUND, "No editor connected".to_string()))
+}
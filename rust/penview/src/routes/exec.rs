@@ -0,0 +1,72 @@
+use std::path::PathBuf;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+
+use super::open::REQUEST_HEADER;
+use crate::{
+    exec::{self, Output},
+    state::AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ExecParams {
+    path: PathBuf,
+    /// Neovim instance editing the file, when several share the server.
+    #[serde(default)]
+    instance: String,
+    /// Source line of the code block's opening fence, from its `data-source-line` attribute.
+    line: usize,
+}
+
+/// Runs the fenced code block at `line` of a document with the command configured for its
+/// language (see `--exec`), and returns what it printed. The code is taken from the latest
+/// buffer content or the file, never from the request, so only code in the document can run.
+pub async fn exec(
+    Query(ExecParams {
+        path,
+        instance,
+        line,
+    }): Query<ExecParams>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Output>, (StatusCode, String)> {
+    if state.options.exec.is_empty() || !headers.contains_key(REQUEST_HEADER) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Code blocks can't be run".to_string(),
+        ));
+    }
+
+    let path = path.resolve().to_path_buf();
+    if !state.options.allows(&path) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Outside of the root directory".to_string(),
+        ));
+    }
+    let content = state
+        .source(&instance, &path)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("Failed to read file: {e}")))?;
+
+    let (lang, code) = exec::code_block_at(&content, line).ok_or((
+        StatusCode::BAD_REQUEST,
+        format!("No code block at line {line}"),
+    ))?;
+    let command = state.options.exec.get(&lang).ok_or((
+        StatusCode::FORBIDDEN,
+        format!("{lang} code blocks can't be run"),
+    ))?;
+
+    let dir = path.parent().unwrap_or(&path);
+    exec::run(command, &code, dir)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}")))
+}
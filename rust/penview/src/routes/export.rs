@@ -0,0 +1,123 @@
+use std::{
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use axum::{
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+
+use crate::{
+    pdf,
+    render::render_page,
+    state::AppState,
+    webhook::{Event, Payload},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ExportParams {
+    path: PathBuf,
+    /// What to export to: `html` or `pdf`.
+    #[serde(default = "default_format")]
+    format: String,
+    /// Neovim instance whose buffer to read, when several share the server.
+    #[serde(default)]
+    instance: String,
+}
+
+fn default_format() -> String {
+    "html".to_string()
+}
+
+/// Returns a document as a download: a standalone HTML page with its stylesheets and images
+/// inlined and without the live preview's script, like `penview render` writes, or that page
+/// printed to PDF by a headless browser (see `--screenshot-browser`).
+pub async fn export(
+    Query(ExportParams {
+        path,
+        format,
+        instance,
+    }): Query<ExportParams>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let (format, content_type) = match format.as_str() {
+        "html" => ("html", "text/html; charset=utf-8"),
+        "pdf" => ("pdf", "application/pdf"),
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Unsupported format {format}, expected html or pdf"),
+            ));
+        }
+    };
+    let browser = match format {
+        "pdf" => Some(
+            pdf::find_browser(state.serve.screenshot_browser.as_deref()).ok_or((
+                StatusCode::NOT_IMPLEMENTED,
+                "No Chromium-based browser found, see --screenshot-browser".to_string(),
+            ))?,
+        ),
+        _ => None,
+    };
+
+    let started = Instant::now();
+    let path = path.resolve().into_owned();
+    let content = state
+        .source(&instance, &path)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+    let html = render_page(&path, &content, false, &state.options)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}")))?;
+
+    let body = match &browser {
+        Some(browser) => pdf::print(browser, &html)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}")))?,
+        None => html.into_bytes(),
+    };
+
+    let mut exported = Payload::new(Event::Exported, &path).duration(started.elapsed());
+    exported.format = Some(format);
+    state.serve.webhooks.notify(exported);
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, attachment(&path, format)),
+        ],
+        body,
+    ))
+}
+
+/// `Content-Disposition` saving the export as the document's name with `extension`.
+fn attachment(path: &Path, extension: &str) -> String {
+    let name = path.with_extension(extension);
+    let name = name
+        .file_name()
+        .map(|name| name.to_string_lossy().replace(['"', '\\'], "_"))
+        .unwrap_or_else(|| format!("document.{extension}"));
+    format!("attachment; filename=\"{name}\"")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_attachment() {
+        assert_eq!(
+            attachment(Path::new("/notes/todo.md"), "html"),
+            "attachment; filename=\"todo.html\""
+        );
+        assert_eq!(
+            attachment(Path::new("/notes/a\"b.md"), "pdf"),
+            "attachment; filename=\"a_b.pdf\""
+        );
+    }
+}
@@ -0,0 +1,132 @@
+use std::path::{Path, PathBuf};
+
+use axum::{
+    extract::{
+        Query, WebSocketUpgrade,
+        ws::{Message, WebSocket},
+    },
+    response::IntoResponse,
+};
+use notify::{Config, EventKind, RecommendedWatcher, Watcher};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+use tracing::{Instrument, Span, info};
+
+use crate::{export::markdown_files, render::is_markdown};
+
+#[derive(Debug, Deserialize)]
+pub struct FollowParams {
+    /// The directory to watch for saved documents.
+    dir: PathBuf,
+}
+
+/// The most recently modified markdown file under `dir`, if any.
+pub async fn latest_document(dir: &Path) -> Option<PathBuf> {
+    let mut latest = None;
+    for file in markdown_files(dir).await.ok()? {
+        let Ok(modified) = tokio::fs::metadata(&file)
+            .await
+            .and_then(|metadata| metadata.modified())
+        else {
+            continue;
+        };
+        if latest.as_ref().is_none_or(|(time, _)| modified > *time) {
+            latest = Some((modified, file));
+        }
+    }
+    latest.map(|(_, file)| file)
+}
+
+/// Whether a changed path is a document worth following, skipping hidden files and directories
+/// (editor swap files, `.git`).
+fn is_followed(path: &Path, dir: &Path) -> bool {
+    is_markdown(path)
+        && path.is_file()
+        && !path
+            .strip_prefix(dir)
+            .unwrap_or(path)
+            .components()
+            .any(|component| component.as_os_str().to_string_lossy().starts_with('.'))
+}
+
+/// A WebSocket endpoint that watches a directory tree and sends the path of each markdown file
+/// saved in it, so the preview can switch to the document being edited.
+pub async fn follow(ws: WebSocketUpgrade, Query(params): Query<FollowParams>) -> impl IntoResponse {
+    // The session logs under the upgrade request's ID
+    let span = Span::current();
+    ws.on_upgrade(|socket| handle_ws(socket, params).instrument(span))
+}
+
+async fn handle_ws(mut socket: WebSocket, FollowParams { dir }: FollowParams) {
+    let (file_tx, mut file_rx) = tokio::sync::mpsc::unbounded_channel();
+
+    let resolved_dir = dir.resolve().to_path_buf();
+
+    let mut watcher = match RecommendedWatcher::new(
+        move |event| {
+            let _ = file_tx.send(event);
+        },
+        Config::default(),
+    ) {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            info!("Failed to create watcher: {e}");
+            return;
+        }
+    };
+
+    if let Err(e) = watcher.watch(&resolved_dir, notify::RecursiveMode::Recursive) {
+        info!("Failed to watch {}: {e}", resolved_dir.to_string_lossy());
+        return;
+    }
+
+    info!(
+        "Browser connected to follow: {}",
+        resolved_dir.to_string_lossy()
+    );
+
+    let mut last = None;
+    while let Some(event) = file_rx.recv().await {
+        let Ok(event) = event else {
+            continue;
+        };
+        if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+            continue;
+        }
+
+        for path in event.paths {
+            // A save usually fires several events for the same file
+            if !is_followed(&path, &resolved_dir) || last.as_ref() == Some(&path) {
+                continue;
+            }
+
+            info!("Following {}", path.to_string_lossy());
+            let text = path.to_string_lossy().to_string();
+            if socket.send(Message::Text(text.into())).await.is_err() {
+                return;
+            }
+            last = Some(path);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_followed() {
+        let dir = std::env::temp_dir().join("penview-follow-test");
+        std::fs::create_dir_all(dir.join(".git")).unwrap();
+        for file in ["page.md", "notes.txt", ".git/page.md"] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+
+        assert!(is_followed(&dir.join("page.md"), &dir));
+        assert!(!is_followed(&dir.join("notes.txt"), &dir));
+        assert!(!is_followed(&dir.join(".git/page.md"), &dir));
+        assert!(!is_followed(&dir.join("missing.md"), &dir));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
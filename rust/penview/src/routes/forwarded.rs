@@ -0,0 +1,60 @@
+use axum::http::{HeaderMap, header};
+
+/// First value of a comma-separated header, which proxies append to.
+fn first_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+    headers
+        .get(name)?
+        .to_str()
+        .ok()?
+        .split(',')
+        .next()
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+}
+
+/// The origin the browser reached the server at (e.g. `https://docs.example.com`), when a proxy
+/// in front of it sent `X-Forwarded-Proto` or `X-Forwarded-Host`. Without those headers the page
+/// works out URLs from its own location.
+pub fn public_origin(headers: &HeaderMap) -> Option<String> {
+    let proto = first_value(headers, "x-forwarded-proto");
+    let forwarded_host = first_value(headers, "x-forwarded-host");
+    if proto.is_none() && forwarded_host.is_none() {
+        return None;
+    }
+
+    let host = forwarded_host.or_else(|| first_value(headers, header::HOST.as_str()))?;
+    let proto = proto.filter(|proto| matches!(*proto, "http" | "https"));
+    Some(format!("{}://{host}", proto.unwrap_or("http")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.parse().unwrap(), value.parse().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_public_origin() {
+        assert_eq!(public_origin(&headers(&[("host", "127.0.0.1:9876")])), None);
+        assert_eq!(
+            public_origin(&headers(&[
+                ("host", "127.0.0.1:9876"),
+                ("x-forwarded-proto", "https, http"),
+                ("x-forwarded-host", "docs.example.com"),
+            ])),
+            Some("https://docs.example.com".to_string())
+        );
+        assert_eq!(
+            public_origin(&headers(&[
+                ("host", "docs.example.com"),
+                ("x-forwarded-proto", "https"),
+            ])),
+            Some("https://docs.example.com".to_string())
+        );
+    }
+}
@@ -0,0 +1,55 @@
+use std::{path::PathBuf, time::Instant};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Html,
+};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+
+use crate::{
+    project,
+    render::render_markdown_to_html,
+    sanitize::sanitize,
+    state::AppState,
+    webhook::{Event, Payload},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct FragmentParams {
+    path: PathBuf,
+    /// Neovim instance whose buffer to read, when several share the server.
+    #[serde(default)]
+    instance: String,
+}
+
+/// Returns the rendered body of a document without the page around it, sanitized for pasting
+/// into mail clients and word processors as rich text.
+///
+/// Nothing of the page's script runs where the fragment is pasted, so math is converted to
+/// MathML and misspellings aren't marked.
+pub async fn fragment(
+    Query(FragmentParams { path, instance }): Query<FragmentParams>,
+    State(state): State<AppState>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let started = Instant::now();
+    let path = path.resolve().into_owned();
+    let content = state
+        .source(&instance, &path)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+    let mut options = project::options_for(&state.options, &path)
+        .await
+        .into_owned();
+    options.math = "mathml".to_string();
+    options.spellcheck = None;
+    let body = sanitize(&render_markdown_to_html(&content, &path, &options).await);
+
+    let mut exported = Payload::new(Event::Exported, &path).duration(started.elapsed());
+    exported.format = Some("html-fragment");
+    state.serve.webhooks.notify(exported);
+
+    Ok(Html(body))
+}
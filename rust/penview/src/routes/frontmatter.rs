@@ -0,0 +1,33 @@
+use std::path::PathBuf;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+
+use crate::{frontmatter::Metadata, state::AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct FrontmatterParams {
+    path: PathBuf,
+    /// Neovim instance whose buffer to read, when several share the server.
+    #[serde(default)]
+    instance: String,
+}
+
+/// Returns the frontmatter as JSON: its title, date and tags, and all of its top-level fields, for
+/// the latest buffer content pushed from Neovim or else the file on disk.
+pub async fn frontmatter(
+    Query(FrontmatterParams { path, instance }): Query<FrontmatterParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Metadata>, (StatusCode, String)> {
+    let content = state
+        .source(&instance, &path.resolve())
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+    Ok(Json(Metadata::of(&content)))
+}
@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+
+use askama::Template;
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+    response::Html,
+};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+
+use crate::{
+    graph_template::GraphTemplate,
+    links::{Graph, graph as link_graph},
+    messages,
+    state::AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct GraphParams {
+    /// Document to highlight.
+    path: Option<PathBuf>,
+}
+
+/// Serves the interactive graph of the workspace's documents.
+pub async fn graph(
+    Query(GraphParams { path }): Query<GraphParams>,
+    State(state): State<AppState>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let path = path.map(|path| {
+        let path = path.resolve();
+        path.canonicalize()
+            .unwrap_or(path.into_owned())
+            .to_string_lossy()
+            .into_owned()
+    });
+
+    GraphTemplate {
+        theme: state.options.theme.clone(),
+        path,
+        base_path: state.options.base_path.clone(),
+        lang: state.options.locale.replace('_', "-"),
+        messages: messages::for_locale(&state.options.locale),
+    }
+    .render()
+    .map(Html)
+    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}")))
+}
+
+/// Returns the documents of the workspace and the links between them as JSON.
+pub async fn graph_data(
+    State(state): State<AppState>,
+) -> Result<Json<Graph>, (StatusCode, String)> {
+    link_graph(&state.options.workspace)
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("{e:#}")))
+}
@@ -0,0 +1,40 @@
+use std::path::PathBuf;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+
+use crate::{
+    headings::{Heading, headings as heading_tree},
+    state::AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct HeadingsParams {
+    path: PathBuf,
+    /// Neovim instance whose buffer to read, when several share the server.
+    #[serde(default)]
+    instance: String,
+}
+
+/// Returns the heading tree with source lines and anchors as JSON, for the latest buffer content
+/// pushed from Neovim or else the file on disk.
+pub async fn headings(
+    Query(HeadingsParams { path, instance }): Query<HeadingsParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Heading>>, (StatusCode, String)> {
+    let content = state
+        .source(&instance, &path.resolve())
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+    Ok(Json(heading_tree(
+        &content,
+        &state.options.transformers,
+        state.options.slugs,
+    )))
+}
@@ -0,0 +1,22 @@
+use std::path::PathBuf;
+
+use axum::{Json, extract::Query, http::StatusCode};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+
+use crate::git::{self, Revision};
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryParams {
+    path: PathBuf,
+}
+
+/// Returns the commits that touched the file as JSON, newest first.
+pub async fn history(
+    Query(HistoryParams { path }): Query<HistoryParams>,
+) -> Result<Json<Vec<Revision>>, (StatusCode, String)> {
+    git::log(&path.resolve())
+        .await
+        .map(Json)
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("{e:#}")))
+}
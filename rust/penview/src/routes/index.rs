@@ -1,29 +1,109 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use axum::{
     extract::{Query, State},
-    response::Html,
+    http::{HeaderMap, StatusCode},
+    response::{Html, IntoResponse, Redirect, Response},
 };
 use resolve_path::PathResolveExt;
 use serde::Deserialize;
 use tracing::info;
 
-use crate::{render::render_doc, state::AppState};
+use crate::{
+    export::{index_markdown, markdown_files},
+    git,
+    render::{RenderOptions, is_markdown, render_doc, render_page},
+    state::AppState,
+};
+
+use super::{follow::latest_document, forwarded::public_origin};
 
 #[derive(Debug, Deserialize)]
 pub struct IndexParams {
     path: PathBuf,
+    /// Commit to render the document at, instead of the working copy.
+    rev: Option<String>,
+    /// Directory whose most recently saved document the preview follows.
+    follow: Option<PathBuf>,
+}
+
+/// Renders a page linking to every markdown file under `dir`.
+async fn render_listing(dir: &Path, options: &RenderOptions) -> anyhow::Result<String> {
+    let dir = dir.canonicalize()?;
+    let documents: Vec<PathBuf> = markdown_files(&dir)
+        .await?
+        .iter()
+        .filter_map(|file| file.strip_prefix(&dir).ok())
+        .map(Path::to_path_buf)
+        .collect();
+
+    let title = dir.to_string_lossy();
+    let markdown = index_markdown(&title, &documents, |document| dir.join(document));
+
+    render_page(&dir.join("index.md"), &markdown, false, options).await
 }
 
 pub async fn index(
-    Query(IndexParams { path }): Query<IndexParams>,
+    Query(IndexParams { path, rev, follow }): Query<IndexParams>,
     State(state): State<AppState>,
-) -> Html<String> {
-    info!("Rendering document {}", path.to_string_lossy());
+    headers: HeaderMap,
+) -> Result<Response, (StatusCode, String)> {
+    let options = RenderOptions {
+        origin: public_origin(&headers),
+        ..state.options.clone()
+    };
+
+    let Some(rev) = rev else {
+        // Following a directory starts from its most recently modified document
+        if let Some(follow) = follow
+            && path.resolve().is_dir()
+            && let Some(latest) = latest_document(&path.resolve()).await
+        {
+            let query = url::form_urlencoded::Serializer::new(String::new())
+                .append_pair("path", &latest.to_string_lossy())
+                .append_pair("follow", &follow.to_string_lossy())
+                .finish();
+            let base_path = &options.base_path;
+            return Ok(Redirect::to(&format!("{base_path}/?{query}")).into_response());
+        }
+
+        if path.resolve().is_dir() {
+            info!("Rendering directory listing {}", path.to_string_lossy());
 
-    Html(
-        render_doc(path.resolve(), true, &state.theme)
+            return render_listing(&path.resolve(), &options)
+                .await
+                .map(|html| Html(html).into_response())
+                .map_err(|e| (StatusCode::NOT_FOUND, format!("{e:#}")));
+        }
+
+        info!("Rendering document {}", path.to_string_lossy());
+
+        // Documents that don't exist yet (e.g. the next daily note) preview as empty, and fill
+        // in once they are opened in the editor
+        if !path.resolve().exists() && is_markdown(&path) {
+            return render_page(&path.resolve(), "", true, &options)
+                .await
+                .map(|html| Html(html).into_response())
+                .map_err(|e| (StatusCode::NOT_FOUND, format!("{e:#}")));
+        }
+
+        let html = render_doc(path.resolve(), true, &options)
             .await
-            .unwrap(),
-    )
+            .map_err(|e| (StatusCode::NOT_FOUND, format!("{e:#}")))?;
+
+        return Ok(Html(html).into_response());
+    };
+
+    info!("Rendering document {} at {}", path.to_string_lossy(), rev);
+
+    let path = path.resolve();
+    let content = git::show(&path, &rev)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("{e:#}")))?;
+
+    let html = render_page(&path, &content, true, &options)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}")))?;
+
+    Ok(Html(html).into_response())
 }
@@ -0,0 +1,24 @@
+use std::path::PathBuf;
+
+use axum::{Json, extract::Query, http::StatusCode};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+use tokio::fs::read_to_string;
+
+use crate::diagnostic::Diagnostic;
+
+#[derive(Debug, Deserialize)]
+pub struct LintParams {
+    path: PathBuf,
+}
+
+/// Returns markdownlint-style warnings for the file on disk as JSON.
+pub async fn lint(
+    Query(LintParams { path }): Query<LintParams>,
+) -> Result<Json<Vec<Diagnostic>>, (StatusCode, String)> {
+    let content = read_to_string(path.resolve())
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+    Ok(Json(crate::lint::lint(&content)))
+}
@@ -0,0 +1,60 @@
+use axum::{
+    Json,
+    extract::State,
+    http::{HeaderMap, StatusCode, header},
+    response::IntoResponse,
+};
+use serde_json::{Value, json};
+
+use super::forwarded::public_origin;
+use crate::{assets, state::AppState};
+
+/// Serves the icon for browsers that request `/favicon.ico` without looking at the page.
+pub async fn favicon() -> Result<impl IntoResponse, StatusCode> {
+    let icon = assets::get("icon.svg").ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, icon.content_type),
+            (header::CACHE_CONTROL, "public, max-age=86400"),
+        ],
+        icon.content,
+    ))
+}
+
+/// A minimal web app manifest, so the preview can be installed as an app on tablets. The app
+/// opens on the listing of the workspace.
+pub async fn manifest(State(state): State<AppState>, headers: HeaderMap) -> impl IntoResponse {
+    let origin = public_origin(&headers).unwrap_or_default();
+
+    let icon = assets::get("icon.svg").map(|icon| {
+        json!({
+            "src": format!("{origin}{}", icon.url(&state.options.base_path)),
+            "sizes": "any",
+            "type": icon.content_type,
+        })
+    });
+
+    let start_url = format!(
+        "{origin}{}/?{}",
+        state.options.base_path,
+        url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("path", &state.options.workspace.to_string_lossy())
+            .finish()
+    );
+
+    let manifest: Value = json!({
+        "name": "Penview",
+        "short_name": "Penview",
+        "start_url": start_url,
+        "display": "standalone",
+        "background_color": "#ffffff",
+        "theme_color": "#0969da",
+        "icons": icon.into_iter().collect::<Vec<_>>(),
+    });
+
+    (
+        [(header::CONTENT_TYPE, "application/manifest+json")],
+        Json(manifest),
+    )
+}
@@ -1,19 +1,135 @@
-use axum::{Router, routing::get};
+use axum::{
+    Router, middleware,
+    routing::{get, post},
+};
 
 use crate::state::AppState;
 
+pub use rate_limit::Client;
+
+mod assets;
+mod auth;
+mod backlinks;
+mod blame;
+mod channels;
+mod confine;
+mod css;
+mod edit;
+mod exec;
+mod export;
+mod follow;
+mod forwarded;
+mod fragment;
+mod frontmatter;
+mod graph;
+mod headings;
+mod history;
 mod index;
+mod lint;
+mod manifest;
+mod open;
 mod preview;
+mod rate_limit;
+mod raw;
+mod request_id;
+mod screenshot;
+mod search;
+mod session;
+mod sourcemap;
+mod tags;
+mod task;
+mod toc;
 mod watch;
 
+use assets::asset;
+use backlinks::backlinks;
+use blame::blame;
+use channels::channels;
+use css::css;
+use edit::edit;
+use exec::exec;
+use export::export;
+use follow::follow;
+use fragment::fragment;
+use frontmatter::frontmatter;
+use graph::{graph, graph_data};
+use headings::headings;
+use history::history;
 use index::index;
+use lint::lint;
+use manifest::{favicon, manifest};
+use open::open;
 use preview::preview;
+use rate_limit::RateLimitLayer;
+use raw::raw;
+use screenshot::screenshot;
+use search::search;
+use session::session;
+use sourcemap::sourcemap;
+use tags::tags;
+use task::task;
+use toc::toc;
 use watch::watch;
 
 pub fn construct_router(state: AppState) -> Router {
-    Router::new()
+    let routes = Router::new()
         .route("/", get(index))
         .route("/watch", get(watch))
+        .route("/follow", get(follow))
         .route("/api/preview", get(preview))
-        .with_state(state)
+        .route("/api/lint", get(lint))
+        .route("/api/blame", get(blame))
+        .route("/api/history", get(history))
+        .route("/api/raw", get(raw))
+        .route("/api/export", get(export))
+        .route("/api/export/fragment", get(fragment))
+        .route("/api/export/screenshot", get(screenshot))
+        .route("/api/open", post(open))
+        .route("/api/edit", post(edit))
+        .route("/api/task", post(task))
+        .route("/api/exec", post(exec))
+        .route("/api/css", get(css))
+        .route("/assets/{file}", get(asset))
+        .route("/favicon.ico", get(favicon))
+        .route("/manifest.webmanifest", get(manifest))
+        .route("/api/sourcemap", get(sourcemap))
+        .route("/api/backlinks", get(backlinks))
+        .route("/api/headings", get(headings))
+        .route("/api/frontmatter", get(frontmatter))
+        .route("/api/toc", get(toc))
+        .route("/api/search", get(search))
+        .route("/api/session", get(session))
+        .route("/api/channels", get(channels))
+        .route("/tags", get(tags))
+        .route("/graph", get(graph))
+        .route("/api/graph", get(graph_data));
+
+    // Behind a reverse proxy, every route lives under the base path, e.g. `/penview/api/raw`
+    let base_path = state.options.base_path.clone();
+    let router = if base_path.is_empty() {
+        routes
+    } else {
+        Router::new()
+            .nest(&base_path, routes)
+            .route(&format!("{base_path}/"), get(index))
+    };
+
+    // Layers run outside in, so floods and unauthenticated requests are turned away first
+    let router = router
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            confine::confine_to_root,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth::require_token,
+        ));
+    let router = match state.serve.rate_limit {
+        0 => router,
+        per_second => router.layer(RateLimitLayer::new(per_second)),
+    };
+    // Outermost, so every response carries its ID
+    let router = router.layer(middleware::from_fn(request_id::assign_request_id));
+
+    router.with_state(state)
 }
@@ -0,0 +1,45 @@
+use std::path::PathBuf;
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+
+use crate::state::AppState;
+
+/// Header the preview sends with open and edit requests. Pages on other origins can't send it
+/// without a CORS preflight, which the server doesn't answer, so they can't reach the editor.
+pub(super) const REQUEST_HEADER: &str = "x-penview";
+
+#[derive(Debug, Deserialize)]
+pub struct OpenParams {
+    path: PathBuf,
+    /// Neovim instance to open the file in, when several share the server.
+    #[serde(default)]
+    instance: String,
+}
+
+/// Asks Neovim to open a file, when links in the preview open in the editor (see
+/// `--link-navigation`). The request is forwarded over the editor's preview sockets.
+pub async fn open(
+    Query(OpenParams { path, instance }): Query<OpenParams>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if state.options.link_navigation != "editor" || !headers.contains_key(REQUEST_HEADER) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Links don't open in the editor".to_string(),
+        ));
+    }
+
+    let request = serde_json::json!({ "type": "open", "path": path.resolve() });
+    state
+        .editor_channel(&instance)
+        .await
+        .send(request.to_string())
+        .map(|_| StatusCode::NO_CONTENT)
+        .map_err(|_| (StatusCode::NOT_FOUND, "No editor connected".to_string()))
+}
@@ -1,4 +1,7 @@
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
 use axum::{
     extract::{
@@ -8,13 +11,31 @@ use axum::{
     response::IntoResponse,
 };
 use serde::{Deserialize, Serialize};
-use tracing::info;
+use tokio::{sync::mpsc, task::JoinHandle};
+use tracing::{Instrument, Span, info};
 
-use crate::{render::render_content, state::AppState};
+use crate::{
+    block_cache::BlockCache,
+    diagnostic::Diagnostic,
+    git::{self, BlameHunk},
+    headings::{self, Slugger},
+    hooks::{self, HookClient, HookResponse, Hooks},
+    html,
+    lint::lint,
+    project,
+    render::{RenderOptions, markdown_events, preprocess, word_count},
+    sourcemap::{SourceSpan, source_map},
+    state::{AppState, Channel, DocumentKey},
+    vale,
+    webhook::{Event, Payload},
+};
 
 #[derive(Debug, Deserialize)]
 pub struct PreviewParams {
     path: PathBuf,
+    /// ID of the Neovim instance, namespacing its buffers when several share the server.
+    #[serde(default)]
+    instance: String,
 }
 
 /// Input message from Neovim containing buffer content and scroll position.
@@ -27,16 +48,251 @@ struct PreviewInput {
     sync_scroll: bool,
 }
 
+/// Changed lines of the buffer, sent by Neovim instead of the whole content once the server has
+/// it, like the `on_lines` events of `nvim_buf_attach()`.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename = "lines")]
+struct LinesInput {
+    /// Edits in the order they were made.
+    edits: Vec<LineEdit>,
+    cursor_line: usize,
+    total_lines: usize,
+    #[serde(default = "default_sync_scroll")]
+    sync_scroll: bool,
+}
+
+/// Replacement of the 0-based lines `first..last` with `lines`.
+#[derive(Debug, Deserialize)]
+struct LineEdit {
+    first: usize,
+    last: usize,
+    lines: Vec<String>,
+}
+
+/// Applies `edits` to the lines of a document. Returns `false`, leaving the lines partly
+/// edited, if an edit doesn't fit them, in which case they are out of sync with the editor.
+fn apply_edits(lines: &mut Vec<String>, edits: Vec<LineEdit>) -> bool {
+    for LineEdit {
+        first,
+        last,
+        lines: new,
+    } in edits
+    {
+        if first > last || last > lines.len() {
+            return false;
+        }
+        lines.splice(first..last, new);
+    }
+    true
+}
+
+/// Lightweight messages from Neovim that don't carry buffer content and therefore never trigger
+/// a render. Distinguished from [`PreviewInput`] by their `type` field.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum PreviewEvent {
+    /// The cursor moved but the buffer content is unchanged.
+    Cursor {
+        cursor_line: usize,
+        total_lines: usize,
+        #[serde(default = "default_sync_scroll")]
+        sync_scroll: bool,
+    },
+    /// The visible window range changed; lines are 1-based and inclusive, like `line("w0")` and
+    /// `line("w$")`.
+    Viewport {
+        topline: usize,
+        botline: usize,
+        total_lines: usize,
+        #[serde(default = "default_sync_scroll")]
+        sync_scroll: bool,
+        /// Cursor line, so the preview can highlight its block.
+        #[serde(default)]
+        cursor_line: Option<usize>,
+    },
+    /// The visual selection changed. Both lines are absent once the selection is cleared.
+    Selection {
+        #[serde(default)]
+        start_line: Option<usize>,
+        #[serde(default)]
+        end_line: Option<usize>,
+    },
+    /// The buffer's diagnostics changed; replaces any previously sent set.
+    Diagnostics { diagnostics: Vec<Diagnostic> },
+    /// Switch the preview to the "light" or "dark" mode, or to a style (see `--style`).
+    Theme { theme: String },
+    /// The Lua render hooks the editor has registered, sent on connect.
+    Hooks {
+        #[serde(default)]
+        events: bool,
+        #[serde(default)]
+        html: bool,
+        #[serde(default)]
+        slug: bool,
+    },
+    /// The editor's answer to a hook request.
+    HookResponse(HookResponse),
+}
+
 fn default_sync_scroll() -> bool {
     true
 }
 
-/// Output message to browser containing rendered HTML and scroll ratio.
+/// Output message to browser, tagged by `type`.
 #[derive(Debug, Serialize)]
-struct PreviewOutput {
+#[serde(tag = "type", rename_all = "snake_case")]
+enum PreviewOutput {
+    /// Rendered HTML along with the scroll ratio.
+    Render {
+        html: String,
+        scroll_ratio: f64,
+        sync_scroll: bool,
+        cursor_line: usize,
+        /// Lint warnings for the content, when linting is enabled.
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        lint: Vec<Diagnostic>,
+        /// Source span of each top-level block, in document order.
+        source_map: Vec<SourceSpan>,
+        /// Word count for the footer, when the footer is enabled.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        words: Option<usize>,
+    },
+    /// Scroll instruction only; the document body is unchanged.
+    Scroll {
+        scroll_ratio: f64,
+        sync_scroll: bool,
+        cursor_line: usize,
+    },
+    /// Visible range of the editor window as ratios of the document, so the preview can mirror it.
+    Viewport {
+        top_ratio: f64,
+        bottom_ratio: f64,
+        sync_scroll: bool,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        cursor_line: Option<usize>,
+    },
+    /// Source line range to highlight, matched against `data-source-line` attributes.
+    Selection {
+        start_line: Option<usize>,
+        end_line: Option<usize>,
+    },
+    /// Diagnostics to show as margin markers, matched against `data-source-line` attributes.
+    Diagnostics { diagnostics: Vec<Diagnostic> },
+    /// Mode or style to switch to, applied in place.
+    Theme { theme: String },
+    /// Vale alerts for the latest content, sent once the buffer has been idle.
+    Vale { diagnostics: Vec<Diagnostic> },
+    /// `git blame` of the latest content, sent once the buffer has been idle.
+    Blame { hunks: Vec<BlameHunk> },
+}
+
+/// How long the buffer must be idle before slower checks (Vale, git blame) run against it.
+const IDLE_DELAY: Duration = Duration::from_millis(1000);
+
+fn send_output(tx: &Channel, output: &PreviewOutput) {
+    if let Ok(json) = serde_json::to_string(output) {
+        tx.send(json);
+    }
+}
+
+fn scroll_ratio(cursor_line: usize, total_lines: usize) -> f64 {
+    if total_lines > 0 {
+        (cursor_line as f64 / total_lines as f64).clamp(0.0, 1.0)
+    } else {
+        0.0
+    }
+}
+
+/// Runs the checks that are too slow to run on every keystroke.
+async fn run_idle_checks(
+    tx: &Channel,
+    content: &str,
+    path: &Path,
+    vale: Option<&str>,
+    blame: bool,
+) {
+    if let Some(vale) = vale {
+        match vale::check(vale, content, path).await {
+            Ok(diagnostics) => send_output(tx, &PreviewOutput::Vale { diagnostics }),
+            Err(e) => info!("Vale error: {:#}", e),
+        }
+    }
+
+    if blame {
+        match git::blame(path, Some(content)).await {
+            Ok(hunks) => send_output(tx, &PreviewOutput::Blame { hunks }),
+            Err(e) => info!("Blame error: {:#}", e),
+        }
+    }
+}
+
+/// Builds the message for a rendered body, adding lint warnings and the word count when enabled.
+fn render_output(
     html: String,
-    scroll_ratio: f64,
+    content: &str,
+    cursor_line: usize,
+    total_lines: usize,
     sync_scroll: bool,
+    options: &RenderOptions,
+) -> PreviewOutput {
+    let lint = if options.lint {
+        lint(content)
+    } else {
+        Vec::new()
+    };
+
+    PreviewOutput::Render {
+        html,
+        scroll_ratio: scroll_ratio(cursor_line, total_lines),
+        sync_scroll,
+        cursor_line,
+        source_map: source_map(content),
+        lint,
+        words: options.footer.then(|| word_count(content)),
+    }
+}
+
+/// Renders `content` through the editor's Lua hooks. Blocks aren't cached, since the hooks may
+/// change their output at any time.
+async fn render_with_hooks(
+    content: &str,
+    path: &Path,
+    options: &RenderOptions,
+    hooks: Hooks,
+    client: &HookClient,
+) -> String {
+    let content = &*preprocess(content, path, options);
+    let mut events = markdown_events(content, path, options).await;
+
+    if hooks.events {
+        let extracted = hooks::extract(&events);
+        if !extracted.is_empty()
+            && let Some(rewritten) = client.rewrite_events(&extracted).await
+        {
+            hooks::apply(&mut events, rewritten);
+        }
+    }
+
+    let mut slugger = Slugger::new(options.slugs);
+    if hooks.slug {
+        let texts = headings::slug_texts(&events);
+        if !texts.is_empty()
+            && let Some(custom) = client.slugify(&texts).await
+        {
+            slugger = slugger.with_custom(custom);
+        }
+    }
+    slugger.assign_ids(&mut events);
+
+    let mut html = String::new();
+    html::push_html(&mut html, content, &options.links, events);
+
+    if hooks.html
+        && let Some(filtered) = client.filter_html(&html).await
+    {
+        html = filtered;
+    }
+    html
 }
 
 /// WebSocket endpoint for Neovim to push buffer content for live preview.
@@ -45,56 +301,366 @@ pub async fn preview(
     Query(params): Query<PreviewParams>,
     State(state): State<AppState>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_preview(socket, params.path, state))
+    // The session logs under the upgrade request's ID
+    let span = Span::current();
+    ws.on_upgrade(|socket| handle_preview(socket, params, state).instrument(span))
 }
 
-async fn handle_preview(mut socket: WebSocket, path: PathBuf, state: AppState) {
+async fn handle_preview(
+    mut socket: WebSocket,
+    PreviewParams { path, instance }: PreviewParams,
+    state: AppState,
+) {
     info!("Neovim connected for preview: {}", path.display());
+    let mut connected = Payload::new(Event::Connected, &path);
+    connected.client = Some("neovim");
+    state.serve.webhooks.notify(connected);
 
-    let tx = state.get_or_create_channel(&path).await;
+    let tx = state.get_or_create_channel(&instance, &path).await;
+    // Pending idle checks, restarted on every content change
+    let mut idle_task: Option<JoinHandle<()>> = None;
+    let mut cache = BlockCache::default();
+    // Project settings are read once per connection; the page reloads with the new ones
+    let options = project::options_for(&state.options, &path)
+        .await
+        .into_owned();
+    // Lines of the buffer, which `lines` messages edit
+    let mut document: Option<Vec<String>> = None;
+
+    // Hook requests are sent to Neovim from render tasks, and answered on this socket
+    let (hook_tx, mut hook_rx) = mpsc::unbounded_channel();
+    let hook_client = HookClient::new(hook_tx);
+    let mut hooks = Hooks::default();
+    // Render waiting on hooks, superseded by newer content
+    let mut render_task: Option<JoinHandle<()>> = None;
+    // Requests from the preview for the editor, such as files to open
+    let mut editor_rx = state.editor_channel(&instance).await.subscribe();
+
+    loop {
+        let msg = tokio::select! {
+            msg = socket.recv() => match msg {
+                Some(Ok(msg)) => msg,
+                _ => break,
+            },
+            Some(request) = hook_rx.recv() => {
+                if socket.send(Message::Text(request.into())).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+            Ok(request) = editor_rx.recv() => {
+                if socket.send(Message::Text(request.into())).await.is_err() {
+                    break;
+                }
+                continue;
+            }
+        };
 
-    while let Some(Ok(msg)) = socket.recv().await {
         if let Message::Text(text) = msg {
-            // Try to parse as JSON first, fall back to plain text for backwards compatibility
+            // Content-free events skip rendering and are forwarded as-is
+            if let Ok(event) = serde_json::from_str::<PreviewEvent>(&text) {
+                let output = match event {
+                    PreviewEvent::Cursor {
+                        cursor_line,
+                        total_lines,
+                        sync_scroll,
+                    } => PreviewOutput::Scroll {
+                        scroll_ratio: scroll_ratio(cursor_line, total_lines),
+                        sync_scroll,
+                        cursor_line,
+                    },
+                    PreviewEvent::Viewport {
+                        topline,
+                        botline,
+                        total_lines,
+                        sync_scroll,
+                        cursor_line,
+                    } => PreviewOutput::Viewport {
+                        top_ratio: scroll_ratio(topline.saturating_sub(1), total_lines),
+                        bottom_ratio: scroll_ratio(botline, total_lines),
+                        sync_scroll,
+                        cursor_line,
+                    },
+                    PreviewEvent::Selection {
+                        start_line,
+                        end_line,
+                    } => PreviewOutput::Selection {
+                        start_line,
+                        end_line,
+                    },
+                    PreviewEvent::Diagnostics { diagnostics } => {
+                        PreviewOutput::Diagnostics { diagnostics }
+                    }
+                    PreviewEvent::Theme { theme } => PreviewOutput::Theme { theme },
+                    PreviewEvent::Hooks { events, html, slug } => {
+                        hooks = Hooks { events, html, slug };
+                        continue;
+                    }
+                    PreviewEvent::HookResponse(response) => {
+                        hook_client.resolve(response);
+                        continue;
+                    }
+                };
+                send_output(&tx, &output);
+                continue;
+            }
+
             let (content, cursor_line, total_lines, sync_scroll) =
-                match serde_json::from_str::<PreviewInput>(&text) {
-                    Ok(input) => (
-                        input.content,
+                if let Ok(input) = serde_json::from_str::<LinesInput>(&text) {
+                    let applied = document
+                        .as_mut()
+                        .is_some_and(|lines| apply_edits(lines, input.edits));
+                    let (true, Some(lines)) = (applied, &document) else {
+                        // Ask for the whole buffer, and ignore edits until it arrives
+                        document = None;
+                        if socket
+                            .send(Message::Text(r#"{"type":"resync"}"#.into()))
+                            .await
+                            .is_err()
+                        {
+                            break;
+                        }
+                        continue;
+                    };
+                    (
+                        lines.join("\n"),
                         input.cursor_line,
                         input.total_lines,
                         input.sync_scroll,
-                    ),
-                    Err(_) => {
-                        // Backwards compatibility: plain markdown text
-                        let lines = text.lines().count();
-                        (text.to_string(), 1, lines.max(1), false)
-                    }
+                    )
+                } else {
+                    // Try to parse as JSON first, fall back to plain text for backwards
+                    // compatibility
+                    let input = match serde_json::from_str::<PreviewInput>(&text) {
+                        Ok(input) => (
+                            input.content,
+                            input.cursor_line,
+                            input.total_lines,
+                            input.sync_scroll,
+                        ),
+                        Err(_) => {
+                            // Backwards compatibility: plain markdown text
+                            let lines = text.lines().count();
+                            (text.to_string(), 1, lines.max(1), false)
+                        }
+                    };
+                    document = Some(input.0.split('\n').map(str::to_string).collect());
+                    input
                 };
 
-            match render_content(&content, &path).await {
-                Ok(html) => {
-                    let scroll_ratio = if total_lines > 0 {
-                        (cursor_line as f64 / total_lines as f64).clamp(0.0, 1.0)
-                    } else {
-                        0.0
-                    };
+            state
+                .sources
+                .lock()
+                .await
+                .insert(DocumentKey::new(&instance, &path), content.clone());
 
-                    let output = PreviewOutput {
-                        html,
-                        scroll_ratio,
-                        sync_scroll,
-                    };
+            if let Some(task) = idle_task.take() {
+                task.abort();
+            }
+            if state.serve.vale.is_some() || state.options.blame {
+                let (tx, content, path) = (tx.clone(), content.clone(), path.clone());
+                let (vale, blame) = (state.serve.vale.clone(), state.options.blame);
+                idle_task = Some(tokio::spawn(
+                    async move {
+                        tokio::time::sleep(IDLE_DELAY).await;
+                        run_idle_checks(&tx, &content, &path, vale.as_deref(), blame).await;
+                    }
+                    .in_current_span(),
+                ));
+            }
 
-                    if let Ok(json) = serde_json::to_string(&output) {
-                        let _ = tx.send(json);
+            if let Some(task) = render_task.take() {
+                task.abort();
+            }
+            if hooks.any() {
+                // Rendered in a task so the socket keeps serving the hook responses
+                let (tx, path, options) = (tx.clone(), path.clone(), options.clone());
+                let client = hook_client.clone();
+                let webhooks = state.serve.webhooks.clone();
+                render_task = Some(tokio::spawn(
+                    async move {
+                        let started = Instant::now();
+                        let html =
+                            render_with_hooks(&content, &path, &options, hooks, &client).await;
+                        webhooks.notify(
+                            Payload::new(Event::Rendered, &path).duration(started.elapsed()),
+                        );
+                        let output = render_output(
+                            html,
+                            &content,
+                            cursor_line,
+                            total_lines,
+                            sync_scroll,
+                            &options,
+                        );
+                        send_output(&tx, &output);
                     }
-                }
-                Err(e) => {
-                    info!("Render error: {}", e);
-                }
+                    .in_current_span(),
+                ));
+                continue;
             }
+
+            let started = Instant::now();
+            let html = cache.render(&content, &path, &options).await;
+            state
+                .serve
+                .webhooks
+                .notify(Payload::new(Event::Rendered, &path).duration(started.elapsed()));
+            let output = render_output(
+                html,
+                &content,
+                cursor_line,
+                total_lines,
+                sync_scroll,
+                &options,
+            );
+            send_output(&tx, &output);
         }
     }
 
+    for task in [idle_task, render_task].into_iter().flatten() {
+        task.abort();
+    }
+    state
+        .sources
+        .lock()
+        .await
+        .remove(&DocumentKey::new(&instance, &path));
+
     info!("Neovim disconnected: {}", path.display());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cursor_message_is_event() {
+        let msg = r#"{"type":"cursor","cursor_line":10,"total_lines":20}"#;
+        match serde_json::from_str::<PreviewEvent>(msg).unwrap() {
+            PreviewEvent::Cursor {
+                cursor_line,
+                total_lines,
+                sync_scroll,
+            } => {
+                assert_eq!(cursor_line, 10);
+                assert_eq!(total_lines, 20);
+                assert!(sync_scroll);
+            }
+            other => panic!("unexpected event {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_viewport_message_is_event() {
+        let msg = r#"{"type":"viewport","topline":11,"botline":20,"total_lines":40}"#;
+        assert!(matches!(
+            serde_json::from_str::<PreviewEvent>(msg).unwrap(),
+            PreviewEvent::Viewport {
+                topline: 11,
+                botline: 20,
+                total_lines: 40,
+                sync_scroll: true,
+                cursor_line: None,
+            }
+        ));
+    }
+
+    #[test]
+    fn test_selection_message_is_event() {
+        let msg = r#"{"type":"selection","start_line":3,"end_line":7}"#;
+        assert!(matches!(
+            serde_json::from_str::<PreviewEvent>(msg).unwrap(),
+            PreviewEvent::Selection {
+                start_line: Some(3),
+                end_line: Some(7),
+            }
+        ));
+
+        let msg = r#"{"type":"selection"}"#;
+        assert!(matches!(
+            serde_json::from_str::<PreviewEvent>(msg).unwrap(),
+            PreviewEvent::Selection {
+                start_line: None,
+                end_line: None,
+            }
+        ));
+    }
+
+    #[test]
+    fn test_diagnostics_message_is_event() {
+        let msg = r#"{"type":"diagnostics","diagnostics":[{"line":4,"severity":"warning","message":"Unused"}]}"#;
+        let PreviewEvent::Diagnostics { diagnostics } =
+            serde_json::from_str::<PreviewEvent>(msg).unwrap()
+        else {
+            panic!("expected diagnostics event");
+        };
+        assert_eq!(
+            diagnostics,
+            vec![Diagnostic {
+                line: 4,
+                severity: crate::diagnostic::Severity::Warning,
+                message: "Unused".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_apply_edits() {
+        let msg = r#"{"type":"lines","edits":[
+            {"first":1,"last":2,"lines":["b!","b2"]},
+            {"first":4,"last":4,"lines":["d"]},
+            {"first":0,"last":1,"lines":[]}
+        ],"cursor_line":1,"total_lines":4}"#;
+        let input: LinesInput = serde_json::from_str(msg).unwrap();
+        assert!(serde_json::from_str::<PreviewEvent>(msg).is_err());
+
+        let mut lines: Vec<String> = "a\nb\nc".split('\n').map(str::to_string).collect();
+        assert!(apply_edits(&mut lines, input.edits));
+        assert_eq!(lines.join("\n"), "b!\nb2\nc\nd");
+
+        let edits = vec![LineEdit {
+            first: 2,
+            last: 9,
+            lines: Vec::new(),
+        }];
+        assert!(!apply_edits(&mut lines, edits));
+    }
+
+    #[test]
+    fn test_hook_messages_are_events() {
+        let msg = r#"{"type":"hooks","html":true,"slug":true}"#;
+        assert!(matches!(
+            serde_json::from_str::<PreviewEvent>(msg).unwrap(),
+            PreviewEvent::Hooks {
+                events: false,
+                html: true,
+                slug: true,
+            }
+        ));
+
+        let msg = r#"{"type":"hook_response","id":3,"html":"<p>x</p>"}"#;
+        let PreviewEvent::HookResponse(response) =
+            serde_json::from_str::<PreviewEvent>(msg).unwrap()
+        else {
+            panic!("expected hook response");
+        };
+        assert_eq!(response.id, 3);
+        assert_eq!(response.html.as_deref(), Some("<p>x</p>"));
+        assert!(response.events.is_none());
+    }
+
+    #[test]
+    fn test_content_message_is_not_event() {
+        let msg = r#"{"content":"Hi","cursor_line":1,"total_lines":1}"#;
+        assert!(serde_json::from_str::<PreviewEvent>(msg).is_err());
+        assert!(serde_json::from_str::<PreviewInput>(msg).is_ok());
+    }
+
+    #[test]
+    fn test_scroll_ratio() {
+        assert_eq!(scroll_ratio(5, 10), 0.5);
+        assert_eq!(scroll_ratio(20, 10), 1.0);
+        assert_eq!(scroll_ratio(1, 0), 0.0);
+    }
+}
@@ -0,0 +1,220 @@
+use std::{
+    collections::HashMap,
+    future::Future,
+    net::IpAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use axum::{
+    extract::{ConnectInfo, Request, connect_info::Connected},
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+    serve::IncomingStream,
+};
+use tokio::net::TcpListener;
+use tower::{Layer, Service};
+
+/// How many seconds' worth of requests a client may make at once, e.g. for the page, its assets
+/// and its WebSockets when it loads.
+const BURST_SECONDS: f64 = 5.0;
+
+/// Buckets kept before idle ones, or failing that the least recently used, are dropped.
+const MAX_BUCKETS: usize = 1024;
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Who a request came from, as the server's connect info: the client's IP address, or for
+/// clients on a unix socket, which have none, the process on the other end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Client {
+    Ip(IpAddr),
+    Process(Option<i32>),
+}
+
+impl Connected<IncomingStream<'_, TcpListener>> for Client {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        Self::Ip(stream.remote_addr().ip())
+    }
+}
+
+#[cfg(unix)]
+impl Connected<IncomingStream<'_, tokio::net::UnixListener>> for Client {
+    fn connect_info(stream: IncomingStream<'_, tokio::net::UnixListener>) -> Self {
+        Self::Process(stream.io().peer_cred().ok().and_then(|cred| cred.pid()))
+    }
+}
+
+/// Token buckets per client. Requests without connect info share a bucket.
+#[derive(Debug)]
+struct Limiter {
+    per_second: f64,
+    buckets: Mutex<HashMap<Option<Client>, Bucket>>,
+}
+
+impl Limiter {
+    fn burst(&self) -> f64 {
+        self.per_second * BURST_SECONDS
+    }
+
+    /// Takes a token from the client's bucket, or returns how many seconds until one is free.
+    fn check(&self, client: Option<Client>, now: Instant) -> Result<(), f64> {
+        let burst = self.burst();
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_BUCKETS && !buckets.contains_key(&client) {
+            let per_second = self.per_second;
+            buckets.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.updated).as_secs_f64() * per_second
+                    < burst
+            });
+            // Clients still sending requests keep their buckets, so when many are, the one
+            // heard from longest ago makes room
+            if buckets.len() >= MAX_BUCKETS
+                && let Some(oldest) = buckets
+                    .iter()
+                    .min_by_key(|(_, bucket)| bucket.updated)
+                    .map(|(client, _)| *client)
+            {
+                buckets.remove(&oldest);
+            }
+        }
+
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: burst,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.per_second).min(burst);
+        bucket.updated = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err((1.0 - bucket.tokens) / self.per_second)
+        }
+    }
+}
+
+/// Limits how many requests, WebSocket upgrades included, each client address can make per
+/// second, so an exposed server can't be made to render files in a tight loop.
+#[derive(Debug, Clone)]
+pub struct RateLimitLayer {
+    limiter: Arc<Limiter>,
+}
+
+impl RateLimitLayer {
+    pub fn new(per_second: u32) -> Self {
+        Self {
+            limiter: Arc::new(Limiter {
+                per_second: per_second as f64,
+                buckets: Mutex::new(HashMap::new()),
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RateLimit {
+            inner,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimit<S> {
+    inner: S,
+    limiter: Arc<Limiter>,
+}
+
+impl<S> Service<Request> for RateLimit<S>
+where
+    S: Service<Request, Response = Response>,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        let client = request
+            .extensions()
+            .get::<ConnectInfo<Client>>()
+            .map(|ConnectInfo(client)| *client);
+
+        match self.limiter.check(client, Instant::now()) {
+            Ok(()) => Box::pin(self.inner.call(request)),
+            Err(wait) => {
+                let mut response =
+                    (StatusCode::TOO_MANY_REQUESTS, "Too many requests").into_response();
+                response.headers_mut().insert(
+                    header::RETRY_AFTER,
+                    HeaderValue::from(wait.ceil().max(1.0) as u64),
+                );
+                Box::pin(async { Ok(response) })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let limiter = RateLimitLayer::new(2).limiter;
+        let start = Instant::now();
+        let client = Some(Client::Ip(IpAddr::from([192, 168, 1, 2])));
+
+        // A burst of 10, then nothing until a token refills
+        for _ in 0..10 {
+            assert!(limiter.check(client, start).is_ok());
+        }
+        assert_eq!(limiter.check(client, start), Err(0.5));
+        assert!(
+            limiter
+                .check(client, start + Duration::from_millis(500))
+                .is_ok()
+        );
+
+        // Other clients have their own buckets
+        assert!(limiter.check(None, start).is_ok());
+        assert!(limiter.check(Some(Client::Process(Some(1))), start).is_ok());
+    }
+
+    #[test]
+    fn test_buckets_bounded() {
+        let limiter = RateLimitLayer::new(2).limiter;
+        let start = Instant::now();
+        let client = |i: usize| Some(Client::Process(Some(i as i32)));
+
+        // Clients that keep sending requests don't let the map grow past its bound
+        for i in 0..MAX_BUCKETS * 2 {
+            let now = start + Duration::from_micros(i as u64);
+            assert!(limiter.check(client(i), now).is_ok());
+            assert!(limiter.check(client(i), now).is_ok());
+        }
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(buckets.len(), MAX_BUCKETS);
+        assert!(buckets.contains_key(&client(MAX_BUCKETS * 2 - 1)));
+        assert!(!buckets.contains_key(&client(0)));
+    }
+}
@@ -0,0 +1,46 @@
+use std::path::PathBuf;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+
+use crate::{git, state::AppState};
+
+#[derive(Debug, Deserialize)]
+pub struct RawParams {
+    path: PathBuf,
+    /// Commit to read the document at, instead of the working copy.
+    rev: Option<String>,
+    /// Neovim instance whose buffer to read, when several share the server.
+    #[serde(default)]
+    instance: String,
+}
+
+/// Returns the raw markdown source as plain text.
+///
+/// For the working copy this is the latest buffer content pushed from Neovim, falling back to
+/// the file on disk, so it always matches the rendered preview.
+pub async fn raw(
+    Query(RawParams {
+        path,
+        rev,
+        instance,
+    }): Query<RawParams>,
+    State(state): State<AppState>,
+) -> Result<String, (StatusCode, String)> {
+    let path = path.resolve();
+
+    if let Some(rev) = rev {
+        return git::show(&path, &rev)
+            .await
+            .map_err(|e| (StatusCode::NOT_FOUND, format!("{e:#}")));
+    }
+
+    state
+        .source(&instance, &path)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))
+}
@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{HeaderName, HeaderValue, header},
+    middleware::Next,
+    response::Response,
+};
+use tracing::Instrument;
+
+/// Response header carrying the request's ID.
+const HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Error bodies larger than this are passed through without the ID.
+const MAX_ERROR_BODY: usize = 64 * 1024;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Gives every request, and so every WebSocket session, an ID that is attached to its log lines
+/// (as a `request` span), returned in the `X-Request-Id` header and appended to plain-text error
+/// messages. Users filing bugs can then point at the log lines of their preview.
+pub async fn assign_request_id(request: Request, next: Next) -> Response {
+    let id = format!("{:08x}", NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let span = tracing::info_span!("request", id = %id);
+
+    let mut response = next.run(request).instrument(span).await;
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(HEADER, value);
+    }
+
+    let is_text = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/plain"));
+    if !(response.status().is_client_error() || response.status().is_server_error()) || !is_text {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, MAX_ERROR_BODY).await else {
+        return Response::from_parts(parts, Body::empty());
+    };
+    let message = format!("{}\n\nRequest ID: {id}", String::from_utf8_lossy(&bytes));
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::from(message))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::{Router, http::StatusCode, middleware, routing::get};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_assign_request_id() {
+        let app = Router::new()
+            .route("/ok", get(|| async { "fine" }))
+            .route(
+                "/missing",
+                get(|| async { (StatusCode::NOT_FOUND, "No such file".to_string()) }),
+            )
+            .layer(middleware::from_fn(assign_request_id));
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/ok").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let id = response.headers()[&HEADER].to_str().unwrap().to_string();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, "fine");
+
+        let response = app
+            .oneshot(Request::get("/missing").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let next_id = response.headers()[&HEADER].to_str().unwrap().to_string();
+        assert_ne!(next_id, id);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(body, format!("No such file\n\nRequest ID: {next_id}"));
+    }
+}
@@ -0,0 +1,85 @@
+use std::{path::PathBuf, time::Instant};
+
+use axum::{
+    extract::{Query, State},
+    http::{StatusCode, header},
+    response::IntoResponse,
+};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+
+use crate::{
+    headings, project,
+    render::render_page,
+    screenshot,
+    state::AppState,
+    webhook::{Event, Payload},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct ScreenshotParams {
+    path: PathBuf,
+    /// Anchor of the heading whose section to capture, instead of the whole document.
+    heading: Option<String>,
+    /// Width of the browser window in pixels.
+    #[serde(default = "default_width")]
+    width: u32,
+    /// Neovim instance whose buffer to read, when several share the server.
+    #[serde(default)]
+    instance: String,
+}
+
+fn default_width() -> u32 {
+    900
+}
+
+/// Returns a PNG screenshot of a document, or of one of its sections, as rendered by a headless
+/// browser (see `--screenshot-browser`).
+pub async fn screenshot(
+    Query(ScreenshotParams {
+        path,
+        heading,
+        width,
+        instance,
+    }): Query<ScreenshotParams>,
+    State(state): State<AppState>,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    let Some(browser) = state
+        .serve
+        .screenshot_browser
+        .clone()
+        .or_else(screenshot::find_browser)
+    else {
+        return Err((
+            StatusCode::NOT_IMPLEMENTED,
+            "No headless browser found, see --screenshot-browser".to_string(),
+        ));
+    };
+
+    let started = Instant::now();
+    let path = path.resolve().into_owned();
+    let content = state
+        .source(&instance, &path)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+    let options = project::options_for(&state.options, &path).await;
+    let content = match &heading {
+        Some(anchor) => headings::section(&content, anchor, &options.transformers, options.slugs)
+            .ok_or_else(|| (StatusCode::NOT_FOUND, format!("No heading #{anchor}")))?,
+        None => &content,
+    };
+
+    let html = render_page(&path, content, false, &options)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}")))?;
+    let png = screenshot::capture(&browser, &html, width.clamp(200, 4000))
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("{e:#}")))?;
+
+    let mut exported = Payload::new(Event::Exported, &path).duration(started.elapsed());
+    exported.format = Some("png");
+    state.serve.webhooks.notify(exported);
+
+    Ok(([(header::CONTENT_TYPE, "image/png")], png))
+}
@@ -0,0 +1,37 @@
+use std::path::PathBuf;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+
+use crate::{
+    search::{Match, index, search as find_matches},
+    state::AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct SearchParams {
+    path: PathBuf,
+    q: String,
+    /// Neovim instance whose buffer to read, when several share the server.
+    #[serde(default)]
+    instance: String,
+}
+
+/// Returns the blocks matching the query as JSON, for the latest buffer content pushed from
+/// Neovim or else the file on disk.
+pub async fn search(
+    Query(SearchParams { path, q, instance }): Query<SearchParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Match>>, (StatusCode, String)> {
+    let content = state
+        .source(&instance, &path.resolve())
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+    Ok(Json(find_matches(&index(&content), &q)))
+}
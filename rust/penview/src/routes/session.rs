@@ -0,0 +1,9 @@
+use axum::{Json, extract::State};
+
+use crate::{session::Session, state::AppState};
+
+/// Returns the documents that were previewed when the server last ran, so that they can be
+/// reopened after a restart.
+pub async fn session(State(state): State<AppState>) -> Json<Session> {
+    Json(state.serve.previous_session.clone())
+}
@@ -0,0 +1,36 @@
+use std::path::PathBuf;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+
+use crate::{
+    sourcemap::{SourceSpan, source_map},
+    state::AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct SourceMapParams {
+    path: PathBuf,
+    /// Neovim instance whose buffer to read, when several share the server.
+    #[serde(default)]
+    instance: String,
+}
+
+/// Returns the source span of every top-level rendered block as JSON, for the latest buffer
+/// content pushed from Neovim or else the file on disk.
+pub async fn sourcemap(
+    Query(SourceMapParams { path, instance }): Query<SourceMapParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<SourceSpan>>, (StatusCode, String)> {
+    let content = state
+        .source(&instance, &path.resolve())
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+    Ok(Json(source_map(&content)))
+}
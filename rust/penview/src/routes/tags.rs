@@ -0,0 +1,52 @@
+use std::path::Path;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Html,
+};
+use serde::Deserialize;
+
+use crate::{
+    export::index_markdown,
+    render::render_page,
+    state::AppState,
+    tags::{tag_index, tags_markdown},
+};
+
+#[derive(Debug, Deserialize)]
+pub struct TagsParams {
+    /// Tag to list the documents of, instead of listing every tag.
+    tag: Option<String>,
+}
+
+/// Lists the workspace's tags, or the documents with a given tag.
+pub async fn tags(
+    Query(TagsParams { tag }): Query<TagsParams>,
+    State(state): State<AppState>,
+) -> Result<Html<String>, (StatusCode, String)> {
+    let workspace = &state.options.workspace;
+    let index = tag_index(workspace)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("{e:#}")))?;
+
+    let markdown = match tag {
+        Some(tag) => {
+            let documents = index.get(&tag).map(Vec::as_slice).unwrap_or_default();
+            index_markdown(&format!("#{tag}"), documents, |document| {
+                workspace.join(document)
+            })
+        }
+        None => tags_markdown(&index, &state.options.base_path),
+    };
+
+    render_page(
+        &workspace.join(Path::new("tags.md")),
+        &markdown,
+        false,
+        &state.options,
+    )
+    .await
+    .map(Html)
+    .map_err(|e| (StatusCode::NOT_FOUND, format!("{e:#}")))
+}
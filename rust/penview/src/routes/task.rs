@@ -0,0 +1,70 @@
+use std::path::PathBuf;
+
+use axum::{
+    extract::{Query, State},
+    http::{HeaderMap, StatusCode},
+};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+
+use super::open::REQUEST_HEADER;
+use crate::{render::is_markdown, state::AppState, tasks};
+
+#[derive(Debug, Deserialize)]
+pub struct TaskParams {
+    path: PathBuf,
+    /// Source line of the task list item, from its `data-source-line` attribute.
+    line: usize,
+    checked: bool,
+}
+
+/// Checks or unchecks a task in a file on disk, when task checkboxes can be toggled (see
+/// `--toggle-tasks`). Files open in Neovim are left alone, since the buffer would overwrite the
+/// change. The file watcher then reloads the preview.
+pub async fn task(
+    Query(TaskParams {
+        path,
+        line,
+        checked,
+    }): Query<TaskParams>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<StatusCode, (StatusCode, String)> {
+    if !state.options.toggle_tasks || !headers.contains_key(REQUEST_HEADER) {
+        return Err((StatusCode::FORBIDDEN, "Tasks can't be toggled".to_string()));
+    }
+
+    let path = path.resolve().to_path_buf();
+    if !is_markdown(&path) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Only markdown files have tasks".to_string(),
+        ));
+    }
+    if state
+        .sources
+        .lock()
+        .await
+        .keys()
+        .any(|key| key.path == path)
+    {
+        return Err((
+            StatusCode::CONFLICT,
+            "The file is open in Neovim".to_string(),
+        ));
+    }
+
+    let content = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, format!("Failed to read file: {e}")))?;
+    let toggled = tasks::toggle(&content, line, checked)
+        .ok_or((StatusCode::BAD_REQUEST, format!("Line {line} isn't a task")))?;
+    tokio::fs::write(&path, toggled).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to write file: {e}"),
+        )
+    })?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use resolve_path::PathResolveExt;
+use serde::Deserialize;
+
+use crate::{
+    headings::{Heading, headings, prune},
+    state::AppState,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct TocParams {
+    path: PathBuf,
+    /// Deepest heading level listed, e.g. 3 for `#` to `###`.
+    #[serde(default = "default_depth")]
+    depth: u8,
+    /// Neovim instance whose buffer to read, when several share the server.
+    #[serde(default)]
+    instance: String,
+}
+
+fn default_depth() -> u8 {
+    6
+}
+
+/// Returns the table of contents as JSON: the heading tree down to `depth`, with the source
+/// line and anchor of each heading, like the page's sidebar lists it.
+pub async fn toc(
+    Query(TocParams {
+        path,
+        depth,
+        instance,
+    }): Query<TocParams>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Heading>>, (StatusCode, String)> {
+    let content = state
+        .source(&instance, &path.resolve())
+        .await
+        .map_err(|e| (StatusCode::NOT_FOUND, e.to_string()))?;
+
+    let tree = headings(&content, &state.options.transformers, state.options.slugs);
+    Ok(Json(prune(tree, depth)))
+}
@@ -1,4 +1,7 @@
-use std::path::PathBuf;
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use axum::{
     extract::{
@@ -9,28 +12,152 @@ use axum::{
 };
 use notify::{Config, RecommendedWatcher, Watcher};
 use resolve_path::PathResolveExt;
-use serde::Deserialize;
-use tracing::info;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+use tracing::{Instrument, Span, info};
 
-use crate::state::AppState;
+use crate::{
+    project,
+    state::AppState,
+    webhook::{Event, Payload},
+};
 
 #[derive(Debug, Deserialize)]
 pub struct WatchParams {
     /// The path to watch for changes.
     path: PathBuf,
+    /// Neovim instance whose live updates to receive, when several share the server.
+    #[serde(default)]
+    instance: String,
+}
+
+/// Scroll position of a browser, relayed to the other browsers watching the same document when
+/// scrolling is mirrored (see `--mirror-scroll`).
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", rename = "mirror_scroll")]
+struct MirrorScroll {
+    /// Random ID of the browser that scrolled, so that it can ignore its own position.
+    client: String,
+    /// Position of the top of the window, as a fraction of the page height.
+    ratio: f64,
+}
+
+/// Sent by a browser once it has applied a rendered update, so that it is sent the next one.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename = "ack")]
+struct Ack {}
+
+/// How long to wait for a browser to acknowledge an update before sending it the next one
+/// anyway, e.g. when its tab is in the background.
+const ACK_TIMEOUT: Duration = Duration::from_secs(2);
+
+fn is_render(message: &str) -> bool {
+    message.starts_with(r#"{"type":"render""#)
+}
+
+/// Takes the update held back while the browser applied the previous one, starting to wait for
+/// its acknowledgment if there is one.
+fn next_frame(held: &mut Option<String>, ack_deadline: &mut Option<Instant>) -> Option<String> {
+    let frame = held.take();
+    *ack_deadline = frame.as_ref().map(|_| Instant::now() + ACK_TIMEOUT);
+    frame
+}
+
+/// Part of a message too long to send at once (see `--max-frame-size`). The page joins the
+/// `data` of chunks `0..count` with the same `id` back into the message.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename = "chunk")]
+struct Chunk<'a> {
+    id: u64,
+    index: usize,
+    count: usize,
+    data: &'a str,
+}
+
+/// Room left in a frame for the fields of a [`Chunk`] around its data.
+const CHUNK_OVERHEAD: usize = 128;
+
+/// Length of `c` in a JSON string.
+fn json_len(c: char) -> usize {
+    match c {
+        '"' | '\\' | '\n' | '\r' | '\t' | '\u{8}' | '\u{c}' => 2,
+        c if c < ' ' => 6,
+        c => c.len_utf8(),
+    }
+}
+
+/// Splits `message` into frames of at most `max_size` bytes, or returns it whole if it fits or
+/// `max_size` is 0.
+fn frames(message: String, id: u64, max_size: usize) -> Vec<String> {
+    if max_size == 0 || message.len() <= max_size {
+        return vec![message];
+    }
+
+    // Cut the message where the escaped data fills a frame
+    let room = max_size.saturating_sub(CHUNK_OVERHEAD).max(1);
+    let mut parts = Vec::new();
+    let (mut start, mut len) = (0, 0);
+    for (i, c) in message.char_indices() {
+        if len + json_len(c) > room && i > start {
+            parts.push(&message[start..i]);
+            (start, len) = (i, 0);
+        }
+        len += json_len(c);
+    }
+    parts.push(&message[start..]);
+
+    let count = parts.len();
+    parts
+        .into_iter()
+        .enumerate()
+        .map(|(index, data)| {
+            serde_json::to_string(&Chunk {
+                id,
+                index,
+                count,
+                data,
+            })
+            .unwrap()
+        })
+        .collect()
+}
+
+/// Sends `message` to the browser, in chunks if it is too long.
+async fn send(
+    socket: &mut WebSocket,
+    message: String,
+    next_id: &mut u64,
+    max_size: usize,
+) -> Result<(), axum::Error> {
+    *next_id += 1;
+    for frame in frames(message, *next_id, max_size) {
+        socket.send(Message::Text(frame.into())).await?;
+    }
+    Ok(())
 }
 
 /// A WebSocket endpoint that watches files for changes and notifies the client when they occur.
-/// Also receives live preview updates from Neovim via the broadcast channel.
+/// Also receives live preview updates from Neovim via the broadcast channel, and scroll positions
+/// from the other browsers watching the same document.
+///
+/// Rendered updates are paced by the browser: after sending one, newer ones are held back until
+/// the browser acknowledges it, and only the latest of them is sent, so a browser on a slow link
+/// skips intermediate states instead of falling behind.
 pub async fn watch(
     ws: WebSocketUpgrade,
     Query(params): Query<WatchParams>,
     State(state): State<AppState>,
 ) -> impl IntoResponse {
-    ws.on_upgrade(|socket| handle_ws(socket, params, state))
+    // The session logs under the upgrade request's ID
+    let span = Span::current();
+    ws.on_upgrade(|socket| handle_ws(socket, params, state).instrument(span))
 }
 
-async fn handle_ws(mut socket: WebSocket, WatchParams { path }: WatchParams, state: AppState) {
+async fn handle_ws(
+    mut socket: WebSocket,
+    WatchParams { path, instance }: WatchParams,
+    state: AppState,
+) {
     let (file_tx, mut file_rx) = tokio::sync::mpsc::unbounded_channel();
 
     let resolved_path = path.resolve().to_path_buf();
@@ -48,25 +175,107 @@ async fn handle_ws(mut socket: WebSocket, WatchParams { path }: WatchParams, sta
         .watch(&resolved_path, notify::RecursiveMode::NonRecursive)
         .unwrap();
 
+    // Editors often save by replacing the file, so watch the stylesheet's directory and pick out
+    // its events
+    let css = project::options_for(&state.options, &resolved_path)
+        .await
+        .css
+        .clone();
+    if let Some(dir) = css.as_deref().and_then(Path::parent)
+        && let Err(e) = watcher.watch(dir, notify::RecursiveMode::NonRecursive)
+    {
+        info!(
+            "Failed to watch stylesheet directory {}: {e}",
+            dir.to_string_lossy()
+        );
+    }
+
     // Subscribe to broadcast channel for live preview updates
-    let tx = state.get_or_create_channel(&resolved_path).await;
+    let tx = state.get_or_create_channel(&instance, &resolved_path).await;
     let mut preview_rx = tx.subscribe();
+    state.preview_opened(&resolved_path).await;
 
     info!(
         "Browser connected for watch: {}",
         resolved_path.to_string_lossy()
     );
+    let mut connected = Payload::new(Event::Connected, &resolved_path);
+    connected.client = Some("browser");
+    state.serve.webhooks.notify(connected);
+
+    // The latest update held back until the browser acknowledges the previous one
+    let mut held: Option<String> = None;
+    let mut ack_deadline: Option<Instant> = None;
+    let max_frame_size = state.options.max_frame_size;
+    let mut chunked = 0;
 
     loop {
         tokio::select! {
             // Live preview update from Neovim
             Ok(html) = preview_rx.recv() => {
-                if socket.send(Message::Text(html.into())).await.is_err() {
+                if is_render(&html) {
+                    if ack_deadline.is_some() {
+                        held = Some(html);
+                        continue;
+                    }
+                    ack_deadline = Some(Instant::now() + ACK_TIMEOUT);
+                }
+                if send(&mut socket, html, &mut chunked, max_frame_size).await.is_err() {
+                    break;
+                }
+            }
+            // Acknowledgment or scroll position from the browser, or it went away
+            message = socket.recv() => {
+                match message {
+                    Some(Ok(Message::Text(text))) => {
+                        if serde_json::from_str::<Ack>(&text).is_ok() {
+                            if let Some(html) = next_frame(&mut held, &mut ack_deadline)
+                                && send(&mut socket, html, &mut chunked, max_frame_size)
+                                    .await
+                                    .is_err()
+                            {
+                                break;
+                            }
+                        } else if state.options.mirror_scroll
+                            && let Ok(scroll) = serde_json::from_str::<MirrorScroll>(&text)
+                        {
+                            tx.send(serde_json::to_string(&scroll).unwrap());
+                        }
+                    }
+                    Some(Ok(_)) => {}
+                    Some(Err(_)) | None => break,
+                }
+            }
+            // The browser didn't acknowledge the last update in time
+            _ = tokio::time::sleep_until(ack_deadline.unwrap_or_else(Instant::now)),
+                if ack_deadline.is_some() =>
+            {
+                if let Some(html) = next_frame(&mut held, &mut ack_deadline)
+                    && send(&mut socket, html, &mut chunked, max_frame_size)
+                        .await
+                        .is_err()
+                {
                     break;
                 }
             }
             // File change on disk (save-triggered)
-            Some(_event) = file_rx.recv() => {
+            Some(event) = file_rx.recv() => {
+                let Ok(event) = event else {
+                    continue;
+                };
+                if css.as_ref().is_some_and(|css| event.paths.contains(css)) {
+                    info!("Received stylesheet change event");
+                    // Refresh the stylesheet in place
+                    let message = r#"{"type":"css"}"#;
+                    if socket.send(Message::Text(message.into())).await.is_err() {
+                        break;
+                    }
+                    continue;
+                }
+                if !event.paths.contains(&resolved_path) {
+                    continue;
+                }
+
                 info!("Received file change event for {}", resolved_path.to_string_lossy());
                 // Send empty message to trigger full reload
                 if socket.send(Message::Text("".into())).await.is_err() {
@@ -76,5 +285,32 @@ async fn handle_ws(mut socket: WebSocket, WatchParams { path }: WatchParams, sta
         }
     }
 
+    state.preview_closed(&resolved_path).await;
     info!("Browser disconnected: {}", resolved_path.to_string_lossy());
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames() {
+        let message = format!(r#"{{"type":"render","html":"{}"}}"#, "a\\\"é".repeat(100));
+        assert_eq!(frames(message.clone(), 1, 0), vec![message.clone()]);
+        assert_eq!(frames(message.clone(), 1, 4096), vec![message.clone()]);
+
+        let frames = frames(message.clone(), 7, 200);
+        assert!(frames.len() > 1);
+        let mut joined = String::new();
+        for (i, frame) in frames.iter().enumerate() {
+            assert!(frame.len() <= 200, "{} bytes", frame.len());
+            let chunk: serde_json::Value = serde_json::from_str(frame).unwrap();
+            assert_eq!(chunk["type"], "chunk");
+            assert_eq!(chunk["id"], 7);
+            assert_eq!(chunk["index"], i);
+            assert_eq!(chunk["count"], frames.len());
+            joined.push_str(chunk["data"].as_str().unwrap());
+        }
+        assert_eq!(joined, message);
+    }
+}
@@ -0,0 +1,144 @@
+//! Runtime files announcing running servers, so the editor can find and reuse one instead of
+//! guessing ports. Each server writes `<pid>.json` to `$XDG_RUNTIME_DIR/penview` (or a
+//! `penview` directory under the system temp dir) and removes it on shutdown.
+
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// How long to wait for a server to accept a connection before treating its file as stale.
+const PROBE_TIMEOUT: Duration = Duration::from_millis(300);
+
+/// What a running server records about itself.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuntimeInfo {
+    pub pid: u32,
+    /// Address the server listens on, as `host:port`, or the path of its unix socket.
+    pub address: String,
+    /// Port the server listens on, 0 on a unix socket.
+    pub port: u16,
+    /// Token clients must present, if the server requires one.
+    pub token: Option<String>,
+    pub base_path: String,
+    pub workspace: PathBuf,
+    /// Whether the server was started to be shared by several editors (see `serve --shared`).
+    pub shared: bool,
+}
+
+/// Directory holding the runtime files.
+pub fn runtime_dir() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("penview"),
+        _ => std::env::temp_dir().join("penview"),
+    }
+}
+
+fn info_path(dir: &Path, pid: u32) -> PathBuf {
+    dir.join(format!("{pid}.json"))
+}
+
+/// Writes the runtime file of this server, readable only by the current user since it may hold
+/// the token. Returns its path, for [`remove`] on shutdown.
+pub fn write(info: &RuntimeInfo) -> std::io::Result<PathBuf> {
+    let dir = runtime_dir();
+    std::fs::create_dir_all(&dir)?;
+    let path = info_path(&dir, info.pid);
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let file = options.open(&path)?;
+    serde_json::to_writer_pretty(file, info)?;
+
+    Ok(path)
+}
+
+pub fn remove(path: &Path) {
+    let _ = std::fs::remove_file(path);
+}
+
+/// Reads the runtime files in `dir`, skipping unreadable ones.
+fn read_all(dir: &Path) -> Vec<(PathBuf, RuntimeInfo)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+
+    let mut servers: Vec<(PathBuf, RuntimeInfo)> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| {
+            let content = std::fs::read_to_string(&path).ok()?;
+            let info = serde_json::from_str(&content).ok()?;
+            Some((path, info))
+        })
+        .collect();
+    servers.sort_by_key(|(_, info)| info.pid);
+    servers
+}
+
+/// Whether a server accepts connections at `address`. Servers bound to all interfaces are
+/// probed on the loopback address.
+async fn is_alive(address: &str) -> bool {
+    let Ok(mut address) = address.parse::<SocketAddr>() else {
+        #[cfg(unix)]
+        return matches!(
+            tokio::time::timeout(PROBE_TIMEOUT, tokio::net::UnixStream::connect(address)).await,
+            Ok(Ok(_))
+        );
+        #[cfg(not(unix))]
+        return false;
+    };
+    if address.ip().is_unspecified() {
+        address.set_ip(std::net::Ipv4Addr::LOCALHOST.into());
+    }
+
+    matches!(
+        tokio::time::timeout(PROBE_TIMEOUT, tokio::net::TcpStream::connect(address)).await,
+        Ok(Ok(_))
+    )
+}
+
+/// Lists the running servers, removing the files of servers that are gone.
+pub async fn discover() -> Vec<RuntimeInfo> {
+    let mut servers = Vec::new();
+    for (path, info) in read_all(&runtime_dir()) {
+        if is_alive(&info.address).await {
+            servers.push(info);
+        } else {
+            remove(&path);
+        }
+    }
+    servers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_all() {
+        let dir = std::env::temp_dir().join("penview-runtime-test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let info = RuntimeInfo {
+            pid: 42,
+            address: "127.0.0.1:9876".to_string(),
+            port: 9876,
+            token: Some("secret".to_string()),
+            base_path: String::new(),
+            workspace: PathBuf::from("/notes"),
+            shared: true,
+        };
+        std::fs::write(info_path(&dir, 42), serde_json::to_string(&info).unwrap()).unwrap();
+        std::fs::write(dir.join("broken.json"), "{").unwrap();
+
+        assert_eq!(read_all(&dir), vec![(info_path(&dir, 42), info)]);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,238 @@
+//! Reduces rendered HTML to markup that is safe and meaningful outside of the preview, for pasting
+//! into mail clients and word processors (see `/api/export/fragment`).
+//!
+//! Elements not on an allowlist are unwrapped by [`ammonia`], keeping their content, except for
+//! scripts, styles and embedded content, which are dropped entirely. Of the attributes, only
+//! those that carry meaning without the page's stylesheet and script are kept; `class`, `style`,
+//! event handlers and the `data-*` attributes the preview relies on are removed.
+
+use std::{borrow::Cow, sync::LazyLock};
+
+use ammonia::{Builder, UrlRelative};
+use url::Url;
+
+/// Elements that are kept.
+const ELEMENTS: &[&str] = &[
+    "a",
+    "abbr",
+    "b",
+    "blockquote",
+    "br",
+    "caption",
+    "cite",
+    "code",
+    "col",
+    "colgroup",
+    "dd",
+    "del",
+    "details",
+    "dfn",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "i",
+    "img",
+    "input",
+    "ins",
+    "kbd",
+    "li",
+    "mark",
+    "ol",
+    "p",
+    "pre",
+    "q",
+    "s",
+    "samp",
+    "small",
+    "span",
+    "strong",
+    "sub",
+    "summary",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "time",
+    "tr",
+    "u",
+    "ul",
+    "var",
+];
+
+/// MathML elements that are kept, for math rendered on the server (see `--math mathml`).
+const MATHML_ELEMENTS: &[&str] = &[
+    "math",
+    "annotation",
+    "menclose",
+    "merror",
+    "mfrac",
+    "mi",
+    "mmultiscripts",
+    "mn",
+    "mo",
+    "mover",
+    "mpadded",
+    "mphantom",
+    "mprescripts",
+    "mroot",
+    "mrow",
+    "ms",
+    "mspace",
+    "msqrt",
+    "mstyle",
+    "msub",
+    "msubsup",
+    "msup",
+    "mtable",
+    "mtd",
+    "mtext",
+    "mtr",
+    "munder",
+    "munderover",
+    "none",
+    "semantics",
+];
+
+/// Elements that are dropped together with their content.
+const DROPPED: &[&str] = &[
+    "script", "style", "iframe", "object", "embed", "template", "noscript", "svg", "canvas",
+    "video", "audio", "form", "button", "select", "textarea",
+];
+
+/// Attributes that are kept on any element that is kept, including those of MathML.
+const ATTRIBUTES: &[&str] = &[
+    "accent",
+    "align",
+    "alt",
+    "checked",
+    "colspan",
+    "columnalign",
+    "datetime",
+    "dir",
+    "disabled",
+    "display",
+    "encoding",
+    "fence",
+    "height",
+    "href",
+    "id",
+    "lang",
+    "linethickness",
+    "mathvariant",
+    "open",
+    "rowspan",
+    "scope",
+    "separator",
+    "src",
+    "start",
+    "stretchy",
+    "title",
+    "type",
+    "width",
+];
+
+/// URL schemes allowed in `href`. Relative links and fragments are allowed too.
+const SCHEMES: &[&str] = &["http", "https", "mailto", "tel"];
+
+/// The sanitizer, configured from the lists above.
+static SANITIZER: LazyLock<Builder<'static>> = LazyLock::new(|| {
+    let mut builder = Builder::empty();
+    builder
+        .tags(ELEMENTS.iter().chain(MATHML_ELEMENTS).copied().collect())
+        .clean_content_tags(DROPPED.iter().copied().collect())
+        .generic_attributes(ATTRIBUTES.iter().copied().collect())
+        .url_schemes(SCHEMES.iter().chain(&["data"]).copied().collect())
+        .url_relative(UrlRelative::PassThrough)
+        .link_rel(None)
+        .attribute_filter(|_, attribute, value| {
+            let allowed = match attribute {
+                "href" => allowed_href(value),
+                "src" => allowed_src(value),
+                _ => true,
+            };
+            allowed.then_some(Cow::Borrowed(value))
+        });
+    builder
+});
+
+/// Sanitizes `html`, a fragment of rendered markdown.
+pub fn sanitize(html: &str) -> String {
+    SANITIZER.clean(html).to_string()
+}
+
+/// The scheme of `url`, if it has one.
+fn scheme(url: &str) -> Option<String> {
+    Url::parse(url.trim())
+        .ok()
+        .map(|url| url.scheme().to_string())
+}
+
+/// Links may be relative or use one of [`SCHEMES`].
+fn allowed_href(url: &str) -> bool {
+    scheme(url).is_none_or(|scheme| SCHEMES.contains(&scheme.as_str()))
+}
+
+/// Images may also be inlined, as local images are (see [`crate::render`]).
+fn allowed_src(url: &str) -> bool {
+    match scheme(url).as_deref() {
+        None | Some("http" | "https") => true,
+        Some("data") => url.trim_start()[5..].starts_with("image/"),
+        Some(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sanitize() {
+        assert_eq!(
+            sanitize(
+                "<h1 id=\"title\" data-source-line=\"1\" class=\"x\">Title</h1>\n\
+                 <p onclick=\"alert(1)\">Text<script>alert(1)</script> more</p>"
+            ),
+            "<h1 id=\"title\">Title</h1>\n<p>Text more</p>"
+        );
+        assert_eq!(
+            sanitize("<a href=\"javascript:alert(1)\">x</a><a href='#a' target=_blank>y</a>"),
+            "<a>x</a><a href=\"#a\">y</a>"
+        );
+        assert_eq!(
+            sanitize(
+                "<img src=\"data:image/png;base64,AA==\" alt=\"a\"><img src=\"data:text/html,x\">"
+            ),
+            "<img src=\"data:image/png;base64,AA==\" alt=\"a\"><img>"
+        );
+        assert_eq!(
+            sanitize("<custom-el>kept</custom-el><!-- gone --><style>p {}</style>1 < 2"),
+            "kept1 &lt; 2"
+        );
+        assert_eq!(
+            sanitize("<input type=\"checkbox\" checked disabled/> done"),
+            "<input type=\"checkbox\" checked=\"\" disabled=\"\"> done"
+        );
+        assert_eq!(
+            sanitize("<math display=\"block\"><mi>x</mi><mo>=</mo><mn>1</mn></math>"),
+            "<math display=\"block\"><mi>x</mi><mo>=</mo><mn>1</mn></math>"
+        );
+        // Entities in attributes are decoded once and escaped again
+        assert_eq!(
+            sanitize("<abbr title=\"a &lt; b &amp;quot;\">x</abbr><abbr title='\"'>y</abbr>"),
+            "<abbr title=\"a &lt; b &amp;quot;\">x</abbr><abbr title=\"&quot;\">y</abbr>"
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! PNG screenshots of rendered documents, taken by a headless browser run as a subprocess, for
+//! embedding rendered snippets into chats and issue trackers.
+//!
+//! Chromium-based browsers capture a fixed window, so the window is made tall and the empty space
+//! below the document is cropped off. Firefox captures the whole page by itself.
+
+use std::{
+    io::Cursor,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use anyhow::{Context, bail};
+use tokio::process::Command;
+
+/// Browsers looked for on the `PATH` when none is configured, in order of preference.
+const BROWSERS: &[&str] = &[
+    "chromium",
+    "chromium-browser",
+    "google-chrome",
+    "google-chrome-stable",
+    "firefox",
+];
+
+/// How long the browser gets to load the page, including its stylesheets and scripts from CDNs.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Height of the window of Chromium-based browsers. Longer documents are cut off.
+const WINDOW_HEIGHT: u32 = 8000;
+
+/// Space kept below the document when cropping, in pixels.
+const PADDING: usize = 32;
+
+/// Hides the page's controls, which would otherwise float over the document.
+const HIDE_CONTROLS: &str = "<style>.theme-toggle, .penview-style, .penview-daily, .penview-toc \
+                             { display: none !important; }</style>";
+
+/// The first of the known browsers on the `PATH`, if any.
+pub fn find_browser() -> Option<String> {
+    let path = std::env::var_os("PATH")?;
+    BROWSERS
+        .iter()
+        .find(|browser| std::env::split_paths(&path).any(|dir| dir.join(browser).is_file()))
+        .map(|browser| browser.to_string())
+}
+
+pub fn is_firefox(browser: &str) -> bool {
+    Path::new(browser)
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().contains("firefox"))
+}
+
+/// A directory of its own for each screenshot or PDF, with the page and a throwaway browser
+/// profile, so concurrent captures and a browser the user has open don't get in each other's way.
+pub fn work_dir() -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "penview-capture-{}-{}",
+        std::process::id(),
+        COUNT.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+/// Renders the standalone page `html` with `browser` in a window `width` pixels wide, and returns
+/// the screenshot as PNG.
+pub async fn capture(browser: &str, html: &str, width: u32) -> anyhow::Result<Vec<u8>> {
+    let dir = work_dir();
+    let profile = dir.join("profile");
+    tokio::fs::create_dir_all(&profile).await?;
+    let result = capture_in(&dir, &profile, browser, html, width).await;
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    result
+}
+
+async fn capture_in(
+    dir: &Path,
+    profile: &Path,
+    browser: &str,
+    html: &str,
+    width: u32,
+) -> anyhow::Result<Vec<u8>> {
+    let page = dir.join("page.html");
+    let out = dir.join("screenshot.png");
+    tokio::fs::write(
+        &page,
+        html.replacen("</head>", &format!("{HIDE_CONTROLS}</head>"), 1),
+    )
+    .await?;
+    let url = url::Url::from_file_path(&page)
+        .map_err(|_| anyhow::anyhow!("Invalid path {}", page.display()))?;
+
+    let mut command = Command::new(browser);
+    if is_firefox(browser) {
+        command
+            .args(["--headless", "--no-remote", "--profile"])
+            .arg(profile)
+            .arg("--screenshot")
+            .arg(&out)
+            .arg(format!("--window-size={width}"));
+    } else {
+        command
+            .args([
+                "--headless",
+                "--disable-gpu",
+                "--hide-scrollbars",
+                "--no-first-run",
+                "--virtual-time-budget=5000",
+            ])
+            .arg(format!("--user-data-dir={}", profile.display()))
+            .arg(format!("--screenshot={}", out.display()))
+            .arg(format!("--window-size={width},{WINDOW_HEIGHT}"));
+    }
+    command
+        .arg(url.as_str())
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+
+    let status = tokio::time::timeout(TIMEOUT, command.status())
+        .await
+        .with_context(|| format!("{browser} took longer than {}s", TIMEOUT.as_secs()))?
+        .with_context(|| format!("Failed to run {browser}"))?;
+    if !status.success() {
+        bail!("{browser} exited with {status}");
+    }
+
+    let png = tokio::fs::read(&out)
+        .await
+        .with_context(|| format!("{browser} didn't save a screenshot"))?;
+    if is_firefox(browser) {
+        Ok(png)
+    } else {
+        crop(&png)
+    }
+}
+
+/// Crops the rows at the bottom of `png` that are the same as its last row, the page's background
+/// below the document, leaving some padding.
+fn crop(png: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut reader = png::Decoder::new(Cursor::new(png)).read_info()?;
+    let size = reader
+        .output_buffer_size()
+        .context("Screenshot is too large")?;
+    let mut pixels = vec![0; size];
+    let info = reader.next_frame(&mut pixels)?;
+    let rows: Vec<_> = pixels[..info.buffer_size()]
+        .chunks_exact(info.line_size)
+        .collect();
+
+    let Some(background) = rows.last() else {
+        return Ok(png.to_vec());
+    };
+    let content = rows
+        .iter()
+        .rposition(|row| row != background)
+        .map_or(0, |last| last + 1);
+    let height = (content + PADDING).min(rows.len());
+
+    let mut out = Vec::new();
+    let mut encoder = png::Encoder::new(&mut out, info.width, height as u32);
+    encoder.set_color(info.color_type);
+    encoder.set_depth(info.bit_depth);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels[..height * info.line_size])?;
+    writer.finish()?;
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crop() {
+        // A 2x100 grayscale image with a dark pixel on row 10
+        let mut pixels = vec![255u8; 2 * 100];
+        pixels[10 * 2] = 0;
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, 2, 100);
+        encoder.set_color(png::ColorType::Grayscale);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&pixels).unwrap();
+        writer.finish().unwrap();
+
+        let cropped = crop(&png).unwrap();
+        let reader = png::Decoder::new(Cursor::new(cropped)).read_info().unwrap();
+        assert_eq!(reader.info().height, 11 + PADDING as u32);
+        assert_eq!(reader.info().width, 2);
+    }
+
+    #[test]
+    fn test_is_firefox() {
+        assert!(is_firefox("/usr/bin/firefox-esr"));
+        assert!(!is_firefox("chromium"));
+    }
+}
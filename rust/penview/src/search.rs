@@ -0,0 +1,222 @@
+//! A per-document search index of text with source lines, for searching long rendered documents
+//! from the preview.
+
+use pulldown_cmark::{Event, Tag, TagEnd};
+use serde::Serialize;
+
+use crate::html::LineIndex;
+
+/// Longest snippet returned for a match, in characters.
+const MAX_SNIPPET: usize = 120;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryKind {
+    Heading,
+    Text,
+    Code,
+}
+
+/// The text of a block (or of one line of a code block).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub kind: EntryKind,
+    /// Line of the block, 1-based.
+    pub line: usize,
+    pub text: String,
+    /// The heading the block is under, if any.
+    pub section: Option<String>,
+}
+
+/// A search result.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Match {
+    pub kind: EntryKind,
+    pub line: usize,
+    /// The matching text, shortened to around the match.
+    pub snippet: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub section: Option<String>,
+}
+
+/// Indexes the text of the headings, paragraphs, list items, table cells and code lines of
+/// `content`.
+pub fn index(content: &str) -> Vec<Entry> {
+    let lines = LineIndex::new(content);
+    let parser = pulldown_cmark::Parser::new_ext(content, pulldown_cmark::Options::all());
+
+    let mut entries = Vec::new();
+    let mut section: Option<String> = None;
+    // Blocks collecting text, innermost last, with their kind, line and text so far
+    let mut open: Vec<(EntryKind, usize, String)> = Vec::new();
+    let mut in_code = false;
+
+    for (event, range) in parser.into_offset_iter() {
+        match event {
+            Event::Start(Tag::Heading { .. }) => {
+                open.push((EntryKind::Heading, lines.line(range.start), String::new()));
+            }
+            Event::Start(Tag::Paragraph | Tag::Item | Tag::TableCell) => {
+                open.push((EntryKind::Text, lines.line(range.start), String::new()));
+            }
+            Event::Start(Tag::CodeBlock(_)) => in_code = true,
+            Event::End(TagEnd::CodeBlock) => in_code = false,
+            Event::End(
+                TagEnd::Heading(_) | TagEnd::Paragraph | TagEnd::Item | TagEnd::TableCell,
+            ) => {
+                let Some((kind, line, text)) = open.pop() else {
+                    continue;
+                };
+                let text = text.trim().to_string();
+                if text.is_empty() {
+                    continue;
+                }
+                if kind == EntryKind::Heading {
+                    section = Some(text.clone());
+                }
+                entries.push(Entry {
+                    kind,
+                    line,
+                    text,
+                    section: section.clone().filter(|_| kind != EntryKind::Heading),
+                });
+            }
+            Event::Text(text) if in_code => {
+                let first = lines.line(range.start);
+                for (i, code_line) in text.lines().enumerate() {
+                    if !code_line.trim().is_empty() {
+                        entries.push(Entry {
+                            kind: EntryKind::Code,
+                            line: first + i,
+                            text: code_line.to_string(),
+                            section: section.clone(),
+                        });
+                    }
+                }
+            }
+            Event::Text(text) | Event::Code(text) | Event::InlineMath(text) => {
+                if let Some((_, _, buffer)) = open.last_mut() {
+                    buffer.push_str(&text);
+                }
+            }
+            Event::SoftBreak | Event::HardBreak => {
+                if let Some((_, _, buffer)) = open.last_mut() {
+                    buffer.push(' ');
+                }
+            }
+            _ => {}
+        }
+    }
+
+    entries.sort_by_key(|entry| entry.line);
+    entries
+}
+
+/// Shortens `text` to around the match at byte `offset`.
+fn snippet(text: &str, offset: usize) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.len() <= MAX_SNIPPET {
+        return text.to_string();
+    }
+
+    let at = text[..offset].chars().count();
+    let start = at.saturating_sub(MAX_SNIPPET / 3);
+    let end = (start + MAX_SNIPPET).min(chars.len());
+    let start = end.saturating_sub(MAX_SNIPPET);
+
+    let mut snippet: String = chars[start..end].iter().collect();
+    if start > 0 {
+        snippet.insert(0, '…');
+    }
+    if end < chars.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+/// Finds the entries containing `query`, case-insensitively, in document order.
+pub fn search(entries: &[Entry], query: &str) -> Vec<Match> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    entries
+        .iter()
+        .filter_map(|entry| {
+            let lower = entry.text.to_lowercase();
+            let offset = lower.find(&query)?;
+            // Lowercasing can change byte lengths, in which case offsets don't carry over
+            let offset = if lower.len() == entry.text.len() {
+                offset
+            } else {
+                0
+            };
+
+            Some(Match {
+                kind: entry.kind,
+                line: entry.line,
+                snippet: snippet(&entry.text, offset),
+                section: entry.section.clone(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index() {
+        let content = "# Setup\n\nInstall with `cargo`\nand go.\n\n- one\n- two\n\n```sh\nmake\n\nmake install\n```\n";
+        let entries: Vec<_> = index(content)
+            .into_iter()
+            .map(|e| (e.kind, e.line, e.text, e.section))
+            .collect();
+        let setup = Some("Setup".to_string());
+
+        assert_eq!(
+            entries,
+            vec![
+                (EntryKind::Heading, 1, "Setup".to_string(), None),
+                (
+                    EntryKind::Text,
+                    3,
+                    "Install with cargo and go.".to_string(),
+                    setup.clone()
+                ),
+                (EntryKind::Text, 6, "one".to_string(), setup.clone()),
+                (EntryKind::Text, 7, "two".to_string(), setup.clone()),
+                (EntryKind::Code, 10, "make".to_string(), setup.clone()),
+                (EntryKind::Code, 12, "make install".to_string(), setup),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_search() {
+        let entries = index("# Intro\n\nPenview renders MARKDOWN.\n\nNothing here.\n");
+        let matches = search(&entries, "markdown");
+
+        assert_eq!(
+            matches,
+            vec![Match {
+                kind: EntryKind::Text,
+                line: 3,
+                snippet: "Penview renders MARKDOWN.".to_string(),
+                section: Some("Intro".to_string()),
+            }]
+        );
+        assert!(search(&entries, "  ").is_empty());
+    }
+
+    #[test]
+    fn test_snippet() {
+        let text = format!("{}needle{}", "a".repeat(200), "b".repeat(200));
+        let snippet = snippet(&text, 200);
+        assert!(snippet.starts_with('…') && snippet.ends_with('…'));
+        assert!(snippet.contains("needle"));
+        assert_eq!(snippet.chars().count(), MAX_SNIPPET + 2);
+    }
+}
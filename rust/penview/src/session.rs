@@ -0,0 +1,75 @@
+//! The documents previewed in browsers, saved so that they can be reopened after the server
+//! restarts (see `serve --restore`). Each workspace has its own session file in
+//! `$XDG_STATE_HOME/penview/sessions` (or `~/.local/state/penview/sessions`), which outlives
+//! reboots unlike the runtime files.
+
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+/// What a session file holds.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    /// Documents with a browser previewing them, sorted.
+    pub paths: Vec<PathBuf>,
+}
+
+/// Directory holding the session files.
+fn sessions_dir() -> PathBuf {
+    let state = match std::env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => std::env::home_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join(".local/state"),
+    };
+    state.join("penview/sessions")
+}
+
+/// Session file of `workspace`, named after its path like Vim's swap and undo files.
+pub fn session_file(workspace: &Path) -> PathBuf {
+    let name = workspace.to_string_lossy().replace(['/', '\\', ':'], "%");
+    sessions_dir().join(format!("{name}.json"))
+}
+
+/// Reads a session file, which is empty if it doesn't exist or can't be read.
+pub async fn load(file: &Path) -> Session {
+    tokio::fs::read_to_string(file)
+        .await
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+pub async fn save(file: &Path, session: &Session) -> std::io::Result<()> {
+    if let Some(dir) = file.parent() {
+        tokio::fs::create_dir_all(dir).await?;
+    }
+    tokio::fs::write(file, serde_json::to_string_pretty(session)?).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session_file() {
+        let file = session_file(Path::new("/home/me/notes"));
+        assert_eq!(file.file_name().unwrap(), "%home%me%notes.json");
+        assert!(file.parent().unwrap().ends_with("penview/sessions"));
+    }
+
+    #[tokio::test]
+    async fn test_save_load() {
+        let file = std::env::temp_dir().join("penview-session-test/notes.json");
+        let session = Session {
+            paths: vec![PathBuf::from("/notes/a.md"), PathBuf::from("/notes/b.md")],
+        };
+        save(&file, &session).await.unwrap();
+        assert_eq!(load(&file).await, session);
+
+        assert_eq!(
+            load(&file.with_file_name("missing.json")).await,
+            Session::default()
+        );
+    }
+}
@@ -0,0 +1,296 @@
+//! Hugo-style shortcodes (`{{< name arg key="value" >}}`), expanded in the markdown source
+//! before it is parsed.
+
+use std::{borrow::Cow, collections::HashMap, path::Path};
+
+use pulldown_cmark_escape::escape_html;
+
+use crate::jail;
+
+/// How many levels of `include` are followed, to stop include cycles.
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// A parsed shortcode invocation.
+#[derive(Debug, PartialEq)]
+struct Shortcode<'a> {
+    name: &'a str,
+    /// Positional arguments, in order.
+    positional: Vec<String>,
+    named: HashMap<String, String>,
+}
+
+impl Shortcode<'_> {
+    /// Returns the named argument `key`, else the positional argument at `index`.
+    fn arg(&self, key: &str, index: usize) -> Option<&str> {
+        self.named
+            .get(key)
+            .or_else(|| self.positional.get(index))
+            .map(String::as_str)
+    }
+}
+
+/// Splits shortcode arguments on whitespace, keeping double-quoted values together.
+fn split_args(args: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut quoted = false;
+    let mut in_token = false;
+
+    for c in args.chars() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                in_token = true;
+            }
+            c if c.is_whitespace() && !quoted => {
+                if in_token {
+                    tokens.push(std::mem::take(&mut token));
+                    in_token = false;
+                }
+            }
+            c => {
+                token.push(c);
+                in_token = true;
+            }
+        }
+    }
+    if in_token {
+        tokens.push(token);
+    }
+
+    tokens
+}
+
+fn parse(inner: &str) -> Option<Shortcode<'_>> {
+    let inner = inner.trim();
+    let (name, args) = inner.split_once(char::is_whitespace).unwrap_or((inner, ""));
+    if name.is_empty() {
+        return None;
+    }
+
+    let mut shortcode = Shortcode {
+        name,
+        positional: Vec::new(),
+        named: HashMap::new(),
+    };
+    for token in split_args(args) {
+        match token.split_once('=') {
+            Some((key, value)) if !key.is_empty() && !key.contains(char::is_whitespace) => {
+                shortcode.named.insert(key.to_string(), value.to_string());
+            }
+            _ => shortcode.positional.push(token),
+        }
+    }
+
+    Some(shortcode)
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::new();
+    let _ = escape_html(&mut out, s);
+    out
+}
+
+/// Expands a shortcode, or returns `None` if it isn't known or is missing arguments.
+fn render(
+    shortcode: &Shortcode,
+    base_path: &Path,
+    custom: &HashMap<String, String>,
+    root: Option<&Path>,
+    depth: usize,
+) -> Option<String> {
+    if let Some(template) = custom.get(shortcode.name) {
+        let mut out = template.clone();
+        for (key, value) in &shortcode.named {
+            out = out.replace(&format!("{{{key}}}"), &escape(value));
+        }
+        for (index, value) in shortcode.positional.iter().enumerate() {
+            out = out.replace(&format!("{{{index}}}"), &escape(value));
+        }
+        return Some(out);
+    }
+
+    match shortcode.name {
+        "youtube" => {
+            let id = shortcode.arg("id", 0)?;
+            Some(format!(
+                "<div class=\"penview-embed\"><iframe src=\"https://www.youtube-nocookie.com/embed/{}\" \
+                 title=\"YouTube video\" allowfullscreen></iframe></div>",
+                escape(id)
+            ))
+        }
+        "gist" => {
+            let user = shortcode.arg("user", 0)?;
+            let id = shortcode.arg("id", 1)?;
+            Some(format!(
+                "<script src=\"https://gist.github.com/{}/{}.js\"></script>",
+                escape(user),
+                escape(id)
+            ))
+        }
+        // The page turns image alt text into a caption
+        "figure" => {
+            let src = shortcode.arg("src", 0)?;
+            let caption = shortcode
+                .arg("caption", 1)
+                .or(shortcode.arg("alt", usize::MAX))
+                .unwrap_or_default();
+            match shortcode.arg("title", usize::MAX) {
+                Some(title) => Some(format!("![{caption}](<{src}> \"{title}\")")),
+                None => Some(format!("![{caption}](<{src}>)")),
+            }
+        }
+        "include" => {
+            let file = base_path.parent()?.join(shortcode.arg("file", 0)?);
+            if root.is_some_and(|root| !jail::contains(root, &file)) {
+                return None;
+            }
+            let content = std::fs::read_to_string(&file).ok()?;
+            if depth >= MAX_INCLUDE_DEPTH {
+                return Some(content);
+            }
+            Some(expand_at_depth(&content, &file, custom, root, depth + 1))
+        }
+        _ => None,
+    }
+}
+
+/// Expands shortcodes in `content`. Shortcodes inside fenced code blocks and unknown shortcodes
+/// are left as written.
+///
+/// `base_path` is the document's path, used to resolve `include`s. `custom` maps shortcode names
+/// to templates in which `{key}` and `{0}`, `{1}`, ... are replaced by the (escaped) arguments.
+/// Files outside `root`, if given, aren't included.
+pub fn expand<'a>(
+    content: &'a str,
+    base_path: &Path,
+    custom: &HashMap<String, String>,
+    root: Option<&Path>,
+) -> Cow<'a, str> {
+    if !content.contains("{{<") {
+        return Cow::Borrowed(content);
+    }
+    Cow::Owned(expand_at_depth(content, base_path, custom, root, 0))
+}
+
+fn expand_at_depth(
+    content: &str,
+    base_path: &Path,
+    custom: &HashMap<String, String>,
+    root: Option<&Path>,
+    depth: usize,
+) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut fence: Option<&str> = None;
+
+    for line in content.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        match fence {
+            Some(marker) if trimmed.starts_with(marker) => fence = None,
+            Some(_) => {}
+            None if trimmed.starts_with("```") => fence = Some("```"),
+            None if trimmed.starts_with("~~~") => fence = Some("~~~"),
+            None => {
+                expand_line(line, base_path, custom, root, depth, &mut out);
+                continue;
+            }
+        }
+        out.push_str(line);
+    }
+
+    out
+}
+
+fn expand_line(
+    line: &str,
+    base_path: &Path,
+    custom: &HashMap<String, String>,
+    root: Option<&Path>,
+    depth: usize,
+    out: &mut String,
+) {
+    let mut rest = line;
+    while let Some(start) = rest.find("{{<") {
+        let Some(len) = rest[start..].find(">}}") else {
+            break;
+        };
+        let end = start + len + 3;
+
+        out.push_str(&rest[..start]);
+        let expanded = parse(&rest[start + 3..start + len])
+            .and_then(|shortcode| render(&shortcode, base_path, custom, root, depth));
+        out.push_str(expanded.as_deref().unwrap_or(&rest[start..end]));
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_str(content: &str) -> String {
+        expand(content, Path::new("/docs/page.md"), &HashMap::new(), None).into_owned()
+    }
+
+    #[test]
+    fn test_parse() {
+        let shortcode = parse(r#" figure img/a.png caption="A caption" "#).unwrap();
+        assert_eq!(shortcode.name, "figure");
+        assert_eq!(shortcode.positional, vec!["img/a.png"]);
+        assert_eq!(shortcode.named["caption"], "A caption");
+    }
+
+    #[test]
+    fn test_builtins() {
+        assert_eq!(
+            expand_str("{{< gist octocat 123 >}}"),
+            "<script src=\"https://gist.github.com/octocat/123.js\"></script>"
+        );
+        assert!(expand_str("{{< youtube id=\"abc\" >}}").contains("/embed/abc\""));
+        assert_eq!(
+            expand_str("{{< figure src=\"a b.png\" caption=\"Flow\" >}}"),
+            "![Flow](<a b.png>)"
+        );
+    }
+
+    #[test]
+    fn test_unknown_and_code_left_alone() {
+        let content = "{{< nope >}}\n```\n{{< gist a b >}}\n```\n";
+        assert_eq!(expand_str(content), content);
+    }
+
+    #[test]
+    fn test_custom() {
+        let custom = HashMap::from([(
+            "badge".to_string(),
+            "<span class=\"badge {color}\">{0}</span>".to_string(),
+        )]);
+        assert_eq!(
+            expand(
+                "Status: {{< badge \"<ok>\" color=green >}}",
+                Path::new("page.md"),
+                &custom,
+                None
+            ),
+            "Status: <span class=\"badge green\">&lt;ok&gt;</span>"
+        );
+    }
+
+    #[test]
+    fn test_include_root() {
+        let dir = std::env::temp_dir().join("penview-include-test");
+        std::fs::create_dir_all(dir.join("docs")).unwrap();
+        std::fs::write(dir.join("docs/part.md"), "Part").unwrap();
+        std::fs::write(dir.join("secret.md"), "Secret").unwrap();
+        let root = dir.join("docs").canonicalize().unwrap();
+        let page = root.join("page.md");
+
+        let content = "{{< include part.md >}} {{< include ../secret.md >}}";
+        assert_eq!(
+            expand(content, &page, &HashMap::new(), Some(&root)),
+            "Part {{< include ../secret.md >}}"
+        );
+        assert_eq!(expand(content, &page, &HashMap::new(), None), "Part Secret");
+    }
+}
@@ -0,0 +1,74 @@
+//! Maps rendered top-level blocks back to the source ranges they were rendered from.
+
+use pulldown_cmark::{Event, Tag};
+use serde::Serialize;
+
+use crate::html::LineIndex;
+
+/// Source range of one top-level block. Blocks are listed in document order, so the n-th span
+/// belongs to the n-th `.markdown-body > [data-source-line]` element.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SourceSpan {
+    /// First line of the block, 1-based.
+    pub start_line: usize,
+    /// Last line of the block, inclusive.
+    pub end_line: usize,
+    /// Byte offset of the start of the block.
+    pub start: usize,
+    /// Byte offset just past the end of the block.
+    pub end: usize,
+}
+
+/// Returns the source spans of the top-level blocks of `content` that are rendered with a
+/// `data-source-line` attribute. Raw HTML and metadata blocks are skipped.
+pub fn source_map(content: &str) -> Vec<SourceSpan> {
+    let lines = LineIndex::new(content);
+    let parser = pulldown_cmark::Parser::new_ext(content, pulldown_cmark::Options::all());
+
+    let mut spans = Vec::new();
+    let mut depth = 0;
+    for (event, range) in parser.into_offset_iter() {
+        let is_block = match event {
+            Event::Start(Tag::HtmlBlock | Tag::MetadataBlock(_)) => false,
+            Event::Start(_) | Event::Rule => true,
+            _ => false,
+        };
+        if depth == 0 && is_block {
+            // Ranges of some blocks (e.g. lists) include the blank lines after them
+            let last = range.start + content[range.clone()].trim_end().len();
+            spans.push(SourceSpan {
+                start_line: lines.line(range.start),
+                end_line: lines.line(last.saturating_sub(1).max(range.start)),
+                start: range.start,
+                end: range.end,
+            });
+        }
+
+        match event {
+            Event::Start(_) => depth += 1,
+            Event::End(_) => depth -= 1,
+            _ => {}
+        }
+    }
+
+    spans
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_source_map() {
+        let content = "---\ntitle: x\n---\n\n# Title\n\n<div>raw</div>\n\n- one\n- two\n\n---\n";
+        let spans = source_map(content);
+        assert_eq!(
+            spans
+                .iter()
+                .map(|s| (s.start_line, s.end_line))
+                .collect::<Vec<_>>(),
+            vec![(5, 5), (9, 10), (12, 12)]
+        );
+        assert_eq!(&content[spans[0].start..spans[0].end], "# Title\n");
+    }
+}
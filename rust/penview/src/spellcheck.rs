@@ -0,0 +1,121 @@
+//! Spellchecking of rendered prose using `hunspell`, so any hunspell dictionary can be used.
+
+use std::{collections::HashSet, process::Stdio};
+
+use anyhow::Context;
+use pulldown_cmark_escape::escape_html_body_text;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+/// Returns the subset of `words` that `hunspell` doesn't recognize in dictionary `lang`
+/// (e.g. "en_US").
+pub async fn misspelled_words<'a>(
+    lang: &str,
+    words: impl IntoIterator<Item = &'a str>,
+) -> anyhow::Result<HashSet<String>> {
+    let mut child = Command::new("hunspell")
+        .args(["-d", lang, "-l"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .context("Failed to run hunspell")?;
+
+    let mut input = String::new();
+    for word in words {
+        input.push_str(word);
+        input.push('\n');
+    }
+
+    let mut stdin = child.stdin.take().context("hunspell stdin unavailable")?;
+    stdin.write_all(input.as_bytes()).await?;
+    drop(stdin);
+
+    let output = child.wait_with_output().await?;
+    anyhow::ensure!(
+        output.status.success(),
+        "hunspell exited with {}",
+        output.status
+    );
+
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(str::to_string)
+        .collect())
+}
+
+/// Splits text into words: runs of letters, allowing apostrophes inside a word ("don't").
+pub fn words(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    let mut start = None;
+    let mut spans = Vec::new();
+
+    for (i, c) in text
+        .char_indices()
+        .chain(std::iter::once((text.len(), ' ')))
+    {
+        if c.is_alphabetic() || (c == '\'' && start.is_some()) {
+            start.get_or_insert(i);
+        } else if let Some(s) = start.take() {
+            let word = text[s..i].trim_end_matches('\'');
+            spans.push((s, word));
+        }
+    }
+
+    spans.into_iter()
+}
+
+/// Escapes `text` as HTML, wrapping misspelled words in a `penview-misspelled` span.
+///
+/// Returns `None` when nothing is misspelled, so the text can be left as is.
+pub fn mark_misspelled(text: &str, misspelled: &HashSet<String>) -> Option<String> {
+    let mut html = String::new();
+    let mut last = 0;
+
+    for (start, word) in words(text) {
+        if !misspelled.contains(word) {
+            continue;
+        }
+        let _ = escape_html_body_text(&mut html, &text[last..start]);
+        html.push_str("<span class=\"penview-misspelled\">");
+        let _ = escape_html_body_text(&mut html, word);
+        html.push_str("</span>");
+        last = start + word.len();
+    }
+
+    if last == 0 {
+        return None;
+    }
+    let _ = escape_html_body_text(&mut html, &text[last..]);
+
+    Some(html)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_words() {
+        let words: Vec<_> = words("Don't stop, 'quoted' naïve 42x").collect();
+        assert_eq!(
+            words,
+            vec![
+                (0, "Don't"),
+                (6, "stop"),
+                (13, "quoted"),
+                (21, "naïve"),
+                (30, "x")
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mark_misspelled() {
+        let misspelled = HashSet::from(["teh".to_string()]);
+        assert_eq!(
+            mark_misspelled("fix teh <bug>", &misspelled).unwrap(),
+            "fix <span class=\"penview-misspelled\">teh</span> &lt;bug&gt;"
+        );
+        assert_eq!(mark_misspelled("all good", &misspelled), None);
+    }
+}
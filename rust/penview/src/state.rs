@@ -1,29 +1,189 @@
+use chrono::{DateTime, Utc};
 use std::{
     collections::HashMap,
     path::{Path, PathBuf},
     sync::Arc,
+    time::Duration,
 };
 use tokio::sync::{Mutex, broadcast};
+use tracing::info;
+
+use crate::{
+    render::RenderOptions,
+    session::{self, Session},
+    webhook::Webhooks,
+};
+
+/// How long a document stays in the session after its last preview closes, so that closing the
+/// browser just before the server (e.g. when logging out) doesn't empty the session.
+const SESSION_GRACE: Duration = Duration::from_secs(10);
+
+/// Server-level settings that aren't part of the rendered page.
+#[derive(Debug, Clone, Default)]
+pub struct ServeOptions {
+    /// Vale binary to run against previewed content, if prose linting is enabled.
+    pub vale: Option<String>,
+    /// Headless browser that takes screenshots and prints PDFs, if not the first one found on the
+    /// `PATH`.
+    pub screenshot_browser: Option<String>,
+    /// Webhooks notified of preview events.
+    pub webhooks: Webhooks,
+    /// Token every request must carry, if access is restricted.
+    pub token: Option<String>,
+    /// Requests each client address may make per second, or 0 for no limit.
+    pub rate_limit: u32,
+    /// File the previewed documents are saved to, if sessions are kept.
+    pub session: Option<PathBuf>,
+    /// The documents previewed when the server last ran.
+    pub previous_session: Session,
+}
+
+/// A previewed file, namespaced by the Neovim instance editing it so that several instances can
+/// share one server without seeing each other's buffers. The instance is empty for a server
+/// started by a single editor.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DocumentKey {
+    pub instance: String,
+    pub path: PathBuf,
+}
+
+impl DocumentKey {
+    pub fn new(instance: &str, path: &Path) -> Self {
+        Self {
+            instance: instance.to_string(),
+            path: path.to_path_buf(),
+        }
+    }
+}
+
+/// Broadcast channel of a previewed document, carrying live updates from Neovim (and scroll
+/// positions from browsers) to the browsers watching it.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    tx: broadcast::Sender<String>,
+    last_message: Arc<std::sync::Mutex<Option<DateTime<Utc>>>>,
+}
+
+impl Channel {
+    fn new() -> Self {
+        Self {
+            tx: broadcast::channel(16).0,
+            last_message: Arc::new(std::sync::Mutex::new(None)),
+        }
+    }
+
+    /// Sends a message to the browsers watching the document, if there are any.
+    pub fn send(&self, message: String) {
+        *self.last_message.lock().unwrap() = Some(Utc::now());
+        let _ = self.tx.send(message);
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<String> {
+        self.tx.subscribe()
+    }
+
+    /// Number of browsers watching the document.
+    pub fn subscribers(&self) -> usize {
+        self.tx.receiver_count()
+    }
+
+    /// When the last message was sent, whether or not anyone received it.
+    pub fn last_message(&self) -> Option<DateTime<Utc>> {
+        *self.last_message.lock().unwrap()
+    }
+}
 
 #[derive(Clone)]
 pub struct AppState {
-    pub channels: Arc<Mutex<HashMap<PathBuf, broadcast::Sender<String>>>>,
-    pub theme: String,
+    pub channels: Arc<Mutex<HashMap<DocumentKey, Channel>>>,
+    /// Latest buffer content pushed from Neovim for each previewed file.
+    pub sources: Arc<Mutex<HashMap<DocumentKey, String>>>,
+    /// Requests for each Neovim instance (such as files to open), forwarded over its preview
+    /// sockets.
+    pub editors: Arc<Mutex<HashMap<String, broadcast::Sender<String>>>>,
+    /// Number of browsers previewing each document, saved as the session.
+    pub previews: Arc<Mutex<HashMap<PathBuf, usize>>>,
+    pub options: RenderOptions,
+    pub serve: ServeOptions,
 }
 
 impl AppState {
-    pub fn new(theme: String) -> Self {
+    pub fn new(options: RenderOptions, serve: ServeOptions) -> Self {
         Self {
             channels: Arc::new(Mutex::new(HashMap::new())),
-            theme,
+            sources: Arc::new(Mutex::new(HashMap::new())),
+            editors: Arc::new(Mutex::new(HashMap::new())),
+            previews: Arc::new(Mutex::new(HashMap::new())),
+            options,
+            serve,
         }
     }
 
-    pub async fn get_or_create_channel(&self, path: &Path) -> broadcast::Sender<String> {
+    /// Returns the latest buffer content pushed from Neovim `instance` for `path`, falling back to
+    /// the file on disk.
+    pub async fn source(&self, instance: &str, path: &Path) -> std::io::Result<String> {
+        if let Some(source) = self
+            .sources
+            .lock()
+            .await
+            .get(&DocumentKey::new(instance, path))
+        {
+            return Ok(source.clone());
+        }
+
+        tokio::fs::read_to_string(path).await
+    }
+
+    pub async fn get_or_create_channel(&self, instance: &str, path: &Path) -> Channel {
         let mut channels = self.channels.lock().await;
         channels
-            .entry(path.to_path_buf())
+            .entry(DocumentKey::new(instance, path))
+            .or_insert_with(Channel::new)
+            .clone()
+    }
+
+    pub async fn editor_channel(&self, instance: &str) -> broadcast::Sender<String> {
+        let mut editors = self.editors.lock().await;
+        editors
+            .entry(instance.to_string())
             .or_insert_with(|| broadcast::channel(16).0)
             .clone()
     }
+
+    /// Records that a browser started previewing `path`.
+    pub async fn preview_opened(&self, path: &Path) {
+        let mut previews = self.previews.lock().await;
+        *previews.entry(path.to_path_buf()).or_default() += 1;
+        self.save_session(&previews).await;
+    }
+
+    /// Records that a browser stopped previewing `path`, which is dropped from the session if no
+    /// other browser previews it within [`SESSION_GRACE`].
+    pub async fn preview_closed(&self, path: &Path) {
+        if let Some(count) = self.previews.lock().await.get_mut(path) {
+            *count = count.saturating_sub(1);
+        }
+
+        let state = self.clone();
+        let path = path.to_path_buf();
+        tokio::spawn(async move {
+            tokio::time::sleep(SESSION_GRACE).await;
+            let mut previews = state.previews.lock().await;
+            if previews.get(&path) == Some(&0) {
+                previews.remove(&path);
+                state.save_session(&previews).await;
+            }
+        });
+    }
+
+    async fn save_session(&self, previews: &HashMap<PathBuf, usize>) {
+        let Some(file) = &self.serve.session else {
+            return;
+        };
+        let mut paths: Vec<PathBuf> = previews.keys().cloned().collect();
+        paths.sort();
+        if let Err(e) = session::save(file, &Session { paths }).await {
+            info!("Failed to save session to {}: {e}", file.display());
+        }
+    }
 }
@@ -0,0 +1,121 @@
+//! An index of the workspace's documents by their frontmatter `tags`.
+
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+use url::form_urlencoded;
+
+use crate::{export::markdown_files, frontmatter};
+
+/// Documents (relative to the workspace) for each tag, sorted by tag.
+pub type TagIndex = BTreeMap<String, Vec<PathBuf>>;
+
+/// Returns the frontmatter tags of a document. Tags may be a list or a comma-separated string,
+/// and a leading `#` is dropped.
+pub fn document_tags(content: &str) -> Vec<String> {
+    tags_field(frontmatter::fields(content).remove("tags"))
+}
+
+/// Reads the value of a frontmatter `tags` field, like [`document_tags`].
+pub fn tags_field(tags: Option<Value>) -> Vec<String> {
+    let tags = match tags {
+        Some(Value::Array(items)) => items
+            .into_iter()
+            .filter_map(|item| match item {
+                Value::String(s) => Some(s),
+                _ => None,
+            })
+            .collect(),
+        Some(Value::String(s)) => s.split(',').map(str::to_string).collect(),
+        _ => Vec::new(),
+    };
+
+    tags.iter()
+        .map(|tag| tag.trim().trim_start_matches('#').to_string())
+        .filter(|tag| !tag.is_empty())
+        .collect()
+}
+
+/// Scans the markdown files under `workspace` for tags.
+pub async fn tag_index(workspace: &Path) -> anyhow::Result<TagIndex> {
+    let mut index = TagIndex::new();
+    for file in markdown_files(workspace).await? {
+        let Ok(content) = tokio::fs::read_to_string(&file).await else {
+            continue;
+        };
+        let Ok(relative) = file.strip_prefix(workspace) else {
+            continue;
+        };
+        for tag in document_tags(&content) {
+            let documents = index.entry(tag).or_default();
+            if !documents.iter().any(|document| document == relative) {
+                documents.push(relative.to_path_buf());
+            }
+        }
+    }
+
+    Ok(index)
+}
+
+/// Link to the page listing the documents tagged `tag`, on a server whose routes are under
+/// `base_path`.
+pub fn tag_link(tag: &str, base_path: &str) -> String {
+    format!(
+        "{base_path}/tags?tag={}",
+        form_urlencoded::byte_serialize(tag.as_bytes()).collect::<String>()
+    )
+}
+
+/// Markdown listing every tag with its number of documents. Tag links are written as HTML so
+/// they aren't rewritten into document links.
+pub fn tags_markdown(index: &TagIndex, base_path: &str) -> String {
+    let mut markdown = "# Tags\n\n".to_string();
+    if index.is_empty() {
+        markdown.push_str("No documents have `tags` in their frontmatter.\n");
+    }
+    for (tag, documents) in index {
+        markdown.push_str(&format!(
+            "- <a href=\"{}\">#{}</a> ({})\n",
+            tag_link(tag, base_path),
+            html_escape(tag),
+            documents.len()
+        ));
+    }
+    markdown
+}
+
+fn html_escape(s: &str) -> String {
+    let mut out = String::new();
+    let _ = pulldown_cmark_escape::escape_html(&mut out, s);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_document_tags() {
+        assert_eq!(
+            document_tags("---\ntags: [rust, \"#nvim\"]\n---\n"),
+            vec!["rust", "nvim"]
+        );
+        assert_eq!(
+            document_tags("---\ntags: rust, notes\n---\n"),
+            vec!["rust", "notes"]
+        );
+        assert!(document_tags("# No frontmatter\n").is_empty());
+    }
+
+    #[test]
+    fn test_tags_markdown() {
+        let index = TagIndex::from([("c++".to_string(), vec![PathBuf::from("a.md")])]);
+        assert_eq!(
+            tags_markdown(&index, ""),
+            "# Tags\n\n- <a href=\"/tags?tag=c%2B%2B\">#c++</a> (1)\n"
+        );
+    }
+}
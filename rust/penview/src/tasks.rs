@@ -0,0 +1,76 @@
+//! Toggling task list items (`- [ ] task`) in the markdown source.
+
+/// Byte offset of the status character of the task marker in `line`, if it is a task list item:
+/// a list marker (`-`, `*`, `+`, `1.` or `1)`), possibly nested in block quotes, followed by `[ ]`,
+/// `[x]` or `[X]`.
+fn status_offset(line: &str) -> Option<usize> {
+    let item = line.trim_start_matches(|c: char| c.is_whitespace() || c == '>');
+    let rest = match item.strip_prefix(['-', '*', '+']) {
+        Some(rest) => rest,
+        None => {
+            let digits = item.len() - item.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            if digits == 0 {
+                return None;
+            }
+            item[digits..].strip_prefix(['.', ')'])?
+        }
+    };
+
+    let marker = rest.trim_start_matches([' ', '\t']);
+    if marker.len() == rest.len() {
+        return None;
+    }
+    let status = marker.strip_prefix('[')?;
+    if !(status.starts_with([' ', 'x', 'X']) && status[1..].starts_with(']')) {
+        return None;
+    }
+    Some(line.len() - status.len())
+}
+
+/// Checks or unchecks the task at 1-based `line` of `content`. Returns `None` if the line isn't a
+/// task list item.
+pub fn toggle(content: &str, line: usize, checked: bool) -> Option<String> {
+    let start: usize = content
+        .split_inclusive('\n')
+        .take(line.checked_sub(1)?)
+        .map(str::len)
+        .sum();
+    let text = content[start..].lines().next()?;
+    let offset = start + status_offset(text)?;
+
+    let mut toggled = content.to_string();
+    toggled.replace_range(offset..offset + 1, if checked { "x" } else { " " });
+    Some(toggled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_offset() {
+        assert_eq!(status_offset("- [ ] task"), Some(3));
+        assert_eq!(status_offset("  * [x] nested"), Some(5));
+        assert_eq!(status_offset("> 12) [X] quoted"), Some(7));
+        assert_eq!(status_offset("-[ ] no space"), None);
+        assert_eq!(status_offset("- [] empty"), None);
+        assert_eq!(status_offset("[ ] no marker"), None);
+        assert_eq!(status_offset("- plain item"), None);
+    }
+
+    #[test]
+    fn test_toggle() {
+        let content = "# Tasks\r\n\r\n- [ ] one\r\n- [x] two\r\n";
+        assert_eq!(
+            toggle(content, 3, true).as_deref(),
+            Some("# Tasks\r\n\r\n- [x] one\r\n- [x] two\r\n")
+        );
+        assert_eq!(
+            toggle(content, 4, false).as_deref(),
+            Some("# Tasks\r\n\r\n- [ ] one\r\n- [ ] two\r\n")
+        );
+        assert_eq!(toggle(content, 1, true), None);
+        assert_eq!(toggle(content, 0, true), None);
+        assert_eq!(toggle(content, 9, true), None);
+    }
+}
@@ -0,0 +1,56 @@
+//! An opt-in templating pass over the markdown source, run before parsing, by [`minijinja`].
+//!
+//! Frontmatter fields are available as top-level variables and environment variables under
+//! `env`. A newline directly after a tag is removed, as with Jinja's `trim_blocks`, and values
+//! are inserted unescaped, as the output is markdown.
+
+use std::collections::HashMap;
+
+use minijinja::{Environment, Value, context};
+
+use crate::frontmatter;
+
+/// Renders `content` as a template, with its frontmatter fields and `env` as context.
+pub fn render(content: &str, env: &HashMap<String, String>) -> anyhow::Result<String> {
+    let mut environment = Environment::new();
+    environment.set_trim_blocks(true);
+    environment.set_keep_trailing_newline(true);
+
+    let fields = Value::from_serialize(frontmatter::fields(content));
+    Ok(environment.render_str(content, context! { env, ..fields })?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render_str(content: &str) -> String {
+        let env = HashMap::from([("USER".to_string(), "ada".to_string())]);
+        render(content, &env).unwrap()
+    }
+
+    #[test]
+    fn test_variables_and_filters() {
+        let content = "---\ntitle: \"Notes\"\ntags: [a, b]\n---\n# {{ title | upper }}\n\
+                       {{ tags | join(\", \") }} by {{ env.USER }}{{ missing | default(\"!\") }}\n";
+        assert!(render_str(content).ends_with("# NOTES\na, b by ada!\n"));
+        assert_eq!(render_str("{{ \"<b>&</b>\" }}"), "<b>&</b>");
+    }
+
+    #[test]
+    fn test_control_flow() {
+        let content = "---\nitems:\n  - one\n  - two\ndraft: false\n---\n\
+                       {% for item in items %}\n{{ loop.index }}. {{ item }}\n{% endfor %}\n\
+                       {% if draft %}draft{% elif items | length == 2 %}two{% else %}other{% endif %}\n\
+                       {% raw %}{{ kept }}{% endraw %} {# comment #}{ x }";
+        assert!(render_str(content).ends_with("1. one\n2. two\ntwo{{ kept }} { x }"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(render("{% if x %}", &HashMap::new()).is_err());
+        assert!(render("{{ x ", &HashMap::new()).is_err());
+        assert!(render("{% endfor %}", &HashMap::new()).is_err());
+        assert!(render("{{ x | nope }}", &HashMap::new()).is_err());
+    }
+}
@@ -0,0 +1,200 @@
+//! Extension point for rewriting the markdown event stream before it is rendered.
+//!
+//! Features that only need to change what pulldown-cmark produced (e.g. emoji shortcodes,
+//! wiki-links, callouts) can be written as a [`RenderTransformer`] and registered in
+//! [`RenderOptions::transformers`](crate::render::RenderOptions::transformers) instead of being
+//! wired into the renderer.
+
+use std::{fmt, iter::Peekable, ops::Range, sync::Arc};
+
+use pulldown_cmark::{CowStr, Event};
+
+use crate::{
+    autolink::Autolink,
+    drafts::Drafts,
+    emoji::Emoji,
+    footnotes::{EndFootnotes, InlineFootnotes},
+    obfuscate::ObfuscateEmails,
+    plot::Plots,
+};
+
+/// Rewrites parsed markdown events before spellchecking, link resolution and HTML output.
+///
+/// Transformers may replace, insert or remove events. The ranges index into the source the
+/// events were parsed from, which during live preview is a single top-level block rather than the
+/// whole document (see [`crate::block_cache`]), so a transformer must not rely on seeing the
+/// whole document at once.
+pub trait RenderTransformer: Send + Sync {
+    /// Name shown in debug output.
+    fn name(&self) -> &str;
+
+    fn transform<'a>(&self, events: &mut Vec<(Event<'a>, Range<usize>)>);
+}
+
+/// The transformers to run, in order.
+#[derive(Clone, Default)]
+pub struct Transformers(Vec<Arc<dyn RenderTransformer>>);
+
+impl Transformers {
+    /// The transformers every document is rendered with.
+    pub fn builtin() -> Self {
+        let mut transformers = Self::default();
+        transformers.register(Drafts);
+        transformers.register(InlineFootnotes);
+        transformers.register(EndFootnotes);
+        transformers.register(Plots);
+        transformers
+    }
+
+    /// The built-in transformers, plus bare URL linking and email obfuscation in the given
+    /// `--autolink` and `--obfuscate-emails` modes, and emoji shortcodes with `--emoji`.
+    pub fn with_extensions(autolink: &str, obfuscate_emails: &str, emoji: bool) -> Self {
+        let mut transformers = Self::builtin();
+        if emoji {
+            transformers.register(Emoji);
+        }
+        if let Some(autolink) = Autolink::from_mode(autolink) {
+            transformers.register(autolink);
+        }
+        // After autolinking, so that linked bare addresses are obfuscated too
+        if let Some(obfuscate) = ObfuscateEmails::from_mode(obfuscate_emails) {
+            transformers.register(obfuscate);
+        }
+        transformers
+    }
+
+    /// Adds a transformer that runs after the ones already registered.
+    pub fn register(&mut self, transformer: impl RenderTransformer + 'static) {
+        self.0.push(Arc::new(transformer));
+    }
+
+    /// Runs every transformer over `events`.
+    pub fn apply<'a>(&self, events: &mut Vec<(Event<'a>, Range<usize>)>) {
+        for transformer in &self.0 {
+            transformer.transform(events);
+        }
+    }
+}
+
+impl fmt::Debug for Transformers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.0.iter().map(|transformer| transformer.name()))
+            .finish()
+    }
+}
+
+/// Joins `text` with the text events directly following it in `events`. The parser splits text
+/// at characters that could be emphasis, such as `_`, which URLs and email addresses often
+/// contain, so transformers looking for them need the whole run of text at once.
+pub fn text_run<'a>(
+    text: CowStr<'a>,
+    range: Range<usize>,
+    events: &mut Peekable<impl Iterator<Item = (Event<'a>, Range<usize>)>>,
+) -> (String, Range<usize>) {
+    let mut run = text.into_string();
+    let mut run_range = range;
+    while let Some((Event::Text(next), next_range)) =
+        events.next_if(|(event, _)| matches!(event, Event::Text(_)))
+    {
+        run.push_str(&next);
+        run_range.end = next_range.end;
+    }
+    (run, run_range)
+}
+
+/// A part of a run of text split by [`split_text`].
+pub enum Piece<T> {
+    Text(String),
+    Match(T),
+}
+
+/// Splits `text` at the matches `find` reports, or returns `None` if there are none. At each
+/// position, `find` is given the text before and from there, and returns the length of the match
+/// starting there along with what it stands for.
+pub fn split_text<T>(
+    text: &str,
+    mut find: impl FnMut(&str, &str) -> Option<(usize, T)>,
+) -> Option<Vec<Piece<T>>> {
+    let mut pieces = Vec::new();
+    let mut plain = 0;
+    let mut pos = 0;
+
+    while pos < text.len() {
+        match find(&text[..pos], &text[pos..]) {
+            Some((len, found)) => {
+                if plain < pos {
+                    pieces.push(Piece::Text(text[plain..pos].to_string()));
+                }
+                pieces.push(Piece::Match(found));
+                pos += len;
+                plain = pos;
+            }
+            None => pos += text[pos..].chars().next().map_or(1, char::len_utf8),
+        }
+    }
+
+    if pieces.is_empty() {
+        return None;
+    }
+    if plain < text.len() {
+        pieces.push(Piece::Text(text[plain..].to_string()));
+    }
+    Some(pieces)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{
+        block_cache::BlockCache,
+        render::{RenderOptions, render_markdown_to_html},
+    };
+
+    /// Replaces every "TODO" text event with a highlighted marker.
+    struct Todo;
+
+    impl RenderTransformer for Todo {
+        fn name(&self) -> &str {
+            "todo"
+        }
+
+        fn transform<'a>(&self, events: &mut Vec<(Event<'a>, Range<usize>)>) {
+            for (event, _) in events.iter_mut() {
+                if matches!(event, Event::Text(text) if text.as_ref() == "TODO") {
+                    *event = Event::Html("<mark>TODO</mark>".into());
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_transformers_apply() {
+        let mut options = RenderOptions::default();
+        assert_eq!(
+            format!("{:?}", options.transformers),
+            "[\"drafts\", \"inline-footnotes\", \"end-footnotes\", \"plot\"]"
+        );
+
+        options.transformers = Transformers::with_extensions("none", "none", true);
+        options.transformers.register(Todo);
+        assert_eq!(
+            format!("{:?}", options.transformers),
+            "[\"drafts\", \"inline-footnotes\", \"end-footnotes\", \"plot\", \"emoji\", \"todo\"]"
+        );
+
+        let content = "# Plan :rocket:\n\nTODO\n";
+        let path = Path::new("doc.md");
+        let expected = "<h1 id=\"plan-\" data-source-line=\"1\">Plan 🚀</h1>\n<p data-source-line=\"3\"><mark>TODO</mark></p>\n";
+        assert_eq!(
+            render_markdown_to_html(content, path, &options).await,
+            expected
+        );
+        assert_eq!(
+            BlockCache::default().render(content, path, &options).await,
+            expected
+        );
+    }
+}
@@ -0,0 +1,155 @@
+//! Public URLs for the preview, through a tunneling service run as a subprocess, so live drafts
+//! can be shared with reviewers outside the local network.
+
+use std::{net::SocketAddr, process::Stdio, time::Duration};
+
+use anyhow::{Context, bail};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::{Child, Command},
+    sync::mpsc,
+};
+
+/// How long the tunnel client gets to report its public URL.
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Backend {
+    /// A Cloudflare quick tunnel (`cloudflared tunnel --url`), which needs no account.
+    Cloudflared,
+    /// `ngrok http`, using the ngrok account configured on this machine.
+    Ngrok,
+}
+
+impl Backend {
+    fn program(self) -> &'static str {
+        match self {
+            Backend::Cloudflared => "cloudflared",
+            Backend::Ngrok => "ngrok",
+        }
+    }
+
+    fn command(self, local: SocketAddr) -> Command {
+        let mut command = Command::new(self.program());
+        match self {
+            Backend::Cloudflared => command.args([
+                "tunnel",
+                "--no-autoupdate",
+                "--url",
+                &format!("http://{local}"),
+            ]),
+            Backend::Ngrok => command.args([
+                "http",
+                &local.to_string(),
+                "--log",
+                "stdout",
+                "--log-format",
+                "json",
+            ]),
+        };
+        command
+    }
+
+    /// The public URL announced on a line of the client's output, if any.
+    fn url_in(self, line: &str) -> Option<String> {
+        match self {
+            // The URL is printed in a banner, e.g. `|  https://a-b-c.trycloudflare.com  |`
+            Backend::Cloudflared => line
+                .split(|c: char| c.is_whitespace() || c == '|')
+                .find(|word| word.starts_with("https://") && word.ends_with(".trycloudflare.com"))
+                .map(str::to_string),
+            Backend::Ngrok => {
+                let entry: serde_json::Value = serde_json::from_str(line).ok()?;
+                if entry["msg"] != "started tunnel" {
+                    return None;
+                }
+                entry["url"].as_str().map(str::to_string)
+            }
+        }
+    }
+}
+
+/// A running tunnel client. It is killed when dropped.
+pub struct Tunnel {
+    _child: Child,
+    pub url: String,
+}
+
+/// Starts a tunnel to the server listening on `local`, and waits for its public URL.
+pub async fn open(backend: Backend, local: SocketAddr) -> anyhow::Result<Tunnel> {
+    // Servers bound to all interfaces are tunneled over loopback
+    let local = if local.ip().is_unspecified() {
+        SocketAddr::from(([127, 0, 0, 1], local.port()))
+    } else {
+        local
+    };
+
+    let mut child = backend
+        .command(local)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .with_context(|| format!("failed to run `{}`", backend.program()))?;
+
+    let (tx, mut rx) = mpsc::channel(1);
+    if let Some(stdout) = child.stdout.take() {
+        tokio::spawn(scan(stdout, backend, tx.clone()));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        tokio::spawn(scan(stderr, backend, tx));
+    }
+
+    match tokio::time::timeout(STARTUP_TIMEOUT, rx.recv()).await {
+        Ok(Some(url)) => Ok(Tunnel { _child: child, url }),
+        Ok(None) => bail!("`{}` exited without reporting a URL", backend.program()),
+        Err(_) => bail!(
+            "`{}` didn't report a URL within {}s",
+            backend.program(),
+            STARTUP_TIMEOUT.as_secs()
+        ),
+    }
+}
+
+/// Reads the client's output for the public URL. Keeps draining it afterwards, so the client
+/// never blocks on a full pipe.
+async fn scan(output: impl AsyncRead + Unpin, backend: Backend, tx: mpsc::Sender<String>) {
+    let mut lines = BufReader::new(output).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if let Some(url) = backend.url_in(&line) {
+            let _ = tx.try_send(url);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_url_in() {
+        let cloudflared = Backend::Cloudflared;
+        assert_eq!(
+            cloudflared.url_in("2024-01-01T00:00:00Z INF |  https://a-b-c.trycloudflare.com  |"),
+            Some("https://a-b-c.trycloudflare.com".to_string())
+        );
+        assert_eq!(
+            cloudflared.url_in("INF Requesting new quick Tunnel on https://api.trycloudflare.com/"),
+            None
+        );
+
+        let ngrok = Backend::Ngrok;
+        assert_eq!(
+            ngrok.url_in(
+                r#"{"lvl":"info","msg":"started tunnel","name":"command_line","url":"https://abc.ngrok-free.app"}"#
+            ),
+            Some("https://abc.ngrok-free.app".to_string())
+        );
+        assert_eq!(
+            ngrok.url_in(r#"{"lvl":"info","msg":"client session established"}"#),
+            None
+        );
+        assert_eq!(ngrok.url_in("not json"), None);
+    }
+}
@@ -0,0 +1,96 @@
+//! Prose linting by running [Vale](https://vale.sh) as a subprocess.
+
+use std::{collections::HashMap, path::Path, process::Stdio};
+
+use anyhow::Context;
+use serde::Deserialize;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::diagnostic::{Diagnostic, Severity};
+
+/// A single alert from `vale --output=JSON`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Alert {
+    line: usize,
+    check: String,
+    message: String,
+    severity: String,
+}
+
+impl From<Alert> for Diagnostic {
+    fn from(alert: Alert) -> Self {
+        let severity = match alert.severity.as_str() {
+            "error" => Severity::Error,
+            "warning" => Severity::Warning,
+            _ => Severity::Hint,
+        };
+
+        Diagnostic {
+            line: alert.line,
+            severity,
+            message: format!("{}: {}", alert.check, alert.message),
+        }
+    }
+}
+
+/// Runs `vale` against `content` and returns its alerts.
+///
+/// Vale runs in the directory of `path` so it picks up the project's `.vale.ini`.
+pub async fn check(vale: &str, content: &str, path: &Path) -> anyhow::Result<Vec<Diagnostic>> {
+    let mut command = Command::new(vale);
+    command
+        .args(["--output=JSON", "--ext=.md"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .kill_on_drop(true);
+    if let Some(dir) = path.parent() {
+        command.current_dir(dir);
+    }
+
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("Failed to run {vale}"))?;
+
+    let mut stdin = child.stdin.take().context("Vale stdin unavailable")?;
+    stdin.write_all(content.as_bytes()).await?;
+    drop(stdin);
+
+    // Vale exits non-zero when it finds errors, so only the output matters
+    let output = child.wait_with_output().await?;
+    parse_output(&output.stdout)
+}
+
+fn parse_output(stdout: &[u8]) -> anyhow::Result<Vec<Diagnostic>> {
+    let files: HashMap<String, Vec<Alert>> =
+        serde_json::from_slice(stdout).context("Unexpected Vale output")?;
+
+    let mut diagnostics: Vec<Diagnostic> = files
+        .into_values()
+        .flatten()
+        .map(Diagnostic::from)
+        .collect();
+    diagnostics.sort_by_key(|d| d.line);
+
+    Ok(diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_output() {
+        let stdout = br#"{"stdin.md":[{"Action":{"Name":"","Params":null},"Span":[1,4],"Check":"Vale.Spelling","Description":"","Link":"","Message":"Did you really mean 'teh'?","Severity":"error","Match":"teh","Line":3}]}"#;
+        assert_eq!(
+            parse_output(stdout).unwrap(),
+            vec![Diagnostic {
+                line: 3,
+                severity: Severity::Error,
+                message: "Vale.Spelling: Did you really mean 'teh'?".to_string(),
+            }]
+        );
+        assert!(parse_output(b"{}").unwrap().is_empty());
+    }
+}
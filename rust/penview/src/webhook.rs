@@ -0,0 +1,187 @@
+//! Webhooks notified of preview events, so external automation such as site rebuilds or chat
+//! notifications can hook into penview. Each event is POSTed as JSON to every webhook URL:
+//!
+//! ```json
+//! {"event":"rendered","path":"/notes/todo.md","timestamp":"2024-05-01T12:00:00.000Z","duration_ms":4.2}
+//! ```
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use chrono::{SecondsFormat, Utc};
+use serde::Serialize;
+use tokio::task::JoinHandle;
+use tracing::{Instrument, warn};
+
+/// How long editing has to pause before a `rendered` event is sent, so that typing doesn't send
+/// one per keystroke. Only the last render is reported.
+const RENDERED_DELAY: Duration = Duration::from_secs(1);
+
+/// How long a webhook gets to respond.
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Event {
+    /// A document was rendered for its previews after an edit.
+    Rendered,
+    /// A document was exported, by `penview export` or an `/api/export/...` endpoint.
+    Exported,
+    /// A browser opened a preview, or Neovim connected to push a buffer.
+    Connected,
+}
+
+/// The JSON body POSTed to webhooks. Fields that don't apply to the event are left out.
+#[derive(Debug, Serialize)]
+pub struct Payload {
+    pub event: Event,
+    /// The document, or the exported directory.
+    pub path: PathBuf,
+    /// When the event happened, in RFC 3339 format.
+    pub timestamp: String,
+    /// How long rendering or exporting took.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<f64>,
+    /// `browser` or `neovim`, for `connected`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<&'static str>,
+    /// What was exported to: `html`, `pdf`, `html-fragment` or `png`, for `exported`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<&'static str>,
+    /// The directory written to, for `penview export`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub out: Option<PathBuf>,
+    /// How many documents were written, for `penview export`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub documents: Option<usize>,
+}
+
+impl Payload {
+    pub fn new(event: Event, path: &Path) -> Self {
+        Self {
+            event,
+            path: path.to_path_buf(),
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            duration_ms: None,
+            client: None,
+            format: None,
+            out: None,
+            documents: None,
+        }
+    }
+
+    pub fn duration(mut self, duration: Duration) -> Self {
+        self.duration_ms = Some(duration.as_secs_f64() * 1000.0);
+        self
+    }
+}
+
+/// The configured webhooks (see `--webhook`).
+#[derive(Debug, Clone, Default)]
+pub struct Webhooks {
+    urls: Vec<String>,
+    /// Events to send; all of them if empty.
+    events: Vec<Event>,
+    /// `rendered` events waiting for editing to pause, by document.
+    pending: Arc<std::sync::Mutex<HashMap<PathBuf, JoinHandle<()>>>>,
+    client: reqwest::Client,
+}
+
+impl Webhooks {
+    pub fn new(urls: Vec<String>, events: Vec<Event>) -> Self {
+        Self {
+            urls,
+            events,
+            ..Default::default()
+        }
+    }
+
+    fn wants(&self, event: Event) -> bool {
+        !self.urls.is_empty() && (self.events.is_empty() || self.events.contains(&event))
+    }
+
+    /// Sends `payload` to every webhook in the background. `rendered` events are held back until
+    /// editing pauses, replacing the one still held for the same document.
+    pub fn notify(&self, payload: Payload) {
+        if !self.wants(payload.event) {
+            return;
+        }
+
+        let webhooks = self.clone();
+        if payload.event != Event::Rendered {
+            tokio::spawn(async move { webhooks.send(&payload).await }.in_current_span());
+            return;
+        }
+
+        let mut pending = self.pending.lock().unwrap();
+        let path = payload.path.clone();
+        if let Some(task) = pending.remove(&path) {
+            task.abort();
+        }
+        let task = tokio::spawn(
+            async move {
+                tokio::time::sleep(RENDERED_DELAY).await;
+                webhooks.pending.lock().unwrap().remove(&payload.path);
+                webhooks.send(&payload).await;
+            }
+            .in_current_span(),
+        );
+        pending.insert(path, task);
+    }
+
+    /// Sends `payload` to every webhook and waits for them, logging failures.
+    pub async fn send(&self, payload: &Payload) {
+        if !self.wants(payload.event) {
+            return;
+        }
+
+        let body = serde_json::to_vec(payload).unwrap();
+        for url in &self.urls {
+            if let Err(e) = self.post(url, body.clone()).await {
+                warn!("Webhook {url} failed: {e}");
+            }
+        }
+    }
+
+    async fn post(&self, url: &str, body: Vec<u8>) -> reqwest::Result<()> {
+        self.client
+            .post(url)
+            .timeout(TIMEOUT)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload() {
+        let mut payload = Payload::new(Event::Connected, Path::new("/notes/todo.md"));
+        payload.client = Some("browser");
+        let json = serde_json::to_value(&payload).unwrap();
+
+        assert_eq!(json["event"], "connected");
+        assert_eq!(json["path"], "/notes/todo.md");
+        assert_eq!(json["client"], "browser");
+        assert!(json.get("duration_ms").is_none());
+        assert!(json["timestamp"].is_string());
+    }
+
+    #[test]
+    fn test_wants() {
+        let url = vec!["http://localhost/hook".to_string()];
+        assert!(Webhooks::new(url.clone(), vec![]).wants(Event::Rendered));
+        assert!(!Webhooks::new(url, vec![Event::Exported]).wants(Event::Rendered));
+        assert!(!Webhooks::new(vec![], vec![]).wants(Event::Exported));
+    }
+}
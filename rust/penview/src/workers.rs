@@ -0,0 +1,102 @@
+//! Bounded concurrency for the CPU-bound stages of rendering (parsing, transformers such as
+//! charts, and writing HTML), so that a heavy document can't starve the async runtime serving
+//! other previews. At most [`configure`]d stages run at once across the server, and the stages of
+//! one document run one at a time, so a burst of renders of the same file occupies one worker.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{Arc, LazyLock, Mutex, OnceLock, Weak},
+};
+
+use tokio::{
+    runtime::{Handle, RuntimeFlavor},
+    sync::Semaphore,
+};
+
+static WORKERS: OnceLock<Semaphore> = OnceLock::new();
+
+/// Queues of the documents being rendered. Entries are dropped along with the last render
+/// waiting on them.
+static QUEUES: LazyLock<Mutex<HashMap<PathBuf, Weak<tokio::sync::Mutex<()>>>>> =
+    LazyLock::new(Default::default);
+
+/// Sets how many CPU-bound stages may run at once; 0 uses the number of CPUs. Only takes effect
+/// before the first render.
+pub fn configure(workers: usize) {
+    let workers = match workers {
+        0 => default_workers(),
+        n => n,
+    };
+    let _ = WORKERS.set(Semaphore::new(workers));
+}
+
+fn default_workers() -> usize {
+    std::thread::available_parallelism().map_or(4, |n| n.get())
+}
+
+fn queue(path: &Path) -> Arc<tokio::sync::Mutex<()>> {
+    let mut queues = QUEUES.lock().unwrap();
+    if let Some(queue) = queues.get(path).and_then(Weak::upgrade) {
+        return queue;
+    }
+    queues.retain(|_, queue| queue.strong_count() > 0);
+    let queue = Arc::default();
+    queues.insert(path.to_path_buf(), Arc::downgrade(&queue));
+    queue
+}
+
+/// Runs the CPU-bound `f` for the document at `path` once a worker is free and no other stage of
+/// the document is running. On the multi-threaded runtime `f` runs with the thread handed over to
+/// blocking work, so other tasks move to the remaining threads meanwhile.
+pub async fn run<T>(path: &Path, f: impl FnOnce() -> T) -> T {
+    let queue = queue(path);
+    let _turn = queue.lock().await;
+    let _permit = WORKERS
+        .get_or_init(|| Semaphore::new(default_workers()))
+        .acquire()
+        .await
+        .expect("the semaphore is never closed");
+
+    match Handle::current().runtime_flavor() {
+        RuntimeFlavor::MultiThread => tokio::task::block_in_place(f),
+        _ => f(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_run_queues_per_path() {
+        let path = Path::new("/tmp/penview-workers-test.md");
+        let running = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let tasks: Vec<_> = (0..4)
+            .map(|_| {
+                let running = running.clone();
+                tokio::spawn(async move {
+                    run(path, || {
+                        use std::sync::atomic::Ordering;
+                        assert_eq!(running.fetch_add(1, Ordering::SeqCst), 0);
+                        std::thread::sleep(std::time::Duration::from_millis(10));
+                        running.fetch_sub(1, Ordering::SeqCst);
+                    })
+                    .await
+                })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+
+        assert!(
+            QUEUES
+                .lock()
+                .unwrap()
+                .get(path)
+                .is_none_or(|q| q.strong_count() == 0)
+        );
+    }
+}
@@ -0,0 +1,982 @@
+// Live preview: updates from the editor, cursor and scroll sync, margins and panels
+
+// Elements mapped to source lines, excluding inline ones (links, images, inline code)
+const sourceBlocks = '.markdown-body [data-source-line]:not(a, img, code)';
+
+// Prefix of the server's routes, when it sits behind a reverse proxy
+const base = penviewConfig.base;
+// Text shown by the script, in the page's language
+const messages = penviewConfig.messages;
+// Origin for WebSockets, as reported by a proxy in front of the server, else the page's own
+const socketOrigin = penviewConfig.socketOrigin
+  || `${location.protocol === 'https:' ? 'wss' : 'ws'}://${location.host}`;
+
+const urlParams = new URLSearchParams(window.location.search);
+const path = urlParams.get('path');
+// Neovim instance whose buffers to show, when several editors share one server
+const instance = urlParams.get('instance');
+
+function withInstance(params) {
+  if (instance) {
+    params.set('instance', instance);
+  }
+  return params;
+}
+
+function syncScroll(msg) {
+  // Typewriter scrolling keeps the cursor's block in the middle of the window
+  if (msg.sync_scroll && penviewConfig.typewriter && typeof msg.cursor_line === 'number') {
+    cursorBlock(msg.cursor_line)?.scrollIntoView({ block: 'center', behavior: 'smooth' });
+    return;
+  }
+  if (msg.sync_scroll && typeof msg.scroll_ratio === 'number') {
+    const maxScroll = document.documentElement.scrollHeight - window.innerHeight;
+    if (maxScroll > 0) {
+      window.scrollTo({
+        top: maxScroll * msg.scroll_ratio,
+        behavior: 'smooth'
+      });
+    }
+  }
+}
+
+function syncViewport(msg) {
+  if (!msg.sync_scroll) {
+    return;
+  }
+  const maxScroll = document.documentElement.scrollHeight - window.innerHeight;
+  if (maxScroll > 0) {
+    // Align the top of the editor window with the top of the page, unless the document
+    // ends first
+    const top = document.documentElement.scrollHeight * msg.top_ratio;
+    window.scrollTo({
+      top: Math.min(top, maxScroll),
+      behavior: 'smooth'
+    });
+  }
+}
+
+// Block under the editor cursor, highlighted according to --cursor-highlight
+const cursorHighlight = penviewConfig.cursorHighlight;
+let cursorLine = null;
+let cursorBlockLine = null;
+
+// The innermost block starting at or above `line`
+function cursorBlock(line) {
+  let block = null;
+  for (const el of document.querySelectorAll(sourceBlocks)) {
+    if (Number(el.dataset.sourceLine) <= line) {
+      block = el;
+    }
+  }
+  return block;
+}
+
+function highlightCursor() {
+  if (cursorHighlight === 'none' || cursorLine == null) {
+    return;
+  }
+
+  const block = cursorBlock(cursorLine);
+
+  // Only flash when the cursor moves to another block
+  const line = block?.dataset.sourceLine ?? null;
+  if (cursorHighlight === 'flash' && line === cursorBlockLine) {
+    return;
+  }
+  cursorBlockLine = line;
+
+  const className = `penview-cursor-${cursorHighlight}`;
+  for (const el of document.querySelectorAll(`.${className}`)) {
+    el.classList.remove(className);
+  }
+  block?.classList.add(className);
+}
+
+// Focus mode: dims everything but the section containing the cursor line, from its heading to
+// the next heading of the same or a higher level
+let focusMode = localStorage.getItem('penview-focus') === 'true';
+
+function highlightFocus() {
+  const blocks = [...document.querySelectorAll('.markdown-body > *')];
+  for (const el of blocks) {
+    el.classList.remove('penview-unfocused');
+  }
+  if (!focusMode || cursorLine == null || blocks.length === 0) {
+    return;
+  }
+
+  const level = (el) => /^H[1-6]$/.test(el.tagName) ? Number(el.tagName[1]) : null;
+  let current = 0;
+  blocks.forEach((el, i) => {
+    if (el.dataset.sourceLine && Number(el.dataset.sourceLine) <= cursorLine) {
+      current = i;
+    }
+  });
+
+  let start = current;
+  while (start > 0 && level(blocks[start]) == null) {
+    start--;
+  }
+  // Text before the first heading ends at any heading
+  const sectionLevel = level(blocks[start]) ?? 6;
+  let end = current + 1;
+  while (end < blocks.length && !(level(blocks[end]) && level(blocks[end]) <= sectionLevel)) {
+    end++;
+  }
+
+  blocks.forEach((el, i) => {
+    if (i < start || i >= end) {
+      el.classList.add('penview-unfocused');
+    }
+  });
+}
+
+function setFocusMode(enabled) {
+  focusMode = enabled;
+  localStorage.setItem('penview-focus', enabled);
+  document.body.classList.toggle('penview-focus', enabled);
+  highlightFocus();
+}
+
+document.addEventListener('DOMContentLoaded', function () {
+  setFocusMode(focusMode);
+  document.getElementById('penview-focus-toggle').addEventListener('click', function () {
+    setFocusMode(!focusMode);
+  });
+  document.addEventListener('keydown', function (event) {
+    if (event.key === 'z' && !event.ctrlKey && !event.metaKey && !event.altKey
+      && !event.target.closest('input, select, textarea, [contenteditable="true"]')) {
+      setFocusMode(!focusMode);
+    }
+  });
+});
+
+// Full width, as last toggled in this browser or as configured
+let fullWidth = JSON.parse(localStorage.getItem('penview-full-width') ?? penviewConfig.fullWidth);
+
+function setFullWidth(enabled) {
+  fullWidth = enabled;
+  localStorage.setItem('penview-full-width', enabled);
+  document.body.classList.toggle('penview-full-width', enabled);
+}
+
+document.addEventListener('DOMContentLoaded', function () {
+  setFullWidth(fullWidth);
+  document.getElementById('penview-width-toggle').addEventListener('click', function () {
+    setFullWidth(!fullWidth);
+  });
+});
+
+// Last visual selection received from the editor, re-applied after every update
+let selection = null;
+
+function highlightSelection() {
+  for (const el of document.querySelectorAll('.penview-selection')) {
+    el.classList.remove('penview-selection');
+  }
+  if (!selection || selection.start_line == null || selection.end_line == null) {
+    return;
+  }
+
+  const start = Math.min(selection.start_line, selection.end_line);
+  const end = Math.max(selection.start_line, selection.end_line);
+  const elements = [...document.querySelectorAll(sourceBlocks)];
+
+  // The block containing the first selected line may start above the selection
+  let containing = null;
+  for (const el of elements) {
+    const line = Number(el.dataset.sourceLine);
+    if (line <= start) {
+      containing = el;
+    }
+    if (line >= start && line <= end) {
+      el.classList.add('penview-selection');
+    }
+  }
+  containing?.classList.add('penview-selection');
+
+  // Only highlight the outermost element of nested blocks
+  for (const el of document.querySelectorAll('.penview-selection .penview-selection')) {
+    el.classList.remove('penview-selection');
+  }
+}
+
+// Last diagnostics received from the editor, re-applied after every update
+let diagnostics = [];
+// Lint warnings for the current content, sent along with each render
+let lint = [];
+// Vale alerts, sent separately once editing pauses
+let vale = [];
+const severityRank = { error: 0, warning: 1, info: 2, hint: 3 };
+
+function renderDiagnostics() {
+  for (const el of document.querySelectorAll('.penview-diagnostic-marker')) {
+    el.remove();
+  }
+  for (const el of document.querySelectorAll('[data-diagnostic]')) {
+    el.removeAttribute('data-diagnostic');
+  }
+
+  // Group diagnostics by the innermost block containing their line
+  const elements = [...document.querySelectorAll(sourceBlocks)];
+  const targets = new Map();
+  for (const d of diagnostics.concat(lint, vale)) {
+    let target = null;
+    for (const el of elements) {
+      if (Number(el.dataset.sourceLine) <= d.line) {
+        target = el;
+      }
+    }
+    if (!target) {
+      continue;
+    }
+    if (!targets.has(target)) {
+      targets.set(target, []);
+    }
+    targets.get(target).push(d);
+  }
+
+  for (const [target, list] of targets) {
+    list.sort((a, b) => severityRank[a.severity] - severityRank[b.severity]);
+    const severity = list[0].severity;
+    target.setAttribute('data-diagnostic', severity);
+
+    const marker = document.createElement('span');
+    marker.className = `penview-diagnostic-marker penview-diagnostic-${severity}`;
+    marker.title = list.map((d) => `${d.line}: [${d.severity}] ${d.message}`).join('\n');
+    marker.textContent = '\u25CF';
+    target.prepend(marker);
+  }
+}
+
+// Last git blame hunks, re-applied after every update
+let blame = [];
+
+function renderBlame() {
+  for (const el of document.querySelectorAll('.penview-blame')) {
+    el.remove();
+  }
+
+  // Each top-level block spans up to the line before the next one
+  const blocks = [...document.querySelectorAll('.markdown-body > [data-source-line]')];
+  blocks.forEach((block, i) => {
+    const start = Number(block.dataset.sourceLine);
+    const end = i + 1 < blocks.length ? Number(blocks[i + 1].dataset.sourceLine) - 1 : Infinity;
+
+    // Show the most recent change within the block
+    let latest = null;
+    for (const hunk of blame) {
+      if (hunk.start_line <= end && hunk.end_line >= start && (!latest || hunk.time > latest.time)) {
+        latest = hunk;
+      }
+    }
+    if (!latest) {
+      return;
+    }
+
+    const uncommitted = /^0+$/.test(latest.commit);
+    const date = new Date(latest.time * 1000).toLocaleDateString();
+    const gutter = document.createElement('span');
+    gutter.className = 'penview-blame';
+    gutter.textContent = uncommitted ? messages.notCommitted : `${latest.author}, ${date}`;
+    gutter.title = uncommitted
+      ? messages.notCommittedYet
+      : `${latest.commit} ${latest.author}, ${date}\n${latest.summary}`;
+    block.style.position = 'relative';
+    block.prepend(gutter);
+  });
+}
+
+if (penviewConfig.blame) {
+  document.addEventListener("DOMContentLoaded", function () {
+    fetch(`${base}/api/blame?path=${encodeURIComponent(path)}`)
+      .then((response) => response.ok ? response.json() : [])
+      .then((hunks) => {
+        blame = hunks;
+        renderBlame();
+      });
+  });
+}
+
+function reloadCss() {
+  fetch(`${base}/api/css?path=${encodeURIComponent(path)}`)
+    .then((response) => response.ok ? response.text() : null)
+    .then((css) => {
+      if (css === null) {
+        return;
+      }
+      let style = document.getElementById('penview-css');
+      if (!style) {
+        style = document.createElement('style');
+        style.id = 'penview-css';
+        document.head.appendChild(style);
+      }
+      style.textContent = css;
+    });
+}
+
+// Scroll position stashed before a reload triggered by a save, restored once the reloaded page
+// (images included) has loaded so that saving doesn't jump back to the top
+const scrollKey = `penview-scroll:${path}`;
+
+window.addEventListener('load', function () {
+  const scrollY = sessionStorage.getItem(scrollKey);
+  if (scrollY !== null) {
+    sessionStorage.removeItem(scrollKey);
+    window.scrollTo({ top: Number(scrollY) });
+  }
+});
+
+// Open/closed state of the document's <details> elements as the user left it, reapplied after
+// every update and reload. Elements are identified by their summary and its occurrence.
+const detailsKey = `penview-details:${path}`;
+const detailsState = JSON.parse(sessionStorage.getItem(detailsKey) || '{}');
+
+function detailsIds() {
+  const seen = {};
+  return [...document.querySelectorAll('.markdown-body details')].map((el) => {
+    const summary = el.querySelector(':scope > summary')?.textContent.trim() ?? '';
+    seen[summary] = (seen[summary] ?? 0) + 1;
+    return [el, `${summary}#${seen[summary]}`];
+  });
+}
+
+function restoreDetails() {
+  for (const [el, id] of detailsIds()) {
+    if (id in detailsState) {
+      el.open = detailsState[id];
+    }
+  }
+}
+
+document.addEventListener('DOMContentLoaded', restoreDetails);
+
+// Toggle events don't bubble
+document.addEventListener('toggle', function (event) {
+  if (!event.target.closest?.('.markdown-body details')) {
+    return;
+  }
+  const entry = detailsIds().find(([el]) => el === event.target);
+  if (entry) {
+    detailsState[entry[1]] = event.target.open;
+    sessionStorage.setItem(detailsKey, JSON.stringify(detailsState));
+  }
+}, true);
+
+function handleMessage(event) {
+  if (!event.data) {
+    // Empty message = file saved, do full reload
+    console.log("File saved, reloading");
+    sessionStorage.setItem(scrollKey, window.scrollY);
+    location.reload();
+    return;
+  }
+
+  // Try to parse as JSON (new format with scroll sync)
+  try {
+    const msg = JSON.parse(event.data);
+
+    // Cursor moved without content changes, only scroll
+    if (msg.type === 'scroll') {
+      cursorLine = msg.cursor_line;
+      highlightCursor();
+      highlightFocus();
+      syncScroll(msg);
+      return;
+    }
+
+    // Editor window scrolled, mirror its visible range
+    if (msg.type === 'viewport') {
+      if (typeof msg.cursor_line === 'number') {
+        cursorLine = msg.cursor_line;
+        highlightCursor();
+        highlightFocus();
+      }
+      syncViewport(msg);
+      return;
+    }
+
+    // Another browser previewing this document scrolled
+    if (msg.type === 'mirror_scroll') {
+      if (msg.client !== mirrorClient) {
+        window.scrollTo({ top: document.documentElement.scrollHeight * msg.ratio });
+      }
+      return;
+    }
+
+    // Visual selection changed in the editor
+    if (msg.type === 'selection') {
+      selection = msg;
+      highlightSelection();
+      return;
+    }
+
+    // Editor diagnostics changed
+    if (msg.type === 'diagnostics') {
+      diagnostics = msg.diagnostics;
+      renderDiagnostics();
+      return;
+    }
+
+    // Vale finished checking the latest content
+    if (msg.type === 'vale') {
+      vale = msg.diagnostics;
+      renderDiagnostics();
+      return;
+    }
+
+    // The editor switched the mode or style
+    if (msg.type === 'theme') {
+      window.penviewSetTheme?.(msg.theme);
+      return;
+    }
+
+    // User stylesheet changed, swap it in place to keep the scroll position
+    if (msg.type === 'css') {
+      reloadCss();
+      return;
+    }
+
+    // git blame of the latest content
+    if (msg.type === 'blame') {
+      blame = msg.hunks;
+      renderBlame();
+      return;
+    }
+
+    document.querySelector('.markdown-body').innerHTML = msg.html;
+    window.hljs?.highlightAll();
+    window.mermaidRender?.();
+    window.mathRender?.();
+    window.chartRender?.();
+    window.graphvizRender?.();
+    window.tocRender?.();
+    window.emailRender?.();
+    lint = msg.lint || [];
+    if (typeof msg.words === 'number') {
+      const words = document.getElementById('penview-footer-words');
+      if (words) {
+        words.textContent = msg.words;
+      }
+    }
+    cursorLine = msg.cursor_line;
+    highlightCursor();
+    highlightFocus();
+    highlightSelection();
+    renderDiagnostics();
+    renderBlame();
+    loadSource();
+    makeEditable();
+    addRunButtons();
+    restoreDetails();
+
+    syncScroll(msg);
+    console.log("Live preview updated, scroll_ratio:", msg.scroll_ratio);
+  } catch (e) {
+    // Fallback for plain HTML (backwards compatibility)
+    document.querySelector('.markdown-body').innerHTML = event.data;
+    window.hljs?.highlightAll();
+    window.mermaidRender?.();
+    window.mathRender?.();
+    window.chartRender?.();
+    window.graphvizRender?.();
+    window.tocRender?.();
+    window.emailRender?.();
+    console.log("Live preview updated (legacy format)");
+  }
+}
+
+// Identifies this page among the browsers mirroring each other's scrolling
+const mirrorClient = Math.random().toString(36).slice(2);
+
+// Past revisions are static, only the working copy gets live updates
+const revision = urlParams.get('rev');
+
+if (!revision) {
+  const socket = new WebSocket(`${socketOrigin}${base}/watch?${withInstance(new URLSearchParams({ path }))}`);
+  // Parts of messages too long for one frame, by message ID
+  const chunks = new Map();
+
+  socket.onmessage = function (event) {
+    let data = event.data;
+    if (data.startsWith('{"type":"chunk"')) {
+      const chunk = JSON.parse(data);
+      const parts = chunks.get(chunk.id) || [];
+      parts[chunk.index] = chunk.data;
+      chunks.set(chunk.id, parts);
+      if (parts.filter((part) => part !== undefined).length < chunk.count) {
+        return;
+      }
+      chunks.delete(chunk.id);
+      data = parts.join('');
+    }
+
+    handleMessage({ data });
+    // The server holds back newer renders until this one is applied, see `watch.rs`
+    if (data.startsWith('{"type":"render"')) {
+      socket.send(JSON.stringify({ type: 'ack' }));
+    }
+  };
+  console.log(`Created websocket connection to listen for changes to ${path}.`);
+
+  // Mirrored scrolling: only scrolls the user made are sent, not the ones following the editor
+  // or other browsers
+  if (penviewConfig.mirrorScroll) {
+    let lastInput = 0;
+    for (const type of ['wheel', 'touchmove', 'keydown', 'mousedown']) {
+      window.addEventListener(type, () => { lastInput = Date.now(); }, { passive: true });
+    }
+
+    let pending = false;
+    window.addEventListener('scroll', function () {
+      if (pending || Date.now() - lastInput > 1000 || socket.readyState !== WebSocket.OPEN) {
+        return;
+      }
+      pending = true;
+      setTimeout(function () {
+        pending = false;
+        const ratio = window.scrollY / document.documentElement.scrollHeight;
+        socket.send(JSON.stringify({ type: 'mirror_scroll', client: mirrorClient, ratio }));
+      }, 50);
+    }, { passive: true });
+  }
+}
+
+// Follow mode: switch to whichever document in the directory is saved next
+const follow = urlParams.get('follow');
+
+if (follow) {
+  const followSocket = new WebSocket(`${socketOrigin}${base}/follow?${new URLSearchParams({ dir: follow })}`);
+  followSocket.onmessage = function (event) {
+    // Saves of this document already reload it over /watch
+    if (event.data && event.data !== path) {
+      const params = new URLSearchParams(location.search);
+      params.set('path', event.data);
+      params.delete('rev');
+      location.search = params.toString();
+    }
+  };
+}
+
+// Split view: the raw markdown source next to the rendered output
+const splitView = urlParams.get('view') === 'split';
+
+function loadSource() {
+  if (!splitView) {
+    return;
+  }
+  const params = new URLSearchParams({ path });
+  if (revision) {
+    params.set('rev', revision);
+  }
+  fetch(`${base}/api/raw?${withInstance(params)}`)
+    .then((response) => response.ok ? response.text() : '')
+    .then((text) => {
+      const source = document.getElementById('penview-source');
+      source.replaceChildren(...text.split('\n').map((line, i) => {
+        const span = document.createElement('span');
+        span.dataset.line = i + 1;
+        span.textContent = line + '\n';
+        return span;
+      }));
+    });
+}
+
+// Linked scrolling, driven by whichever pane is under the pointer
+function scrollRenderedToSource() {
+  const source = document.getElementById('penview-source');
+  if (!source.matches(':hover')) {
+    return;
+  }
+  const top = [...source.children].find((span) => span.offsetTop >= source.scrollTop);
+  if (!top) {
+    return;
+  }
+  const line = Number(top.dataset.line);
+  let target = null;
+  for (const el of document.querySelectorAll(sourceBlocks)) {
+    if (Number(el.dataset.sourceLine) > line) {
+      break;
+    }
+    target = el;
+  }
+  window.scrollTo({ top: target ? target.getBoundingClientRect().top + window.scrollY - 16 : 0 });
+}
+
+function scrollSourceToRendered() {
+  const source = document.getElementById('penview-source');
+  if (source.matches(':hover')) {
+    return;
+  }
+  const top = [...document.querySelectorAll(sourceBlocks)]
+    .find((el) => el.getBoundingClientRect().bottom > 0);
+  const span = top && source.querySelector(`[data-line="${top.dataset.sourceLine}"]`);
+  source.scrollTop = span ? span.offsetTop - 16 : 0;
+}
+
+document.addEventListener("DOMContentLoaded", function () {
+  const toggle = document.getElementById('penview-split-toggle');
+  toggle.addEventListener('click', function () {
+    const params = new URLSearchParams(location.search);
+    if (splitView) {
+      params.delete('view');
+    } else {
+      params.set('view', 'split');
+    }
+    location.search = params.toString();
+  });
+
+  if (splitView) {
+    document.body.classList.add('penview-split');
+    document.getElementById('penview-source').hidden = false;
+    document.getElementById('penview-source').addEventListener('scroll', scrollRenderedToSource);
+    window.addEventListener('scroll', scrollSourceToRendered);
+    loadSource();
+  }
+});
+
+// Revision selector, shown when the file has git history
+document.addEventListener("DOMContentLoaded", function () {
+  fetch(`${base}/api/history?path=${encodeURIComponent(path)}`)
+    .then((response) => response.ok ? response.json() : [])
+    .then((revisions) => {
+      if (revisions.length === 0) {
+        return;
+      }
+
+      const select = document.getElementById('penview-history');
+      select.add(new Option(messages.workingCopy, ''));
+      for (const rev of revisions) {
+        const date = new Date(rev.time * 1000).toLocaleDateString();
+        select.add(new Option(`${rev.commit.slice(0, 7)} ${date} ${rev.summary}`, rev.commit));
+      }
+      select.value = revision || '';
+      select.hidden = false;
+
+      select.addEventListener('change', function () {
+        const params = withInstance(new URLSearchParams({ path }));
+        if (select.value) {
+          params.set('rev', select.value);
+        }
+        location.search = params.toString();
+      });
+    });
+});
+
+// Keep links to other documents in this editor instance's namespace
+if (instance) {
+  document.addEventListener('click', function (event) {
+    const link = event.target.closest('a[href]');
+    if (!link) {
+      return;
+    }
+    const url = new URL(link.href, location.href);
+    if (url.origin === location.origin && url.pathname === `${base}/` && !url.searchParams.has('instance')) {
+      url.searchParams.set('instance', instance);
+      link.href = url.toString();
+    }
+  }, true);
+}
+
+// Links to local files in the document open in the editor, or do nothing, instead of navigating
+// the preview when configured so
+if (penviewConfig.linkNavigation !== 'navigate') {
+  document.addEventListener('click', function (event) {
+    const link = event.target.closest('.markdown-body a[href]');
+    if (!link) {
+      return;
+    }
+    const url = new URL(link.href, location.href);
+    if (url.origin !== location.origin || url.pathname !== `${base}/` || !url.searchParams.has('path')) {
+      return;
+    }
+    event.preventDefault();
+
+    if (penviewConfig.linkNavigation === 'editor') {
+      const params = withInstance(new URLSearchParams({ path: url.searchParams.get('path') }));
+      fetch(`${base}/api/open?${params}`, { method: 'POST', headers: { 'X-Penview': '1' } });
+    }
+  });
+}
+
+// Editable preview: text edits in leaf blocks are sent to the editor, which applies them to the
+// buffer and so re-renders the preview
+const editableBlocks = '.markdown-body :is(p, h1, h2, h3, h4, h5, h6, li, th, td)[data-source-line]';
+// Text of the block being edited, when it got focus
+let editOriginal = null;
+
+function makeEditable() {
+  if (!penviewConfig.editable) {
+    return;
+  }
+  for (const block of document.querySelectorAll(editableBlocks)) {
+    // Blocks containing other blocks (e.g. list items with nested lists) map to several lines
+    if (!block.querySelector(sourceBlocks)) {
+      block.contentEditable = 'true';
+    }
+  }
+}
+
+// The changed part of `before`, widened to whole words so that it can be found in the source
+function textEdit(before, after) {
+  let start = 0;
+  while (start < before.length && start < after.length && before[start] === after[start]) {
+    start++;
+  }
+  let end = 0;
+  while (end < before.length - start && end < after.length - start
+    && before[before.length - 1 - end] === after[after.length - 1 - end]) {
+    end++;
+  }
+
+  // Move boundaries that fall inside a word to its edges
+  const inWord = (i) => /\S/.test(before[i - 1] ?? ' ') && /\S/.test(before[i] ?? ' ');
+  while (inWord(start)) {
+    start--;
+  }
+  while (inWord(before.length - end)) {
+    end--;
+  }
+  // Text inserted between words is anchored to the word before it, or else the one after it
+  if (before.slice(start, before.length - end).trim() === '') {
+    if (start > 0) {
+      start = before.slice(0, start).trimEnd().search(/\S+$/);
+    } else {
+      end = Math.max(0, before.length - before.search(/\s|$/));
+    }
+  }
+  return {
+    old: before.slice(start, before.length - end),
+    new: after.slice(start, after.length - end),
+  };
+}
+
+if (penviewConfig.editable) {
+  document.addEventListener('DOMContentLoaded', makeEditable);
+
+  document.addEventListener('focusin', function (event) {
+    if (event.target.isContentEditable) {
+      editOriginal = event.target.textContent;
+    }
+  });
+
+  document.addEventListener('keydown', function (event) {
+    // Edits are single-line, Enter applies them and Escape discards them
+    if (!event.target.isContentEditable) {
+      return;
+    }
+    if (event.key === 'Enter') {
+      event.preventDefault();
+      event.target.blur();
+    } else if (event.key === 'Escape') {
+      event.target.textContent = editOriginal;
+      event.target.blur();
+    }
+  });
+
+  document.addEventListener('focusout', function (event) {
+    const block = event.target;
+    if (!block.isContentEditable || editOriginal === null || block.textContent === editOriginal) {
+      return;
+    }
+    const edit = textEdit(editOriginal, block.textContent);
+    editOriginal = null;
+    if (!edit.old) {
+      return;
+    }
+
+    const params = withInstance(new URLSearchParams({ path }));
+    fetch(`${base}/api/edit?${params}`, {
+      method: 'POST',
+      headers: { 'Content-Type': 'application/json', 'X-Penview': '1' },
+      body: JSON.stringify({ line: Number(block.dataset.sourceLine), ...edit }),
+    });
+  });
+}
+
+// Clickable task checkboxes: the server checks or unchecks the task in the file, and the file
+// watcher reloads the page. Files open in Neovim are refused, and the checkbox reverts.
+if (penviewConfig.toggleTasks) {
+  document.addEventListener('DOMContentLoaded', function () {
+    for (const checkbox of document.querySelectorAll('.markdown-body li input[type="checkbox"]')) {
+      checkbox.disabled = false;
+    }
+  });
+
+  document.addEventListener('change', function (event) {
+    const checkbox = event.target;
+    const item = checkbox.closest?.('.markdown-body li[data-source-line]');
+    if (checkbox.type !== 'checkbox' || !item) {
+      return;
+    }
+
+    const params = new URLSearchParams({
+      path,
+      line: item.dataset.sourceLine,
+      checked: checkbox.checked,
+    });
+    fetch(`${base}/api/task?${params}`, { method: 'POST', headers: { 'X-Penview': '1' } })
+      .then(async (response) => {
+        if (!response.ok) {
+          checkbox.checked = !checkbox.checked;
+          console.warn('Failed to toggle task:', await response.text());
+        }
+      });
+  });
+}
+
+// Running code blocks (see `--exec`), with their output shown beneath them. Outputs are kept by
+// code so that they survive live updates
+const execOutputs = new Map();
+
+function addRunButtons() {
+  for (const pre of document.querySelectorAll('.markdown-body pre[data-source-line]')) {
+    const code = pre.querySelector('code');
+    const lang = code?.className.match(/language-(\S+)/)?.[1];
+    if (!penviewConfig.exec.includes(lang)) {
+      continue;
+    }
+
+    const button = document.createElement('button');
+    button.className = 'penview-exec-run';
+    button.textContent = messages.run;
+    button.title = messages.runBlock.replace('{lang}', lang);
+    button.addEventListener('click', () => runBlock(pre, button));
+    pre.append(button);
+
+    const output = execOutputs.get(code.textContent);
+    if (output) {
+      showOutput(pre, output);
+    }
+  }
+}
+
+function runBlock(pre, button) {
+  const code = pre.querySelector('code').textContent;
+  button.disabled = true;
+  button.textContent = messages.running;
+
+  const params = withInstance(new URLSearchParams({ path, line: pre.dataset.sourceLine }));
+  fetch(`${base}/api/exec?${params}`, { method: 'POST', headers: { 'X-Penview': '1' } })
+    .then(async (response) => {
+      const output = response.ok ? await response.json() : { error: await response.text() };
+      execOutputs.set(code, output);
+      showOutput(pre, output);
+    })
+    .finally(() => {
+      button.disabled = false;
+      button.textContent = messages.run;
+    });
+}
+
+function showOutput(pre, output) {
+  let element = pre.nextElementSibling;
+  if (!element?.classList.contains('penview-exec-output')) {
+    element = document.createElement('div');
+    element.className = 'penview-exec-output';
+    pre.after(element);
+  }
+  element.replaceChildren();
+
+  const streams = [['stdout', output.stdout], ['stderr', output.stderr ?? output.error]];
+  for (const [stream, text] of streams) {
+    if (text) {
+      const block = document.createElement('pre');
+      block.className = `penview-exec-${stream}`;
+      block.textContent = text;
+      element.append(block);
+    }
+  }
+  if (!output.error && !output.timed_out && output.status !== 0) {
+    const status = document.createElement('div');
+    status.className = 'penview-exec-status';
+    status.textContent = output.status === null
+      ? messages.killed
+      : messages.exited.replace('{status}', output.status);
+    element.append(status);
+  }
+  if (!element.hasChildNodes()) {
+    element.textContent = messages.noOutput;
+  }
+}
+
+if (penviewConfig.exec.length > 0) {
+  document.addEventListener('DOMContentLoaded', addRunButtons);
+}
+
+// Daily note navigation
+document.addEventListener("DOMContentLoaded", function () {
+  for (const link of document.querySelectorAll('.penview-daily-link')) {
+    link.href = `${base}/?${new URLSearchParams({ path: link.dataset.path })}`;
+  }
+});
+
+// Swiping sideways on a touch screen goes to the previous or next day, like the links
+document.addEventListener("DOMContentLoaded", function () {
+  if (!document.querySelector('.penview-daily')) {
+    return;
+  }
+
+  let start = null;
+  document.addEventListener('touchstart', function (event) {
+    // Two fingers are zooming, not swiping
+    start = event.touches.length === 1 ? event.touches[0] : null;
+  }, { passive: true });
+  document.addEventListener('touchend', function (event) {
+    const end = event.changedTouches[0];
+    if (!start || window.visualViewport?.scale > 1) {
+      return;
+    }
+    const dx = end.clientX - start.clientX;
+    const dy = end.clientY - start.clientY;
+    start = null;
+    // Mostly horizontal and long enough not to be a scroll or a tap
+    if (Math.abs(dx) > 80 && Math.abs(dx) > 2 * Math.abs(dy)) {
+      const link = document.getElementById(dx > 0 ? 'penview-daily-prev' : 'penview-daily-next');
+      location.href = link.href;
+    }
+  }, { passive: true });
+});
+
+// Highlight this document in the graph view
+document.addEventListener("DOMContentLoaded", function () {
+  document.getElementById('penview-graph-link').href = `${base}/graph?${new URLSearchParams({ path })}`;
+});
+
+// Documents in the workspace linking to this one
+document.addEventListener("DOMContentLoaded", function () {
+  fetch(`${base}/api/backlinks?${withInstance(new URLSearchParams({ path }))}`)
+    .then((response) => response.ok ? response.json() : [])
+    .then((backlinks) => {
+      if (backlinks.length === 0) {
+        return;
+      }
+
+      const list = document.getElementById('penview-backlinks-list');
+      for (const backlink of backlinks) {
+        const item = document.createElement('li');
+        const link = document.createElement('a');
+        link.href = `${base}/?${new URLSearchParams({ path: backlink.path })}`;
+        link.textContent = backlink.title;
+        const context = document.createElement('div');
+        context.className = 'penview-backlinks-context';
+        context.textContent = `${backlink.line}: ${backlink.context}`;
+        item.append(link, context);
+        list.appendChild(item);
+      }
+      document.getElementById('penview-backlinks-count').textContent = backlinks.length;
+      document.getElementById('penview-backlinks').hidden = false;
+    });
+});
+
+if (penviewConfig.lint) {
+  document.addEventListener("DOMContentLoaded", function () {
+    fetch(`${base}/api/lint?path=${encodeURIComponent(path)}`)
+      .then((response) => response.json())
+      .then((warnings) => {
+        lint = warnings;
+        renderDiagnostics();
+      });
+  });
+}
@@ -0,0 +1,111 @@
+// In-page search: matches come from the server's index of the document's source, so they
+// map to blocks by source line
+(function() {
+  const input = document.getElementById('penview-search-input');
+  const count = document.getElementById('penview-search-count');
+  const path = new URLSearchParams(location.search).get('path');
+  let matches = [];
+  let current = -1;
+  let pending = null;
+
+  function clearHighlights() {
+    for (const el of document.querySelectorAll('.penview-search-hit, .penview-search-current')) {
+      el.classList.remove('penview-search-hit', 'penview-search-current');
+    }
+    window.CSS?.highlights?.delete('penview-search');
+  }
+
+  // Marks the query's occurrences in the matching blocks, where the browser supports it
+  function highlightText(blocks, query) {
+    if (!window.CSS?.highlights || !window.Highlight) {
+      return;
+    }
+    const ranges = [];
+    const needle = query.toLowerCase();
+    for (const block of blocks) {
+      const walker = document.createTreeWalker(block, NodeFilter.SHOW_TEXT);
+      while (walker.nextNode()) {
+        const node = walker.currentNode;
+        const text = node.textContent.toLowerCase();
+        for (let i = text.indexOf(needle); i !== -1; i = text.indexOf(needle, i + needle.length)) {
+          const range = new Range();
+          range.setStart(node, i);
+          range.setEnd(node, i + needle.length);
+          ranges.push(range);
+        }
+      }
+    }
+    CSS.highlights.set('penview-search', new Highlight(...ranges));
+  }
+
+  function show() {
+    clearHighlights();
+    const blocks = new Set(matches.map((m) => window.penviewBlockAt(m.line)).filter(Boolean));
+    for (const block of blocks) {
+      block.classList.add('penview-search-hit');
+    }
+    highlightText(blocks, input.value.trim());
+
+    count.textContent = input.value.trim()
+      ? `${matches.length ? current + 1 : 0}/${matches.length}`
+      : '';
+    const el = matches[current] && window.penviewBlockAt(matches[current].line);
+    if (el) {
+      el.classList.add('penview-search-current');
+      el.scrollIntoView({ block: 'center' });
+    }
+  }
+
+  function search() {
+    const q = input.value.trim();
+    if (!q) {
+      matches = [];
+      current = -1;
+      show();
+      return;
+    }
+    fetch(`${penviewConfig.base}/api/search?${withInstance(new URLSearchParams({ path, q }))}`)
+      .then((response) => response.ok ? response.json() : [])
+      .then((results) => {
+        if (input.value.trim() !== q) {
+          return;
+        }
+        matches = results;
+        current = matches.length ? 0 : -1;
+        show();
+      });
+  }
+
+  function step(delta) {
+    if (matches.length) {
+      current = (current + delta + matches.length) % matches.length;
+      show();
+    }
+  }
+
+  input.addEventListener('input', function () {
+    clearTimeout(pending);
+    pending = setTimeout(search, 150);
+  });
+  input.addEventListener('keydown', function (event) {
+    if (event.key === 'Enter') {
+      event.preventDefault();
+      step(event.shiftKey ? -1 : 1);
+    } else if (event.key === 'Escape') {
+      input.value = '';
+      search();
+      input.blur();
+    }
+  });
+  document.getElementById('penview-search-prev').addEventListener('click', () => step(-1));
+  document.getElementById('penview-search-next').addEventListener('click', () => step(1));
+
+  // "/" focuses the search box, like in many documentation sites
+  document.addEventListener('keydown', function (event) {
+    if (event.key === '/' && !event.target.closest('input, select, textarea')) {
+      event.preventDefault();
+      input.focus();
+      input.select();
+    }
+  });
+})();